@@ -1,22 +1,305 @@
-use proc_macro2::Literal;
+use proc_macro2::{Literal, TokenStream};
 use std::fs::{read_to_string, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use syn::__private::quote::quote;
-use syn::{parse_file, Attribute, FnArg, Item, Pat, PatIdent};
+use syn::__private::quote::{format_ident, quote};
+use syn::{parse_file, Attribute, FnArg, GenericArgument, Ident, Item, ItemFn, Pat, PathArguments, ReturnType, Type};
 
 const FFI_OUT_PATH: &'static str = "src/";
 const FFI_FILE_NAME: &'static str = "codec_ffi.rs";
+const HASH_FILE_NAME: &str = "interface_hash.rs";
 
 const FFI_IN_PATH: &'static str = "../codec/src/interface.rs";
 
 const FFI_STUB_NAME: &'static str = "rehash_codec_ffi";
 
+const PLAIN_TYPES: [&str; 13] = ["bool", "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize", "f32", "f64"];
+
 fn has_ffi_stub_attribute(attrs: &[Attribute]) -> bool {
     attrs.iter().any(|a| a.path().is_ident(FFI_STUB_NAME))
 }
 
+/// How a Rust type crosses the C boundary, mirrors the proc macro
+enum Marshal {
+    Plain,
+    Str,
+    Bytes,
+    Json(Box<Type>),
+    Progress,
+}
+
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
+
+fn marshal(ty: &Type) -> Marshal {
+    if let Type::Reference(reference) = ty {
+        return match type_name(&reference.elem).as_str() {
+            "str" => Marshal::Str,
+            "[u8]" => Marshal::Bytes,
+            "CodecProgress" => Marshal::Progress,
+            _ => Marshal::Json(reference.elem.clone()),
+        };
+    }
+
+    match type_name(ty).as_str() {
+        "String" => Marshal::Str,
+        "Vec<u8>" => Marshal::Bytes,
+        name if PLAIN_TYPES.contains(&name) => Marshal::Plain,
+        _ => Marshal::Json(Box::new(ty.clone())),
+    }
+}
+
+/// `T` of a `Result<T, CodecError>` return, mirrors the proc macro
+fn result_ok_type(ty: &Type) -> Option<Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last().filter(|segment| segment.ident == "Result")?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first() {
+        Some(GenericArgument::Type(ty)) => Some(ty.clone()),
+        _ => None,
+    }
+}
+
+/// `&[T]` for a `Vec<T>` argument, `&T` otherwise
+fn borrowed(ty: &Type) -> TokenStream {
+    if let Type::Path(path) = ty {
+        let segment = path.path.segments.last();
+        if let Some(PathArguments::AngleBracketed(args)) = segment.filter(|segment| segment.ident == "Vec").map(|segment| &segment.arguments)
+            && let Some(GenericArgument::Type(elem)) = args.args.first()
+        {
+            return quote!(&[#elem]);
+        }
+    }
+    quote!(&#ty)
+}
+
+/// `&str` arguments named like paths are taken as `&Path` on the host
+fn is_path(name: &str) -> bool {
+    name.contains("path") || name.contains("dir")
+}
+
+/// `probe_video` to `ProbeVideo`, naming the worker request of an export
+fn variant_name(fn_name: &Ident) -> Ident {
+    let name = fn_name.to_string().split('_').map(|word| {
+        let mut chars = word.chars();
+        chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+    }).collect::<String>();
+    format_ident!("{}", name)
+}
+
+/// FNV-1a over the exported signatures, bodies and docs can change without breaking the ABI
+fn interface_hash(signatures: &[String]) -> u64 {
+    signatures.iter().flat_map(|signature| signature.bytes().chain([b'\n'])).fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+struct Export {
+    field: TokenStream,
+    load: TokenStream,
+    wrapper: TokenStream,
+    request: TokenStream,
+    request_file: TokenStream,
+    request_handle: TokenStream,
+    handler: TokenStream,
+    worker_wrapper: TokenStream,
+    backend_method: TokenStream,
+    library_impl: TokenStream,
+    worker_impl: TokenStream,
+}
+
+/// Function table entry and host wrapper decoding what the proc macro encoded
+fn export(func: &ItemFn) -> Export {
+    let fn_name = &func.sig.ident;
+    let fn_name_str = Literal::byte_string(fn_name.to_string().as_bytes());
+
+    let mut c_types = Vec::new();
+    let mut params = Vec::new();
+    let mut setup = Vec::new();
+    let mut call_args = Vec::new();
+    let mut names = Vec::new();
+    let mut request_fields = Vec::new();
+    let mut field_names = Vec::new();
+    let mut request_values = Vec::new();
+    let mut handler_args = Vec::new();
+    let mut file_path = None;
+    let mut handle = None;
+    let mut progress = None;
+
+    for fn_arg in &func.sig.inputs {
+        let FnArg::Typed(pat_type) = fn_arg else {
+            continue;
+        };
+        let Pat::Ident(pat_ident) = &*pat_type.pat else {
+            panic!("{} needs plain argument names", fn_name);
+        };
+        let name = &pat_ident.ident;
+        let label = name.to_string();
+        names.push(quote!(#name));
+
+        match marshal(&pat_type.ty) {
+            Marshal::Plain => {
+                let ty = &pat_type.ty;
+                c_types.push(quote!(#ty));
+                params.push(quote!(#name: #ty));
+                call_args.push(quote!(#name));
+                request_fields.push(quote!(#name: #ty));
+                field_names.push(quote!(#name));
+                request_values.push(quote!(#name));
+                handler_args.push(quote!(#name));
+                if label == "handle" || label == "job" {
+                    handle = Some(name.clone());
+                }
+            }
+            Marshal::Str => {
+                c_types.push(quote!(*const c_char));
+                if is_path(&label) {
+                    params.push(quote!(#name: &Path));
+                    setup.push(quote!(let #name = marshal::c_path(#name, #label)?;));
+                    request_values.push(quote!(#name: marshal::path_str(#name, #label)?.to_string()));
+                    handler_args.push(quote!(Path::new(&#name)));
+                    // what a call writes isn't what it works on
+                    if file_path.is_none() && label.contains("path") && !label.starts_with("output") {
+                        file_path = Some(name.clone());
+                    }
+                } else {
+                    params.push(quote!(#name: &str));
+                    setup.push(quote!(let #name = marshal::c_str(#name, #label)?;));
+                    request_values.push(quote!(#name: #name.to_string()));
+                    handler_args.push(quote!(&#name));
+                }
+                call_args.push(quote!(#name.as_ptr()));
+                request_fields.push(quote!(#name: String));
+                field_names.push(quote!(#name));
+            }
+            Marshal::Bytes => {
+                c_types.push(quote!(*const u8));
+                c_types.push(quote!(usize));
+                params.push(quote!(#name: &[u8]));
+                call_args.push(quote!(#name.as_ptr()));
+                call_args.push(quote!(#name.len()));
+                request_fields.push(quote!(#name: Vec<u8>));
+                field_names.push(quote!(#name));
+                request_values.push(quote!(#name: #name.to_vec()));
+                handler_args.push(quote!(&#name));
+            }
+            Marshal::Json(ty) => {
+                c_types.push(quote!(*const c_char));
+                let param_ty = borrowed(&ty);
+                params.push(quote!(#name: #param_ty));
+                setup.push(quote!(let #name = marshal::c_json(#name, #label)?;));
+                call_args.push(quote!(#name.as_ptr()));
+                request_fields.push(quote!(#name: #ty));
+                field_names.push(quote!(#name));
+                request_values.push(quote!(#name: #name.to_owned()));
+                handler_args.push(quote!(&#name));
+            }
+            Marshal::Progress => {
+                c_types.push(quote!(*const CodecProgress));
+                params.push(quote!(#name: &ProgressReporter));
+                setup.push(quote!(let #name = #name.as_codec();));
+                call_args.push(quote!(&#name));
+                handler_args.push(quote!(progress));
+                progress = Some(name.clone());
+            }
+        }
+    }
+
+    let value_type = match &func.sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(result_ok_type(ty).unwrap_or_else(|| (**ty).clone())),
+    };
+
+    let returns_bytes = value_type.as_ref().is_some_and(|ty| matches!(marshal(ty), Marshal::Bytes));
+    let (return_type, out_type, value) = match value_type.filter(|ty| type_name(ty) != "()") {
+        None => (quote!(()), None, quote!(Ok(()))),
+        Some(ty) => match marshal(&ty) {
+            Marshal::Plain => (quote!(#ty), Some(quote!(#ty)), quote!(Ok(unsafe { out.assume_init() }))),
+            Marshal::Str => (quote!(String), Some(quote!(*mut c_char)), quote!(unsafe { self.take_string(out.assume_init()) })),
+            Marshal::Bytes => (quote!(Vec<u8>), Some(quote!(CodecBuffer)), quote!(Ok(unsafe { self.take_buffer(out.assume_init()) }))),
+            Marshal::Json(ty) => (quote!(#ty), Some(quote!(*mut c_char)), quote!(marshal::from_json(&unsafe { self.take_string(out.assume_init())? }))),
+            Marshal::Progress => panic!("{} can't return progress", fn_name),
+        },
+    };
+
+    let out_param = out_type.iter();
+    let field = quote! {
+        #fn_name: unsafe extern "C" fn(#(#c_types,)* #(*mut #out_param)*) -> i32
+    };
+    let load = quote! {
+        #fn_name: *lib.get(#fn_name_str).map_err(|e| format!("Codec is missing {}: {}", stringify!(#fn_name), e))?
+    };
+
+    let call = match out_type {
+        Some(out_type) => quote! {
+            let mut out = MaybeUninit::<#out_type>::uninit();
+            let status = unsafe { (self.functions.#fn_name)(#(#call_args,)* out.as_mut_ptr()) };
+        },
+        None => quote! {
+            let status = unsafe { (self.functions.#fn_name)(#(#call_args),*) };
+        },
+    };
+
+    let wrapper = quote! {
+        pub fn #fn_name(&self, #(#params),*) -> Result<#return_type, CodecError> {
+            #(#setup)*
+            #call
+            self.check_status(status)?;
+            #value
+        }
+    };
+
+    let variant = variant_name(fn_name);
+    let request = quote!(#variant { #(#request_fields),* });
+    let request_file = match file_path {
+        Some(path) => quote!(CodecRequest::#variant { #path, .. } => Some(#path.as_str())),
+        None => quote!(CodecRequest::#variant { .. } => None),
+    };
+    let request_handle = match handle {
+        Some(handle) => quote!(CodecRequest::#variant { #handle, .. } => Some(#handle)),
+        None => quote!(CodecRequest::#variant { .. } => None),
+    };
+
+    let handled = quote!(self.#fn_name(#(#handler_args),*)?);
+    let handler = if returns_bytes {
+        quote!(CodecRequest::#variant { #(#field_names),* } => Ok(WorkerValue::Bytes(#handled)))
+    } else {
+        quote!(CodecRequest::#variant { #(#field_names),* } => WorkerValue::json(&#handled))
+    };
+
+    let progress_arg = match &progress {
+        Some(progress) => quote!(Some(#progress)),
+        None => quote!(None),
+    };
+    let into_value = if returns_bytes { quote!(into_bytes()) } else { quote!(into_json()) };
+    let worker_wrapper = quote! {
+        pub fn #fn_name(&self, #(#params),*) -> Result<#return_type, CodecError> {
+            let request = CodecRequest::#variant { #(#request_values),* };
+            self.call(request, #progress_arg)?.#into_value
+        }
+    };
+
+    let signature = quote!(fn #fn_name(&self, #(#params),*) -> Result<#return_type, CodecError>);
+    let unsupported = format!("{} needs the ffmpeg codec, which is not loaded", fn_name.to_string().replace('_', " "));
+    let backend_method = quote! {
+        #[allow(unused_variables)]
+        #signature {
+            Err(CodecError::unsupported(#unsupported))
+        }
+    };
+    let library_impl = quote!(#signature { RehashCodecLibrary::#fn_name(self, #(#names),*) });
+    let worker_impl = quote!(#signature { CodecWorker::#fn_name(self, #(#names),*) });
+
+    Export { field, load, wrapper, request, request_file, request_handle, handler, worker_wrapper, backend_method, library_impl, worker_impl }
+}
+
 fn run_rust_fmt(path: &Path) -> std::io::Result<()> {
     eprintln!("Running rustfmt at {}", path.display());
     let status = Command::new("rustfmt").arg(path).status()?;
@@ -47,46 +330,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let generated_code = fn_to_gen.iter().map(|func| {
-        let fn_name = &func.sig.ident;
-        let inputs = &func.sig.inputs;
-        let outputs = &func.sig.output;
-        // let generics = &func.sig.generics;
-
-        let func_input_types = inputs.iter().filter_map(|fn_arg| {
-            if let FnArg::Typed(pat_type) = fn_arg {
-                Some(pat_type.ty.clone())
-            } else {
-                None
-            }
-        });
-
-        let func_input_parameters = inputs.iter().filter_map(|fn_arg| {
-            if let FnArg::Typed(pat_type) = fn_arg {
-                match *pat_type.pat {
-                    Pat::Ident(PatIdent { ref ident, .. }) => Some(ident.clone()),
-                    _ => None,
-                }
-            } else {
-                None
-            }
-        });
+    let exports = fn_to_gen.iter().map(export).collect::<Vec<_>>();
+    let table_fields = exports.iter().map(|export| &export.field);
+    let table_loads = exports.iter().map(|export| &export.load);
+    let wrappers = exports.iter().map(|export| &export.wrapper);
+    let requests = exports.iter().map(|export| &export.request);
+    let request_files = exports.iter().map(|export| &export.request_file);
+    let request_handles = exports.iter().map(|export| &export.request_handle);
+    let handlers = exports.iter().map(|export| &export.handler);
+    let worker_wrappers = exports.iter().map(|export| &export.worker_wrapper);
+    let backend_methods = exports.iter().map(|export| &export.backend_method);
+    let library_impls = exports.iter().map(|export| &export.library_impl);
+    let worker_impls = exports.iter().map(|export| &export.worker_impl);
 
-        let fn_name_str = Literal::byte_string(fn_name.to_string().as_bytes());
+    let out_dir = PathBuf::from(FFI_OUT_PATH);
+    let out_path = Path::new(&out_dir).join(FFI_FILE_NAME);
 
-        let fn_ffi_gen = quote! {
-            pub fn #fn_name(&self, #inputs) #outputs {
-                unsafe {
-                    let func: Symbol<unsafe extern "C" fn(#(#func_input_types),*) #outputs> = self.lib.get(#fn_name_str).expect("Failed to load symbol");
-                    func(#(#func_input_parameters),*)
-                }
-            }
-        };
-        fn_ffi_gen
+    let signatures = fn_to_gen.iter().map(|func| {
+        let sig = &func.sig;
+        quote!(#sig).to_string()
     }).collect::<Vec<_>>();
+    let hash = interface_hash(&signatures);
 
-    let out_dir = PathBuf::from(FFI_OUT_PATH);
-    let out_path = Path::new(&out_dir).join(FFI_FILE_NAME);
+    let mut hash_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(out_dir.join(HASH_FILE_NAME))
+        .expect("Failed to open interface hash file");
+    writeln!(&mut hash_file, "/* THIS FILE IS GENERATED DO NOT EDIT */")?;
+    writeln!(&mut hash_file, "pub const INTERFACE_HASH: u64 = 0x{:016x};", hash)?;
 
 
     let mut file = OpenOptions::new()
@@ -100,18 +373,134 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     writeln!(&mut file, "/* THIS FILE IS GENERATED DO NOT EDIT */")?;
     writeln!(&mut file, "{}\n", quote! {
         #[cfg(target_os = "linux")]
-        use libloading::Symbol;
+        use libloading::Library;
 
         #[cfg(target_os = "windows")]
-        use libloading::os::windows::Symbol;
+        use libloading::os::windows::Library;
 
+        use crate::codec::*;
+        use crate::error::{CodecError, CodecStatus};
+        use crate::marshal::{self, CodecBuffer};
+        use crate::progress::{CodecProgress, ProgressReporter};
+        use crate::worker::{CodecWorker, WorkerValue};
         use crate::RehashCodecLibrary;
-        use std::ffi::{c_char, c_uchar};
+        use serde::{Deserialize, Serialize};
+        use std::ffi::{c_char, CStr};
+        use std::mem::MaybeUninit;
+        use std::path::Path;
     })?;
 
+    let last_error = format_ident!("rehash_last_error");
+    let free_string = format_ident!("rehash_free_string");
+    let free_buffer = format_ident!("rehash_free_buffer");
+
     let generated_code = quote! {
+        /// Every codec export, resolved once when the library is loaded
+        pub struct CodecFunctions {
+            #last_error: unsafe extern "C" fn() -> *const c_char,
+            #free_string: unsafe extern "C" fn(*mut c_char),
+            #free_buffer: unsafe extern "C" fn(CodecBuffer),
+            #(#table_fields,)*
+        }
+
+        impl CodecFunctions {
+            /// # Safety
+            /// `lib` must be a codec whose ABI descriptor matched, and must outlive the table
+            pub unsafe fn load(lib: &Library) -> Result<Self, String> {
+                unsafe {
+                    Ok(Self {
+                        #last_error: *lib.get(b"rehash_last_error").map_err(|e| format!("Codec is missing rehash_last_error: {}", e))?,
+                        #free_string: *lib.get(b"rehash_free_string").map_err(|e| format!("Codec is missing rehash_free_string: {}", e))?,
+                        #free_buffer: *lib.get(b"rehash_free_buffer").map_err(|e| format!("Codec is missing rehash_free_buffer: {}", e))?,
+                        #(#table_loads,)*
+                    })
+                }
+            }
+        }
+
         impl RehashCodecLibrary {
-            #(#generated_code)*
+            fn check_status(&self, status: i32) -> Result<(), CodecError> {
+                let status = CodecStatus::from_code(status);
+                if status == CodecStatus::Ok {
+                    return Ok(());
+                }
+
+                let message = unsafe {
+                    let ptr = (self.functions.#last_error)();
+                    if ptr.is_null() {
+                        String::from("Codec reported no error message")
+                    } else {
+                        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+                    }
+                };
+                Err(CodecError::new(status, message))
+            }
+
+            /// Copies a codec owned string and hands it back to the codec
+            unsafe fn take_string(&self, ptr: *mut c_char) -> Result<String, CodecError> {
+                if ptr.is_null() {
+                    return Err(CodecError::failed("Codec returned no data"));
+                }
+
+                let value = unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+                unsafe { (self.functions.#free_string)(ptr) };
+                Ok(value)
+            }
+
+            /// Copies a codec owned buffer and hands it back to the codec
+            unsafe fn take_buffer(&self, buffer: CodecBuffer) -> Vec<u8> {
+                let bytes = unsafe { buffer.to_vec() };
+                unsafe { (self.functions.#free_buffer)(buffer) };
+                bytes
+            }
+
+            #(#wrappers)*
+
+            /// Runs a request the codec worker received from the host
+            pub fn handle_request(&self, request: CodecRequest, progress: &ProgressReporter) -> Result<WorkerValue, CodecError> {
+                match request {
+                    #(#handlers,)*
+                }
+            }
+        }
+
+        /// Every export as a message to the codec worker
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        pub enum CodecRequest {
+            #(#requests,)*
+        }
+
+        impl CodecRequest {
+            /// File the request works on, named when the worker crashes on it
+            pub fn file_path(&self) -> Option<&str> {
+                match self {
+                    #(#request_files,)*
+                }
+            }
+
+            /// Stream handle or job the request works on, the worker swaps it for the one its process knows
+            pub fn handle_mut(&mut self) -> Option<&mut u64> {
+                match self {
+                    #(#request_handles,)*
+                }
+            }
+        }
+
+        impl CodecWorker {
+            #(#worker_wrappers)*
+        }
+
+        /// Everything the app asks of a codec, whatever a backend can't do fails as `CodecStatus::Unsupported`
+        pub trait CodecBackend: Send + Sync {
+            #(#backend_methods)*
+        }
+
+        impl CodecBackend for RehashCodecLibrary {
+            #(#library_impls)*
+        }
+
+        impl CodecBackend for CodecWorker {
+            #(#worker_impls)*
         }
     };
 
@@ -0,0 +1,141 @@
+pub use crate::interface_hash::INTERFACE_HASH;
+
+/// Bumped on major for breaking changes outside the exported signatures, on minor for additions
+pub const ABI_VERSION: AbiVersion = AbiVersion { major: 2, minor: 0, patch: 0 };
+
+pub const ABI_SYMBOL: &[u8] = b"rehash_codec_abi";
+
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+
+impl AbiVersion {
+    /// Whether a codec built against `self` can serve a host built against `required`
+    pub fn satisfies(&self, required: &AbiVersion) -> bool {
+        self.major == required.major && (self.minor, self.patch) >= (required.minor, required.patch)
+    }
+}
+
+
+impl std::fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Streaming,
+    FrameDecode,
+    Thumbnails,
+    Import,
+    Proxy,
+    ClipExport,
+    Reel,
+    PlayDetection,
+    AudioEvents,
+    KeyframeIndex,
+    Segments,
+}
+
+
+impl Capability {
+    pub const ALL: [Capability; 11] = [
+        Capability::Streaming,
+        Capability::FrameDecode,
+        Capability::Thumbnails,
+        Capability::Import,
+        Capability::Proxy,
+        Capability::ClipExport,
+        Capability::Reel,
+        Capability::PlayDetection,
+        Capability::AudioEvents,
+        Capability::KeyframeIndex,
+        Capability::Segments,
+    ];
+
+    pub fn flag(&self) -> u64 {
+        1 << (*self as u64)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Streaming => "streaming",
+            Capability::FrameDecode => "frame-decode",
+            Capability::Thumbnails => "thumbnails",
+            Capability::Import => "import",
+            Capability::Proxy => "proxy",
+            Capability::ClipExport => "clip-export",
+            Capability::Reel => "reel",
+            Capability::PlayDetection => "play-detection",
+            Capability::AudioEvents => "audio-events",
+            Capability::KeyframeIndex => "keyframe-index",
+            Capability::Segments => "segments",
+        }
+    }
+
+    pub fn flags(capabilities: &[Capability]) -> u64 {
+        capabilities.iter().fold(0, |flags, capability| flags | capability.flag())
+    }
+}
+
+
+/// Descriptor the codec exports under `ABI_SYMBOL`, checked before any other symbol is used
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecAbi {
+    pub version: AbiVersion,
+    pub interface_hash: u64,
+    pub capabilities: u64,
+}
+
+
+impl CodecAbi {
+    pub fn new(capabilities: &[Capability]) -> Self {
+        Self { version: ABI_VERSION, interface_hash: INTERFACE_HASH, capabilities: Capability::flags(capabilities) }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities & capability.flag() != 0
+    }
+
+    pub fn capabilities(&self) -> Vec<Capability> {
+        Capability::ALL.into_iter().filter(|capability| self.has(*capability)).collect()
+    }
+
+    /// Checks a loaded codec against what this host was built for
+    pub fn verify(&self, required: &[Capability]) -> Result<(), String> {
+        if !self.version.satisfies(&ABI_VERSION) {
+            return Err(format!("Codec ABI {} is incompatible with the expected {}", self.version, ABI_VERSION));
+        }
+
+        if self.interface_hash != INTERFACE_HASH {
+            return Err(format!(
+                "Codec interface {:016x} does not match the expected {:016x}, rebuild the codec and the app together",
+                self.interface_hash, INTERFACE_HASH
+            ));
+        }
+
+        let missing = required.iter().filter(|capability| !self.has(**capability)).map(|capability| capability.name()).collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(format!("Codec is missing capabilities: {}", missing.join(", ")));
+        }
+
+        Ok(())
+    }
+}
+
+
+impl std::fmt::Display for CodecAbi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let capabilities = self.capabilities().iter().map(|capability| capability.name()).collect::<Vec<_>>();
+        write!(f, "ABI {} interface {:016x} [{}]", self.version, self.interface_hash, capabilities.join(", "))
+    }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VideoStreamMeta {
+    pub handle: u64,
     pub file_path: String,
     pub current_position: u64,
     pub total_size: u64,
@@ -10,15 +12,454 @@ pub struct VideoStreamMeta {
 
 
 impl VideoStreamMeta {
-    pub fn new(file_path: String, current_position: u64, total_size: u64, chunk_size: usize) -> Self {
-        Self { file_path, current_position, total_size, chunk_size }
+    pub fn new(handle: u64, file_path: String, current_position: u64, total_size: u64, chunk_size: usize) -> Self {
+        Self { handle, file_path, current_position, total_size, chunk_size }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current_position >= self.total_size
     }
 }
 
 
+/// Chunk of a video stream, sent over IPC as a fixed header followed by the bytes
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VideoStreamChunk {
     pub bytes: Vec<u8>,
     pub position: u64,
     pub is_final: bool,
-}
\ No newline at end of file
+}
+
+
+impl VideoStreamChunk {
+    pub const HEADER_SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::HEADER_SIZE + self.bytes.len());
+        bytes.extend_from_slice(&self.position.to_le_bytes());
+        bytes.extend_from_slice(&u64::from(self.is_final).to_le_bytes());
+        bytes.extend_from_slice(&self.bytes);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::HEADER_SIZE {
+            return None;
+        }
+
+        let position = u64::from_le_bytes(bytes[0..8].try_into().ok()?);
+        let is_final = u64::from_le_bytes(bytes[8..16].try_into().ok()?) != 0;
+        Some(Self { bytes: bytes[Self::HEADER_SIZE..].to_vec(), position, is_final })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+    Unknown,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamProbe {
+    pub index: usize,
+    pub kind: StreamKind,
+    pub codec_name: String,
+    pub duration: f64,
+    pub bit_rate: i64,
+    pub frame_rate: f64,
+    pub frame_count: i64,
+    pub width: u32,
+    pub height: u32,
+    pub rotation: i32,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoProbe {
+    pub file_path: String,
+    pub format_name: String,
+    pub format_long_name: String,
+    pub duration: f64,
+    pub bit_rate: i64,
+    pub creation_time: Option<String>,
+    pub video_stream: Option<usize>,
+    pub audio_stream: Option<usize>,
+    pub streams: Vec<StreamProbe>,
+}
+
+
+impl VideoProbe {
+    const PLAYABLE_VIDEO_CODECS: [&'static str; 4] = ["h264", "vp8", "vp9", "av1"];
+    const PLAYABLE_AUDIO_CODECS: [&'static str; 5] = ["aac", "mp3", "opus", "vorbis", "flac"];
+
+    pub fn video(&self) -> Option<&StreamProbe> {
+        self.streams.iter().find(|s| Some(s.index) == self.video_stream)
+    }
+
+    pub fn audio(&self) -> Option<&StreamProbe> {
+        self.streams.iter().find(|s| Some(s.index) == self.audio_stream)
+    }
+
+    /// Reason the webview `<video>` element would fail to play this file, `None` if it should play
+    pub fn unsupported_reason(&self) -> Option<String> {
+        let video = match self.video() {
+            Some(video) => video,
+            None => return Some("File has no video stream".to_string()),
+        };
+
+        if !Self::PLAYABLE_VIDEO_CODECS.contains(&video.codec_name.as_str()) {
+            return Some(format!("Unsupported video codec: {}", video.codec_name));
+        }
+
+        if let Some(audio) = self.audio()
+            && !Self::PLAYABLE_AUDIO_CODECS.contains(&audio.codec_name.as_str())
+        {
+            return Some(format!("Unsupported audio codec: {}", audio.codec_name));
+        }
+
+        None
+    }
+
+    /// Anything other than a playable MP4 is imported first, as long as there is a video stream to import
+    pub fn needs_import(&self) -> bool {
+        let is_mp4 = Path::new(&self.file_path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
+
+        !is_mp4 || self.unsupported_reason().is_some()
+    }
+
+    pub fn can_import(&self) -> bool {
+        self.video().is_some()
+    }
+}
+
+
+/// Decoded RGBA frame, sent over FFI and IPC as a fixed header followed by the pixels
+#[derive(Debug, Clone)]
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: f64,
+    pub frame_index: u64,
+    pub rgba: Vec<u8>,
+}
+
+
+impl VideoFrame {
+    pub const HEADER_SIZE: usize = 24;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::HEADER_SIZE + self.rgba.len());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&self.frame_index.to_le_bytes());
+        bytes.extend_from_slice(&self.rgba);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::HEADER_SIZE {
+            return None;
+        }
+
+        let width = u32::from_le_bytes(bytes[0..4].try_into().ok()?);
+        let height = u32::from_le_bytes(bytes[4..8].try_into().ok()?);
+        let timestamp = f64::from_le_bytes(bytes[8..16].try_into().ok()?);
+        let frame_index = u64::from_le_bytes(bytes[16..24].try_into().ok()?);
+        let rgba = bytes[Self::HEADER_SIZE..].to_vec();
+
+        if rgba.len() != width as usize * height as usize * 4 {
+            return None;
+        }
+
+        Some(Self { width, height, timestamp, frame_index, rgba })
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thumbnail {
+    pub timestamp: f64,
+    pub file_name: String,
+}
+
+
+/// Thumbnails written to a cache directory, `interval` is None when taken at every keyframe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThumbnailIndex {
+    pub file_path: String,
+    pub duration: f64,
+    pub interval: Option<f64>,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+
+impl ThumbnailIndex {
+    pub const INDEX_FILE_NAME: &'static str = "index.json";
+
+    /// Last thumbnail at or before `time`, falling back to the first one
+    pub fn nearest(&self, time: f64) -> Option<usize> {
+        if self.thumbnails.is_empty() {
+            return None;
+        }
+
+        let after = self.thumbnails.partition_point(|t| t.timestamp <= time);
+        Some(after.saturating_sub(1))
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportMode {
+    /// Streams are already playable and only the container changes
+    Remux,
+    Transcode,
+    /// Video is scaled down to a playback proxy, audio is copied where possible
+    Proxy,
+}
+
+
+/// Height of a playback proxy, the width follows the source aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProxyResolution {
+    P540,
+    P720,
+}
+
+
+impl ProxyResolution {
+    pub fn height(&self) -> u32 {
+        match self {
+            ProxyResolution::P540 => 540,
+            ProxyResolution::P720 => 720,
+        }
+    }
+}
+
+
+/// State of a long running codec job such as an import or reel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    Running,
+    Finished,
+    Failed,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportStatus {
+    pub job: u64,
+    pub file_path: String,
+    pub output_path: String,
+    pub mode: ImportMode,
+    pub state: JobState,
+    pub progress: f64,
+    pub error: Option<String>,
+}
+
+
+impl ImportStatus {
+    pub fn is_done(&self) -> bool {
+        self.state != JobState::Running
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClipMode {
+    /// The range starts and ends on keyframes and every packet was copied
+    Copy,
+    /// Only the GOPs containing the cut points were re-encoded
+    SmartCut,
+    /// The source codec can't be spliced so the whole range was re-encoded
+    Reencode,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipExport {
+    pub file_path: String,
+    pub output_path: String,
+    pub start: f64,
+    pub end: f64,
+    pub mode: ClipMode,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReelSegment {
+    pub file_path: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+
+impl ReelSegment {
+    pub fn duration(&self) -> f64 {
+        (self.end - self.start).max(0f64)
+    }
+}
+
+
+/// Every segment of a reel is normalized to `width`x`height` at `frame_rate`, taken from the first segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReelStatus {
+    pub job: u64,
+    pub output_path: String,
+    pub segment_count: usize,
+    pub current_segment: usize,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub state: JobState,
+    pub progress: f64,
+    pub error: Option<String>,
+}
+
+
+impl ReelStatus {
+    pub fn is_done(&self) -> bool {
+        self.state != JobState::Running
+    }
+}
+
+
+/// Progress of a codec call the host runs for the frontend, `total` is 0 when the codec doesn't know it.
+/// A last one is sent once the call has returned, with `state` telling whether it succeeded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationProgress {
+    pub operation: String,
+    pub file_path: String,
+    pub processed: u64,
+    pub total: u64,
+    pub state: JobState,
+}
+
+
+/// What marked the start of a candidate play
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayBoundary {
+    StreamStart,
+    SceneCut,
+    /// Movement resumed after the players were set
+    Motion,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayCandidate {
+    pub start: f64,
+    pub end: f64,
+    pub boundary: PlayBoundary,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaySegmentation {
+    pub file_path: String,
+    pub duration: f64,
+    pub candidates: Vec<PlayCandidate>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioEventKind {
+    Whistle,
+    /// Crowd noise picking up, usually while a play develops
+    LoudnessRise,
+    /// Crowd noise dying down, usually once a play is over
+    LoudnessDrop,
+    Silence,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioEvent {
+    pub start: f64,
+    pub end: f64,
+    pub kind: AudioEventKind,
+    pub strength: f64,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioAnalysis {
+    pub file_path: String,
+    pub duration: f64,
+    pub events: Vec<AudioEvent>,
+}
+
+
+impl AudioAnalysis {
+    /// First whistle that starts after `time`
+    pub fn next_whistle(&self, time: f64) -> Option<&AudioEvent> {
+        self.events.iter().find(|event| event.kind == AudioEventKind::Whistle && event.start > time)
+    }
+}
+
+
+/// Presentation times, in seconds, of every keyframe in the video stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyframeIndex {
+    pub file_path: String,
+    pub duration: f64,
+    pub keyframes: Vec<f64>,
+}
+
+
+impl KeyframeIndex {
+    /// Keyframe closest to `time`, seeking there needs no decoding from an earlier keyframe
+    pub fn nearest(&self, time: f64) -> Option<f64> {
+        let after = self.keyframes.partition_point(|keyframe| *keyframe < time);
+        let before = after.checked_sub(1).and_then(|i| self.keyframes.get(i));
+        match (before, self.keyframes.get(after)) {
+            (Some(before), Some(after)) => Some(if time - before <= after - time { *before } else { *after }),
+            (before, after) => before.or(after).copied(),
+        }
+    }
+}
+
+
+/// Span of a segmented stream, appended to a `SourceBuffer` as one fragmented MP4 media segment
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MediaSegment {
+    pub index: usize,
+    /// Presentation times in seconds from the stream start
+    pub start: f64,
+    pub end: f64,
+    /// Seconds the segment's own timestamps, which start from zero, are shifted by when appended
+    pub timestamp_offset: f64,
+}
+
+
+/// Source remuxed or transcoded on demand into an init segment and media segments that a `MediaSource` can play
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentedStream {
+    pub handle: u64,
+    pub file_path: String,
+    pub duration: f64,
+    /// `SourceBuffer` type, with the codecs parameter filled in
+    pub mime_type: String,
+    pub mode: ImportMode,
+    pub segments: Vec<MediaSegment>,
+}
+
+
+impl SegmentedStream {
+    /// Segment presenting `time`, the last one for anything past the end
+    pub fn segment_at(&self, time: f64) -> Option<&MediaSegment> {
+        let index = self.segments.partition_point(|segment| segment.end <= time);
+        self.segments.get(index.min(self.segments.len().saturating_sub(1)))
+    }
+}
@@ -1,37 +1,1345 @@
 /* THIS FILE IS GENERATED DO NOT EDIT */
+use crate::codec::*;
+use crate::error::{CodecError, CodecStatus};
+use crate::marshal::{self, CodecBuffer};
+use crate::progress::{CodecProgress, ProgressReporter};
+use crate::worker::{CodecWorker, WorkerValue};
 use crate::RehashCodecLibrary;
 #[cfg(target_os = "windows")]
-use libloading::os::windows::Symbol;
+use libloading::os::windows::Library;
 #[cfg(target_os = "linux")]
-use libloading::Symbol;
-use std::ffi::{c_char, c_uchar};
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr};
+use std::mem::MaybeUninit;
+use std::path::Path;
 
-impl RehashCodecLibrary {
-    pub fn print_codec_version(&self) {
+#[doc = r" Every codec export, resolved once when the library is loaded"]
+pub struct CodecFunctions {
+    rehash_last_error: unsafe extern "C" fn() -> *const c_char,
+    rehash_free_string: unsafe extern "C" fn(*mut c_char),
+    rehash_free_buffer: unsafe extern "C" fn(CodecBuffer),
+    print_codec_version: unsafe extern "C" fn() -> i32,
+    probe_video: unsafe extern "C" fn(*const c_char, *mut *mut c_char) -> i32,
+    open_video_stream: unsafe extern "C" fn(*const c_char, usize, *mut *mut c_char) -> i32,
+    get_video_stream_meta: unsafe extern "C" fn(u64, *mut *mut c_char) -> i32,
+    read_video_stream_chunk: unsafe extern "C" fn(u64, *mut CodecBuffer) -> i32,
+    seek_video_stream: unsafe extern "C" fn(u64, u64, *mut *mut c_char) -> i32,
+    close_video_stream: unsafe extern "C" fn(u64, *mut bool) -> i32,
+    decode_frame_at: unsafe extern "C" fn(u64, f64, bool, *mut CodecBuffer) -> i32,
+    generate_thumbnails: unsafe extern "C" fn(
+        *const c_char,
+        *const c_char,
+        f64,
+        u32,
+        *const CodecProgress,
+        *mut *mut c_char,
+    ) -> i32,
+    start_import: unsafe extern "C" fn(*const c_char, *const c_char, *mut u64) -> i32,
+    start_proxy: unsafe extern "C" fn(*const c_char, *const c_char, u32, *mut u64) -> i32,
+    get_import_status: unsafe extern "C" fn(u64, *mut *mut c_char) -> i32,
+    close_import: unsafe extern "C" fn(u64, *mut bool) -> i32,
+    export_clip: unsafe extern "C" fn(
+        *const c_char,
+        *const c_char,
+        f64,
+        f64,
+        *const CodecProgress,
+        *mut *mut c_char,
+    ) -> i32,
+    start_reel: unsafe extern "C" fn(*const c_char, *const c_char, *mut u64) -> i32,
+    get_reel_status: unsafe extern "C" fn(u64, *mut *mut c_char) -> i32,
+    close_reel: unsafe extern "C" fn(u64, *mut bool) -> i32,
+    detect_plays:
+        unsafe extern "C" fn(*const c_char, *const CodecProgress, *mut *mut c_char) -> i32,
+    detect_audio_events:
+        unsafe extern "C" fn(*const c_char, *const CodecProgress, *mut *mut c_char) -> i32,
+    get_keyframe_index:
+        unsafe extern "C" fn(*const c_char, *const CodecProgress, *mut *mut c_char) -> i32,
+    open_segmented_stream:
+        unsafe extern "C" fn(*const c_char, f64, *const CodecProgress, *mut *mut c_char) -> i32,
+    read_init_segment: unsafe extern "C" fn(u64, *mut CodecBuffer) -> i32,
+    read_media_segment: unsafe extern "C" fn(u64, usize, *mut CodecBuffer) -> i32,
+    close_segmented_stream: unsafe extern "C" fn(u64, *mut bool) -> i32,
+}
+impl CodecFunctions {
+    #[doc = r" # Safety"]
+    #[doc = r" `lib` must be a codec whose ABI descriptor matched, and must outlive the table"]
+    pub unsafe fn load(lib: &Library) -> Result<Self, String> {
         unsafe {
-            let func: Symbol<unsafe extern "C" fn()> = self
-                .lib
-                .get(b"print_codec_version")
-                .expect("Failed to load symbol");
-            func()
+            Ok(Self {
+                rehash_last_error: *lib
+                    .get(b"rehash_last_error")
+                    .map_err(|e| format!("Codec is missing rehash_last_error: {}", e))?,
+                rehash_free_string: *lib
+                    .get(b"rehash_free_string")
+                    .map_err(|e| format!("Codec is missing rehash_free_string: {}", e))?,
+                rehash_free_buffer: *lib
+                    .get(b"rehash_free_buffer")
+                    .map_err(|e| format!("Codec is missing rehash_free_buffer: {}", e))?,
+                print_codec_version: *lib.get(b"print_codec_version").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(print_codec_version),
+                        e
+                    )
+                })?,
+                probe_video: *lib
+                    .get(b"probe_video")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(probe_video), e))?,
+                open_video_stream: *lib.get(b"open_video_stream").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(open_video_stream), e)
+                })?,
+                get_video_stream_meta: *lib.get(b"get_video_stream_meta").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(get_video_stream_meta),
+                        e
+                    )
+                })?,
+                read_video_stream_chunk: *lib.get(b"read_video_stream_chunk").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(read_video_stream_chunk),
+                        e
+                    )
+                })?,
+                seek_video_stream: *lib.get(b"seek_video_stream").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(seek_video_stream), e)
+                })?,
+                close_video_stream: *lib.get(b"close_video_stream").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(close_video_stream), e)
+                })?,
+                decode_frame_at: *lib.get(b"decode_frame_at").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(decode_frame_at), e)
+                })?,
+                generate_thumbnails: *lib.get(b"generate_thumbnails").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(generate_thumbnails),
+                        e
+                    )
+                })?,
+                start_import: *lib
+                    .get(b"start_import")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(start_import), e))?,
+                start_proxy: *lib
+                    .get(b"start_proxy")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(start_proxy), e))?,
+                get_import_status: *lib.get(b"get_import_status").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(get_import_status), e)
+                })?,
+                close_import: *lib
+                    .get(b"close_import")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(close_import), e))?,
+                export_clip: *lib
+                    .get(b"export_clip")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(export_clip), e))?,
+                start_reel: *lib
+                    .get(b"start_reel")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(start_reel), e))?,
+                get_reel_status: *lib.get(b"get_reel_status").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(get_reel_status), e)
+                })?,
+                close_reel: *lib
+                    .get(b"close_reel")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(close_reel), e))?,
+                detect_plays: *lib
+                    .get(b"detect_plays")
+                    .map_err(|e| format!("Codec is missing {}: {}", stringify!(detect_plays), e))?,
+                detect_audio_events: *lib.get(b"detect_audio_events").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(detect_audio_events),
+                        e
+                    )
+                })?,
+                get_keyframe_index: *lib.get(b"get_keyframe_index").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(get_keyframe_index), e)
+                })?,
+                open_segmented_stream: *lib.get(b"open_segmented_stream").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(open_segmented_stream),
+                        e
+                    )
+                })?,
+                read_init_segment: *lib.get(b"read_init_segment").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(read_init_segment), e)
+                })?,
+                read_media_segment: *lib.get(b"read_media_segment").map_err(|e| {
+                    format!("Codec is missing {}: {}", stringify!(read_media_segment), e)
+                })?,
+                close_segmented_stream: *lib.get(b"close_segmented_stream").map_err(|e| {
+                    format!(
+                        "Codec is missing {}: {}",
+                        stringify!(close_segmented_stream),
+                        e
+                    )
+                })?,
+            })
         }
     }
-    pub fn get_bytes_from_video(&self, path: *const c_char, out_len: *mut usize) -> *mut c_uchar {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(*const c_char, *mut usize) -> *mut c_uchar> =
-                self.lib
-                    .get(b"get_bytes_from_video")
-                    .expect("Failed to load symbol");
-            func(path, out_len)
+}
+impl RehashCodecLibrary {
+    fn check_status(&self, status: i32) -> Result<(), CodecError> {
+        let status = CodecStatus::from_code(status);
+        if status == CodecStatus::Ok {
+            return Ok(());
         }
+        let message = unsafe {
+            let ptr = (self.functions.rehash_last_error)();
+            if ptr.is_null() {
+                String::from("Codec reported no error message")
+            } else {
+                CStr::from_ptr(ptr).to_string_lossy().into_owned()
+            }
+        };
+        Err(CodecError::new(status, message))
     }
-    pub fn free_file_bytes(&self, ptr: *mut c_uchar, len: usize) {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(*mut c_uchar, usize)> = self
-                .lib
-                .get(b"free_file_bytes")
-                .expect("Failed to load symbol");
-            func(ptr, len)
+    #[doc = r" Copies a codec owned string and hands it back to the codec"]
+    unsafe fn take_string(&self, ptr: *mut c_char) -> Result<String, CodecError> {
+        if ptr.is_null() {
+            return Err(CodecError::failed("Codec returned no data"));
+        }
+        let value = unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { (self.functions.rehash_free_string)(ptr) };
+        Ok(value)
+    }
+    #[doc = r" Copies a codec owned buffer and hands it back to the codec"]
+    unsafe fn take_buffer(&self, buffer: CodecBuffer) -> Vec<u8> {
+        let bytes = unsafe { buffer.to_vec() };
+        unsafe { (self.functions.rehash_free_buffer)(buffer) };
+        bytes
+    }
+    pub fn print_codec_version(&self) -> Result<(), CodecError> {
+        let status = unsafe { (self.functions.print_codec_version)() };
+        self.check_status(status)?;
+        Ok(())
+    }
+    pub fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe { (self.functions.probe_video)(path.as_ptr(), out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn open_video_stream(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.open_video_stream)(path.as_ptr(), chunk_size, out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe { (self.functions.get_video_stream_meta)(handle, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        let mut out = MaybeUninit::<CodecBuffer>::uninit();
+        let status = unsafe { (self.functions.read_video_stream_chunk)(handle, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { self.take_buffer(out.assume_init()) })
+    }
+    pub fn seek_video_stream(
+        &self,
+        handle: u64,
+        position: u64,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status =
+            unsafe { (self.functions.seek_video_stream)(handle, position, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        let mut out = MaybeUninit::<bool>::uninit();
+        let status = unsafe { (self.functions.close_video_stream)(handle, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn decode_frame_at(
+        &self,
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    ) -> Result<Vec<u8>, CodecError> {
+        let mut out = MaybeUninit::<CodecBuffer>::uninit();
+        let status = unsafe {
+            (self.functions.decode_frame_at)(handle, position, is_frame_index, out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        Ok(unsafe { self.take_buffer(out.assume_init()) })
+    }
+    pub fn generate_thumbnails(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        interval: f64,
+        width: u32,
+        progress: &ProgressReporter,
+    ) -> Result<ThumbnailIndex, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let output_dir = marshal::c_path(output_dir, "output_dir")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.generate_thumbnails)(
+                path.as_ptr(),
+                output_dir.as_ptr(),
+                interval,
+                width,
+                &progress,
+                out.as_mut_ptr(),
+            )
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn start_import(&self, path: &Path, output_path: &Path) -> Result<u64, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let output_path = marshal::c_path(output_path, "output_path")?;
+        let mut out = MaybeUninit::<u64>::uninit();
+        let status = unsafe {
+            (self.functions.start_import)(path.as_ptr(), output_path.as_ptr(), out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn start_proxy(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        height: u32,
+    ) -> Result<u64, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let output_path = marshal::c_path(output_path, "output_path")?;
+        let mut out = MaybeUninit::<u64>::uninit();
+        let status = unsafe {
+            (self.functions.start_proxy)(
+                path.as_ptr(),
+                output_path.as_ptr(),
+                height,
+                out.as_mut_ptr(),
+            )
+        };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn get_import_status(&self, job: u64) -> Result<ImportStatus, CodecError> {
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe { (self.functions.get_import_status)(job, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn close_import(&self, job: u64) -> Result<bool, CodecError> {
+        let mut out = MaybeUninit::<bool>::uninit();
+        let status = unsafe { (self.functions.close_import)(job, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn export_clip(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        start: f64,
+        end: f64,
+        progress: &ProgressReporter,
+    ) -> Result<ClipExport, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let output_path = marshal::c_path(output_path, "output_path")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.export_clip)(
+                path.as_ptr(),
+                output_path.as_ptr(),
+                start,
+                end,
+                &progress,
+                out.as_mut_ptr(),
+            )
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn start_reel(
+        &self,
+        segments: &[ReelSegment],
+        output_path: &Path,
+    ) -> Result<u64, CodecError> {
+        let segments = marshal::c_json(segments, "segments")?;
+        let output_path = marshal::c_path(output_path, "output_path")?;
+        let mut out = MaybeUninit::<u64>::uninit();
+        let status = unsafe {
+            (self.functions.start_reel)(segments.as_ptr(), output_path.as_ptr(), out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn get_reel_status(&self, job: u64) -> Result<ReelStatus, CodecError> {
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe { (self.functions.get_reel_status)(job, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn close_reel(&self, job: u64) -> Result<bool, CodecError> {
+        let mut out = MaybeUninit::<bool>::uninit();
+        let status = unsafe { (self.functions.close_reel)(job, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    pub fn detect_plays(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<PlaySegmentation, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status =
+            unsafe { (self.functions.detect_plays)(path.as_ptr(), &progress, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn detect_audio_events(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<AudioAnalysis, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.detect_audio_events)(path.as_ptr(), &progress, out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn get_keyframe_index(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<KeyframeIndex, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.get_keyframe_index)(path.as_ptr(), &progress, out.as_mut_ptr())
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn open_segmented_stream(
+        &self,
+        path: &Path,
+        segment_seconds: f64,
+        progress: &ProgressReporter,
+    ) -> Result<SegmentedStream, CodecError> {
+        let path = marshal::c_path(path, "path")?;
+        let progress = progress.as_codec();
+        let mut out = MaybeUninit::<*mut c_char>::uninit();
+        let status = unsafe {
+            (self.functions.open_segmented_stream)(
+                path.as_ptr(),
+                segment_seconds,
+                &progress,
+                out.as_mut_ptr(),
+            )
+        };
+        self.check_status(status)?;
+        marshal::from_json(&unsafe { self.take_string(out.assume_init())? })
+    }
+    pub fn read_init_segment(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        let mut out = MaybeUninit::<CodecBuffer>::uninit();
+        let status = unsafe { (self.functions.read_init_segment)(handle, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { self.take_buffer(out.assume_init()) })
+    }
+    pub fn read_media_segment(&self, handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+        let mut out = MaybeUninit::<CodecBuffer>::uninit();
+        let status =
+            unsafe { (self.functions.read_media_segment)(handle, index, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { self.take_buffer(out.assume_init()) })
+    }
+    pub fn close_segmented_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        let mut out = MaybeUninit::<bool>::uninit();
+        let status = unsafe { (self.functions.close_segmented_stream)(handle, out.as_mut_ptr()) };
+        self.check_status(status)?;
+        Ok(unsafe { out.assume_init() })
+    }
+    #[doc = r" Runs a request the codec worker received from the host"]
+    pub fn handle_request(
+        &self,
+        request: CodecRequest,
+        progress: &ProgressReporter,
+    ) -> Result<WorkerValue, CodecError> {
+        match request {
+            CodecRequest::PrintCodecVersion {} => WorkerValue::json(&self.print_codec_version()?),
+            CodecRequest::ProbeVideo { path } => {
+                WorkerValue::json(&self.probe_video(Path::new(&path))?)
+            }
+            CodecRequest::OpenVideoStream { path, chunk_size } => {
+                WorkerValue::json(&self.open_video_stream(Path::new(&path), chunk_size)?)
+            }
+            CodecRequest::GetVideoStreamMeta { handle } => {
+                WorkerValue::json(&self.get_video_stream_meta(handle)?)
+            }
+            CodecRequest::ReadVideoStreamChunk { handle } => {
+                Ok(WorkerValue::Bytes(self.read_video_stream_chunk(handle)?))
+            }
+            CodecRequest::SeekVideoStream { handle, position } => {
+                WorkerValue::json(&self.seek_video_stream(handle, position)?)
+            }
+            CodecRequest::CloseVideoStream { handle } => {
+                WorkerValue::json(&self.close_video_stream(handle)?)
+            }
+            CodecRequest::DecodeFrameAt {
+                handle,
+                position,
+                is_frame_index,
+            } => Ok(WorkerValue::Bytes(self.decode_frame_at(
+                handle,
+                position,
+                is_frame_index,
+            )?)),
+            CodecRequest::GenerateThumbnails {
+                path,
+                output_dir,
+                interval,
+                width,
+            } => WorkerValue::json(&self.generate_thumbnails(
+                Path::new(&path),
+                Path::new(&output_dir),
+                interval,
+                width,
+                progress,
+            )?),
+            CodecRequest::StartImport { path, output_path } => {
+                WorkerValue::json(&self.start_import(Path::new(&path), Path::new(&output_path))?)
+            }
+            CodecRequest::StartProxy {
+                path,
+                output_path,
+                height,
+            } => WorkerValue::json(&self.start_proxy(
+                Path::new(&path),
+                Path::new(&output_path),
+                height,
+            )?),
+            CodecRequest::GetImportStatus { job } => {
+                WorkerValue::json(&self.get_import_status(job)?)
+            }
+            CodecRequest::CloseImport { job } => WorkerValue::json(&self.close_import(job)?),
+            CodecRequest::ExportClip {
+                path,
+                output_path,
+                start,
+                end,
+            } => WorkerValue::json(&self.export_clip(
+                Path::new(&path),
+                Path::new(&output_path),
+                start,
+                end,
+                progress,
+            )?),
+            CodecRequest::StartReel {
+                segments,
+                output_path,
+            } => WorkerValue::json(&self.start_reel(&segments, Path::new(&output_path))?),
+            CodecRequest::GetReelStatus { job } => WorkerValue::json(&self.get_reel_status(job)?),
+            CodecRequest::CloseReel { job } => WorkerValue::json(&self.close_reel(job)?),
+            CodecRequest::DetectPlays { path } => {
+                WorkerValue::json(&self.detect_plays(Path::new(&path), progress)?)
+            }
+            CodecRequest::DetectAudioEvents { path } => {
+                WorkerValue::json(&self.detect_audio_events(Path::new(&path), progress)?)
+            }
+            CodecRequest::GetKeyframeIndex { path } => {
+                WorkerValue::json(&self.get_keyframe_index(Path::new(&path), progress)?)
+            }
+            CodecRequest::OpenSegmentedStream {
+                path,
+                segment_seconds,
+            } => WorkerValue::json(&self.open_segmented_stream(
+                Path::new(&path),
+                segment_seconds,
+                progress,
+            )?),
+            CodecRequest::ReadInitSegment { handle } => {
+                Ok(WorkerValue::Bytes(self.read_init_segment(handle)?))
+            }
+            CodecRequest::ReadMediaSegment { handle, index } => {
+                Ok(WorkerValue::Bytes(self.read_media_segment(handle, index)?))
+            }
+            CodecRequest::CloseSegmentedStream { handle } => {
+                WorkerValue::json(&self.close_segmented_stream(handle)?)
+            }
+        }
+    }
+}
+#[doc = r" Every export as a message to the codec worker"]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CodecRequest {
+    PrintCodecVersion {},
+    ProbeVideo {
+        path: String,
+    },
+    OpenVideoStream {
+        path: String,
+        chunk_size: usize,
+    },
+    GetVideoStreamMeta {
+        handle: u64,
+    },
+    ReadVideoStreamChunk {
+        handle: u64,
+    },
+    SeekVideoStream {
+        handle: u64,
+        position: u64,
+    },
+    CloseVideoStream {
+        handle: u64,
+    },
+    DecodeFrameAt {
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    },
+    GenerateThumbnails {
+        path: String,
+        output_dir: String,
+        interval: f64,
+        width: u32,
+    },
+    StartImport {
+        path: String,
+        output_path: String,
+    },
+    StartProxy {
+        path: String,
+        output_path: String,
+        height: u32,
+    },
+    GetImportStatus {
+        job: u64,
+    },
+    CloseImport {
+        job: u64,
+    },
+    ExportClip {
+        path: String,
+        output_path: String,
+        start: f64,
+        end: f64,
+    },
+    StartReel {
+        segments: Vec<ReelSegment>,
+        output_path: String,
+    },
+    GetReelStatus {
+        job: u64,
+    },
+    CloseReel {
+        job: u64,
+    },
+    DetectPlays {
+        path: String,
+    },
+    DetectAudioEvents {
+        path: String,
+    },
+    GetKeyframeIndex {
+        path: String,
+    },
+    OpenSegmentedStream {
+        path: String,
+        segment_seconds: f64,
+    },
+    ReadInitSegment {
+        handle: u64,
+    },
+    ReadMediaSegment {
+        handle: u64,
+        index: usize,
+    },
+    CloseSegmentedStream {
+        handle: u64,
+    },
+}
+impl CodecRequest {
+    #[doc = r" File the request works on, named when the worker crashes on it"]
+    pub fn file_path(&self) -> Option<&str> {
+        match self {
+            CodecRequest::PrintCodecVersion { .. } => None,
+            CodecRequest::ProbeVideo { path, .. } => Some(path.as_str()),
+            CodecRequest::OpenVideoStream { path, .. } => Some(path.as_str()),
+            CodecRequest::GetVideoStreamMeta { .. } => None,
+            CodecRequest::ReadVideoStreamChunk { .. } => None,
+            CodecRequest::SeekVideoStream { .. } => None,
+            CodecRequest::CloseVideoStream { .. } => None,
+            CodecRequest::DecodeFrameAt { .. } => None,
+            CodecRequest::GenerateThumbnails { path, .. } => Some(path.as_str()),
+            CodecRequest::StartImport { path, .. } => Some(path.as_str()),
+            CodecRequest::StartProxy { path, .. } => Some(path.as_str()),
+            CodecRequest::GetImportStatus { .. } => None,
+            CodecRequest::CloseImport { .. } => None,
+            CodecRequest::ExportClip { path, .. } => Some(path.as_str()),
+            CodecRequest::StartReel { .. } => None,
+            CodecRequest::GetReelStatus { .. } => None,
+            CodecRequest::CloseReel { .. } => None,
+            CodecRequest::DetectPlays { path, .. } => Some(path.as_str()),
+            CodecRequest::DetectAudioEvents { path, .. } => Some(path.as_str()),
+            CodecRequest::GetKeyframeIndex { path, .. } => Some(path.as_str()),
+            CodecRequest::OpenSegmentedStream { path, .. } => Some(path.as_str()),
+            CodecRequest::ReadInitSegment { .. } => None,
+            CodecRequest::ReadMediaSegment { .. } => None,
+            CodecRequest::CloseSegmentedStream { .. } => None,
         }
     }
+    #[doc = r" Stream handle or job the request works on, the worker swaps it for the one its process knows"]
+    pub fn handle_mut(&mut self) -> Option<&mut u64> {
+        match self {
+            CodecRequest::PrintCodecVersion { .. } => None,
+            CodecRequest::ProbeVideo { .. } => None,
+            CodecRequest::OpenVideoStream { .. } => None,
+            CodecRequest::GetVideoStreamMeta { handle, .. } => Some(handle),
+            CodecRequest::ReadVideoStreamChunk { handle, .. } => Some(handle),
+            CodecRequest::SeekVideoStream { handle, .. } => Some(handle),
+            CodecRequest::CloseVideoStream { handle, .. } => Some(handle),
+            CodecRequest::DecodeFrameAt { handle, .. } => Some(handle),
+            CodecRequest::GenerateThumbnails { .. } => None,
+            CodecRequest::StartImport { .. } => None,
+            CodecRequest::StartProxy { .. } => None,
+            CodecRequest::GetImportStatus { job, .. } => Some(job),
+            CodecRequest::CloseImport { job, .. } => Some(job),
+            CodecRequest::ExportClip { .. } => None,
+            CodecRequest::StartReel { .. } => None,
+            CodecRequest::GetReelStatus { job, .. } => Some(job),
+            CodecRequest::CloseReel { job, .. } => Some(job),
+            CodecRequest::DetectPlays { .. } => None,
+            CodecRequest::DetectAudioEvents { .. } => None,
+            CodecRequest::GetKeyframeIndex { .. } => None,
+            CodecRequest::OpenSegmentedStream { .. } => None,
+            CodecRequest::ReadInitSegment { handle, .. } => Some(handle),
+            CodecRequest::ReadMediaSegment { handle, .. } => Some(handle),
+            CodecRequest::CloseSegmentedStream { handle, .. } => Some(handle),
+        }
+    }
+}
+impl CodecWorker {
+    pub fn print_codec_version(&self) -> Result<(), CodecError> {
+        let request = CodecRequest::PrintCodecVersion {};
+        self.call(request, None)?.into_json()
+    }
+    pub fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        let request = CodecRequest::ProbeVideo {
+            path: marshal::path_str(path, "path")?.to_string(),
+        };
+        self.call(request, None)?.into_json()
+    }
+    pub fn open_video_stream(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        let request = CodecRequest::OpenVideoStream {
+            path: marshal::path_str(path, "path")?.to_string(),
+            chunk_size,
+        };
+        self.call(request, None)?.into_json()
+    }
+    pub fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        let request = CodecRequest::GetVideoStreamMeta { handle };
+        self.call(request, None)?.into_json()
+    }
+    pub fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        let request = CodecRequest::ReadVideoStreamChunk { handle };
+        self.call(request, None)?.into_bytes()
+    }
+    pub fn seek_video_stream(
+        &self,
+        handle: u64,
+        position: u64,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        let request = CodecRequest::SeekVideoStream { handle, position };
+        self.call(request, None)?.into_json()
+    }
+    pub fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        let request = CodecRequest::CloseVideoStream { handle };
+        self.call(request, None)?.into_json()
+    }
+    pub fn decode_frame_at(
+        &self,
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    ) -> Result<Vec<u8>, CodecError> {
+        let request = CodecRequest::DecodeFrameAt {
+            handle,
+            position,
+            is_frame_index,
+        };
+        self.call(request, None)?.into_bytes()
+    }
+    pub fn generate_thumbnails(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        interval: f64,
+        width: u32,
+        progress: &ProgressReporter,
+    ) -> Result<ThumbnailIndex, CodecError> {
+        let request = CodecRequest::GenerateThumbnails {
+            path: marshal::path_str(path, "path")?.to_string(),
+            output_dir: marshal::path_str(output_dir, "output_dir")?.to_string(),
+            interval,
+            width,
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn start_import(&self, path: &Path, output_path: &Path) -> Result<u64, CodecError> {
+        let request = CodecRequest::StartImport {
+            path: marshal::path_str(path, "path")?.to_string(),
+            output_path: marshal::path_str(output_path, "output_path")?.to_string(),
+        };
+        self.call(request, None)?.into_json()
+    }
+    pub fn start_proxy(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        height: u32,
+    ) -> Result<u64, CodecError> {
+        let request = CodecRequest::StartProxy {
+            path: marshal::path_str(path, "path")?.to_string(),
+            output_path: marshal::path_str(output_path, "output_path")?.to_string(),
+            height,
+        };
+        self.call(request, None)?.into_json()
+    }
+    pub fn get_import_status(&self, job: u64) -> Result<ImportStatus, CodecError> {
+        let request = CodecRequest::GetImportStatus { job };
+        self.call(request, None)?.into_json()
+    }
+    pub fn close_import(&self, job: u64) -> Result<bool, CodecError> {
+        let request = CodecRequest::CloseImport { job };
+        self.call(request, None)?.into_json()
+    }
+    pub fn export_clip(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        start: f64,
+        end: f64,
+        progress: &ProgressReporter,
+    ) -> Result<ClipExport, CodecError> {
+        let request = CodecRequest::ExportClip {
+            path: marshal::path_str(path, "path")?.to_string(),
+            output_path: marshal::path_str(output_path, "output_path")?.to_string(),
+            start,
+            end,
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn start_reel(
+        &self,
+        segments: &[ReelSegment],
+        output_path: &Path,
+    ) -> Result<u64, CodecError> {
+        let request = CodecRequest::StartReel {
+            segments: segments.to_owned(),
+            output_path: marshal::path_str(output_path, "output_path")?.to_string(),
+        };
+        self.call(request, None)?.into_json()
+    }
+    pub fn get_reel_status(&self, job: u64) -> Result<ReelStatus, CodecError> {
+        let request = CodecRequest::GetReelStatus { job };
+        self.call(request, None)?.into_json()
+    }
+    pub fn close_reel(&self, job: u64) -> Result<bool, CodecError> {
+        let request = CodecRequest::CloseReel { job };
+        self.call(request, None)?.into_json()
+    }
+    pub fn detect_plays(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<PlaySegmentation, CodecError> {
+        let request = CodecRequest::DetectPlays {
+            path: marshal::path_str(path, "path")?.to_string(),
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn detect_audio_events(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<AudioAnalysis, CodecError> {
+        let request = CodecRequest::DetectAudioEvents {
+            path: marshal::path_str(path, "path")?.to_string(),
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn get_keyframe_index(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<KeyframeIndex, CodecError> {
+        let request = CodecRequest::GetKeyframeIndex {
+            path: marshal::path_str(path, "path")?.to_string(),
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn open_segmented_stream(
+        &self,
+        path: &Path,
+        segment_seconds: f64,
+        progress: &ProgressReporter,
+    ) -> Result<SegmentedStream, CodecError> {
+        let request = CodecRequest::OpenSegmentedStream {
+            path: marshal::path_str(path, "path")?.to_string(),
+            segment_seconds,
+        };
+        self.call(request, Some(progress))?.into_json()
+    }
+    pub fn read_init_segment(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        let request = CodecRequest::ReadInitSegment { handle };
+        self.call(request, None)?.into_bytes()
+    }
+    pub fn read_media_segment(&self, handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+        let request = CodecRequest::ReadMediaSegment { handle, index };
+        self.call(request, None)?.into_bytes()
+    }
+    pub fn close_segmented_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        let request = CodecRequest::CloseSegmentedStream { handle };
+        self.call(request, None)?.into_json()
+    }
+}
+#[doc = r" Everything the app asks of a codec, whatever a backend can't do fails as `CodecStatus::Unsupported`"]
+pub trait CodecBackend: Send + Sync {
+    #[allow(unused_variables)]
+    fn print_codec_version(&self) -> Result<(), CodecError> {
+        Err(CodecError::unsupported(
+            "print codec version needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        Err(CodecError::unsupported(
+            "probe video needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn open_video_stream(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        Err(CodecError::unsupported(
+            "open video stream needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        Err(CodecError::unsupported(
+            "get video stream meta needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        Err(CodecError::unsupported(
+            "read video stream chunk needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn seek_video_stream(&self, handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+        Err(CodecError::unsupported(
+            "seek video stream needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        Err(CodecError::unsupported(
+            "close video stream needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn decode_frame_at(
+        &self,
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    ) -> Result<Vec<u8>, CodecError> {
+        Err(CodecError::unsupported(
+            "decode frame at needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn generate_thumbnails(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        interval: f64,
+        width: u32,
+        progress: &ProgressReporter,
+    ) -> Result<ThumbnailIndex, CodecError> {
+        Err(CodecError::unsupported(
+            "generate thumbnails needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn start_import(&self, path: &Path, output_path: &Path) -> Result<u64, CodecError> {
+        Err(CodecError::unsupported(
+            "start import needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn start_proxy(&self, path: &Path, output_path: &Path, height: u32) -> Result<u64, CodecError> {
+        Err(CodecError::unsupported(
+            "start proxy needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn get_import_status(&self, job: u64) -> Result<ImportStatus, CodecError> {
+        Err(CodecError::unsupported(
+            "get import status needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn close_import(&self, job: u64) -> Result<bool, CodecError> {
+        Err(CodecError::unsupported(
+            "close import needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn export_clip(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        start: f64,
+        end: f64,
+        progress: &ProgressReporter,
+    ) -> Result<ClipExport, CodecError> {
+        Err(CodecError::unsupported(
+            "export clip needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn start_reel(&self, segments: &[ReelSegment], output_path: &Path) -> Result<u64, CodecError> {
+        Err(CodecError::unsupported(
+            "start reel needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn get_reel_status(&self, job: u64) -> Result<ReelStatus, CodecError> {
+        Err(CodecError::unsupported(
+            "get reel status needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn close_reel(&self, job: u64) -> Result<bool, CodecError> {
+        Err(CodecError::unsupported(
+            "close reel needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn detect_plays(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<PlaySegmentation, CodecError> {
+        Err(CodecError::unsupported(
+            "detect plays needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn detect_audio_events(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<AudioAnalysis, CodecError> {
+        Err(CodecError::unsupported(
+            "detect audio events needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn get_keyframe_index(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<KeyframeIndex, CodecError> {
+        Err(CodecError::unsupported(
+            "get keyframe index needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn open_segmented_stream(
+        &self,
+        path: &Path,
+        segment_seconds: f64,
+        progress: &ProgressReporter,
+    ) -> Result<SegmentedStream, CodecError> {
+        Err(CodecError::unsupported(
+            "open segmented stream needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn read_init_segment(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        Err(CodecError::unsupported(
+            "read init segment needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn read_media_segment(&self, handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+        Err(CodecError::unsupported(
+            "read media segment needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+    #[allow(unused_variables)]
+    fn close_segmented_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        Err(CodecError::unsupported(
+            "close segmented stream needs the ffmpeg codec, which is not loaded",
+        ))
+    }
+}
+impl CodecBackend for RehashCodecLibrary {
+    fn print_codec_version(&self) -> Result<(), CodecError> {
+        RehashCodecLibrary::print_codec_version(self)
+    }
+    fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        RehashCodecLibrary::probe_video(self, path)
+    }
+    fn open_video_stream(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        RehashCodecLibrary::open_video_stream(self, path, chunk_size)
+    }
+    fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        RehashCodecLibrary::get_video_stream_meta(self, handle)
+    }
+    fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        RehashCodecLibrary::read_video_stream_chunk(self, handle)
+    }
+    fn seek_video_stream(&self, handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+        RehashCodecLibrary::seek_video_stream(self, handle, position)
+    }
+    fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        RehashCodecLibrary::close_video_stream(self, handle)
+    }
+    fn decode_frame_at(
+        &self,
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    ) -> Result<Vec<u8>, CodecError> {
+        RehashCodecLibrary::decode_frame_at(self, handle, position, is_frame_index)
+    }
+    fn generate_thumbnails(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        interval: f64,
+        width: u32,
+        progress: &ProgressReporter,
+    ) -> Result<ThumbnailIndex, CodecError> {
+        RehashCodecLibrary::generate_thumbnails(self, path, output_dir, interval, width, progress)
+    }
+    fn start_import(&self, path: &Path, output_path: &Path) -> Result<u64, CodecError> {
+        RehashCodecLibrary::start_import(self, path, output_path)
+    }
+    fn start_proxy(&self, path: &Path, output_path: &Path, height: u32) -> Result<u64, CodecError> {
+        RehashCodecLibrary::start_proxy(self, path, output_path, height)
+    }
+    fn get_import_status(&self, job: u64) -> Result<ImportStatus, CodecError> {
+        RehashCodecLibrary::get_import_status(self, job)
+    }
+    fn close_import(&self, job: u64) -> Result<bool, CodecError> {
+        RehashCodecLibrary::close_import(self, job)
+    }
+    fn export_clip(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        start: f64,
+        end: f64,
+        progress: &ProgressReporter,
+    ) -> Result<ClipExport, CodecError> {
+        RehashCodecLibrary::export_clip(self, path, output_path, start, end, progress)
+    }
+    fn start_reel(&self, segments: &[ReelSegment], output_path: &Path) -> Result<u64, CodecError> {
+        RehashCodecLibrary::start_reel(self, segments, output_path)
+    }
+    fn get_reel_status(&self, job: u64) -> Result<ReelStatus, CodecError> {
+        RehashCodecLibrary::get_reel_status(self, job)
+    }
+    fn close_reel(&self, job: u64) -> Result<bool, CodecError> {
+        RehashCodecLibrary::close_reel(self, job)
+    }
+    fn detect_plays(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<PlaySegmentation, CodecError> {
+        RehashCodecLibrary::detect_plays(self, path, progress)
+    }
+    fn detect_audio_events(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<AudioAnalysis, CodecError> {
+        RehashCodecLibrary::detect_audio_events(self, path, progress)
+    }
+    fn get_keyframe_index(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<KeyframeIndex, CodecError> {
+        RehashCodecLibrary::get_keyframe_index(self, path, progress)
+    }
+    fn open_segmented_stream(
+        &self,
+        path: &Path,
+        segment_seconds: f64,
+        progress: &ProgressReporter,
+    ) -> Result<SegmentedStream, CodecError> {
+        RehashCodecLibrary::open_segmented_stream(self, path, segment_seconds, progress)
+    }
+    fn read_init_segment(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        RehashCodecLibrary::read_init_segment(self, handle)
+    }
+    fn read_media_segment(&self, handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+        RehashCodecLibrary::read_media_segment(self, handle, index)
+    }
+    fn close_segmented_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        RehashCodecLibrary::close_segmented_stream(self, handle)
+    }
+}
+impl CodecBackend for CodecWorker {
+    fn print_codec_version(&self) -> Result<(), CodecError> {
+        CodecWorker::print_codec_version(self)
+    }
+    fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        CodecWorker::probe_video(self, path)
+    }
+    fn open_video_stream(
+        &self,
+        path: &Path,
+        chunk_size: usize,
+    ) -> Result<VideoStreamMeta, CodecError> {
+        CodecWorker::open_video_stream(self, path, chunk_size)
+    }
+    fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        CodecWorker::get_video_stream_meta(self, handle)
+    }
+    fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        CodecWorker::read_video_stream_chunk(self, handle)
+    }
+    fn seek_video_stream(&self, handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+        CodecWorker::seek_video_stream(self, handle, position)
+    }
+    fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        CodecWorker::close_video_stream(self, handle)
+    }
+    fn decode_frame_at(
+        &self,
+        handle: u64,
+        position: f64,
+        is_frame_index: bool,
+    ) -> Result<Vec<u8>, CodecError> {
+        CodecWorker::decode_frame_at(self, handle, position, is_frame_index)
+    }
+    fn generate_thumbnails(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        interval: f64,
+        width: u32,
+        progress: &ProgressReporter,
+    ) -> Result<ThumbnailIndex, CodecError> {
+        CodecWorker::generate_thumbnails(self, path, output_dir, interval, width, progress)
+    }
+    fn start_import(&self, path: &Path, output_path: &Path) -> Result<u64, CodecError> {
+        CodecWorker::start_import(self, path, output_path)
+    }
+    fn start_proxy(&self, path: &Path, output_path: &Path, height: u32) -> Result<u64, CodecError> {
+        CodecWorker::start_proxy(self, path, output_path, height)
+    }
+    fn get_import_status(&self, job: u64) -> Result<ImportStatus, CodecError> {
+        CodecWorker::get_import_status(self, job)
+    }
+    fn close_import(&self, job: u64) -> Result<bool, CodecError> {
+        CodecWorker::close_import(self, job)
+    }
+    fn export_clip(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        start: f64,
+        end: f64,
+        progress: &ProgressReporter,
+    ) -> Result<ClipExport, CodecError> {
+        CodecWorker::export_clip(self, path, output_path, start, end, progress)
+    }
+    fn start_reel(&self, segments: &[ReelSegment], output_path: &Path) -> Result<u64, CodecError> {
+        CodecWorker::start_reel(self, segments, output_path)
+    }
+    fn get_reel_status(&self, job: u64) -> Result<ReelStatus, CodecError> {
+        CodecWorker::get_reel_status(self, job)
+    }
+    fn close_reel(&self, job: u64) -> Result<bool, CodecError> {
+        CodecWorker::close_reel(self, job)
+    }
+    fn detect_plays(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<PlaySegmentation, CodecError> {
+        CodecWorker::detect_plays(self, path, progress)
+    }
+    fn detect_audio_events(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<AudioAnalysis, CodecError> {
+        CodecWorker::detect_audio_events(self, path, progress)
+    }
+    fn get_keyframe_index(
+        &self,
+        path: &Path,
+        progress: &ProgressReporter,
+    ) -> Result<KeyframeIndex, CodecError> {
+        CodecWorker::get_keyframe_index(self, path, progress)
+    }
+    fn open_segmented_stream(
+        &self,
+        path: &Path,
+        segment_seconds: f64,
+        progress: &ProgressReporter,
+    ) -> Result<SegmentedStream, CodecError> {
+        CodecWorker::open_segmented_stream(self, path, segment_seconds, progress)
+    }
+    fn read_init_segment(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        CodecWorker::read_init_segment(self, handle)
+    }
+    fn read_media_segment(&self, handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+        CodecWorker::read_media_segment(self, handle, index)
+    }
+    fn close_segmented_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        CodecWorker::close_segmented_stream(self, handle)
+    }
 }
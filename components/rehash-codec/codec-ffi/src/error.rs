@@ -0,0 +1,154 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Status every codec export returns, the value itself is written through its out parameter
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    NotFound = 2,
+    Io = 3,
+    Decode = 4,
+    Encode = 5,
+    Failed = 6,
+    Panic = 7,
+    Cancelled = 8,
+    /// the loaded backend can't do this, only the ffmpeg codec can
+    Unsupported = 9,
+}
+
+
+impl CodecStatus {
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => CodecStatus::Ok,
+            1 => CodecStatus::InvalidArgument,
+            2 => CodecStatus::NotFound,
+            3 => CodecStatus::Io,
+            4 => CodecStatus::Decode,
+            5 => CodecStatus::Encode,
+            7 => CodecStatus::Panic,
+            8 => CodecStatus::Cancelled,
+            9 => CodecStatus::Unsupported,
+            _ => CodecStatus::Failed,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError {
+    pub status: CodecStatus,
+    pub message: String,
+}
+
+
+impl CodecError {
+    pub fn new(status: CodecStatus, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::InvalidArgument, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::NotFound, message)
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::Io, message)
+    }
+
+    pub fn decode(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::Decode, message)
+    }
+
+    pub fn encode(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::Encode, message)
+    }
+
+    pub fn failed(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::Failed, message)
+    }
+
+    /// The host cancelled the call before it finished
+    pub fn cancelled() -> Self {
+        Self::new(CodecStatus::Cancelled, "Cancelled")
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        Self::new(CodecStatus::Unsupported, message)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.status == CodecStatus::Cancelled
+    }
+}
+
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.message, self.status)
+    }
+}
+
+
+impl std::error::Error for CodecError {}
+
+
+impl From<CodecError> for String {
+    fn from(error: CodecError) -> Self {
+        error.to_string()
+    }
+}
+
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Message of the last failed export on this thread, valid until the next export fails on it
+pub fn last_error_ptr() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Codec panicked")
+    }
+}
+
+/// Runs an export body so neither errors nor panics cross the C boundary
+///
+/// # Safety
+/// `out` must be null or valid for writing a `T`
+pub unsafe fn ffi_call<T>(out: *mut T, body: impl FnOnce() -> Result<T, CodecError>) -> CodecStatus {
+    let error = match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => {
+            if !out.is_null() {
+                unsafe { out.write(value) };
+            }
+            return CodecStatus::Ok;
+        }
+        Ok(Err(error)) => error,
+        Err(payload) => CodecError::new(CodecStatus::Panic, panic_message(payload.as_ref())),
+    };
+
+    set_last_error(&error.message);
+    error.status
+}
@@ -0,0 +1,2 @@
+/* THIS FILE IS GENERATED DO NOT EDIT */
+pub const INTERFACE_HASH: u64 = 0x35476071b174346b;
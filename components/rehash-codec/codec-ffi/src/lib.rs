@@ -1,19 +1,41 @@
+pub mod abi;
 pub mod codec;
+pub mod error;
+mod interface_hash;
+pub mod marshal;
+pub mod progress;
+#[cfg(feature = "library")]
+pub mod worker;
+#[cfg(feature = "library")]
 pub mod codec_ffi;
+#[cfg(feature = "library")]
+pub mod mp4;
 
+#[cfg(feature = "library")]
 pub use rehash_codec_library::*;
 
+/// Capabilities the app can't run without, a codec lacking any of them is rejected at load
+#[cfg(feature = "library")]
+const REQUIRED_CAPABILITIES: [abi::Capability; 2] = [abi::Capability::Streaming, abi::Capability::FrameDecode];
 
-#[cfg(target_os = "windows")]
+
+#[cfg(all(feature = "library", target_os = "windows"))]
 pub mod rehash_codec_library {
-    use libloading::os::windows::Library;
+    use super::REQUIRED_CAPABILITIES;
+    use crate::abi::{CodecAbi, ABI_SYMBOL};
+    use crate::codec_ffi::CodecFunctions;
+    use libloading::os::windows::{Library, Symbol};
     use std::ffi::OsStr;
     use std::os::windows::ffi::OsStrExt;
     use std::path::Path;
     use windows_sys::Win32::System::LibraryLoader::{AddDllDirectory, SetDefaultDllDirectories, LOAD_LIBRARY_SEARCH_DEFAULT_DIRS, LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR};
 
     pub struct RehashCodecLibrary {
-        pub lib: Library,
+        // only held so the resolved functions stay valid
+        #[allow(dead_code)]
+        lib: Library,
+        pub abi: CodecAbi,
+        pub(crate) functions: CodecFunctions,
     }
 
     impl RehashCodecLibrary {
@@ -23,44 +45,72 @@ pub mod rehash_codec_library {
         }
 
         #[cfg(target_os = "windows")]
-        pub fn new<T: AsRef<str>>(path: &T) -> Self {
+        pub fn new<T: AsRef<str>>(path: &T) -> Result<Self, String> {
             let path = Path::new(path.as_ref());
-            let parent = path.parent().unwrap();
+            let parent = path.parent().ok_or_else(|| format!("Codec path {} has no parent directory", path.display()))?;
 
             let lib = unsafe {
                 SetDefaultDllDirectories(LOAD_LIBRARY_SEARCH_DEFAULT_DIRS);
                 let directory = Self::convert_to_wide(parent.as_os_str());
                 AddDllDirectory(directory.as_ptr());
 
-                Library::load_with_flags(path.as_os_str(), LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR | LOAD_LIBRARY_SEARCH_DEFAULT_DIRS).expect("Failed to load windows library")
+                Library::load_with_flags(path.as_os_str(), LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR | LOAD_LIBRARY_SEARCH_DEFAULT_DIRS)
+                    .map_err(|e| format!("Failed to load codec {}: {}", path.display(), e))?
+            };
+
+            let abi = unsafe {
+                let func: Symbol<unsafe extern "C" fn() -> CodecAbi> = lib.get(ABI_SYMBOL)
+                    .map_err(|_| format!("Codec {} does not export an ABI descriptor, it predates this app", path.display()))?;
+                func()
             };
+            abi.verify(&REQUIRED_CAPABILITIES)?;
+            let functions = unsafe { CodecFunctions::load(&lib)? };
 
-            Self {
-                lib
-            }
+            Ok(Self {
+                lib,
+                abi,
+                functions,
+            })
         }
     }
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(feature = "library", target_os = "linux"))]
 pub mod rehash_codec_library {
-    use libloading::Library;
+    use super::REQUIRED_CAPABILITIES;
+    use crate::abi::{CodecAbi, ABI_SYMBOL};
+    use crate::codec_ffi::CodecFunctions;
+    use libloading::{Library, Symbol};
 
     pub struct RehashCodecLibrary {
-        pub lib: Library,
+        // only held so the resolved functions stay valid
+        #[allow(dead_code)]
+        lib: Library,
+        pub abi: CodecAbi,
+        pub(crate) functions: CodecFunctions,
     }
 
 
     impl RehashCodecLibrary {
         #[cfg(target_os = "linux")]
-        pub fn new<T: AsRef<str>>(path: &T) -> Self {
+        pub fn new<T: AsRef<str>>(path: &T) -> Result<Self, String> {
             let lib = unsafe {
-                Library::new(path.as_ref()).expect("Failed to loaded library")
+                Library::new(path.as_ref()).map_err(|e| format!("Failed to load codec {}: {}", path.as_ref(), e))?
+            };
+
+            let abi = unsafe {
+                let func: Symbol<unsafe extern "C" fn() -> CodecAbi> = lib.get(ABI_SYMBOL)
+                    .map_err(|_| format!("Codec {} does not export an ABI descriptor, it predates this app", path.as_ref()))?;
+                func()
             };
+            abi.verify(&REQUIRED_CAPABILITIES)?;
+            let functions = unsafe { CodecFunctions::load(&lib)? };
 
-            Self {
-                lib
-            }
+            Ok(Self {
+                lib,
+                abi,
+                functions,
+            })
         }
     }
 }
@@ -0,0 +1,106 @@
+use crate::error::CodecError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::{c_char, CStr, CString};
+use std::path::Path;
+
+/// Bytes handed across the boundary, owned by whichever side allocated them until freed there
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CodecBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+
+impl CodecBuffer {
+    pub fn from_vec(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        // boxed so the capacity matches the length handed back to the free function
+        let ptr = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        Self { ptr, len }
+    }
+
+    /// # Safety
+    /// Must come from `from_vec` in this same library and not have been freed
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        if self.ptr.is_null() {
+            return Vec::new();
+        }
+        unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.ptr, self.len)) }.into_vec()
+    }
+
+    /// # Safety
+    /// `ptr` must be null or valid for `len` bytes
+    pub unsafe fn to_vec(&self) -> Vec<u8> {
+        if self.ptr.is_null() {
+            return Vec::new();
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }.to_vec()
+    }
+}
+
+
+/// # Safety
+/// `ptr` must be null or a nul terminated string that outlives `'a`
+pub unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, CodecError> {
+    if ptr.is_null() {
+        return Err(CodecError::invalid_argument(format!("{} is null", name)));
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().map_err(|_| CodecError::invalid_argument(format!("{} is not valid UTF-8", name)))
+}
+
+/// # Safety
+/// Same as `str_arg`
+pub unsafe fn json_arg<T: DeserializeOwned>(ptr: *const c_char, name: &str) -> Result<T, CodecError> {
+    let json = unsafe { str_arg(ptr, name) }?;
+    serde_json::from_str(json).map_err(|e| CodecError::invalid_argument(format!("Malformed {}: {}", name, e)))
+}
+
+/// # Safety
+/// `ptr` must be null or valid for `len` bytes that outlive `'a`
+pub unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if ptr.is_null() || len == 0 {
+        return &[];
+    }
+    unsafe { std::slice::from_raw_parts(ptr, len) }
+}
+
+pub fn into_c_string(value: impl Into<String>) -> Result<*mut c_char, CodecError> {
+    let c_string = CString::new(value.into()).map_err(|e| CodecError::failed(e.to_string()))?;
+    Ok(c_string.into_raw())
+}
+
+pub fn into_json<T: Serialize + ?Sized>(value: &T) -> Result<*mut c_char, CodecError> {
+    let json = serde_json::to_string(value).map_err(|e| CodecError::failed(format!("Failed to serialise result: {}", e)))?;
+    into_c_string(json)
+}
+
+/// # Safety
+/// `ptr` must be null or come from `into_c_string` in this same library
+pub unsafe fn free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(unsafe { CString::from_raw(ptr) });
+    }
+}
+
+pub fn c_str(value: &str, name: &str) -> Result<CString, CodecError> {
+    CString::new(value).map_err(|_| CodecError::invalid_argument(format!("{} contains a nul byte", name)))
+}
+
+pub fn path_str<'a>(path: &'a Path, name: &str) -> Result<&'a str, CodecError> {
+    path.to_str().ok_or_else(|| CodecError::invalid_argument(format!("{} is not valid UTF-8", name)))
+}
+
+pub fn c_path(path: &Path, name: &str) -> Result<CString, CodecError> {
+    c_str(path_str(path, name)?, name)
+}
+
+pub fn c_json<T: Serialize + ?Sized>(value: &T, name: &str) -> Result<CString, CodecError> {
+    let json = serde_json::to_string(value).map_err(|e| CodecError::invalid_argument(format!("Failed to serialise {}: {}", name, e)))?;
+    c_str(&json, name)
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, CodecError> {
+    serde_json::from_str(json).map_err(|e| CodecError::failed(format!("Malformed codec response: {}", e)))
+}
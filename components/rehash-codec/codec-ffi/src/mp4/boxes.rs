@@ -0,0 +1,535 @@
+use crate::codec::StreamKind;
+use crate::error::CodecError;
+use crate::progress::ProgressReporter;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+
+type FourCc = [u8; 4];
+
+/// Boxes an MP4 or QuickTime file can start with
+const TOP_LEVEL_BOXES: [&FourCc; 8] = [b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide", b"pdin", b"uuid"];
+/// Seconds from the MP4 epoch, 1904-01-01, to the unix one
+const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+/// Samples between progress reports and cancellation checks while indexing
+const PROGRESS_SAMPLES: u64 = 1000;
+/// Largest moov box read into memory, far above the sample tables of hours of video
+const MAX_MOOV_SIZE: u64 = 256 << 20;
+
+
+/// Cursor over a box payload, reads past the end fail rather than panic on truncated files
+struct BoxReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+
+impl<'a> BoxReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("Truncated MP4 box")?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.array().map(i32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        self.array().map(i64::from_be_bytes)
+    }
+
+    /// Version of a full box, its flags are skipped
+    fn version(&mut self) -> Result<u8, String> {
+        let version = self.u8()?;
+        self.skip(3)?;
+        Ok(version)
+    }
+
+    /// 32 or 64 bits depending on the full box version
+    fn versioned(&mut self, version: u8) -> Result<u64, String> {
+        if version == 1 { self.u64() } else { self.u32().map(u64::from) }
+    }
+}
+
+
+/// Child boxes of a container payload, in file order
+fn children(data: &[u8]) -> Result<Vec<(FourCc, &[u8])>, String> {
+    let mut reader = BoxReader::new(data);
+    let mut boxes = Vec::new();
+
+    while reader.remaining() >= 8 {
+        let size = reader.u32()?;
+        let kind = reader.array::<4>()?;
+        let payload_len = match size {
+            0 => reader.remaining() as u64,
+            1 => reader.u64()?.checked_sub(16).ok_or("Malformed MP4 box size")?,
+            size => u64::from(size).checked_sub(8).ok_or("Malformed MP4 box size")?,
+        };
+        boxes.push((kind, reader.bytes(payload_len as usize)?));
+    }
+    Ok(boxes)
+}
+
+fn child<'a>(data: &'a [u8], kind: &FourCc) -> Result<Option<&'a [u8]>, String> {
+    Ok(children(data)?.into_iter().find(|(child_kind, _)| child_kind == kind).map(|(_, payload)| payload))
+}
+
+/// Payload of the box at `path` below `data`, None when any box on the way is missing
+fn find<'a>(data: &'a [u8], path: &[&FourCc]) -> Result<Option<&'a [u8]>, String> {
+    let mut current = data;
+    for kind in path {
+        match child(current, kind)? {
+            Some(payload) => current = payload,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+
+/// Codec and format of a track's first sample description
+#[derive(Debug, Default)]
+struct SampleEntry {
+    codec_name: String,
+    width: u32,
+    height: u32,
+    sample_rate: u32,
+    channels: u16,
+}
+
+
+fn codec_name(format: &FourCc) -> String {
+    let name = match format {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"av01" => "av1",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"mp4a" => "aac",
+        b".mp3" => "mp3",
+        b"Opus" => "opus",
+        b"fLaC" => "flac",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        _ => return String::from_utf8_lossy(format).trim().to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// Length of an MPEG-4 descriptor, up to four bytes of seven bits each
+fn descriptor_length(reader: &mut BoxReader) -> Result<usize, String> {
+    let mut length = 0usize;
+    for _ in 0..4 {
+        let byte = reader.u8()?;
+        length = (length << 7) | usize::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(length)
+}
+
+// mp4a covers every MPEG audio codec, the decoder config in esds says which
+fn esds_codec_name(esds: &[u8]) -> Result<Option<&'static str>, String> {
+    let mut reader = BoxReader::new(esds);
+    reader.version()?;
+    if reader.u8()? != 0x03 {
+        return Ok(None);
+    }
+    descriptor_length(&mut reader)?;
+    reader.skip(2)?;
+    let flags = reader.u8()?;
+    if flags & 0x80 != 0 {
+        reader.skip(2)?;
+    }
+    if flags & 0x40 != 0 {
+        let url_len = reader.u8()?;
+        reader.skip(usize::from(url_len))?;
+    }
+    if flags & 0x20 != 0 {
+        reader.skip(2)?;
+    }
+
+    if reader.u8()? != 0x04 {
+        return Ok(None);
+    }
+    descriptor_length(&mut reader)?;
+    Ok(match reader.u8()? {
+        0x40 | 0x66..=0x68 => Some("aac"),
+        0x69 | 0x6b => Some("mp3"),
+        _ => None,
+    })
+}
+
+fn parse_stsd(stsd: &[u8], kind: StreamKind) -> Result<SampleEntry, String> {
+    let mut reader = BoxReader::new(stsd);
+    reader.version()?;
+    if reader.u32()? == 0 {
+        return Ok(SampleEntry::default());
+    }
+
+    let (format, entry) = children(reader.bytes(reader.remaining())?)?.into_iter().next().ok_or("Empty sample description")?;
+    let mut entry_reader = BoxReader::new(entry);
+    // reserved and data reference index
+    entry_reader.skip(8)?;
+    let mut sample_entry = SampleEntry { codec_name: codec_name(&format), ..SampleEntry::default() };
+
+    match kind {
+        StreamKind::Video => {
+            entry_reader.skip(16)?;
+            sample_entry.width = u32::from(entry_reader.u16()?);
+            sample_entry.height = u32::from(entry_reader.u16()?);
+        }
+        StreamKind::Audio => {
+            let version = entry_reader.u16()?;
+            entry_reader.skip(6)?;
+            sample_entry.channels = entry_reader.u16()?;
+            entry_reader.skip(6)?;
+            sample_entry.sample_rate = entry_reader.u32()? >> 16;
+            // QuickTime sound descriptions carry extra fields before the child boxes
+            entry_reader.skip(match version {
+                1 => 16,
+                2 => 36,
+                _ => 0,
+            })?;
+
+            if &format == b"mp4a"
+                && let Some(esds) = child(entry_reader.bytes(entry_reader.remaining())?, b"esds")?
+                && let Some(name) = esds_codec_name(esds)?
+            {
+                sample_entry.codec_name = name.to_string();
+            }
+        }
+        _ => {}
+    }
+    Ok(sample_entry)
+}
+
+
+pub(crate) struct Track {
+    pub(crate) kind: StreamKind,
+    pub(crate) codec_name: String,
+    pub(crate) timescale: u32,
+    /// in `timescale` units
+    pub(crate) duration: u64,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) rotation: i32,
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u16,
+    pub(crate) sample_count: u64,
+    pub(crate) sample_bytes: u64,
+    /// first media time the edit list presents, subtracted so presentation starts at zero
+    media_start: i64,
+    /// (sample count, duration of each) from stts
+    sample_deltas: Vec<(u32, u32)>,
+    /// (sample count, presentation offset of each) from ctts
+    composition_offsets: Vec<(u32, i32)>,
+    /// 1-based sync sample numbers from stss, None when every sample is a sync sample
+    sync_samples: Option<Vec<u32>>,
+}
+
+
+impl Track {
+    fn parse(trak: &[u8]) -> Result<Self, String> {
+        let tkhd = child(trak, b"tkhd")?.ok_or("Track has no tkhd box")?;
+        let mut reader = BoxReader::new(tkhd);
+        let version = reader.version()?;
+        // creation, modification, track id, reserved and duration
+        reader.skip(if version == 1 { 32 } else { 20 })?;
+        reader.skip(16)?;
+        let matrix = (0..9).map(|_| reader.i32()).collect::<Result<Vec<_>, _>>()?;
+        let tkhd_width = reader.u32()? >> 16;
+        let tkhd_height = reader.u32()? >> 16;
+        // the display matrix rotates clockwise by the angle of its first column
+        let rotation = (f64::from(matrix[1]).atan2(f64::from(matrix[0])).to_degrees().round() as i32).rem_euclid(360);
+
+        let mdia = child(trak, b"mdia")?.ok_or("Track has no mdia box")?;
+        let mut reader = BoxReader::new(child(mdia, b"mdhd")?.ok_or("Track has no mdhd box")?);
+        let version = reader.version()?;
+        reader.skip(if version == 1 { 16 } else { 8 })?;
+        let timescale = reader.u32()?;
+        let duration = reader.versioned(version)?;
+        if timescale == 0 {
+            return Err("Track has no timescale".to_string());
+        }
+
+        let mut reader = BoxReader::new(child(mdia, b"hdlr")?.ok_or("Track has no hdlr box")?);
+        reader.version()?;
+        reader.skip(4)?;
+        let kind = match &reader.array::<4>()? {
+            b"vide" => StreamKind::Video,
+            b"soun" => StreamKind::Audio,
+            b"sbtl" | b"subt" | b"text" => StreamKind::Subtitle,
+            b"meta" | b"tmcd" => StreamKind::Data,
+            _ => StreamKind::Unknown,
+        };
+
+        let stbl = find(mdia, &[b"minf", b"stbl"])?.ok_or("Track has no sample table")?;
+        let entry = match child(stbl, b"stsd")? {
+            Some(stsd) => parse_stsd(stsd, kind)?,
+            None => SampleEntry::default(),
+        };
+
+        let sample_deltas = child(stbl, b"stts")?.map(|stts| parse_table(stts, |reader| Ok((reader.u32()?, reader.u32()?)))).transpose()?.unwrap_or_default();
+        let composition_offsets = child(stbl, b"ctts")?.map(|ctts| parse_table(ctts, |reader| Ok((reader.u32()?, reader.i32()?)))).transpose()?.unwrap_or_default();
+        let sync_samples = child(stbl, b"stss")?.map(|stss| parse_table(stss, |reader| reader.u32())).transpose()?;
+
+        let (sample_count, sample_bytes) = match child(stbl, b"stsz")? {
+            Some(stsz) => parse_stsz(stsz)?,
+            None => (sample_deltas.iter().map(|(count, _)| u64::from(*count)).sum(), 0),
+        };
+
+        let media_start = match find(trak, &[b"edts", b"elst"])? {
+            Some(elst) => parse_elst(elst)?,
+            None => 0,
+        };
+
+        Ok(Self {
+            kind,
+            codec_name: entry.codec_name,
+            timescale,
+            duration,
+            width: if entry.width > 0 { entry.width } else { tkhd_width },
+            height: if entry.height > 0 { entry.height } else { tkhd_height },
+            rotation: if kind == StreamKind::Video { rotation } else { 0 },
+            sample_rate: entry.sample_rate,
+            channels: entry.channels,
+            sample_count,
+            sample_bytes,
+            media_start,
+            sample_deltas,
+            composition_offsets,
+            sync_samples,
+        })
+    }
+
+    pub(crate) fn duration_seconds(&self) -> f64 {
+        self.duration as f64 / f64::from(self.timescale)
+    }
+
+    /// Presentation times, in seconds, of every sync sample in order
+    pub(crate) fn keyframe_times(&self, progress: &ProgressReporter) -> Result<Vec<f64>, CodecError> {
+        let mut offsets = self.composition_offsets.iter().flat_map(|(count, offset)| std::iter::repeat_n(*offset, *count as usize));
+        let mut sync_samples = self.sync_samples.as_ref().map(|samples| samples.iter().peekable());
+        let mut keyframes = Vec::new();
+        let mut dts = 0i64;
+        let mut sample = 0u64;
+
+        'samples: for (count, delta) in &self.sample_deltas {
+            for _ in 0..*count {
+                sample += 1;
+                if sample > self.sample_count {
+                    break 'samples;
+                }
+
+                let offset = i64::from(offsets.next().unwrap_or(0));
+                let is_sync = match sync_samples.as_mut() {
+                    Some(samples) => {
+                        while samples.next_if(|number| u64::from(**number) < sample).is_some() {}
+                        samples.next_if(|number| u64::from(**number) == sample).is_some()
+                    }
+                    None => true,
+                };
+                if is_sync {
+                    keyframes.push((dts + offset - self.media_start) as f64 / f64::from(self.timescale));
+                }
+                dts += i64::from(*delta);
+
+                if sample.is_multiple_of(PROGRESS_SAMPLES) {
+                    if progress.token().is_cancelled() {
+                        return Err(CodecError::cancelled());
+                    }
+                    progress.report(sample, self.sample_count);
+                }
+            }
+        }
+        progress.report(self.sample_count, self.sample_count);
+
+        keyframes.sort_by(f64::total_cmp);
+        keyframes.dedup();
+        Ok(keyframes)
+    }
+}
+
+
+/// Entries of a full box holding a count and then that many fixed size entries
+fn parse_table<T>(data: &[u8], mut entry: impl FnMut(&mut BoxReader) -> Result<T, String>) -> Result<Vec<T>, String> {
+    let mut reader = BoxReader::new(data);
+    reader.version()?;
+    let count = reader.u32()?;
+    (0..count).map(|_| entry(&mut reader)).collect()
+}
+
+/// Sample count and the total size of every sample
+fn parse_stsz(stsz: &[u8]) -> Result<(u64, u64), String> {
+    let mut reader = BoxReader::new(stsz);
+    reader.version()?;
+    let sample_size = u64::from(reader.u32()?);
+    let sample_count = u64::from(reader.u32()?);
+
+    if sample_size > 0 {
+        return Ok((sample_count, sample_size * sample_count));
+    }
+    let sample_bytes = (0..sample_count).map(|_| reader.u32().map(u64::from)).sum::<Result<u64, String>>()?;
+    Ok((sample_count, sample_bytes))
+}
+
+fn parse_elst(elst: &[u8]) -> Result<i64, String> {
+    let mut reader = BoxReader::new(elst);
+    let version = reader.version()?;
+    let count = reader.u32()?;
+
+    for _ in 0..count {
+        let media_time = if version == 1 {
+            reader.skip(8)?;
+            reader.i64()?
+        } else {
+            reader.skip(4)?;
+            i64::from(reader.i32()?)
+        };
+        reader.skip(4)?;
+
+        // -1 is an empty edit that only delays the start
+        if media_time >= 0 {
+            return Ok(media_time);
+        }
+    }
+    Ok(0)
+}
+
+
+/// What the demuxer needs from an MP4, read from its moov box without touching the media data
+pub(crate) struct Mp4File {
+    pub(crate) file_size: u64,
+    pub(crate) timescale: u32,
+    pub(crate) duration: u64,
+    /// unix seconds
+    pub(crate) creation_time: Option<u64>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+
+impl Mp4File {
+    pub(crate) fn open(path: &str) -> Result<Self, CodecError> {
+        let mut file = File::open(path).map_err(|e| CodecError::io(format!("Failed to open video file: {}", e)))?;
+        let file_size = file.metadata().map_err(|e| CodecError::io(format!("Failed to read metadata: {}", e)))?.len();
+        let moov = Self::read_moov(&mut file, file_size).map_err(|e| e.to_string());
+
+        match moov {
+            Ok(Some(moov)) => Self::parse(&moov, file_size).map_err(|e| CodecError::decode(format!("Malformed MP4 {}: {}", path, e))),
+            Ok(None) => Err(CodecError::unsupported(format!("{} is not an MP4 file, only the ffmpeg codec can open it", path))),
+            Err(e) => Err(CodecError::io(format!("Failed to read {}: {}", path, e))),
+        }
+    }
+
+    /// Payload of the moov box, None when the file doesn't look like an MP4
+    fn read_moov(file: &mut File, file_size: u64) -> std::io::Result<Option<Vec<u8>>> {
+        let mut position = 0u64;
+
+        while position + 8 <= file_size {
+            file.seek(SeekFrom::Start(position))?;
+            let mut header = [0u8; 8];
+            file.read_exact(&mut header)?;
+            let kind: FourCc = header[4..].try_into().unwrap();
+            if position == 0 && !TOP_LEVEL_BOXES.contains(&&kind) {
+                return Ok(None);
+            }
+
+            let (size, header_len) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                0 => (file_size - position, 8),
+                1 => {
+                    let mut large = [0u8; 8];
+                    file.read_exact(&mut large)?;
+                    (u64::from_be_bytes(large), 16)
+                }
+                size => (u64::from(size), 8),
+            };
+            if size < header_len || size > file_size - position {
+                return Ok(None);
+            }
+
+            if &kind == b"moov" {
+                if size - header_len > MAX_MOOV_SIZE {
+                    let message = format!("moov box of {} bytes is over the {} byte limit", size - header_len, MAX_MOOV_SIZE);
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message));
+                }
+                let mut payload = vec![0u8; (size - header_len) as usize];
+                file.read_exact(&mut payload)?;
+                return Ok(Some(payload));
+            }
+            position += size;
+        }
+        Ok(None)
+    }
+
+    fn parse(moov: &[u8], file_size: u64) -> Result<Self, String> {
+        let mut reader = BoxReader::new(child(moov, b"mvhd")?.ok_or("No mvhd box")?);
+        let version = reader.version()?;
+        let creation_time = reader.versioned(version)?;
+        reader.versioned(version)?;
+        let timescale = reader.u32()?;
+        let duration = reader.versioned(version)?;
+
+        let tracks = children(moov)?
+            .into_iter()
+            .filter(|(kind, _)| kind == b"trak")
+            .map(|(_, trak)| Track::parse(trak))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            file_size,
+            timescale,
+            duration,
+            creation_time: creation_time.checked_sub(MP4_EPOCH_OFFSET).filter(|_| creation_time > 0),
+            tracks,
+        })
+    }
+
+    pub(crate) fn duration_seconds(&self) -> f64 {
+        if self.timescale > 0 && self.duration > 0 {
+            return self.duration as f64 / f64::from(self.timescale);
+        }
+        self.tracks.iter().map(Track::duration_seconds).fold(0f64, f64::max)
+    }
+
+    /// Index of the first track of `kind`, the same index ffmpeg gives its stream
+    pub(crate) fn first(&self, kind: StreamKind) -> Option<usize> {
+        self.tracks.iter().position(|track| track.kind == kind)
+    }
+}
@@ -0,0 +1,263 @@
+mod boxes;
+
+use crate::codec::{KeyframeIndex, StreamKind, StreamProbe, VideoProbe, VideoStreamMeta};
+use crate::codec_ffi::CodecBackend;
+use crate::error::CodecError;
+use crate::marshal::path_str;
+use crate::progress::ProgressReporter;
+use boxes::{Mp4File, Track};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+
+struct Mp4Stream {
+    file: File,
+    meta: VideoStreamMeta,
+}
+
+
+/// Built-in backend for when the ffmpeg codec can't be loaded: probes, streams and indexes MP4s by reading
+/// their boxes, everything that needs decoding fails as unsupported
+pub struct Mp4Backend {
+    streams: Mutex<HashMap<u64, Mp4Stream>>,
+    next_handle: AtomicU64,
+}
+
+
+impl Mp4Backend {
+    pub fn new() -> Self {
+        Self {
+            streams: Mutex::new(HashMap::new()),
+            next_handle: AtomicU64::new(1),
+        }
+    }
+
+    fn with_stream<T>(&self, handle: u64, run: impl FnOnce(&mut Mp4Stream) -> Result<T, CodecError>) -> Result<T, CodecError> {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.get_mut(&handle).ok_or_else(|| CodecError::not_found(format!("Unknown video stream {}", handle)))?;
+        run(stream)
+    }
+
+    fn stream_probe(index: usize, track: &Track) -> StreamProbe {
+        let duration = track.duration_seconds();
+        let per_second = |value: u64| if duration > 0f64 { value as f64 / duration } else { 0f64 };
+
+        StreamProbe {
+            index,
+            kind: track.kind,
+            codec_name: track.codec_name.clone(),
+            duration,
+            bit_rate: per_second(track.sample_bytes * 8) as i64,
+            frame_rate: if track.kind == StreamKind::Video { per_second(track.sample_count) } else { 0f64 },
+            frame_count: track.sample_count as i64,
+            width: track.width,
+            height: track.height,
+            rotation: track.rotation,
+            sample_rate: track.sample_rate,
+            channels: track.channels,
+        }
+    }
+}
+
+
+impl Default for Mp4Backend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl CodecBackend for Mp4Backend {
+    fn print_codec_version(&self) -> Result<(), CodecError> {
+        println!("Codec version: built-in MP4 demuxer {}, ffmpeg features unavailable", env!("CARGO_PKG_VERSION"));
+        Ok(())
+    }
+
+    fn probe_video(&self, path: &Path) -> Result<VideoProbe, CodecError> {
+        let path = path_str(path, "path")?;
+        let mp4 = Mp4File::open(path)?;
+        let duration = mp4.duration_seconds();
+
+        Ok(VideoProbe {
+            file_path: path.to_string(),
+            format_name: "mov,mp4,m4a,3gp,3g2,mj2".to_string(),
+            format_long_name: "QuickTime / MOV".to_string(),
+            duration,
+            bit_rate: if duration > 0f64 { (mp4.file_size as f64 * 8f64 / duration) as i64 } else { 0 },
+            creation_time: mp4.creation_time.map(iso_timestamp),
+            video_stream: mp4.first(StreamKind::Video),
+            audio_stream: mp4.first(StreamKind::Audio),
+            streams: mp4.tracks.iter().enumerate().map(|(index, track)| Self::stream_probe(index, track)).collect(),
+        })
+    }
+
+    fn open_video_stream(&self, path: &Path, chunk_size: usize) -> Result<VideoStreamMeta, CodecError> {
+        if chunk_size == 0 {
+            return Err(CodecError::invalid_argument("Chunk size must be greater than zero"));
+        }
+
+        let path = path_str(path, "path")?;
+        let file = File::open(path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => CodecError::not_found(format!("No video file at {}", path)),
+            _ => CodecError::io(format!("Failed to open stream for {}: {}", path, e)),
+        })?;
+        let total_size = file.metadata().map_err(|e| CodecError::io(format!("Failed to read metadata: {}", e)))?.len();
+
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        let meta = VideoStreamMeta::new(handle, path.to_string(), 0, total_size, chunk_size);
+        self.streams.lock().unwrap().insert(handle, Mp4Stream { file, meta: meta.clone() });
+
+        Ok(meta)
+    }
+
+    fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        self.with_stream(handle, |stream| Ok(stream.meta.clone()))
+    }
+
+    fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        self.with_stream(handle, |stream| {
+            let position = stream.meta.current_position;
+            let to_read = stream.meta.total_size.saturating_sub(position).min(stream.meta.chunk_size as u64) as usize;
+
+            let mut bytes = vec![0u8; to_read];
+            stream.file.read_exact(&mut bytes).map_err(|e| CodecError::io(format!("Failed to read chunk at {}: {}", position, e)))?;
+            stream.meta.current_position = position + to_read as u64;
+            Ok(bytes)
+        })
+    }
+
+    fn seek_video_stream(&self, handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+        self.with_stream(handle, |stream| {
+            let position = position.min(stream.meta.total_size);
+            stream.file.seek(SeekFrom::Start(position)).map_err(|e| CodecError::io(format!("Failed to seek to {}: {}", position, e)))?;
+            stream.meta.current_position = position;
+            Ok(stream.meta.clone())
+        })
+    }
+
+    fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        Ok(self.streams.lock().unwrap().remove(&handle).is_some())
+    }
+
+    fn get_keyframe_index(&self, path: &Path, progress: &ProgressReporter) -> Result<KeyframeIndex, CodecError> {
+        let path = path_str(path, "path")?;
+        let mp4 = Mp4File::open(path)?;
+        let video = mp4.first(StreamKind::Video).ok_or_else(|| CodecError::decode(format!("No video stream in {}", path)))?;
+
+        Ok(KeyframeIndex {
+            file_path: path.to_string(),
+            duration: mp4.duration_seconds(),
+            keyframes: mp4.tracks[video].keyframe_times(progress)?,
+        })
+    }
+}
+
+
+/// Formats unix seconds the way ffmpeg reports creation_time
+fn iso_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+
+    // civil date from days since 1970-01-01, Howard Hinnant's algorithm
+    let shifted = days + 719_468;
+    let era = shifted / 146_097;
+    let day_of_era = shifted % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000000Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CodecStatus;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    const RED_MP4: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../codec/tests/resources/red.mp4");
+
+    /// Copy of red.mp4 changed by `edit`, written to the temp dir
+    fn edited_red_mp4(name: &str, edit: impl FnOnce(&mut Vec<u8>)) -> PathBuf {
+        let mut bytes = std::fs::read(RED_MP4).unwrap();
+        edit(&mut bytes);
+        let path = std::env::temp_dir().join(format!("rehash-mp4-{}-{}.mp4", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn red_mp4_is_probed_from_its_boxes() {
+        let probe = Mp4Backend::new().probe_video(Path::new(RED_MP4)).unwrap();
+        assert_eq!(probe.duration, 30f64);
+        assert_eq!((probe.video_stream, probe.audio_stream, probe.streams.len()), (Some(0), None, 1));
+
+        let video = &probe.streams[0];
+        assert_eq!((video.kind, video.codec_name.as_str()), (StreamKind::Video, "h264"));
+        assert_eq!((video.width, video.height, video.rotation), (1920, 1080, 0));
+        assert_eq!((video.frame_rate, video.frame_count), (30f64, 900));
+    }
+
+    #[test]
+    fn rotation_comes_from_the_display_matrix() {
+        // a quarter turn clockwise, the matrix starts 40 bytes into a version 0 tkhd payload
+        let path = edited_red_mp4("rotated", |bytes| {
+            let matrix = bytes.windows(4).position(|window| window == b"tkhd").unwrap() + 4 + 40;
+            bytes[matrix..matrix + 16].copy_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+        });
+
+        let probe = Mp4Backend::new().probe_video(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(probe.unwrap().streams[0].rotation, 90);
+    }
+
+    #[test]
+    fn keyframes_come_from_the_sync_samples() {
+        let index = Mp4Backend::new().get_keyframe_index(Path::new(RED_MP4), &ProgressReporter::silent()).unwrap();
+        assert_eq!(index.duration, 30f64);
+
+        // x264 placed one every 250 frames
+        let expected = [0f64, 250f64 / 30f64, 500f64 / 30f64, 750f64 / 30f64];
+        assert_eq!(index.keyframes.len(), expected.len(), "{:?}", index.keyframes);
+        for (keyframe, expected) in index.keyframes.iter().zip(expected) {
+            assert!((keyframe - expected).abs() < 1e-6, "{:?}", index.keyframes);
+        }
+    }
+
+    #[test]
+    fn truncated_and_foreign_files_are_unsupported() {
+        // the moov box is at the end, so cutting the file cuts it short
+        let truncated = edited_red_mp4("truncated", |bytes| bytes.truncate(bytes.len() - 100));
+        let foreign = edited_red_mp4("foreign", |bytes| *bytes = b"RIFF\0\0\0\0WAVEfmt not an mp4 at all".to_vec());
+
+        let results = [truncated.as_path(), foreign.as_path()].map(|path| Mp4Backend::new().probe_video(path));
+        let _ = std::fs::remove_file(&truncated);
+        let _ = std::fs::remove_file(&foreign);
+        for result in results {
+            assert_eq!(result.unwrap_err().status, CodecStatus::Unsupported);
+        }
+    }
+
+    #[test]
+    fn oversized_moov_boxes_are_not_read() {
+        // a sparse file whose moov box claims a gigabyte
+        let path = std::env::temp_dir().join(format!("rehash-mp4-oversized-{}.mp4", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[&8u32.to_be_bytes()[..], b"ftyp", &(1u32 << 30).to_be_bytes(), b"moov"].concat()).unwrap();
+        file.set_len(1 << 31).unwrap();
+
+        let error = Mp4Backend::new().probe_video(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(error.status, CodecStatus::Io);
+        assert!(error.message.contains("limit"), "{}", error);
+    }
+}
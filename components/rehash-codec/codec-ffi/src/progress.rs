@@ -0,0 +1,126 @@
+use crate::error::CodecError;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Called with the frames processed so far and the total, a total of 0 means it isn't known
+pub type ProgressCallback = unsafe extern "C" fn(user_data: *mut c_void, processed: u64, total: u64);
+
+/// Handed to long-running exports so the host can follow them and flip `cancelled` to stop them
+#[repr(C)]
+#[derive(Debug)]
+pub struct CodecProgress {
+    pub callback: Option<ProgressCallback>,
+    pub user_data: *mut c_void,
+    pub cancelled: *const AtomicBool,
+}
+
+
+// the host keeps both pointers alive and usable from any thread for the length of the call
+unsafe impl Send for CodecProgress {}
+unsafe impl Sync for CodecProgress {}
+
+
+static NO_PROGRESS: CodecProgress = CodecProgress { callback: None, user_data: std::ptr::null_mut(), cancelled: std::ptr::null() };
+
+
+impl CodecProgress {
+    /// Reports nowhere and is never cancelled
+    pub fn none() -> &'static CodecProgress {
+        &NO_PROGRESS
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        !self.cancelled.is_null() && unsafe { (*self.cancelled).load(Ordering::Relaxed) }
+    }
+
+    /// Reports progress, fails once the host has cancelled so loops can bail out with `?`
+    pub fn report(&self, processed: u64, total: u64) -> Result<(), String> {
+        if self.is_cancelled() {
+            return Err(String::from("Cancelled"));
+        }
+
+        if let Some(callback) = self.callback {
+            unsafe { callback(self.user_data, processed, total) };
+        }
+        Ok(())
+    }
+
+    /// Turns the failure of a cancelled call into the cancelled status
+    pub fn fail(&self, error: CodecError) -> CodecError {
+        if self.is_cancelled() { CodecError::cancelled() } else { error }
+    }
+}
+
+
+/// # Safety
+/// `ptr` must be null or valid for the length of the export
+pub unsafe fn progress_arg<'a>(ptr: *const CodecProgress) -> &'a CodecProgress {
+    if ptr.is_null() { CodecProgress::none() } else { unsafe { &*ptr } }
+}
+
+
+/// Shared flag the host flips to stop a running export, the codec checks it between frames
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+
+/// Host side of a `CodecProgress`: a callback for progress and the token cancelling the call
+pub struct ProgressReporter {
+    callback: Option<Box<dyn Fn(u64, u64) + Send + Sync>>,
+    token: CancelToken,
+}
+
+
+impl ProgressReporter {
+    pub fn new(callback: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        Self { callback: Some(Box::new(callback)), token: CancelToken::new() }
+    }
+
+    /// Reports nowhere but can still be cancelled
+    pub fn silent() -> Self {
+        Self { callback: None, token: CancelToken::new() }
+    }
+
+    pub fn token(&self) -> CancelToken {
+        self.token.clone()
+    }
+
+    /// Passes progress on to the callback, a panicking callback is contained
+    pub fn report(&self, processed: u64, total: u64) {
+        if let Some(callback) = &self.callback {
+            let _ = catch_unwind(AssertUnwindSafe(|| callback(processed, total)));
+        }
+    }
+
+    /// View handed to the codec, only valid while `self` is
+    pub fn as_codec(&self) -> CodecProgress {
+        CodecProgress {
+            callback: self.callback.as_ref().map(|_| report_progress as ProgressCallback),
+            user_data: self as *const Self as *mut c_void,
+            cancelled: Arc::as_ptr(&self.token.0),
+        }
+    }
+}
+
+
+unsafe extern "C" fn report_progress(user_data: *mut c_void, processed: u64, total: u64) {
+    let reporter = unsafe { &*(user_data as *const ProgressReporter) };
+    reporter.report(processed, total);
+}
@@ -0,0 +1,554 @@
+use crate::codec_ffi::CodecRequest;
+use crate::error::{CodecError, CodecStatus};
+use crate::progress::{CancelToken, ProgressReporter};
+use crate::RehashCodecLibrary;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// First argument of a process started as the codec worker, followed by the codec path and host address.
+/// The token it connects with comes on its stdin, where other processes can't read it
+pub const WORKER_ARG: &str = "--codec-worker";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Frames between progress messages from the worker, the host throttles further
+const PROGRESS_STRIDE: u64 = 25;
+/// Handles the host sees carry the generation of the worker process above these bits, every process starts numbering again
+const GENERATION_SHIFT: u32 = 32;
+
+
+/// Result of a call as it comes back over the socket
+pub enum WorkerValue {
+    Json(serde_json::Value),
+    Bytes(Vec<u8>),
+}
+
+
+impl WorkerValue {
+    pub fn json<T: Serialize>(value: &T) -> Result<Self, CodecError> {
+        serde_json::to_value(value).map(WorkerValue::Json).map_err(|e| CodecError::failed(format!("Failed to serialise result: {}", e)))
+    }
+
+    pub fn into_json<T: DeserializeOwned>(self) -> Result<T, CodecError> {
+        match self {
+            WorkerValue::Json(value) => serde_json::from_value(value).map_err(|e| CodecError::failed(format!("Malformed worker response: {}", e))),
+            WorkerValue::Bytes(_) => Err(CodecError::failed("Worker sent bytes where a value was expected")),
+        }
+    }
+
+    pub fn into_bytes(self) -> Result<Vec<u8>, CodecError> {
+        match self {
+            WorkerValue::Bytes(bytes) => Ok(bytes),
+            WorkerValue::Json(_) => Err(CodecError::failed("Worker sent a value where bytes were expected")),
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+enum HostMessage {
+    Call { id: u64, request: CodecRequest },
+    Cancel { id: u64 },
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Reply {
+    Json(serde_json::Value),
+    /// the bytes follow the header as the frame payload
+    Bytes,
+    Error { status: i32, message: String },
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+enum WorkerMessage {
+    Hello { token: String, error: Option<String> },
+    Progress { id: u64, processed: u64, total: u64 },
+    Done { id: u64, reply: Reply },
+}
+
+
+// A frame is the header and payload lengths, a JSON header and then the raw payload
+fn write_frame(stream: &mut impl Write, header: &impl Serialize, payload: &[u8]) -> io::Result<()> {
+    let header = serde_json::to_vec(header).map_err(io::Error::other)?;
+    let mut frame = Vec::with_capacity(8 + header.len() + payload.len());
+    frame.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+fn read_frame<T: DeserializeOwned>(stream: &mut impl Read) -> io::Result<(T, Vec<u8>)> {
+    let mut lengths = [0u8; 8];
+    stream.read_exact(&mut lengths)?;
+    let header_len = u32::from_le_bytes(lengths[..4].try_into().unwrap()) as usize;
+    let payload_len = u32::from_le_bytes(lengths[4..].try_into().unwrap()) as usize;
+
+    let mut header = vec![0u8; header_len];
+    stream.read_exact(&mut header)?;
+    let mut payload = vec![0u8; payload_len];
+    stream.read_exact(&mut payload)?;
+
+    let header = serde_json::from_slice(&header).map_err(io::Error::other)?;
+    Ok((header, payload))
+}
+
+
+/// Files the worker was working on when it died, reported so the user knows what to avoid
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerCrash {
+    pub message: String,
+    pub file_paths: Vec<String>,
+}
+
+
+/// The codec numbers each kind of handle on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum HandleKind {
+    Stream,
+    Segments,
+    Import,
+    Reel,
+}
+
+
+impl HandleKind {
+    /// Kind of handle `request` opens or works on
+    fn of(request: &CodecRequest) -> Option<Self> {
+        use CodecRequest::*;
+
+        match request {
+            OpenVideoStream { .. } | GetVideoStreamMeta { .. } | ReadVideoStreamChunk { .. } | SeekVideoStream { .. } | CloseVideoStream { .. }
+            | DecodeFrameAt { .. } => Some(HandleKind::Stream),
+            OpenSegmentedStream { .. } | ReadInitSegment { .. } | ReadMediaSegment { .. } | CloseSegmentedStream { .. } => Some(HandleKind::Segments),
+            StartImport { .. } | StartProxy { .. } | GetImportStatus { .. } | CloseImport { .. } => Some(HandleKind::Import),
+            StartReel { .. } | GetReelStatus { .. } | CloseReel { .. } => Some(HandleKind::Reel),
+            _ => None,
+        }
+    }
+}
+
+
+/// Whether `request` gives up the handle it works on
+fn closes_handle(request: &CodecRequest) -> bool {
+    use CodecRequest::*;
+
+    matches!(request, CloseVideoStream { .. } | CloseSegmentedStream { .. } | CloseImport { .. } | CloseReel { .. })
+}
+
+
+enum WorkerEvent {
+    Progress(u64, u64),
+    Done(Result<WorkerValue, CodecError>),
+    Crashed(String),
+}
+
+
+struct PendingCall {
+    file_paths: Vec<String>,
+    events: Sender<WorkerEvent>,
+}
+
+
+struct Connection {
+    generation: u64,
+    child: Mutex<Child>,
+    writer: Mutex<TcpStream>,
+    pending: Mutex<HashMap<u64, PendingCall>>,
+    /// files each open handle was opened with, keyed by the handle the worker knows
+    opened: Mutex<HashMap<(HandleKind, u64), Vec<String>>>,
+    next_id: AtomicU64,
+    alive: AtomicBool,
+    // set when the host stops the worker itself, so that isn't reported as a crash
+    closing: AtomicBool,
+}
+
+
+impl Connection {
+    fn send(&self, message: &HostMessage) -> Result<(), CodecError> {
+        write_frame(&mut *self.writer.lock().unwrap(), message, &[]).map_err(|e| CodecError::io(format!("Failed to reach the codec worker: {}", e)))
+    }
+
+    fn register(&self, file_paths: Vec<String>, events: Sender<WorkerEvent>) -> Result<u64, CodecError> {
+        let mut pending = self.pending.lock().unwrap();
+        if !self.alive.load(Ordering::SeqCst) {
+            return Err(CodecError::failed("Codec worker has stopped"));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        pending.insert(id, PendingCall { file_paths, events });
+        Ok(id)
+    }
+
+    /// Handle the worker knows for one the host was given, which has to come from this worker process
+    fn worker_handle(&self, handle: u64) -> Result<u64, CodecError> {
+        if handle >> GENERATION_SHIFT != self.generation {
+            return Err(CodecError::not_found(format!("Handle {} is from a codec worker that has since stopped", handle)));
+        }
+        Ok(handle & ((1 << GENERATION_SHIFT) - 1))
+    }
+
+    fn host_handle(&self, handle: u64) -> u64 {
+        self.generation << GENERATION_SHIFT | handle
+    }
+
+    /// Files `request` works on: its own, the sources of a reel or those its handle was opened with
+    fn file_paths(&self, request: &CodecRequest, kind: Option<HandleKind>, handle: Option<u64>) -> Vec<String> {
+        if let CodecRequest::StartReel { segments, .. } = request {
+            let mut paths: Vec<String> = Vec::new();
+            for segment in segments {
+                if !paths.contains(&segment.file_path) {
+                    paths.push(segment.file_path.clone());
+                }
+            }
+            return paths;
+        }
+
+        match kind.zip(handle) {
+            Some(key) => self.opened.lock().unwrap().get(&key).cloned().unwrap_or_default(),
+            None => request.file_path().map(String::from).into_iter().collect(),
+        }
+    }
+
+    /// Gives the handle in a reply the host's numbering, remembering the files of one that was just opened
+    fn host_reply(&self, kind: HandleKind, handle: Option<u64>, closes: bool, file_paths: Vec<String>, value: WorkerValue) -> WorkerValue {
+        let WorkerValue::Json(mut json) = value else {
+            return value;
+        };
+
+        let field = match &mut json {
+            serde_json::Value::Object(fields) => {
+                let key = if fields.contains_key("handle") { "handle" } else { "job" };
+                fields.get_mut(key)
+            }
+            // starting a job replies with nothing but the job
+            job if handle.is_none() && job.is_u64() => Some(job),
+            _ => None,
+        };
+        let worker_handle = field.and_then(|field| {
+            let worker_handle = field.as_u64()?;
+            *field = self.host_handle(worker_handle).into();
+            Some(worker_handle)
+        });
+
+        let mut opened = self.opened.lock().unwrap();
+        match (handle, worker_handle) {
+            (None, Some(worker_handle)) => {
+                opened.insert((kind, worker_handle), file_paths);
+            }
+            (Some(handle), _) if closes => {
+                opened.remove(&(kind, handle));
+            }
+            _ => {}
+        }
+        WorkerValue::Json(json)
+    }
+
+    fn read_replies(&self, mut reader: TcpStream, on_crash: &(dyn Fn(&WorkerCrash) + Send + Sync)) {
+        while let Ok((message, payload)) = read_frame::<WorkerMessage>(&mut reader) {
+            match message {
+                WorkerMessage::Progress { id, processed, total } => {
+                    if let Some(call) = self.pending.lock().unwrap().get(&id) {
+                        let _ = call.events.send(WorkerEvent::Progress(processed, total));
+                    }
+                }
+                WorkerMessage::Done { id, reply } => {
+                    let result = match reply {
+                        Reply::Json(value) => Ok(WorkerValue::Json(value)),
+                        Reply::Bytes => Ok(WorkerValue::Bytes(payload)),
+                        Reply::Error { status, message } => Err(CodecError::new(CodecStatus::from_code(status), message)),
+                    };
+                    if let Some(call) = self.pending.lock().unwrap().remove(&id) {
+                        let _ = call.events.send(WorkerEvent::Done(result));
+                    }
+                }
+                WorkerMessage::Hello { .. } => {}
+            }
+        }
+
+        // the socket only closes when the worker has gone, whatever took it down
+        let mut pending = self.pending.lock().unwrap();
+        self.alive.store(false, Ordering::SeqCst);
+        let status = {
+            let mut child = self.child.lock().unwrap();
+            let _ = child.kill();
+            child.wait().map(|status| status.to_string()).unwrap_or_else(|e| e.to_string())
+        };
+
+        let message = format!("Codec worker stopped ({})", status);
+        let mut file_paths = Vec::new();
+        for (_, call) in pending.drain() {
+            let message = match call.file_paths.is_empty() {
+                true => message.clone(),
+                false => format!("{} while working on {}", message, call.file_paths.join(", ")),
+            };
+            file_paths.extend(call.file_paths);
+            let _ = call.events.send(WorkerEvent::Crashed(message));
+        }
+        drop(pending);
+
+        if !self.closing.load(Ordering::SeqCst) {
+            eprintln!("{}, it will be restarted on the next call", message);
+            on_crash(&WorkerCrash { message, file_paths });
+        }
+    }
+}
+
+
+/// The codec running in a separate process, so a crash in ffmpeg only takes down the worker.
+/// A worker that died is started again on the next call
+pub struct CodecWorker {
+    program: PathBuf,
+    codec_path: PathBuf,
+    connection: Mutex<Option<Arc<Connection>>>,
+    /// bumped for every process started, so handles from one that died are told apart
+    generations: AtomicU64,
+    on_crash: Arc<dyn Fn(&WorkerCrash) + Send + Sync>,
+}
+
+
+impl CodecWorker {
+    /// Starts `program` as the worker, which has to hand its arguments to `serve` when the first is `WORKER_ARG`
+    pub fn spawn(program: &Path, codec_path: &Path, on_crash: impl Fn(&WorkerCrash) + Send + Sync + 'static) -> Result<Self, String> {
+        let worker = Self {
+            program: program.to_path_buf(),
+            codec_path: codec_path.to_path_buf(),
+            connection: Mutex::new(None),
+            generations: AtomicU64::new(0),
+            on_crash: Arc::new(on_crash),
+        };
+        worker.connection()?;
+        Ok(worker)
+    }
+
+    fn connection(&self) -> Result<Arc<Connection>, String> {
+        let mut connection = self.connection.lock().unwrap();
+        if let Some(live) = connection.as_ref().filter(|c| c.alive.load(Ordering::SeqCst)) {
+            return Ok(live.clone());
+        }
+
+        let started = self.start()?;
+        *connection = Some(started.clone());
+        Ok(started)
+    }
+
+    fn start(&self) -> Result<Arc<Connection>, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Failed to listen for the codec worker: {}", e))?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?;
+        // anything else on the machine could connect first, the worker proves itself with this
+        let mut secret = [0u8; 16];
+        getrandom::fill(&mut secret).map_err(|e| format!("Failed to generate the codec worker token: {}", e))?;
+        let token: String = secret.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let mut child = Command::new(&self.program)
+            .arg(WORKER_ARG)
+            .arg(&self.codec_path)
+            .arg(address.to_string())
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start the codec worker: {}", e))?;
+        // dropped once written, so the worker's stdin ends after the token
+        let sent = child.stdin.take().map(|mut stdin| writeln!(stdin, "{}", token));
+        if !matches!(sent, Some(Ok(()))) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(String::from("Failed to send the codec worker its token"));
+        }
+
+        let stream = match accept_worker(&listener, &mut child, &token) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+        };
+        println!("Codec worker {} started", child.id());
+
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let connection = Arc::new(Connection {
+            generation: self.generations.fetch_add(1, Ordering::Relaxed),
+            child: Mutex::new(child),
+            writer: Mutex::new(stream),
+            pending: Mutex::new(HashMap::new()),
+            opened: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            alive: AtomicBool::new(true),
+            closing: AtomicBool::new(false),
+        });
+
+        let (replies, on_crash) = (connection.clone(), self.on_crash.clone());
+        thread::spawn(move || replies.read_replies(reader, on_crash.as_ref()));
+        Ok(connection)
+    }
+
+    /// Process of the worker while it is running
+    pub fn process_id(&self) -> Option<u32> {
+        let connection = self.connection.lock().unwrap();
+        connection.as_ref().filter(|c| c.alive.load(Ordering::SeqCst)).map(|c| c.child.lock().unwrap().id())
+    }
+
+    /// Sends `request` to the worker and waits for its reply, passing progress on and forwarding cancellation
+    pub(crate) fn call(&self, mut request: CodecRequest, progress: Option<&ProgressReporter>) -> Result<WorkerValue, CodecError> {
+        let connection = self.connection().map_err(CodecError::failed)?;
+        let handle = match request.handle_mut() {
+            Some(handle) => {
+                *handle = connection.worker_handle(*handle)?;
+                Some(*handle)
+            }
+            None => None,
+        };
+        let kind = HandleKind::of(&request);
+        let file_paths = connection.file_paths(&request, kind, handle);
+        let closes = closes_handle(&request);
+
+        let (sender, events) = mpsc::channel();
+        let id = connection.register(file_paths.clone(), sender)?;
+
+        if let Err(e) = connection.send(&HostMessage::Call { id, request }) {
+            connection.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        let mut cancel_sent = false;
+        loop {
+            match events.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(WorkerEvent::Progress(processed, total)) => {
+                    if let Some(progress) = progress {
+                        progress.report(processed, total);
+                    }
+                }
+                Ok(WorkerEvent::Done(result)) => {
+                    return match kind {
+                        Some(kind) => result.map(|value| connection.host_reply(kind, handle, closes, file_paths, value)),
+                        None => result,
+                    };
+                }
+                Ok(WorkerEvent::Crashed(message)) => return Err(CodecError::failed(message)),
+                Err(RecvTimeoutError::Timeout) => {
+                    if !cancel_sent && progress.is_some_and(|progress| progress.token().is_cancelled()) {
+                        cancel_sent = connection.send(&HostMessage::Cancel { id }).is_ok();
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(CodecError::failed("Codec worker dropped the call")),
+            }
+        }
+    }
+}
+
+
+impl Drop for CodecWorker {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.lock().unwrap().take() {
+            connection.closing.store(true, Ordering::SeqCst);
+            let _ = connection.child.lock().unwrap().kill();
+        }
+    }
+}
+
+
+fn accept_worker(listener: &TcpListener, child: &mut Child, token: &str) -> Result<TcpStream, String> {
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let started = Instant::now();
+
+    loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+                stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+
+                let Ok((WorkerMessage::Hello { token: sent, error }, _)) = read_frame::<WorkerMessage>(&mut stream) else {
+                    continue;
+                };
+                if sent != token {
+                    continue;
+                }
+                if let Some(error) = error {
+                    return Err(error);
+                }
+
+                stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(format!("Failed to accept the codec worker: {}", e)),
+        }
+
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("Codec worker exited before connecting ({})", status));
+        }
+        if started.elapsed() > CONNECT_TIMEOUT {
+            return Err(String::from("Codec worker did not connect in time"));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+
+/// Runs the worker side: loads the codec and serves calls from the host until it disconnects.
+/// `args` are what followed `WORKER_ARG`
+pub fn serve(args: &[String]) -> Result<(), String> {
+    let [codec_path, address] = args else {
+        return Err(format!("Usage: {} <codec> <address>, with the token on stdin", WORKER_ARG));
+    };
+    let mut token = String::new();
+    std::io::stdin().read_line(&mut token).map_err(|e| format!("Failed to read the token: {}", e))?;
+    let token = token.trim_end().to_string();
+
+    let mut stream = TcpStream::connect(address).map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+    let codec = RehashCodecLibrary::new(codec_path);
+    let hello = WorkerMessage::Hello { token, error: codec.as_ref().err().cloned() };
+    write_frame(&mut stream, &hello, &[]).map_err(|e| e.to_string())?;
+
+    let codec = Arc::new(codec?);
+    let writer = Arc::new(Mutex::new(stream.try_clone().map_err(|e| e.to_string())?));
+    let running: Arc<Mutex<HashMap<u64, CancelToken>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    // the host closing the socket is the only way the worker is asked to stop
+    while let Ok((message, _)) = read_frame::<HostMessage>(&mut stream) {
+        match message {
+            HostMessage::Call { id, request } => {
+                let (codec, writer, running) = (codec.clone(), writer.clone(), running.clone());
+                thread::spawn(move || {
+                    let progress_writer = writer.clone();
+                    let progress = ProgressReporter::new(move |processed, total| {
+                        if processed % PROGRESS_STRIDE == 0 || processed == total {
+                            let message = WorkerMessage::Progress { id, processed, total };
+                            let _ = write_frame(&mut *progress_writer.lock().unwrap(), &message, &[]);
+                        }
+                    });
+                    running.lock().unwrap().insert(id, progress.token());
+                    let result = codec.handle_request(request, &progress);
+                    running.lock().unwrap().remove(&id);
+
+                    let (reply, payload) = match result {
+                        Ok(WorkerValue::Json(value)) => (Reply::Json(value), Vec::new()),
+                        Ok(WorkerValue::Bytes(bytes)) => (Reply::Bytes, bytes),
+                        Err(error) => (Reply::Error { status: error.status as i32, message: error.message }, Vec::new()),
+                    };
+                    let _ = write_frame(&mut *writer.lock().unwrap(), &WorkerMessage::Done { id, reply }, &payload);
+                });
+            }
+            HostMessage::Cancel { id } => {
+                if let Some(token) = running.lock().unwrap().get(&id) {
+                    token.cancel();
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
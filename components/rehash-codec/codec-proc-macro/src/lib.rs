@@ -1,24 +1,190 @@
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use syn::__private::quote::quote;
-use syn::token::Pub;
-use syn::{parse_macro_input, parse_quote, Abi, Attribute, ItemFn, LitStr, Visibility};
+use syn::__private::quote::{format_ident, quote};
+use syn::{parse_macro_input, FnArg, GenericArgument, Ident, ItemFn, Pat, PathArguments, ReturnType, Type};
+
+const PLAIN_TYPES: [&str; 13] = ["bool", "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize", "f32", "f64"];
+
+/// How a Rust type crosses the C boundary, `build.rs` in codec-ffi mirrors this on the host side
+enum Marshal {
+    /// passed by value
+    Plain,
+    /// `&str` or `String` as a nul terminated string
+    Str { owned: bool },
+    /// `&[u8]` or `Vec<u8>` as a pointer and length in, a `CodecBuffer` out
+    Bytes { owned: bool },
+    /// anything else as a JSON string
+    Json { ty: Box<Type>, by_ref: bool },
+    /// `&CodecProgress` as a pointer the host keeps alive for the call
+    Progress,
+}
+
+
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(' ', "")
+}
+
+fn marshal(ty: &Type) -> Marshal {
+    if let Type::Reference(reference) = ty {
+        return match type_name(&reference.elem).as_str() {
+            "str" => Marshal::Str { owned: false },
+            "[u8]" => Marshal::Bytes { owned: false },
+            "CodecProgress" => Marshal::Progress,
+            _ => Marshal::Json { ty: reference.elem.clone(), by_ref: true },
+        };
+    }
+
+    match type_name(ty).as_str() {
+        "String" => Marshal::Str { owned: true },
+        "Vec<u8>" => Marshal::Bytes { owned: true },
+        name if PLAIN_TYPES.contains(&name) => Marshal::Plain,
+        _ => Marshal::Json { ty: Box::new(ty.clone()), by_ref: false },
+    }
+}
+
+/// `T` of a `Result<T, CodecError>` return, bodies returning anything else can't fail
+fn result_ok_type(ty: &Type) -> Option<Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last().filter(|segment| segment.ident == "Result")?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first() {
+        Some(GenericArgument::Type(ty)) => Some(ty.clone()),
+        _ => None,
+    }
+}
 
 #[proc_macro_attribute]
 pub fn rehash_codec_ffi(_attr: TokenStream, item: TokenStream) -> TokenStream {
-    let mut input_fn = parse_macro_input!(item as ItemFn);
+    let input_fn = parse_macro_input!(item as ItemFn);
+    let fn_name = &input_fn.sig.ident;
+    let attrs = &input_fn.attrs;
+
+    let mut inner = input_fn.clone();
+    inner.attrs.clear();
+    inner.sig.ident = Ident::new("inner", Span::call_site());
+
+    let mut c_inputs = Vec::new();
+    let mut conversions = Vec::new();
+    let mut call_args = Vec::new();
+    let mut progress = None;
+
+    for fn_arg in &input_fn.sig.inputs {
+        let FnArg::Typed(pat_type) = fn_arg else {
+            continue;
+        };
+        let Pat::Ident(pat_ident) = &*pat_type.pat else {
+            return syn::Error::new_spanned(pat_type, "codec exports need plain argument names").to_compile_error().into();
+        };
+        let name = &pat_ident.ident;
+        let label = name.to_string();
+
+        match marshal(&pat_type.ty) {
+            Marshal::Plain => {
+                let ty = &pat_type.ty;
+                c_inputs.push(quote!(#name: #ty));
+                call_args.push(quote!(#name));
+            }
+            Marshal::Str { owned } => {
+                c_inputs.push(quote!(#name: *const std::ffi::c_char));
+                conversions.push(quote!(let #name = ::rehash_codec_ffi::marshal::str_arg(#name, #label)?;));
+                call_args.push(if owned { quote!(#name.to_string()) } else { quote!(#name) });
+            }
+            Marshal::Bytes { owned } => {
+                let len = format_ident!("{}_len", name);
+                c_inputs.push(quote!(#name: *const u8));
+                c_inputs.push(quote!(#len: usize));
+                conversions.push(quote!(let #name = ::rehash_codec_ffi::marshal::bytes_arg(#name, #len);));
+                call_args.push(if owned { quote!(#name.to_vec()) } else { quote!(#name) });
+            }
+            Marshal::Json { ty, by_ref } => {
+                c_inputs.push(quote!(#name: *const std::ffi::c_char));
+                conversions.push(quote!(let #name = ::rehash_codec_ffi::marshal::json_arg::<#ty>(#name, #label)?;));
+                call_args.push(if by_ref { quote!(&#name) } else { quote!(#name) });
+            }
+            Marshal::Progress => {
+                c_inputs.push(quote!(#name: *const ::rehash_codec_ffi::progress::CodecProgress));
+                conversions.push(quote!(let #name = ::rehash_codec_ffi::progress::progress_arg(#name);));
+                call_args.push(quote!(#name));
+                progress = Some(name.clone());
+            }
+        }
+    }
 
-    let unsafe_attr: Attribute = parse_quote!(#[unsafe(no_mangle)]);
-    input_fn.attrs.push(unsafe_attr);
+    let (value_type, is_fallible) = match &input_fn.sig.output {
+        ReturnType::Default => (None, false),
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(ok_ty) => (Some(ok_ty), true),
+            None => (Some((**ty).clone()), false),
+        },
+    };
+
+    let call = match (is_fallible, progress) {
+        // whatever a cancelled call fails with, the host only needs to know it was cancelled
+        (true, Some(progress)) => quote!(inner(#(#call_args),*).map_err(|error| #progress.fail(error))?),
+        (true, None) => quote!(inner(#(#call_args),*)?),
+        (false, _) => quote!(inner(#(#call_args),*)),
+    };
+
+    // the value goes out through a trailing pointer so the return is left for the status
+    let (out, body) = match value_type.as_ref().filter(|ty| type_name(ty) != "()") {
+        None => (quote!(std::ptr::null_mut::<()>()), quote!(#call; Ok(()))),
+        Some(ty) => {
+            let (out_ty, value) = match marshal(ty) {
+                Marshal::Plain => (quote!(#ty), quote!(Ok(value))),
+                Marshal::Str { .. } => (quote!(*mut std::ffi::c_char), quote!(::rehash_codec_ffi::marshal::into_c_string(value))),
+                Marshal::Bytes { owned } => {
+                    let bytes = if owned { quote!(value) } else { quote!(value.to_vec()) };
+                    (quote!(::rehash_codec_ffi::marshal::CodecBuffer), quote!(Ok(::rehash_codec_ffi::marshal::CodecBuffer::from_vec(#bytes))))
+                }
+                Marshal::Json { .. } => (quote!(*mut std::ffi::c_char), quote!(::rehash_codec_ffi::marshal::into_json(&value))),
+                Marshal::Progress => {
+                    return syn::Error::new_spanned(ty, "progress can only be passed into an export").to_compile_error().into();
+                }
+            };
+            c_inputs.push(quote!(rehash_out: *mut #out_ty));
+            (quote!(rehash_out), quote!(let value = #call; #value))
+        }
+    };
+
+    TokenStream::from(quote! {
+        #(#attrs)*
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #fn_name(#(#c_inputs),*) -> i32 {
+            #inner
+
+            unsafe {
+                ::rehash_codec_ffi::error::ffi_call(#out, move || {
+                    #(#conversions)*
+                    #body
+                }) as i32
+            }
+        }
+    })
+}
 
-    input_fn.vis = Visibility::Public(Pub {
-        span: Span::call_site(),
-    });
+/// Exports every codec needs besides its interface: the last error and the free functions for what it hands out
+#[proc_macro]
+pub fn rehash_codec_runtime(_item: TokenStream) -> TokenStream {
+    TokenStream::from(quote! {
+        /// Message of the last export that failed on the calling thread, owned by the codec
+        #[unsafe(no_mangle)]
+        pub extern "C" fn rehash_last_error() -> *const std::ffi::c_char {
+            ::rehash_codec_ffi::error::last_error_ptr()
+        }
 
-    input_fn.sig.abi = Some(Abi {
-        extern_token: <syn::token::Extern>::default(),
-        name: Some(LitStr::new("C", Span::call_site())),
-    });
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn rehash_free_string(ptr: *mut std::ffi::c_char) {
+            unsafe { ::rehash_codec_ffi::marshal::free_string(ptr) }
+        }
 
-    TokenStream::from(quote!(#input_fn))
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn rehash_free_buffer(buffer: ::rehash_codec_ffi::marshal::CodecBuffer) {
+            drop(unsafe { buffer.into_vec() })
+        }
+    })
 }
@@ -0,0 +1,342 @@
+use crate::clip::stream_start;
+use crate::import::AudioTranscoder;
+use crate::{expected_frames, init_ffmpeg, input_duration};
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::{codec, decoder, filter, frame, Rational};
+use rehash_codec_ffi::codec::{AudioAnalysis, AudioEvent, AudioEventKind};
+use rehash_codec_ffi::progress::CodecProgress;
+use std::f32::consts::PI;
+
+
+// Audio is analysed as mono in short windows, the whistle band sits well below the 8kHz this leaves
+const ANALYSIS_RATE: i32 = 16000;
+const WINDOW_SIZE: usize = 512;
+
+/// Referee whistles have their fundamental in this range
+const WHISTLE_BAND_HZ: (f32, f32) = (2000f32, 4500f32);
+/// Share of the window energy that has to sit in the whistle band
+const WHISTLE_BAND_SHARE: f32 = 0.3;
+/// Share of the band energy around its strongest frequency, crowd noise is spread over the band while a whistle is a tone
+const WHISTLE_TONALITY: f32 = 0.35;
+const WHISTLE_MIN_DB: f32 = -50f32;
+const MIN_WHISTLE_SECONDS: f64 = 0.15;
+/// Blasts closer together than this are the same whistle
+const WHISTLE_GAP_SECONDS: f64 = 0.25;
+
+/// Windows per loudness block, roughly a quarter of a second
+const LEVEL_BLOCK_WINDOWS: usize = 8;
+const SILENCE_DB: f64 = -45f64;
+const MIN_SILENCE_SECONDS: f64 = 0.5;
+/// Loudness is compared over this many blocks either side of a point
+const TRANSITION_BLOCKS: usize = 8;
+const LOUDNESS_CHANGE_DB: f64 = 6f64;
+
+
+struct AnalysisWindow {
+    time: f64,
+    energy: f64,
+    tonality: Option<f32>,
+}
+
+
+struct WindowAnalyser {
+    hann: Vec<f32>,
+    pending: Vec<f32>,
+    pending_start: Option<f64>,
+    windows: Vec<AnalysisWindow>,
+}
+
+
+impl WindowAnalyser {
+    fn new() -> Self {
+        Self {
+            hann: (0..WINDOW_SIZE).map(|i| 0.5 - 0.5 * (2f32 * PI * i as f32 / WINDOW_SIZE as f32).cos()).collect(),
+            pending: Vec::with_capacity(WINDOW_SIZE * 2),
+            pending_start: None,
+            windows: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, time: f64, samples: &[f32]) {
+        let start = *self.pending_start.get_or_insert(time);
+        self.pending.extend_from_slice(samples);
+
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= WINDOW_SIZE {
+            let window_time = start + consumed as f64 / ANALYSIS_RATE as f64;
+            let window = self.analyse_window(window_time, consumed);
+            self.windows.push(window);
+            consumed += WINDOW_SIZE;
+        }
+
+        self.pending.drain(..consumed);
+        self.pending_start = Some(start + consumed as f64 / ANALYSIS_RATE as f64);
+    }
+
+    fn analyse_window(&self, time: f64, offset: usize) -> AnalysisWindow {
+        let samples = &self.pending[offset..offset + WINDOW_SIZE];
+        let energy = samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / WINDOW_SIZE as f64;
+
+        let mut re: Vec<f32> = samples.iter().zip(self.hann.iter()).map(|(s, w)| s * w).collect();
+        let mut im = vec![0f32; WINDOW_SIZE];
+        fft(&mut re, &mut im);
+
+        let power: Vec<f32> = (0..WINDOW_SIZE / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+        let total: f32 = power[1..].iter().sum();
+
+        let bin = |hz: f32| (hz * WINDOW_SIZE as f32 / ANALYSIS_RATE as f32).round() as usize;
+        let (low, high) = (bin(WHISTLE_BAND_HZ.0), bin(WHISTLE_BAND_HZ.1));
+        let band: f32 = power[low..=high].iter().sum();
+
+        let tonality = if total > 0f32 && band / total >= WHISTLE_BAND_SHARE && to_db(energy) as f32 >= WHISTLE_MIN_DB {
+            let peak = (low..=high).max_by(|a, b| power[*a].total_cmp(&power[*b])).unwrap_or(low);
+            let around_peak: f32 = power[peak - 1..=peak + 1].iter().sum();
+            Some(around_peak / band).filter(|tonality| *tonality >= WHISTLE_TONALITY)
+        } else {
+            None
+        };
+
+        AnalysisWindow { time, energy, tonality }
+    }
+}
+
+
+// In-place radix-2 FFT, `re` and `im` have to be the same power of two length
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2f32 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (w_re, w_im) = ((angle * k as f32).cos(), (angle * k as f32).sin());
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+fn to_db(energy: f64) -> f64 {
+    10f64 * (energy + 1e-10).log10()
+}
+
+
+fn audio_filter(decoder: &decoder::Audio, time_base: Rational) -> Result<filter::Graph, ffmpeg_next::Error> {
+    let mut graph = filter::Graph::new();
+
+    let args = format!(
+        "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+        time_base,
+        decoder.rate(),
+        decoder.format().name(),
+        AudioTranscoder::input_layout(decoder).bits()
+    );
+
+    graph.add(&filter::find("abuffer").ok_or(ffmpeg_next::Error::FilterNotFound)?, "in", &args)?;
+    graph.add(&filter::find("abuffersink").ok_or(ffmpeg_next::Error::FilterNotFound)?, "out", "")?;
+
+    graph.output("in", 0)?.input("out", 0)?.parse(&format!("aresample={},aformat=sample_fmts=fltp:channel_layouts=mono", ANALYSIS_RATE))?;
+    graph.validate()?;
+    Ok(graph)
+}
+
+fn receive_samples(graph: &mut filter::Graph, analyser: &mut WindowAnalyser, start_seconds: f64) {
+    let mut filtered = frame::Audio::empty();
+    while graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+        // aresample hands out timestamps in 1/sample_rate
+        let time = filtered.pts().map(|pts| pts as f64 / ANALYSIS_RATE as f64 - start_seconds).unwrap_or_default();
+        analyser.push(time, filtered.plane::<f32>(0));
+    }
+}
+
+fn analyse(path: &str, progress: &CodecProgress) -> Result<(Vec<AnalysisWindow>, f64), String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let duration = input_duration(&input);
+
+    let stream = input.streams().best(MediaType::Audio).ok_or("File has no audio stream")?;
+    let stream_index = stream.index();
+    let total = expected_frames(&stream, duration);
+    let start_time = stream_start(stream.start_time());
+    let start_seconds = start_time as f64 * f64::from(stream.time_base());
+
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())
+        .and_then(|context| context.decoder().audio())
+        .map_err(|e| format!("Failed to open audio decoder: {}", e))?;
+    let mut graph = audio_filter(&decoder, stream.time_base()).map_err(|e| format!("Failed to create audio filter: {}", e))?;
+
+    let mut analyser = WindowAnalyser::new();
+    let mut decoded = frame::Audio::empty();
+    let mut processed = 0u64;
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet).map_err(|e| format!("Failed to decode audio: {}", e))?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            graph.get("in").unwrap().source().add(&decoded).map_err(|e| format!("Failed to filter audio: {}", e))?;
+            receive_samples(&mut graph, &mut analyser, start_seconds);
+            processed += 1;
+            progress.report(processed, total)?;
+        }
+    }
+
+    let _ = decoder.send_eof();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        graph.get("in").unwrap().source().add(&decoded).map_err(|e| format!("Failed to filter audio: {}", e))?;
+    }
+    let _ = graph.get("in").unwrap().source().flush();
+    receive_samples(&mut graph, &mut analyser, start_seconds);
+
+    Ok((analyser.windows, duration))
+}
+
+
+fn window_seconds() -> f64 {
+    WINDOW_SIZE as f64 / ANALYSIS_RATE as f64
+}
+
+fn push_whistle(whistles: &mut Vec<AudioEvent>, start: f64, end: f64, tonality: &[f32]) {
+    if end - start >= MIN_WHISTLE_SECONDS {
+        let strength = tonality.iter().sum::<f32>() as f64 / tonality.len().max(1) as f64;
+        whistles.push(AudioEvent { start, end, kind: AudioEventKind::Whistle, strength });
+    }
+}
+
+fn find_whistles(windows: &[AnalysisWindow]) -> Vec<AudioEvent> {
+    let mut whistles = Vec::new();
+    // start, end and the tonality of every window in between
+    let mut current: Option<(f64, f64, Vec<f32>)> = None;
+
+    for window in windows {
+        let Some(tonality) = window.tonality else {
+            continue;
+        };
+        let end = window.time + window_seconds();
+
+        match current.as_mut() {
+            Some((_, current_end, values)) if window.time - *current_end <= WHISTLE_GAP_SECONDS => {
+                *current_end = end;
+                values.push(tonality);
+            }
+            _ => {
+                if let Some((start, end, values)) = current.replace((window.time, end, vec![tonality])) {
+                    push_whistle(&mut whistles, start, end, &values);
+                }
+            }
+        }
+    }
+
+    if let Some((start, end, values)) = current {
+        push_whistle(&mut whistles, start, end, &values);
+    }
+    whistles
+}
+
+// (start time, mean energy) of every loudness block
+fn level_blocks(windows: &[AnalysisWindow]) -> Vec<(f64, f64)> {
+    windows
+        .chunks(LEVEL_BLOCK_WINDOWS)
+        .map(|block| (block[0].time, block.iter().map(|w| w.energy).sum::<f64>() / block.len() as f64))
+        .collect()
+}
+
+fn find_silences(blocks: &[(f64, f64)]) -> Vec<AudioEvent> {
+    let block_seconds = window_seconds() * LEVEL_BLOCK_WINDOWS as f64;
+    let mut silences = Vec::new();
+    let mut silent_since: Option<f64> = None;
+
+    for (i, (time, energy)) in blocks.iter().enumerate() {
+        let silent = to_db(*energy) < SILENCE_DB;
+        if silent {
+            silent_since.get_or_insert(*time);
+        }
+
+        if !silent || i + 1 == blocks.len() {
+            let end = if silent { time + block_seconds } else { *time };
+            if let Some(start) = silent_since.take().filter(|start| end - start >= MIN_SILENCE_SECONDS) {
+                silences.push(AudioEvent { start, end, kind: AudioEventKind::Silence, strength: 1f64 });
+            }
+        }
+    }
+    silences
+}
+
+// A transition is where the loudness after a point differs most from the loudness before it
+fn find_transitions(blocks: &[(f64, f64)]) -> Vec<AudioEvent> {
+    if blocks.len() < TRANSITION_BLOCKS * 2 {
+        return Vec::new();
+    }
+
+    let mean_db = |range: &[(f64, f64)]| to_db(range.iter().map(|(_, energy)| energy).sum::<f64>() / range.len() as f64);
+    let changes: Vec<f64> = (TRANSITION_BLOCKS..=blocks.len() - TRANSITION_BLOCKS)
+        .map(|i| mean_db(&blocks[i..i + TRANSITION_BLOCKS]) - mean_db(&blocks[i - TRANSITION_BLOCKS..i]))
+        .collect();
+
+    let mut transitions = Vec::new();
+    for (i, change) in changes.iter().enumerate() {
+        if change.abs() < LOUDNESS_CHANGE_DB {
+            continue;
+        }
+
+        let neighbours = &changes[i.saturating_sub(TRANSITION_BLOCKS)..(i + TRANSITION_BLOCKS + 1).min(changes.len())];
+        if neighbours.iter().any(|other| other.abs() > change.abs()) {
+            continue;
+        }
+        // equal neighbours on a plateau only count once
+        if transitions.last().is_some_and(|(last, _): &(usize, f64)| i - last <= TRANSITION_BLOCKS) {
+            continue;
+        }
+        transitions.push((i, *change));
+    }
+
+    transitions
+        .into_iter()
+        .map(|(i, change)| {
+            let time = blocks[i + TRANSITION_BLOCKS].0;
+            let kind = if change > 0f64 { AudioEventKind::LoudnessRise } else { AudioEventKind::LoudnessDrop };
+            AudioEvent { start: time, end: time, kind, strength: change.abs() }
+        })
+        .collect()
+}
+
+
+/// Decodes the audio track for whistle blasts, silences and changes in crowd loudness, ordered by start time
+pub(crate) fn detect_audio_events(path: &str, progress: &CodecProgress) -> Result<AudioAnalysis, String> {
+    let (windows, duration) = analyse(path, progress)?;
+    let blocks = level_blocks(&windows);
+
+    let mut events = find_whistles(&windows);
+    events.extend(find_silences(&blocks));
+    events.extend(find_transitions(&blocks));
+    events.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    println!("Found {} audio events in {}", events.len(), path);
+    Ok(AudioAnalysis {
+        file_path: path.to_string(),
+        duration,
+        events,
+    })
+}
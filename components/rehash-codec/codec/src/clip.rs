@@ -0,0 +1,240 @@
+use crate::import::{can_copy_video, copy_stream, AudioTranscoder, StreamPlan, VideoTranscoder, COPYABLE_AUDIO_CODECS};
+use crate::{frames_in, init_ffmpeg};
+use crate::keyframes::scan_keyframes;
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::{rescale, Dictionary, Rational, Rescale};
+use rehash_codec_ffi::codec::{ClipExport, ClipMode};
+use rehash_codec_ffi::progress::CodecProgress;
+use std::fs;
+use std::path::Path;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Head,
+    Copy,
+    Tail,
+    Done,
+}
+
+
+pub(crate) fn seconds_to_timestamp(seconds: f64, start_time: i64, time_base: Rational) -> i64 {
+    start_time + (seconds / f64::from(time_base)).round() as i64
+}
+
+pub(crate) fn stream_start(start_time: i64) -> i64 {
+    if start_time == ffmpeg_next::ffi::AV_NOPTS_VALUE { 0 } else { start_time }
+}
+
+fn run_export(path: &str, output_path: &str, start: f64, end: f64, progress: &CodecProgress) -> Result<ClipMode, String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let (video_index, video_time_base) = (video.index(), video.time_base());
+    let video_start = stream_start(video.start_time());
+    let copyable = can_copy_video(&video);
+    let total = frames_in(&video, end - start);
+
+    let start_pts = seconds_to_timestamp(start, video_start, video_time_base);
+    let mut end_pts = seconds_to_timestamp(end, video_start, video_time_base);
+
+    let (keyframes, last_pts) = scan_keyframes(&mut input, video_index, CodecProgress::none())?;
+    if let Some(last_pts) = last_pts.filter(|last| end_pts > *last) {
+        end_pts = last_pts + 1;
+    }
+
+    // Whole GOPs between the first keyframe at or after the start and the last one before the end are copied,
+    // the partial GOPs either side of them are re-encoded so the cut lands on the exact frame
+    let (copy_from, copy_to) = match keyframes.iter().find(|k| k.pts >= start_pts) {
+        Some(first) if copyable && first.pts < end_pts => {
+            let last = if last_pts.is_some_and(|last| end_pts > last) {
+                end_pts
+            } else {
+                keyframes.iter().rev().find(|k| k.pts <= end_pts).map_or(first.pts, |k| k.pts)
+            };
+            (first.pts, last)
+        }
+        _ => (end_pts, end_pts),
+    };
+
+    // Copied packets keep their b-frame decode delay, encoded ones are shifted by the same amount to keep dts increasing
+    let reorder_delay = keyframes.iter()
+        .find(|k| k.pts == copy_from)
+        .map_or(0, |k| (k.pts - k.dts).max(0));
+
+    let partial_path = format!("{}.part", output_path);
+    let mut octx = ffmpeg_next::format::output_as(&partial_path, "mp4")
+        .map_err(|e| format!("Failed to create {}: {}", partial_path, e))?;
+
+    let video = input.stream(video_index).ok_or("Video stream disappeared")?;
+    let (mut head, mut tail, video_output) = if copyable {
+        let output_index = copy_stream(&video, &mut octx)?;
+        let head = (start_pts < copy_from)
+            .then(|| VideoTranscoder::splice(&video, output_index, reorder_delay).map(|t| t.with_window(start_pts..copy_from, start_pts)))
+            .transpose()?;
+        let tail = (copy_to < end_pts)
+            .then(|| VideoTranscoder::splice(&video, output_index, reorder_delay).map(|t| t.with_window(copy_to..end_pts, start_pts)))
+            .transpose()?;
+        (head, tail, output_index)
+    } else {
+        let head = VideoTranscoder::new(&video, &mut octx)?.with_window(start_pts..end_pts, start_pts);
+        let output_index = head.output_index();
+        (Some(head), None, output_index)
+    };
+
+    let mode = match (copyable, head.is_some() || tail.is_some()) {
+        (false, _) => ClipMode::Reencode,
+        (true, false) => ClipMode::Copy,
+        (true, true) => ClipMode::SmartCut,
+    };
+
+    let audio = input.streams().best(MediaType::Audio).map(|audio| {
+        let audio_start = stream_start(audio.start_time());
+        let window = seconds_to_timestamp(start, audio_start, audio.time_base())..seconds_to_timestamp(end, audio_start, audio.time_base());
+        (audio.index(), audio.time_base(), window)
+    });
+    let mut audio_plan = match &audio {
+        Some((index, _, _)) => {
+            let stream = input.stream(*index).ok_or("Audio stream disappeared")?;
+            Some(if COPYABLE_AUDIO_CODECS.contains(&stream.parameters().id()) {
+                StreamPlan::Copy(copy_stream(&stream, &mut octx)?)
+            } else {
+                StreamPlan::Audio(AudioTranscoder::new(&stream, &mut octx)?)
+            })
+        }
+        None => None,
+    };
+    let mut audio_done = audio.is_none();
+
+    let mut options = Dictionary::new();
+    options.set("movflags", "+faststart");
+    octx.write_header_with(options).map_err(|e| format!("Failed to write header: {}", e))?;
+
+    let position = start_pts.rescale(video_time_base, rescale::TIME_BASE);
+    input.seek(position, ..position).map_err(|e| format!("Failed to seek: {}", e))?;
+
+    let mut phase = if head.is_some() { Phase::Head } else { Phase::Copy };
+    let mut processed = 0u64;
+    for (stream, mut packet) in input.packets() {
+        if stream.index() == video_index && phase != Phase::Done {
+            let Some(pts) = packet.pts().or(packet.dts()) else {
+                continue;
+            };
+            if pts >= start_pts {
+                processed += 1;
+                progress.report(processed, total)?;
+            }
+
+            if phase == Phase::Head && packet.is_key() && pts >= copy_from {
+                if let Some(mut head) = head.take() {
+                    head.finish(&mut octx)?;
+                }
+                phase = Phase::Copy;
+            }
+            if phase == Phase::Copy && packet.is_key() && pts >= copy_to {
+                phase = if tail.is_some() { Phase::Tail } else { Phase::Done };
+            }
+
+            match phase {
+                Phase::Head | Phase::Tail => {
+                    let transcoder = if phase == Phase::Head { &mut head } else { &mut tail };
+                    let Some(active) = transcoder.as_mut() else {
+                        continue;
+                    };
+
+                    active.send_packet(&packet, &mut octx)?;
+                    if active.is_past_window() {
+                        if let Some(mut finished) = transcoder.take() {
+                            finished.finish(&mut octx)?;
+                        }
+                        phase = if phase == Phase::Head { Phase::Copy } else { Phase::Done };
+                    }
+                }
+                // leading frames of an open GOP reference the previous one and were already re-encoded in the head
+                Phase::Copy if pts >= copy_from && pts < copy_to => {
+                    let output_time_base = octx.stream(video_output).map(|s| s.time_base()).ok_or("Missing output stream")?;
+                    packet.set_pts(Some(pts - start_pts));
+                    packet.set_dts(packet.dts().map(|dts| dts - start_pts));
+                    packet.rescale_ts(video_time_base, output_time_base);
+                    packet.set_position(-1);
+                    packet.set_stream(video_output);
+                    packet.write_interleaved(&mut octx).map_err(|e| format!("Failed to write packet: {}", e))?;
+                }
+                _ => {}
+            }
+        } else if let (Some((audio_index, audio_time_base, window)), Some(plan)) = (&audio, audio_plan.as_mut()) {
+            if stream.index() != *audio_index || audio_done {
+                continue;
+            }
+
+            let Some(pts) = packet.pts() else {
+                continue;
+            };
+            if pts >= window.end {
+                audio_done = true;
+                continue;
+            }
+            if pts < window.start {
+                continue;
+            }
+
+            packet.set_pts(Some(pts - window.start));
+            packet.set_dts(packet.dts().map(|dts| dts - window.start));
+
+            match plan {
+                StreamPlan::Copy(output_index) => {
+                    let output_time_base = octx.stream(*output_index).map(|s| s.time_base()).ok_or("Missing output stream")?;
+                    packet.rescale_ts(*audio_time_base, output_time_base);
+                    packet.set_position(-1);
+                    packet.set_stream(*output_index);
+                    packet.write_interleaved(&mut octx).map_err(|e| format!("Failed to write packet: {}", e))?;
+                }
+                StreamPlan::Audio(transcoder) => transcoder.send_packet(&packet, &mut octx)?,
+                StreamPlan::Video(_) => {}
+            }
+        }
+
+        if phase == Phase::Done && audio_done {
+            break;
+        }
+    }
+
+    for transcoder in [head.as_mut(), tail.as_mut()].into_iter().flatten() {
+        transcoder.finish(&mut octx)?;
+    }
+    if let Some(StreamPlan::Audio(transcoder)) = audio_plan.as_mut() {
+        transcoder.finish(&mut octx)?;
+    }
+
+    octx.write_trailer().map_err(|e| format!("Failed to write trailer: {}", e))?;
+    drop(octx);
+
+    fs::rename(&partial_path, output_path).map_err(|e| format!("Failed to move {} into place: {}", partial_path, e))?;
+    Ok(mode)
+}
+
+
+/// Cuts `start..end` seconds of `path` into an MP4 at `output_path`, starting on the exact frame at `start`
+pub(crate) fn export_clip(path: &str, output_path: &str, start: f64, end: f64, progress: &CodecProgress) -> Result<ClipExport, String> {
+    if !(start >= 0f64 && end > start) {
+        return Err(format!("Invalid clip range {}..{}", start, end));
+    }
+
+    if let Some(parent) = Path::new(output_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create clip directory: {}", e))?;
+    }
+
+    let mode = run_export(path, output_path, start, end, progress).inspect_err(|_| {
+        let _ = fs::remove_file(format!("{}.part", output_path));
+    })?;
+    println!("Exported {}..{} of {} as {:?}", start, end, path, mode);
+
+    Ok(ClipExport {
+        file_path: path.to_string(),
+        output_path: output_path.to_string(),
+        start,
+        end,
+        mode,
+    })
+}
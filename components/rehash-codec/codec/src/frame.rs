@@ -0,0 +1,243 @@
+use crate::clip::stream_start;
+use crate::init_ffmpeg;
+use crate::stream::stream_meta;
+use ffmpeg_next::format::context::Input;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::software::scaling::{self, Flags};
+use ffmpeg_next::{codec, decoder, frame, rescale, Rational, Rescale};
+use rehash_codec_ffi::codec::VideoFrame;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+
+static DECODERS: LazyLock<Mutex<HashMap<u64, FrameDecoder>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Targets further ahead than this are reached by seeking rather than decoding forward
+const MAX_FORWARD_DECODE_SECONDS: f64 = 2.0;
+
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FrameTarget {
+    Seconds(f64),
+    Index(u64),
+}
+
+
+pub(crate) struct FrameDecoder {
+    input: Input,
+    decoder: decoder::Video,
+    stream_index: usize,
+    time_base: Rational,
+    start_time: i64,
+    frame_rate: f64,
+    scaler: Option<scaling::Context>,
+    last_frame: Option<VideoFrame>,
+    last_timestamp: Option<i64>,
+}
+
+// The ffmpeg contexts are only ever used while the DECODERS lock is held
+unsafe impl Send for FrameDecoder {}
+
+
+impl FrameDecoder {
+    pub(crate) fn open(path: &str) -> Result<Self, String> {
+        init_ffmpeg();
+        let input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+        let stream = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+
+        let stream_index = stream.index();
+        let time_base = stream.time_base();
+        let start_time = stream_start(stream.start_time());
+        let frame_rate = if stream.avg_frame_rate().denominator() != 0 {
+            f64::from(stream.avg_frame_rate())
+        } else {
+            f64::from(stream.rate())
+        };
+
+        let context = codec::context::Context::from_parameters(stream.parameters())
+            .map_err(|e| format!("Failed to create decoder context: {}", e))?;
+        let decoder = context.decoder().video().map_err(|e| format!("Failed to open video decoder: {}", e))?;
+
+        Ok(Self {
+            input,
+            decoder,
+            stream_index,
+            time_base,
+            start_time,
+            frame_rate,
+            scaler: None,
+            last_frame: None,
+            last_timestamp: None,
+        })
+    }
+
+    fn seconds_to_timestamp(&self, seconds: f64) -> i64 {
+        self.start_time + (seconds / f64::from(self.time_base)).round() as i64
+    }
+
+    fn timestamp_to_seconds(&self, timestamp: i64) -> f64 {
+        (timestamp - self.start_time) as f64 * f64::from(self.time_base)
+    }
+
+    fn target_timestamp(&self, target: FrameTarget) -> i64 {
+        match target {
+            FrameTarget::Seconds(seconds) => self.seconds_to_timestamp(seconds.max(0f64)),
+            FrameTarget::Index(index) if self.frame_rate > 0f64 => self.seconds_to_timestamp(index as f64 / self.frame_rate),
+            FrameTarget::Index(_) => self.start_time,
+        }
+    }
+
+    fn seek(&mut self, timestamp: i64) -> Result<(), String> {
+        let position = timestamp.rescale(self.time_base, rescale::TIME_BASE);
+        self.input.seek(position, ..position).map_err(|e| format!("Failed to seek: {}", e))?;
+        self.decoder.flush();
+        self.last_frame = None;
+        self.last_timestamp = None;
+        Ok(())
+    }
+
+    pub(crate) fn decode_at(&mut self, target: FrameTarget) -> Result<VideoFrame, String> {
+        let target_ts = self.target_timestamp(target);
+
+        if let (Some(last_ts), Some(last_frame)) = (self.last_timestamp, &self.last_frame) {
+            if last_ts == target_ts {
+                return Ok(last_frame.clone());
+            }
+        }
+
+        let max_forward = self.seconds_to_timestamp(MAX_FORWARD_DECODE_SECONDS) - self.start_time;
+        let can_decode_forward = match self.last_timestamp {
+            Some(last_ts) => last_ts < target_ts && target_ts - last_ts <= max_forward,
+            None => false,
+        };
+
+        if !can_decode_forward {
+            self.seek(target_ts)?;
+        }
+
+        // Accept frames within half a frame of the target to absorb timestamp rounding
+        let tolerance = if self.frame_rate > 0f64 {
+            self.seconds_to_timestamp(0.5 / self.frame_rate) - self.start_time
+        } else {
+            0
+        };
+
+        let mut decoded = frame::Video::empty();
+        let mut last_decoded = frame::Video::empty();
+        let mut has_decoded = false;
+
+        let mut found = None;
+
+        'packets: for (stream, packet) in self.input.packets() {
+            if stream.index() != self.stream_index {
+                continue;
+            }
+
+            if self.decoder.send_packet(&packet).is_err() {
+                continue;
+            }
+
+            while self.decoder.receive_frame(&mut decoded).is_ok() {
+                let timestamp = decoded.timestamp().unwrap_or(target_ts);
+                if timestamp + tolerance >= target_ts {
+                    found = Some(timestamp);
+                    break 'packets;
+                }
+                std::mem::swap(&mut decoded, &mut last_decoded);
+                has_decoded = true;
+            }
+        }
+
+        if let Some(timestamp) = found {
+            return self.finish_frame(&decoded, timestamp);
+        }
+
+        // Target lies beyond the last packet, drain the decoder and return the final frame
+        let _ = self.decoder.send_eof();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp().unwrap_or(target_ts);
+            if timestamp + tolerance >= target_ts {
+                let frame = self.finish_frame(&decoded, timestamp);
+                self.last_timestamp = None;
+                return frame;
+            }
+            std::mem::swap(&mut decoded, &mut last_decoded);
+            has_decoded = true;
+        }
+
+        if !has_decoded {
+            return Err("No frame could be decoded at the requested position".to_string());
+        }
+
+        let timestamp = last_decoded.timestamp().unwrap_or(target_ts);
+        let frame = self.finish_frame(&last_decoded, timestamp);
+        // decoder has been drained, force a seek on the next request
+        self.last_timestamp = None;
+        frame
+    }
+
+    fn finish_frame(&mut self, decoded: &frame::Video, timestamp: i64) -> Result<VideoFrame, String> {
+        let frame = self.to_rgba(decoded, timestamp)?;
+        self.last_timestamp = Some(timestamp);
+        self.last_frame = Some(frame.clone());
+        Ok(frame)
+    }
+
+    fn to_rgba(&mut self, decoded: &frame::Video, timestamp: i64) -> Result<VideoFrame, String> {
+        let width = decoded.width();
+        let height = decoded.height();
+
+        let reuse_scaler = match &self.scaler {
+            Some(scaler) => {
+                let input = scaler.input();
+                input.format == decoded.format() && input.width == width && input.height == height
+            }
+            None => false,
+        };
+
+        if !reuse_scaler {
+            let scaler = scaling::Context::get(decoded.format(), width, height, Pixel::RGBA, width, height, Flags::BILINEAR)
+                .map_err(|e| format!("Failed to create scaler: {}", e))?;
+            self.scaler = Some(scaler);
+        }
+
+        let mut rgba_frame = frame::Video::empty();
+        self.scaler.as_mut().unwrap().run(decoded, &mut rgba_frame)
+            .map_err(|e| format!("Failed to convert frame to RGBA: {}", e))?;
+
+        let row_size = width as usize * 4;
+        let stride = rgba_frame.stride(0);
+        let data = rgba_frame.data(0);
+        let mut rgba = Vec::with_capacity(row_size * height as usize);
+        for row in 0..height as usize {
+            let offset = row * stride;
+            rgba.extend_from_slice(&data[offset..offset + row_size]);
+        }
+
+        let seconds = self.timestamp_to_seconds(timestamp);
+        Ok(VideoFrame {
+            width,
+            height,
+            timestamp: seconds,
+            frame_index: (seconds * self.frame_rate).round().max(0f64) as u64,
+            rgba,
+        })
+    }
+}
+
+
+pub(crate) fn decode_frame(handle: u64, target: FrameTarget) -> Result<VideoFrame, String> {
+    let mut decoders = DECODERS.lock().unwrap();
+
+    if !decoders.contains_key(&handle) {
+        let meta = stream_meta(handle)?;
+        decoders.insert(handle, FrameDecoder::open(&meta.file_path)?);
+    }
+
+    decoders.get_mut(&handle).unwrap().decode_at(target)
+}
+
+pub(crate) fn close_frame_decoder(handle: u64) {
+    DECODERS.lock().unwrap().remove(&handle);
+}
@@ -0,0 +1,637 @@
+use crate::clip::stream_start;
+use crate::{init_ffmpeg, input_duration};
+use ffmpeg_next::channel_layout::ChannelLayout;
+use ffmpeg_next::ffi::AVPixelFormat;
+use ffmpeg_next::format::context::{Input, Output};
+use ffmpeg_next::format::stream::Stream;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::software::scaling::{self, Flags};
+use ffmpeg_next::{codec, decoder, encoder, filter, frame, picture, Dictionary, Packet, Rational};
+use rehash_codec_ffi::codec::{ImportMode, JobState, ImportStatus};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+
+static IMPORTS: LazyLock<Mutex<HashMap<u64, ImportStatus>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) const COPYABLE_VIDEO_CODECS: [codec::Id; 1] = [codec::Id::H264];
+pub(crate) const COPYABLE_AUDIO_CODECS: [codec::Id; 2] = [codec::Id::AAC, codec::Id::MP3];
+
+const AUDIO_BIT_RATE: usize = 192_000;
+/// Proxies get a keyframe every second so seeking in them never decodes far
+const PROXY_GOP_SECONDS: f64 = 1.0;
+
+
+fn update_status(job: u64, update: impl FnOnce(&mut ImportStatus)) {
+    if let Some(status) = IMPORTS.lock().unwrap().get_mut(&job) {
+        update(status);
+    }
+}
+
+pub(crate) fn copy_stream(input: &Stream, octx: &mut Output) -> Result<usize, String> {
+    let mut output = octx.add_stream(encoder::find(codec::Id::None)).map_err(|e| format!("Failed to add stream: {}", e))?;
+    output.set_parameters(input.parameters());
+    // the source container's codec tag is rarely valid in mp4
+    unsafe {
+        (*output.parameters().as_mut_ptr()).codec_tag = 0;
+    }
+    Ok(output.index())
+}
+
+pub(crate) fn can_copy_video(stream: &Stream) -> bool {
+    let parameters = stream.parameters();
+    let format = unsafe { (*parameters.as_ptr()).format };
+
+    // 10 bit and 4:2:2 h264 from prosumer cameras is not decodable by the webview
+    COPYABLE_VIDEO_CODECS.contains(&parameters.id())
+        && (format == AVPixelFormat::AV_PIX_FMT_YUV420P as i32 || format == AVPixelFormat::AV_PIX_FMT_YUVJ420P as i32)
+}
+
+
+// NAL length size from an avcC header, None when the stream carries annex b start codes instead
+fn nal_length_size(stream: &Stream) -> Option<usize> {
+    let parameters = stream.parameters();
+    let extradata = unsafe {
+        let raw = parameters.as_ptr();
+        if (*raw).extradata.is_null() || (*raw).extradata_size < 5 {
+            return None;
+        }
+        std::slice::from_raw_parts((*raw).extradata, (*raw).extradata_size as usize)
+    };
+
+    (extradata[0] == 1).then(|| usize::from(extradata[4] & 3) + 1)
+}
+
+// libx264 writes annex b without a global header, mp4 samples must be length prefixed like the copied ones
+fn annex_b_to_length_prefixed(packet: &Packet, length_size: usize) -> Packet {
+    let data = packet.data().unwrap_or_default();
+    let mut nal_units = Vec::new();
+    let mut nal_start = None;
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = nal_start {
+                // a four byte start code leaves its leading zero on the previous unit
+                let end = if i > 0 && data[i - 1] == 0 { i - 1 } else { i };
+                nal_units.push(&data[start..end]);
+            }
+            i += 3;
+            nal_start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = nal_start {
+        nal_units.push(&data[start..]);
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() + nal_units.len() * length_size);
+    for nal in nal_units {
+        bytes.extend_from_slice(&(nal.len() as u32).to_be_bytes()[4 - length_size..]);
+        bytes.extend_from_slice(nal);
+    }
+
+    let mut converted = Packet::copy(&bytes);
+    converted.set_pts(packet.pts());
+    converted.set_dts(packet.dts());
+    converted.set_duration(packet.duration());
+    converted.set_flags(packet.flags());
+    converted
+}
+
+pub(crate) struct VideoTranscoder {
+    decoder: decoder::Video,
+    encoder: encoder::Video,
+    scaler: Option<scaling::Context>,
+    time_base: Rational,
+    output_index: usize,
+    window: Range<i64>,
+    pts_offset: i64,
+    dts_offset: i64,
+    nal_length_size: Option<usize>,
+    past_window: bool,
+}
+
+
+impl VideoTranscoder {
+    pub(crate) fn new(input: &Stream, octx: &mut Output) -> Result<Self, String> {
+        let global_header = octx.format().flags().contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+        let h264 = encoder::find(codec::Id::H264).ok_or("H.264 encoder is not available")?;
+        let mut output = octx.add_stream(h264).map_err(|e| format!("Failed to add video stream: {}", e))?;
+
+        let transcoder = Self::open(input, output.index(), Some(Pixel::YUV420P), global_header, true, None)?;
+        output.set_parameters(&transcoder.encoder);
+        Ok(transcoder)
+    }
+
+    /// Encoder for a playback proxy scaled down to `height`, sources that are not taller keep their size
+    pub(crate) fn proxy(input: &Stream, octx: &mut Output, height: u32) -> Result<Self, String> {
+        let global_header = octx.format().flags().contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+        let h264 = encoder::find(codec::Id::H264).ok_or("H.264 encoder is not available")?;
+        let mut output = octx.add_stream(h264).map_err(|e| format!("Failed to add video stream: {}", e))?;
+
+        let transcoder = Self::open(input, output.index(), Some(Pixel::YUV420P), global_header, true, Some(height))?;
+        output.set_parameters(&transcoder.encoder);
+        Ok(transcoder)
+    }
+
+    /// Encoder for fragmented MP4 segments, without b-frames so every segment decodes from its own first frame
+    pub(crate) fn segment(input: &Stream, octx: &mut Output) -> Result<Self, String> {
+        let global_header = octx.format().flags().contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+        let h264 = encoder::find(codec::Id::H264).ok_or("H.264 encoder is not available")?;
+        let mut output = octx.add_stream(h264).map_err(|e| format!("Failed to add video stream: {}", e))?;
+
+        let transcoder = Self::open(input, output.index(), Some(Pixel::YUV420P), global_header, false, None)?;
+        output.set_parameters(&transcoder.encoder);
+        Ok(transcoder)
+    }
+
+    /// Encoder for the boundary GOPs of a stream whose other packets are copied into `output_index`.
+    /// The copied stream's avcC stays the stream header, so parameter sets are sent in-band and
+    /// b-frames are disabled so the encoded packets never need to decode ahead of the copied ones.
+    pub(crate) fn splice(input: &Stream, output_index: usize, dts_offset: i64) -> Result<Self, String> {
+        let mut transcoder = Self::open(input, output_index, None, false, false, None)?;
+        transcoder.dts_offset = dts_offset;
+        transcoder.nal_length_size = nal_length_size(input);
+        Ok(transcoder)
+    }
+
+    /// Only encodes frames presented within `window`, shifted back by `pts_offset`
+    pub(crate) fn with_window(mut self, window: Range<i64>, pts_offset: i64) -> Self {
+        self.window = window;
+        self.pts_offset = pts_offset;
+        self
+    }
+
+    pub(crate) fn output_index(&self) -> usize {
+        self.output_index
+    }
+
+    pub(crate) fn is_past_window(&self) -> bool {
+        self.past_window
+    }
+
+    fn open(input: &Stream, output_index: usize, format: Option<Pixel>, global_header: bool, b_frames: bool,
+            proxy_height: Option<u32>) -> Result<Self, String> {
+        let decoder = codec::context::Context::from_parameters(input.parameters())
+            .and_then(|context| context.decoder().video())
+            .map_err(|e| format!("Failed to open video decoder: {}", e))?;
+
+        let h264 = encoder::find(codec::Id::H264).ok_or("H.264 encoder is not available")?;
+        let mut context = codec::context::Context::new_with_codec(h264).encoder().video()
+            .map_err(|e| format!("Failed to create video encoder: {}", e))?;
+
+        let (width, height) = match proxy_height {
+            Some(height) if height < decoder.height() => {
+                let width = (decoder.width() as u64 * height as u64 / decoder.height().max(1) as u64) as u32;
+                (width, height)
+            }
+            _ => (decoder.width(), decoder.height()),
+        };
+        context.set_width(width & !1);
+        context.set_height(height & !1);
+        context.set_aspect_ratio(decoder.aspect_ratio());
+        context.set_format(format.unwrap_or(decoder.format()));
+        context.set_frame_rate(Some(input.avg_frame_rate()));
+        context.set_time_base(input.time_base());
+        if global_header {
+            context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        if !b_frames {
+            context.set_max_b_frames(0);
+        }
+        if proxy_height.is_some() {
+            let frame_rate = f64::from(input.avg_frame_rate());
+            let frame_rate = if frame_rate.is_finite() && frame_rate > 0f64 { frame_rate } else { 30f64 };
+            context.set_gop((frame_rate * PROXY_GOP_SECONDS).round() as u32);
+        }
+
+        let mut options = Dictionary::new();
+        options.set("preset", "veryfast");
+        options.set("crf", if proxy_height.is_some() { "23" } else { "20" });
+        let encoder = context.open_as_with(h264, options).map_err(|e| format!("Failed to open H.264 encoder: {}", e))?;
+
+        Ok(Self {
+            decoder,
+            encoder,
+            scaler: None,
+            time_base: input.time_base(),
+            output_index,
+            window: i64::MIN..i64::MAX,
+            pts_offset: 0,
+            dts_offset: 0,
+            nal_length_size: None,
+            past_window: false,
+        })
+    }
+
+    pub(crate) fn send_packet(&mut self, packet: &Packet, octx: &mut Output) -> Result<(), String> {
+        self.decoder.send_packet(packet).map_err(|e| format!("Failed to decode video: {}", e))?;
+        self.receive_frames(octx)
+    }
+
+    pub(crate) fn finish(&mut self, octx: &mut Output) -> Result<(), String> {
+        let _ = self.decoder.send_eof();
+        self.receive_frames(octx)?;
+        let _ = self.encoder.send_eof();
+        self.receive_packets(octx)
+    }
+
+    fn receive_frames(&mut self, octx: &mut Output) -> Result<(), String> {
+        let mut decoded = frame::Video::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            if let Some(timestamp) = timestamp {
+                if timestamp >= self.window.end {
+                    self.past_window = true;
+                    continue;
+                }
+                if timestamp < self.window.start {
+                    continue;
+                }
+            }
+
+            let mut converted = self.convert(&decoded)?;
+            converted.set_pts(timestamp.map(|timestamp| timestamp - self.pts_offset));
+            converted.set_kind(picture::Type::None);
+
+            self.encoder.send_frame(&converted).map_err(|e| format!("Failed to encode video: {}", e))?;
+            self.receive_packets(octx)?;
+        }
+        Ok(())
+    }
+
+    fn convert(&mut self, decoded: &frame::Video) -> Result<frame::Video, String> {
+        let (width, height, format) = (self.encoder.width(), self.encoder.height(), self.encoder.format());
+
+        let reuse_scaler = match &self.scaler {
+            Some(scaler) => {
+                let input = scaler.input();
+                input.format == decoded.format() && input.width == decoded.width() && input.height == decoded.height()
+            }
+            None => false,
+        };
+
+        if !reuse_scaler {
+            let scaler = scaling::Context::get(decoded.format(), decoded.width(), decoded.height(), format, width, height, Flags::BILINEAR)
+                .map_err(|e| format!("Failed to create scaler: {}", e))?;
+            self.scaler = Some(scaler);
+        }
+
+        let mut converted = frame::Video::empty();
+        self.scaler.as_mut().unwrap().run(decoded, &mut converted)
+            .map_err(|e| format!("Failed to convert frame: {}", e))?;
+        Ok(converted)
+    }
+
+    fn receive_packets(&mut self, octx: &mut Output) -> Result<(), String> {
+        let output_time_base = octx.stream(self.output_index).map(|s| s.time_base()).unwrap_or(self.time_base);
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            if let Some(length_size) = self.nal_length_size {
+                encoded = annex_b_to_length_prefixed(&encoded, length_size);
+            }
+
+            encoded.set_dts(encoded.dts().map(|dts| dts - self.dts_offset));
+            encoded.set_stream(self.output_index);
+            encoded.rescale_ts(self.time_base, output_time_base);
+            encoded.write_interleaved(octx).map_err(|e| format!("Failed to write video packet: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+
+pub(crate) struct AudioTranscoder {
+    decoder: decoder::Audio,
+    encoder: encoder::Audio,
+    filter: filter::Graph,
+    time_base: Rational,
+    output_index: usize,
+}
+
+
+impl AudioTranscoder {
+    pub(crate) fn new(input: &Stream, octx: &mut Output) -> Result<Self, String> {
+        let global_header = octx.format().flags().contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+        let decoder = codec::context::Context::from_parameters(input.parameters())
+            .and_then(|context| context.decoder().audio())
+            .map_err(|e| format!("Failed to open audio decoder: {}", e))?;
+
+        let aac = encoder::find(codec::Id::AAC)
+            .ok_or("AAC encoder is not available")?
+            .audio()
+            .map_err(|e| format!("Failed to find AAC encoder: {}", e))?;
+        let mut output = octx.add_stream(aac).map_err(|e| format!("Failed to add audio stream: {}", e))?;
+
+        let mut context = codec::context::Context::new_with_codec(*aac).encoder().audio()
+            .map_err(|e| format!("Failed to create audio encoder: {}", e))?;
+
+        let rate = decoder.rate() as i32;
+        let input_layout = Self::input_layout(&decoder);
+        let channel_layout = aac.channel_layouts()
+            .map(|layouts| layouts.best(input_layout.channels()))
+            .unwrap_or(ChannelLayout::STEREO);
+        let sample_format = aac.formats().and_then(|mut formats| formats.next()).ok_or("AAC encoder has no sample formats")?;
+
+        context.set_rate(rate);
+        context.set_channel_layout(channel_layout);
+        context.set_format(sample_format);
+        context.set_bit_rate(AUDIO_BIT_RATE);
+        context.set_time_base((1, rate));
+        if global_header {
+            context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        let encoder = context.open_as(aac).map_err(|e| format!("Failed to open AAC encoder: {}", e))?;
+        output.set_parameters(&encoder);
+        output.set_time_base((1, rate));
+
+        let filter = Self::filter(&decoder, input_layout, input.time_base(), &encoder)
+            .map_err(|e| format!("Failed to create audio filter: {}", e))?;
+
+        Ok(Self {
+            decoder,
+            encoder,
+            filter,
+            time_base: Rational(1, rate),
+            output_index: output.index(),
+        })
+    }
+
+    pub(crate) fn input_layout(decoder: &decoder::Audio) -> ChannelLayout {
+        let layout = decoder.channel_layout();
+        if layout.bits() == 0 { ChannelLayout::default(layout.channels().max(1)) } else { layout }
+    }
+
+    // Converts to the encoder's format and chunks into its frame size, with timestamps in 1/sample_rate
+    fn filter(decoder: &decoder::Audio, layout: ChannelLayout, time_base: Rational, encoder: &encoder::Audio) -> Result<filter::Graph, ffmpeg_next::Error> {
+        let mut graph = filter::Graph::new();
+
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            decoder.rate(),
+            decoder.format().name(),
+            layout.bits()
+        );
+
+        graph.add(&filter::find("abuffer").ok_or(ffmpeg_next::Error::FilterNotFound)?, "in", &args)?;
+        graph.add(&filter::find("abuffersink").ok_or(ffmpeg_next::Error::FilterNotFound)?, "out", "")?;
+
+        {
+            let mut out = graph.get("out").unwrap();
+            out.set_sample_format(encoder.format());
+            out.set_channel_layout(encoder.channel_layout());
+            out.set_sample_rate(encoder.rate());
+        }
+
+        graph.output("in", 0)?.input("out", 0)?.parse(&format!("asettb=1/{}", encoder.rate()))?;
+        graph.validate()?;
+
+        graph.get("out").unwrap().sink().set_frame_size(encoder.frame_size());
+        Ok(graph)
+    }
+
+    pub(crate) fn send_packet(&mut self, packet: &Packet, octx: &mut Output) -> Result<(), String> {
+        self.decoder.send_packet(packet).map_err(|e| format!("Failed to decode audio: {}", e))?;
+        self.receive_frames(octx)
+    }
+
+    pub(crate) fn finish(&mut self, octx: &mut Output) -> Result<(), String> {
+        let _ = self.decoder.send_eof();
+        self.receive_frames(octx)?;
+        let _ = self.filter.get("in").unwrap().source().flush();
+        self.receive_filtered(octx)?;
+        let _ = self.encoder.send_eof();
+        self.receive_packets(octx)
+    }
+
+    fn receive_frames(&mut self, octx: &mut Output) -> Result<(), String> {
+        let mut decoded = frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.filter.get("in").unwrap().source().add(&decoded).map_err(|e| format!("Failed to filter audio: {}", e))?;
+            self.receive_filtered(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_filtered(&mut self, octx: &mut Output) -> Result<(), String> {
+        let mut filtered = frame::Audio::empty();
+        while self.filter.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            self.encoder.send_frame(&filtered).map_err(|e| format!("Failed to encode audio: {}", e))?;
+            self.receive_packets(octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_packets(&mut self, octx: &mut Output) -> Result<(), String> {
+        let output_time_base = octx.stream(self.output_index).map(|s| s.time_base()).unwrap_or(self.time_base);
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.output_index);
+            encoded.rescale_ts(self.time_base, output_time_base);
+            encoded.write_interleaved(octx).map_err(|e| format!("Failed to write audio packet: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+
+pub(crate) enum StreamPlan {
+    Copy(usize),
+    Video(VideoTranscoder),
+    Audio(AudioTranscoder),
+}
+
+
+impl StreamPlan {
+    pub(crate) fn output_index(&self) -> usize {
+        match self {
+            StreamPlan::Copy(index) => *index,
+            StreamPlan::Video(transcoder) => transcoder.output_index,
+            StreamPlan::Audio(transcoder) => transcoder.output_index,
+        }
+    }
+
+    fn is_copy(&self) -> bool {
+        matches!(self, StreamPlan::Copy(_))
+    }
+}
+
+
+fn plan_streams(input: &Input, octx: &mut Output, proxy_height: Option<u32>) -> Result<HashMap<usize, StreamPlan>, String> {
+    let mut plans = HashMap::new();
+
+    // Only the main video and audio streams are kept, camera data tracks have no place in mp4
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let video_plan = match proxy_height {
+        Some(height) => StreamPlan::Video(VideoTranscoder::proxy(&video, octx, height)?),
+        None if can_copy_video(&video) => StreamPlan::Copy(copy_stream(&video, octx)?),
+        None => StreamPlan::Video(VideoTranscoder::new(&video, octx)?),
+    };
+    plans.insert(video.index(), video_plan);
+
+    if let Some(audio) = input.streams().best(MediaType::Audio) {
+        let audio_plan = if COPYABLE_AUDIO_CODECS.contains(&audio.parameters().id()) {
+            StreamPlan::Copy(copy_stream(&audio, octx)?)
+        } else {
+            StreamPlan::Audio(AudioTranscoder::new(&audio, octx)?)
+        };
+        plans.insert(audio.index(), audio_plan);
+    }
+
+    Ok(plans)
+}
+
+fn run_import(job: u64, path: &str, output_path: &str, proxy_height: Option<u32>) -> Result<(), String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+
+    let duration = input_duration(&input);
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let (video_index, video_time_base) = (video.index(), f64::from(video.time_base()));
+    let video_start = stream_start(video.start_time());
+
+    // written next to the destination and renamed at the end so a partial file is never picked up
+    let partial_path = format!("{}.part", output_path);
+    let mut octx = ffmpeg_next::format::output_as(&partial_path, "mp4")
+        .map_err(|e| format!("Failed to create {}: {}", partial_path, e))?;
+
+    let mut plans = plan_streams(&input, &mut octx, proxy_height)?;
+    let mode = match proxy_height {
+        Some(_) => ImportMode::Proxy,
+        None if plans.values().all(StreamPlan::is_copy) => ImportMode::Remux,
+        None => ImportMode::Transcode,
+    };
+    update_status(job, |status| status.mode = mode);
+    println!("Importing {} as {:?}", path, mode);
+
+    let mut options = Dictionary::new();
+    options.set("movflags", "+faststart");
+    octx.write_header_with(options).map_err(|e| format!("Failed to write header: {}", e))?;
+
+    let input_time_bases: HashMap<usize, Rational> = input.streams().map(|s| (s.index(), s.time_base())).collect();
+    let mut last_progress = 0f64;
+
+    for (stream, mut packet) in input.packets() {
+        let Some(plan) = plans.get_mut(&stream.index()) else {
+            continue;
+        };
+
+        if stream.index() == video_index && duration > 0f64 {
+            if let Some(timestamp) = packet.dts().or(packet.pts()) {
+                let progress = ((timestamp - video_start) as f64 * video_time_base / duration).clamp(0f64, 1f64);
+                if progress - last_progress >= 0.005 {
+                    last_progress = progress;
+                    update_status(job, |status| status.progress = progress);
+                }
+            }
+        }
+
+        match plan {
+            StreamPlan::Copy(output_index) => {
+                let output_time_base = octx.stream(*output_index).map(|s| s.time_base()).ok_or("Missing output stream")?;
+                packet.rescale_ts(input_time_bases[&stream.index()], output_time_base);
+                packet.set_position(-1);
+                packet.set_stream(*output_index);
+                packet.write_interleaved(&mut octx).map_err(|e| format!("Failed to write packet: {}", e))?;
+            }
+            StreamPlan::Video(transcoder) => transcoder.send_packet(&packet, &mut octx)?,
+            StreamPlan::Audio(transcoder) => transcoder.send_packet(&packet, &mut octx)?,
+        }
+    }
+
+    let mut plans: Vec<StreamPlan> = plans.into_values().collect();
+    plans.sort_by_key(StreamPlan::output_index);
+    for plan in plans.iter_mut() {
+        match plan {
+            StreamPlan::Copy(_) => {}
+            StreamPlan::Video(transcoder) => transcoder.finish(&mut octx)?,
+            StreamPlan::Audio(transcoder) => transcoder.finish(&mut octx)?,
+        }
+    }
+
+    octx.write_trailer().map_err(|e| format!("Failed to write trailer: {}", e))?;
+    drop(octx);
+
+    fs::rename(&partial_path, output_path).map_err(|e| format!("Failed to move {} into place: {}", partial_path, e))?;
+    Ok(())
+}
+
+
+/// Starts importing `path` into a faststart MP4 at `output_path` on a background thread
+pub(crate) fn start_import(path: &str, output_path: &str) -> Result<u64, String> {
+    start_job(path, output_path, None)
+}
+
+/// Starts writing a playback proxy of `path` scaled down to `height`, reported like an import
+pub(crate) fn start_proxy(path: &str, output_path: &str, height: u32) -> Result<u64, String> {
+    if height == 0 {
+        return Err(String::from("Proxy height must not be zero"));
+    }
+    start_job(path, output_path, Some(height))
+}
+
+fn start_job(path: &str, output_path: &str, proxy_height: Option<u32>) -> Result<u64, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("{} is not a file", path));
+    }
+
+    if let Some(parent) = Path::new(output_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create import directory: {}", e))?;
+    }
+
+    let job = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+    IMPORTS.lock().unwrap().insert(job, ImportStatus {
+        job,
+        file_path: path.to_string(),
+        output_path: output_path.to_string(),
+        mode: if proxy_height.is_some() { ImportMode::Proxy } else { ImportMode::Remux },
+        state: JobState::Running,
+        progress: 0f64,
+        error: None,
+    });
+
+    let (path, output_path) = (path.to_string(), output_path.to_string());
+    std::thread::spawn(move || {
+        let result = run_import(job, &path, &output_path, proxy_height);
+        if result.is_err() {
+            let _ = fs::remove_file(format!("{}.part", output_path));
+        }
+
+        update_status(job, |status| match result {
+            Ok(_) => {
+                status.state = JobState::Finished;
+                status.progress = 1f64;
+            }
+            Err(e) => {
+                println!("Import of {} failed: {}", path, e);
+                status.state = JobState::Failed;
+                status.error = Some(e);
+            }
+        });
+    });
+
+    Ok(job)
+}
+
+pub(crate) fn import_status(job: u64) -> Result<ImportStatus, String> {
+    IMPORTS.lock().unwrap()
+        .get(&job)
+        .cloned()
+        .ok_or(format!("Import job {} does not exist", job))
+}
+
+pub(crate) fn close_import(job: u64) -> bool {
+    IMPORTS.lock().unwrap().remove(&job).is_some()
+}
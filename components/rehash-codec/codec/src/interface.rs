@@ -1,67 +1,158 @@
-use rehash_codec_proc_macro::rehash_codec_ffi;
-use std::ffi::{c_char, c_uchar, CStr};
-use std::fs::File;
-use std::io::Read;
+use crate::audio::detect_audio_events as detect_audio_cues;
+use crate::clip::export_clip as export_clip_file;
+use crate::frame::{close_frame_decoder, decode_frame, FrameTarget};
+use crate::import::{close_import as close_import_job, import_status, start_import as start_import_job, start_proxy as start_proxy_job};
+use crate::keyframes::keyframe_index;
+use crate::plays::detect_plays as detect_play_segments;
+use crate::probe::probe_video_file;
+use crate::reel::{close_reel as close_reel_job, reel_status, start_reel as start_reel_job};
+use crate::segment::{close_segmented_stream as close_segments, open_segmented_stream as open_segments, read_init_segment as read_init, read_media_segment as read_media};
+use crate::thumbnail::generate_thumbnails as generate_thumbnail_files;
+use crate::stream::{close_stream, open_stream, read_stream_chunk, seek_stream, stream_meta};
+use rehash_codec_ffi::abi::{Capability, CodecAbi};
+use rehash_codec_ffi::codec::{AudioAnalysis, ClipExport, ImportStatus, KeyframeIndex, PlaySegmentation, ReelSegment, ReelStatus, SegmentedStream, ThumbnailIndex, VideoProbe, VideoStreamMeta};
+use rehash_codec_ffi::error::CodecError;
+use rehash_codec_ffi::progress::CodecProgress;
+use rehash_codec_proc_macro::{rehash_codec_ffi, rehash_codec_runtime};
+
+rehash_codec_runtime!();
+
+/// Read by the loader before anything else, so its shape never changes with the interface
+#[unsafe(no_mangle)]
+pub extern "C" fn rehash_codec_abi() -> CodecAbi {
+    CodecAbi::new(&Capability::ALL)
+}
 
 #[rehash_codec_ffi]
 fn print_codec_version() {
-    println!("Codec version: {}", env!("CARGO_PKG_VERSION"));
+    println!("Codec version: {}, {}", env!("CARGO_PKG_VERSION"), rehash_codec_abi());
+}
+
+#[rehash_codec_ffi]
+fn probe_video(path: &str) -> Result<VideoProbe, CodecError> {
+    probe_video_file(path).map_err(|e| CodecError::decode(format!("Failed to probe {}: {}", path, e)))
 }
 
+#[rehash_codec_ffi]
+fn open_video_stream(path: &str, chunk_size: usize) -> Result<VideoStreamMeta, CodecError> {
+    open_stream(path, chunk_size)
+}
 
-fn get_video_bytes(path: &str) -> Result<Vec<u8>, String> {
-    println!("Reading video file: {}", path);
-    let mut file = File::open(path).ok().ok_or("Failed to open video file")?;
-    let metadata = file.metadata().ok().ok_or("Failed to read metadata")?;
-    let mut buffer = Vec::with_capacity(metadata.len() as usize);
+#[rehash_codec_ffi]
+fn get_video_stream_meta(handle: u64) -> Result<VideoStreamMeta, CodecError> {
+    stream_meta(handle)
+}
 
-    match file.read_to_end(&mut buffer) {
-        Ok(size) => {
-            assert_eq!(size, metadata.len() as usize);
-            println!("Successfully read {} bytes", size);
-            Ok(buffer)
-        }
-        Err(e) => {
-            println!("Error reading file: {}", e);
-            Err(e.to_string())
-        }
-    }
+/// Next chunk of the stream, its position is what `get_video_stream_meta` reported before the read
+#[rehash_codec_ffi]
+fn read_video_stream_chunk(handle: u64) -> Result<Vec<u8>, CodecError> {
+    read_stream_chunk(handle).map(|chunk| chunk.bytes)
 }
 
 #[rehash_codec_ffi]
-fn get_bytes_from_video(path: *const c_char, out_len: *mut usize) -> *mut c_uchar {
-    let c_str = unsafe {
-        assert!(!path.is_null());
-        CStr::from_ptr(path)
-    };
+fn seek_video_stream(handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+    seek_stream(handle, position)
+}
 
-    let path_str = match c_str.to_str() {
-        Ok(s) => s,
-        Err(_) => return std::ptr::null_mut(), // invalid UTF-8
-    };
+#[rehash_codec_ffi]
+fn close_video_stream(handle: u64) -> bool {
+    close_frame_decoder(handle);
+    close_stream(handle)
+}
 
-    let bytes = match get_video_bytes(path_str) {
-        Ok(bytes) => bytes,
-        Err(_) => return std::ptr::null_mut(),
+#[rehash_codec_ffi]
+fn decode_frame_at(handle: u64, position: f64, is_frame_index: bool) -> Result<Vec<u8>, CodecError> {
+    let target = if is_frame_index {
+        FrameTarget::Index(position.max(0f64) as u64)
+    } else {
+        FrameTarget::Seconds(position)
     };
 
-    let len = bytes.len();
+    // a closed stream is a stale handle, not a frame that failed to decode
+    stream_meta(handle)?;
+    decode_frame(handle, target)
+        .map(|frame| frame.to_bytes())
+        .map_err(|e| CodecError::decode(format!("Failed to decode frame at {:?} for stream {}: {}", target, handle, e)))
+}
+
+#[rehash_codec_ffi]
+fn generate_thumbnails(path: &str, output_dir: &str, interval: f64, width: u32, progress: &CodecProgress) -> Result<ThumbnailIndex, CodecError> {
+    generate_thumbnail_files(path, output_dir, interval, width, progress)
+        .map_err(|e| CodecError::decode(format!("Failed to generate thumbnails for {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn start_import(path: &str, output_path: &str) -> Result<u64, CodecError> {
+    start_import_job(path, output_path).map_err(|e| CodecError::failed(format!("Failed to start import of {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn start_proxy(path: &str, output_path: &str, height: u32) -> Result<u64, CodecError> {
+    start_proxy_job(path, output_path, height).map_err(|e| CodecError::failed(format!("Failed to start proxy of {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn get_import_status(job: u64) -> Result<ImportStatus, CodecError> {
+    import_status(job).map_err(CodecError::not_found)
+}
+
+#[rehash_codec_ffi]
+fn close_import(job: u64) -> bool {
+    close_import_job(job)
+}
+
+#[rehash_codec_ffi]
+fn export_clip(path: &str, output_path: &str, start: f64, end: f64, progress: &CodecProgress) -> Result<ClipExport, CodecError> {
+    export_clip_file(path, output_path, start, end, progress).map_err(|e| CodecError::encode(format!("Failed to export clip from {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn start_reel(segments: Vec<ReelSegment>, output_path: &str) -> Result<u64, CodecError> {
+    start_reel_job(segments, output_path).map_err(|e| CodecError::failed(format!("Failed to start reel {}: {}", output_path, e)))
+}
 
-    unsafe {
-        if !out_len.is_null() {
-            *out_len = len;
-        }
-    }
-    let ptr = bytes.as_ptr() as *mut c_uchar;
-    std::mem::forget(bytes);
-    ptr
+#[rehash_codec_ffi]
+fn get_reel_status(job: u64) -> Result<ReelStatus, CodecError> {
+    reel_status(job).map_err(CodecError::not_found)
+}
+
+#[rehash_codec_ffi]
+fn close_reel(job: u64) -> bool {
+    close_reel_job(job)
 }
 
 #[rehash_codec_ffi]
-fn free_file_bytes(ptr: *mut c_uchar, len: usize) {
-    if !ptr.is_null() && len > 0 {
-        unsafe {
-            drop(Vec::from_raw_parts(ptr, len, len));
-        }
-    }
-}
\ No newline at end of file
+fn detect_plays(path: &str, progress: &CodecProgress) -> Result<PlaySegmentation, CodecError> {
+    detect_play_segments(path, progress).map_err(|e| CodecError::decode(format!("Failed to detect plays in {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn detect_audio_events(path: &str, progress: &CodecProgress) -> Result<AudioAnalysis, CodecError> {
+    detect_audio_cues(path, progress).map_err(|e| CodecError::decode(format!("Failed to detect audio events in {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn get_keyframe_index(path: &str, progress: &CodecProgress) -> Result<KeyframeIndex, CodecError> {
+    keyframe_index(path, progress).map_err(|e| CodecError::decode(format!("Failed to index keyframes of {}: {}", path, e)))
+}
+
+/// Indexes `path` for playback through a `MediaSource`, segments are only muxed when read
+#[rehash_codec_ffi]
+fn open_segmented_stream(path: &str, segment_seconds: f64, progress: &CodecProgress) -> Result<SegmentedStream, CodecError> {
+    open_segments(path, segment_seconds, progress).map_err(|e| CodecError::decode(format!("Failed to segment {}: {}", path, e)))
+}
+
+#[rehash_codec_ffi]
+fn read_init_segment(handle: u64) -> Result<Vec<u8>, CodecError> {
+    read_init(handle)
+}
+
+#[rehash_codec_ffi]
+fn read_media_segment(handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+    read_media(handle, index)
+}
+
+#[rehash_codec_ffi]
+fn close_segmented_stream(handle: u64) -> bool {
+    close_segments(handle)
+}
@@ -0,0 +1,69 @@
+use crate::clip::stream_start;
+use crate::{expected_frames, init_ffmpeg, input_duration};
+use ffmpeg_next::format::context::Input;
+use ffmpeg_next::media::Type as MediaType;
+use rehash_codec_ffi::codec::KeyframeIndex;
+use rehash_codec_ffi::progress::CodecProgress;
+
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Keyframe {
+    pub(crate) pts: i64,
+    pub(crate) dts: i64,
+}
+
+
+/// Keyframes of `stream_index` in presentation order, along with the last presentation timestamp in the stream
+pub(crate) fn scan_keyframes(input: &mut Input, stream_index: usize, progress: &CodecProgress) -> Result<(Vec<Keyframe>, Option<i64>), String> {
+    let duration = input_duration(input);
+    let total = input.stream(stream_index).map_or(0, |stream| expected_frames(&stream, duration));
+    let mut keyframes = Vec::new();
+    let mut last_pts = None;
+    let mut processed = 0u64;
+
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        processed += 1;
+        progress.report(processed, total)?;
+
+        let Some(pts) = packet.pts().or(packet.dts()) else {
+            continue;
+        };
+        last_pts = last_pts.max(Some(pts));
+
+        if packet.is_key() {
+            keyframes.push(Keyframe { pts, dts: packet.dts().unwrap_or(pts) });
+        }
+    }
+
+    keyframes.sort_by_key(|keyframe| keyframe.pts);
+    keyframes.dedup_by_key(|keyframe| keyframe.pts);
+    Ok((keyframes, last_pts))
+}
+
+/// Presentation times, in seconds from the stream start, of every keyframe in the video stream
+pub(crate) fn list_keyframes(path: &str, progress: &CodecProgress) -> Result<Vec<f64>, String> {
+    keyframe_index(path, progress).map(|index| index.keyframes)
+}
+
+pub(crate) fn keyframe_index(path: &str, progress: &CodecProgress) -> Result<KeyframeIndex, String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let duration = input_duration(&input);
+    let stream = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let start_time = stream_start(stream.start_time());
+
+    let (keyframes, _) = scan_keyframes(&mut input, stream_index, progress)?;
+    println!("Indexed {} keyframes in {}", keyframes.len(), path);
+
+    Ok(KeyframeIndex {
+        file_path: path.to_string(),
+        duration,
+        keyframes: keyframes.iter().map(|keyframe| (keyframe.pts - start_time) as f64 * time_base).collect(),
+    })
+}
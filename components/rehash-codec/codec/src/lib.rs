@@ -1,4 +1,19 @@
+mod audio;
+mod clip;
+mod frame;
+mod import;
 mod interface;
+mod keyframes;
+mod plays;
+mod probe;
+mod reel;
+mod segment;
+mod stream;
+mod thumbnail;
+
+use std::sync::Once;
+
+static FFMPEG_INIT: Once = Once::new();
 
 
 struct RehashCodec;
@@ -9,3 +24,27 @@ impl RehashCodec {
 }
 
 
+/// Length of `input` from its container in seconds, 0 when it isn't known
+pub(crate) fn input_duration(input: &ffmpeg_next::format::context::Input) -> f64 {
+    (input.duration() as f64 * f64::from(ffmpeg_next::rescale::TIME_BASE)).max(0f64)
+}
+
+/// Frames `stream` should hold, from the container or estimated from `duration`, 0 when neither is known
+pub(crate) fn expected_frames(stream: &ffmpeg_next::Stream, duration: f64) -> u64 {
+    if stream.frames() > 0 { stream.frames() as u64 } else { frames_in(stream, duration) }
+}
+
+/// Frames `stream` shows in `seconds` at its average rate, 0 when the rate isn't known
+pub(crate) fn frames_in(stream: &ffmpeg_next::Stream, seconds: f64) -> u64 {
+    let rate = stream.avg_frame_rate();
+    if rate.numerator() > 0 && rate.denominator() > 0 { (seconds * f64::from(rate)).round() as u64 } else { 0 }
+}
+
+pub(crate) fn init_ffmpeg() {
+    FFMPEG_INIT.call_once(|| {
+        if let Err(e) = ffmpeg_next::init() {
+            println!("Failed to initialise ffmpeg: {}", e);
+        }
+    });
+}
+
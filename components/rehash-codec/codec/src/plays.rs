@@ -0,0 +1,236 @@
+use crate::clip::stream_start;
+use crate::{expected_frames, init_ffmpeg, input_duration};
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::software::scaling::{self, Flags};
+use ffmpeg_next::{codec, frame};
+use rehash_codec_ffi::codec::{PlayBoundary, PlayCandidate, PlaySegmentation};
+use rehash_codec_ffi::progress::CodecProgress;
+
+
+// Frames are compared as small greyscale images a few times a second, plenty to see cuts and movement
+const ANALYSIS_WIDTH: u32 = 64;
+const ANALYSIS_HEIGHT: u32 = 36;
+const ANALYSIS_INTERVAL: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 32;
+
+/// Share of the luma histogram that has to change between samples to count as a camera cut
+const SCENE_CUT_THRESHOLD: f64 = 0.4;
+/// Mean luma change per pixel, as a fraction of full scale, below which the picture is considered still
+const STILL_THRESHOLD: f64 = 0.01;
+const MOTION_SMOOTHING_SAMPLES: usize = 5;
+/// Players have to be set for this long before the stillness ends a play
+const MIN_STILL_SECONDS: f64 = 1.0;
+const MIN_PLAY_SECONDS: f64 = 2.0;
+/// Kept before the first movement so the snap itself is in the clip
+const PRE_ROLL_SECONDS: f64 = 0.5;
+
+
+struct AnalysisSample {
+    time: f64,
+    scene_score: f64,
+    motion: f64,
+}
+
+
+struct Greyscale {
+    pixels: Vec<u8>,
+    histogram: [f64; HISTOGRAM_BINS],
+}
+
+
+impl Greyscale {
+    fn from_frame(frame: &frame::Video) -> Self {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let stride = frame.stride(0);
+        let data = frame.data(0);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            pixels.extend_from_slice(&data[row * stride..row * stride + width]);
+        }
+
+        let mut histogram = [0f64; HISTOGRAM_BINS];
+        for pixel in &pixels {
+            histogram[*pixel as usize * HISTOGRAM_BINS / 256] += 1f64;
+        }
+        let total = pixels.len().max(1) as f64;
+        histogram.iter_mut().for_each(|bin| *bin /= total);
+
+        Self { pixels, histogram }
+    }
+
+    fn scene_score(&self, previous: &Greyscale) -> f64 {
+        0.5 * self.histogram.iter().zip(previous.histogram.iter()).map(|(a, b)| (a - b).abs()).sum::<f64>()
+    }
+
+    fn motion(&self, previous: &Greyscale) -> f64 {
+        let difference: u64 = self.pixels.iter().zip(previous.pixels.iter()).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+        difference as f64 / (self.pixels.len().max(1) as f64 * 255f64)
+    }
+}
+
+
+struct Sampler {
+    start_time: i64,
+    time_base: f64,
+    scaler: Option<scaling::Context>,
+    scaled: frame::Video,
+    previous: Option<Greyscale>,
+    next_sample: f64,
+    samples: Vec<AnalysisSample>,
+}
+
+
+impl Sampler {
+    fn push(&mut self, decoded: &frame::Video) -> Result<(), String> {
+        let Some(timestamp) = decoded.timestamp() else {
+            return Ok(());
+        };
+        let time = (timestamp - self.start_time) as f64 * self.time_base;
+        if time < self.next_sample {
+            return Ok(());
+        }
+        self.next_sample = time + ANALYSIS_INTERVAL;
+
+        let reuse_scaler = self.scaler.as_ref().is_some_and(|scaler| {
+            let input = scaler.input();
+            input.format == decoded.format() && input.width == decoded.width() && input.height == decoded.height()
+        });
+        if !reuse_scaler {
+            let scaler = scaling::Context::get(decoded.format(), decoded.width(), decoded.height(), Pixel::GRAY8, ANALYSIS_WIDTH, ANALYSIS_HEIGHT, Flags::AREA)
+                .map_err(|e| format!("Failed to create analysis scaler: {}", e))?;
+            self.scaler = Some(scaler);
+        }
+        self.scaler.as_mut().unwrap().run(decoded, &mut self.scaled).map_err(|e| format!("Failed to scale frame: {}", e))?;
+
+        let current = Greyscale::from_frame(&self.scaled);
+        if let Some(previous) = &self.previous {
+            self.samples.push(AnalysisSample { time, scene_score: current.scene_score(previous), motion: current.motion(previous) });
+        }
+        self.previous = Some(current);
+        Ok(())
+    }
+}
+
+
+fn analyse(path: &str, progress: &CodecProgress) -> Result<(Vec<AnalysisSample>, f64), String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let duration = input_duration(&input);
+
+    let stream = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let stream_index = stream.index();
+    let total = expected_frames(&stream, duration);
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())
+        .and_then(|context| context.decoder().video())
+        .map_err(|e| format!("Failed to open video decoder: {}", e))?;
+
+    let mut sampler = Sampler {
+        start_time: stream_start(stream.start_time()),
+        time_base: f64::from(stream.time_base()),
+        scaler: None,
+        scaled: frame::Video::empty(),
+        previous: None,
+        next_sample: 0f64,
+        samples: Vec::new(),
+    };
+
+    let mut decoded = frame::Video::empty();
+    let mut processed = 0u64;
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet).map_err(|e| format!("Failed to decode video: {}", e))?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            sampler.push(&decoded)?;
+            processed += 1;
+            progress.report(processed, total)?;
+        }
+    }
+
+    let _ = decoder.send_eof();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        sampler.push(&decoded)?;
+        processed += 1;
+        progress.report(processed, total)?;
+    }
+
+    Ok((sampler.samples, duration))
+}
+
+fn smoothed_motion(samples: &[AnalysisSample]) -> Vec<f64> {
+    let half = MOTION_SMOOTHING_SAMPLES / 2;
+    (0..samples.len())
+        .map(|i| {
+            let window = &samples[i.saturating_sub(half)..(i + half + 1).min(samples.len())];
+            window.iter().map(|s| s.motion).sum::<f64>() / window.len() as f64
+        })
+        .collect()
+}
+
+fn push_play(candidates: &mut Vec<PlayCandidate>, start: f64, end: f64, boundary: PlayBoundary) {
+    if end - start >= MIN_PLAY_SECONDS {
+        candidates.push(PlayCandidate { start, end, boundary });
+    }
+}
+
+// A play runs from a cut, or from movement after a still period, until the next cut or until the players are still again
+fn find_plays(samples: &[AnalysisSample], duration: f64) -> Vec<PlayCandidate> {
+    let motion = smoothed_motion(samples);
+    let mut candidates = Vec::new();
+    let mut current: Option<(f64, PlayBoundary)> = None;
+    let mut still_since: Option<f64> = None;
+    let mut previous_end = 0f64;
+
+    for (sample, motion) in samples.iter().zip(motion) {
+        if sample.scene_score >= SCENE_CUT_THRESHOLD {
+            if let Some((start, boundary)) = current.take() {
+                push_play(&mut candidates, start, sample.time, boundary);
+            }
+            previous_end = sample.time;
+            current = Some((sample.time, PlayBoundary::SceneCut));
+            still_since = None;
+            continue;
+        }
+
+        if motion < STILL_THRESHOLD {
+            let since = *still_since.get_or_insert(sample.time);
+            if sample.time - since >= MIN_STILL_SECONDS {
+                if let Some((start, boundary)) = current.take() {
+                    push_play(&mut candidates, start, since, boundary);
+                    previous_end = since;
+                }
+            }
+        } else {
+            still_since = None;
+            if current.is_none() {
+                let boundary = if candidates.is_empty() && previous_end == 0f64 { PlayBoundary::StreamStart } else { PlayBoundary::Motion };
+                let start = (sample.time - PRE_ROLL_SECONDS).max(previous_end);
+                current = Some((start, boundary));
+            }
+        }
+    }
+
+    if let Some((start, boundary)) = current {
+        push_play(&mut candidates, start, duration.max(start), boundary);
+    }
+
+    candidates
+}
+
+
+/// Analyses the whole video for candidate play boundaries using scene change scores and stillness between plays
+pub(crate) fn detect_plays(path: &str, progress: &CodecProgress) -> Result<PlaySegmentation, String> {
+    let (samples, duration) = analyse(path, progress)?;
+    let candidates = find_plays(&samples, duration);
+    println!("Found {} candidate plays in {}", candidates.len(), path);
+
+    Ok(PlaySegmentation {
+        file_path: path.to_string(),
+        duration,
+        candidates,
+    })
+}
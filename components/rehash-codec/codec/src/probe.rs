@@ -0,0 +1,110 @@
+use crate::{init_ffmpeg, input_duration};
+use ffmpeg_next::codec::packet::side_data::Type as SideDataType;
+use ffmpeg_next::format::stream::Stream;
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::{ffi, Rational};
+use rehash_codec_ffi::codec::{StreamKind, StreamProbe, VideoProbe};
+
+
+fn to_seconds(value: i64, time_base: Rational) -> f64 {
+    if value <= 0 || time_base.denominator() == 0 {
+        return 0f64;
+    }
+    value as f64 * f64::from(time_base)
+}
+
+fn stream_kind(medium: MediaType) -> StreamKind {
+    match medium {
+        MediaType::Video => StreamKind::Video,
+        MediaType::Audio => StreamKind::Audio,
+        MediaType::Subtitle => StreamKind::Subtitle,
+        MediaType::Data => StreamKind::Data,
+        MediaType::Attachment => StreamKind::Attachment,
+        MediaType::Unknown => StreamKind::Unknown,
+    }
+}
+
+fn stream_rotation(stream: &Stream) -> i32 {
+    for side_data in stream.side_data() {
+        if side_data.kind() == SideDataType::DisplayMatrix && side_data.data().len() >= 9 * size_of::<i32>() {
+            let matrix = side_data.data().as_ptr() as *const i32;
+            let rotation = unsafe { ffi::av_display_rotation_get(matrix) };
+            if rotation.is_finite() {
+                // display matrix rotation is counter-clockwise, players report clockwise
+                return (-rotation.round() as i32).rem_euclid(360);
+            }
+        }
+    }
+
+    stream.metadata().get("rotate")
+        .and_then(|r| r.parse::<i32>().ok())
+        .map(|r| r.rem_euclid(360))
+        .unwrap_or(0)
+}
+
+fn probe_stream(stream: &Stream) -> StreamProbe {
+    let parameters = stream.parameters();
+    let codec_id = parameters.id();
+    let (width, height, bit_rate, sample_rate, channels) = unsafe {
+        let par = &*parameters.as_ptr();
+        (par.width.max(0) as u32, par.height.max(0) as u32, par.bit_rate, par.sample_rate.max(0) as u32, par.ch_layout.nb_channels.max(0) as u16)
+    };
+
+    let frame_rate = if stream.avg_frame_rate().denominator() != 0 {
+        f64::from(stream.avg_frame_rate())
+    } else {
+        0f64
+    };
+    let duration = to_seconds(stream.duration(), stream.time_base());
+
+    // Not every container stores a frame count, estimate it when missing
+    let frame_count = match stream.frames() {
+        frames if frames > 0 => frames,
+        _ => (duration * frame_rate).round() as i64,
+    };
+
+    let kind = stream_kind(parameters.medium());
+    let rotation = if kind == StreamKind::Video { stream_rotation(stream) } else { 0 };
+
+    StreamProbe {
+        index: stream.index(),
+        kind,
+        codec_name: codec_id.name().to_string(),
+        duration,
+        bit_rate,
+        frame_rate,
+        frame_count,
+        width,
+        height,
+        rotation,
+        sample_rate,
+        channels,
+    }
+}
+
+pub(crate) fn probe_video_file(path: &str) -> Result<VideoProbe, String> {
+    init_ffmpeg();
+    let input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+
+    let streams: Vec<StreamProbe> = input.streams().map(|s| probe_stream(&s)).collect();
+
+    let duration = if input.duration() > 0 {
+        input_duration(&input)
+    } else {
+        streams.iter().map(|s| s.duration).fold(0f64, f64::max)
+    };
+
+    let creation_time = input.metadata().get("creation_time").map(|s| s.to_string());
+
+    Ok(VideoProbe {
+        file_path: path.to_string(),
+        format_name: input.format().name().to_string(),
+        format_long_name: input.format().description().to_string(),
+        duration,
+        bit_rate: input.bit_rate(),
+        creation_time,
+        video_stream: input.streams().best(MediaType::Video).map(|s| s.index()),
+        audio_stream: input.streams().best(MediaType::Audio).map(|s| s.index()),
+        streams,
+    })
+}
@@ -0,0 +1,484 @@
+use crate::clip::{seconds_to_timestamp, stream_start};
+use crate::import::AudioTranscoder;
+use crate::init_ffmpeg;
+use ffmpeg_next::channel_layout::ChannelLayout;
+use ffmpeg_next::ffi::AVPixelFormat;
+use ffmpeg_next::format::context::Output;
+use ffmpeg_next::format::{sample, Pixel, Sample};
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::{codec, decoder, encoder, filter, frame, picture, rescale, Dictionary, Packet, Rational, Rescale};
+use rehash_codec_ffi::codec::{JobState, ReelSegment, ReelStatus};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+
+static REELS: LazyLock<Mutex<HashMap<u64, ReelStatus>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+const AUDIO_RATE: i32 = 48_000;
+const AUDIO_CHANNELS: usize = 2;
+const AUDIO_BIT_RATE: usize = 192_000;
+const DEFAULT_FRAME_RATE: Rational = Rational(30, 1);
+
+
+fn update_status(job: u64, update: impl FnOnce(&mut ReelStatus)) {
+    if let Some(status) = REELS.lock().unwrap().get_mut(&job) {
+        update(status);
+    }
+}
+
+
+/// Output format of the reel, taken from the first segment
+#[derive(Debug, Clone, Copy)]
+struct ReelFormat {
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+}
+
+
+impl ReelFormat {
+    fn from_segment(segment: &ReelSegment) -> Result<Self, String> {
+        let input = ffmpeg_next::format::input(&segment.file_path)
+            .map_err(|e| format!("Failed to open {}: {}", segment.file_path, e))?;
+        let stream = input.streams().best(MediaType::Video).ok_or(format!("{} has no video stream", segment.file_path))?;
+        let parameters = stream.parameters();
+        let (width, height) = unsafe { ((*parameters.as_ptr()).width as u32, (*parameters.as_ptr()).height as u32) };
+
+        let frame_rate = match stream.avg_frame_rate() {
+            rate if rate.numerator() > 0 && rate.denominator() > 0 => rate.reduce(),
+            _ => DEFAULT_FRAME_RATE,
+        };
+
+        Ok(Self { width: width.max(2) & !1, height: height.max(2) & !1, frame_rate })
+    }
+
+    fn frame_time_base(&self) -> Rational {
+        self.frame_rate.invert()
+    }
+}
+
+
+struct ReelEncoder {
+    octx: Output,
+    format: ReelFormat,
+    video: encoder::Video,
+    video_index: usize,
+    frames: i64,
+    audio: encoder::Audio,
+    audio_index: usize,
+    samples: i64,
+    // resampled audio waiting to be cut into encoder sized frames, one buffer per channel
+    pending_audio: [Vec<f32>; AUDIO_CHANNELS],
+}
+
+
+impl ReelEncoder {
+    fn new(output_path: &str, format: ReelFormat) -> Result<Self, String> {
+        let mut octx = ffmpeg_next::format::output_as(&output_path, "mp4")
+            .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+        let global_header = octx.format().flags().contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+
+        let h264 = encoder::find(codec::Id::H264).ok_or("H.264 encoder is not available")?;
+        let mut context = codec::context::Context::new_with_codec(h264).encoder().video()
+            .map_err(|e| format!("Failed to create video encoder: {}", e))?;
+        context.set_width(format.width);
+        context.set_height(format.height);
+        context.set_aspect_ratio(Rational(1, 1));
+        context.set_format(Pixel::YUV420P);
+        context.set_frame_rate(Some(format.frame_rate));
+        context.set_time_base(format.frame_time_base());
+        if global_header {
+            context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        let mut options = Dictionary::new();
+        options.set("preset", "veryfast");
+        options.set("crf", "20");
+        let video = context.open_as_with(h264, options).map_err(|e| format!("Failed to open H.264 encoder: {}", e))?;
+
+        let mut output = octx.add_stream(h264).map_err(|e| format!("Failed to add video stream: {}", e))?;
+        output.set_parameters(&video);
+        output.set_time_base(format.frame_time_base());
+        let video_index = output.index();
+
+        let aac = encoder::find(codec::Id::AAC).ok_or("AAC encoder is not available")?;
+        let mut context = codec::context::Context::new_with_codec(aac).encoder().audio()
+            .map_err(|e| format!("Failed to create audio encoder: {}", e))?;
+        context.set_rate(AUDIO_RATE);
+        context.set_channel_layout(ChannelLayout::STEREO);
+        context.set_format(Sample::F32(sample::Type::Planar));
+        context.set_bit_rate(AUDIO_BIT_RATE);
+        context.set_time_base((1, AUDIO_RATE));
+        if global_header {
+            context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let audio = context.open_as(aac).map_err(|e| format!("Failed to open AAC encoder: {}", e))?;
+
+        let mut output = octx.add_stream(aac).map_err(|e| format!("Failed to add audio stream: {}", e))?;
+        output.set_parameters(&audio);
+        output.set_time_base((1, AUDIO_RATE));
+        let audio_index = output.index();
+
+        let mut options = Dictionary::new();
+        options.set("movflags", "+faststart");
+        octx.write_header_with(options).map_err(|e| format!("Failed to write header: {}", e))?;
+
+        Ok(Self {
+            octx,
+            format,
+            video,
+            video_index,
+            frames: 0,
+            audio,
+            audio_index,
+            samples: 0,
+            pending_audio: [Vec::new(), Vec::new()],
+        })
+    }
+
+    // Scales to fit inside the reel, letterboxing anything with a different aspect ratio, and resamples to the reel frame rate
+    fn video_filter(&self, decoder: &decoder::Video, time_base: Rational) -> Result<filter::Graph, ffmpeg_next::Error> {
+        let mut graph = filter::Graph::new();
+        let aspect = match decoder.aspect_ratio() {
+            aspect if aspect.numerator() > 0 => aspect,
+            _ => Rational(1, 1),
+        };
+
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}:pixel_aspect={}",
+            decoder.width(),
+            decoder.height(),
+            AVPixelFormat::from(decoder.format()) as i32,
+            time_base,
+            aspect
+        );
+
+        graph.add(&filter::find("buffer").ok_or(ffmpeg_next::Error::FilterNotFound)?, "in", &args)?;
+        graph.add(&filter::find("buffersink").ok_or(ffmpeg_next::Error::FilterNotFound)?, "out", "")?;
+
+        let (width, height) = (self.format.width, self.format.height);
+        let spec = format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={},format=yuv420p",
+            self.format.frame_rate,
+            w = width,
+            h = height
+        );
+
+        graph.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    fn audio_filter(decoder: &decoder::Audio, time_base: Rational) -> Result<filter::Graph, ffmpeg_next::Error> {
+        let mut graph = filter::Graph::new();
+
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            decoder.rate(),
+            decoder.format().name(),
+            AudioTranscoder::input_layout(decoder).bits()
+        );
+
+        graph.add(&filter::find("abuffer").ok_or(ffmpeg_next::Error::FilterNotFound)?, "in", &args)?;
+        graph.add(&filter::find("abuffersink").ok_or(ffmpeg_next::Error::FilterNotFound)?, "out", "")?;
+
+        graph.output("in", 0)?.input("out", 0)?.parse(&format!("aresample={},aformat=sample_fmts=fltp:channel_layouts=stereo", AUDIO_RATE))?;
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    fn encode_segment(&mut self, segment: &ReelSegment, on_progress: impl Fn(f64)) -> Result<(), String> {
+        let mut input = ffmpeg_next::format::input(&segment.file_path)
+            .map_err(|e| format!("Failed to open {}: {}", segment.file_path, e))?;
+
+        let video = input.streams().best(MediaType::Video).ok_or(format!("{} has no video stream", segment.file_path))?;
+        let (video_index, video_time_base) = (video.index(), video.time_base());
+        let video_start = stream_start(video.start_time());
+        let video_window = seconds_to_timestamp(segment.start, video_start, video_time_base)..seconds_to_timestamp(segment.end, video_start, video_time_base);
+
+        let mut video_decoder = codec::context::Context::from_parameters(video.parameters())
+            .and_then(|context| context.decoder().video())
+            .map_err(|e| format!("Failed to open video decoder: {}", e))?;
+        let mut video_graph = self.video_filter(&video_decoder, video_time_base)
+            .map_err(|e| format!("Failed to create video filter: {}", e))?;
+
+        let mut audio = match input.streams().best(MediaType::Audio) {
+            Some(stream) => {
+                let audio_start = stream_start(stream.start_time());
+                let window = seconds_to_timestamp(segment.start, audio_start, stream.time_base())..seconds_to_timestamp(segment.end, audio_start, stream.time_base());
+                let decoder = codec::context::Context::from_parameters(stream.parameters())
+                    .and_then(|context| context.decoder().audio())
+                    .map_err(|e| format!("Failed to open audio decoder: {}", e))?;
+                let graph = Self::audio_filter(&decoder, stream.time_base())
+                    .map_err(|e| format!("Failed to create audio filter: {}", e))?;
+                Some((stream.index(), window, decoder, graph))
+            }
+            None => None,
+        };
+
+        let position = video_window.start.rescale(video_time_base, rescale::TIME_BASE);
+        input.seek(position, ..position).map_err(|e| format!("Failed to seek: {}", e))?;
+
+        let mut video_done = false;
+        let mut audio_done = audio.is_none();
+        let mut decoded = frame::Video::empty();
+        let mut decoded_audio = frame::Audio::empty();
+
+        for (stream, packet) in input.packets() {
+            if stream.index() == video_index && !video_done {
+                video_decoder.send_packet(&packet).map_err(|e| format!("Failed to decode video: {}", e))?;
+                while video_decoder.receive_frame(&mut decoded).is_ok() {
+                    let Some(timestamp) = decoded.timestamp() else {
+                        continue;
+                    };
+                    if timestamp >= video_window.end {
+                        video_done = true;
+                        continue;
+                    }
+                    if timestamp < video_window.start {
+                        continue;
+                    }
+
+                    decoded.set_pts(Some(timestamp - video_window.start));
+                    video_graph.get("in").unwrap().source().add(&decoded).map_err(|e| format!("Failed to filter video: {}", e))?;
+                    self.receive_video(&mut video_graph)?;
+                    on_progress((timestamp - video_window.start) as f64 / (video_window.end - video_window.start).max(1) as f64);
+                }
+            } else if let Some((audio_index, window, decoder, graph)) = audio.as_mut() {
+                if stream.index() != *audio_index || audio_done {
+                    continue;
+                }
+
+                decoder.send_packet(&packet).map_err(|e| format!("Failed to decode audio: {}", e))?;
+                while decoder.receive_frame(&mut decoded_audio).is_ok() {
+                    let Some(timestamp) = decoded_audio.timestamp() else {
+                        continue;
+                    };
+                    if timestamp >= window.end {
+                        audio_done = true;
+                        continue;
+                    }
+                    if timestamp < window.start {
+                        continue;
+                    }
+
+                    decoded_audio.set_pts(Some(timestamp - window.start));
+                    graph.get("in").unwrap().source().add(&decoded_audio).map_err(|e| format!("Failed to filter audio: {}", e))?;
+                    self.receive_audio(graph);
+                }
+            }
+
+            if video_done && audio_done {
+                break;
+            }
+        }
+
+        // frames still buffered in the decoder may fall inside the window when the file ended before it
+        if !video_done {
+            let _ = video_decoder.send_eof();
+            while video_decoder.receive_frame(&mut decoded).is_ok() {
+                if let Some(timestamp) = decoded.timestamp().filter(|ts| video_window.contains(ts)) {
+                    decoded.set_pts(Some(timestamp - video_window.start));
+                    video_graph.get("in").unwrap().source().add(&decoded).map_err(|e| format!("Failed to filter video: {}", e))?;
+                }
+            }
+        }
+        let _ = video_graph.get("in").unwrap().source().flush();
+        self.receive_video(&mut video_graph)?;
+
+        if let Some((_, _, _, graph)) = audio.as_mut() {
+            let _ = graph.get("in").unwrap().source().flush();
+            self.receive_audio(graph);
+        }
+
+        self.sync_audio()
+    }
+
+    fn receive_video(&mut self, graph: &mut filter::Graph) -> Result<(), String> {
+        let mut filtered = frame::Video::empty();
+        while graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            filtered.set_pts(Some(self.frames));
+            filtered.set_kind(picture::Type::None);
+            self.frames += 1;
+
+            self.video.send_frame(&filtered).map_err(|e| format!("Failed to encode video: {}", e))?;
+            self.receive_video_packets()?;
+        }
+        Ok(())
+    }
+
+    fn receive_audio(&mut self, graph: &mut filter::Graph) {
+        let mut filtered = frame::Audio::empty();
+        while graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            for (channel, pending) in self.pending_audio.iter_mut().enumerate() {
+                pending.extend_from_slice(filtered.plane::<f32>(channel.min(filtered.planes() - 1)));
+            }
+        }
+    }
+
+    // Pads or trims the audio so it ends exactly with the video, segments without audio become silence
+    fn sync_audio(&mut self) -> Result<(), String> {
+        let video_end = self.frames.rescale(self.format.frame_time_base(), Rational(1, AUDIO_RATE));
+        let target = (video_end - self.samples).max(0) as usize;
+        for pending in self.pending_audio.iter_mut() {
+            pending.resize(target, 0f32);
+        }
+
+        self.encode_pending_audio(false)
+    }
+
+    fn encode_pending_audio(&mut self, flush: bool) -> Result<(), String> {
+        let frame_size = (self.audio.frame_size() as usize).max(1);
+
+        while self.pending_audio[0].len() >= frame_size || (flush && !self.pending_audio[0].is_empty()) {
+            let mut frame = frame::Audio::new(Sample::F32(sample::Type::Planar), frame_size, ChannelLayout::STEREO);
+            frame.set_rate(AUDIO_RATE as u32);
+            frame.set_pts(Some(self.samples));
+
+            let available = self.pending_audio[0].len().min(frame_size);
+            for (channel, pending) in self.pending_audio.iter_mut().enumerate() {
+                let plane = frame.plane_mut::<f32>(channel);
+                plane[..available].copy_from_slice(&pending[..available]);
+                plane[available..].fill(0f32);
+                pending.drain(..available);
+            }
+            self.samples += frame_size as i64;
+
+            self.audio.send_frame(&frame).map_err(|e| format!("Failed to encode audio: {}", e))?;
+            self.receive_audio_packets()?;
+        }
+        Ok(())
+    }
+
+    fn receive_video_packets(&mut self) -> Result<(), String> {
+        let output_time_base = self.octx.stream(self.video_index).map(|s| s.time_base()).ok_or("Missing video stream")?;
+        let mut encoded = Packet::empty();
+        while self.video.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.video_index);
+            encoded.rescale_ts(self.format.frame_time_base(), output_time_base);
+            encoded.write_interleaved(&mut self.octx).map_err(|e| format!("Failed to write video packet: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn receive_audio_packets(&mut self) -> Result<(), String> {
+        let output_time_base = self.octx.stream(self.audio_index).map(|s| s.time_base()).ok_or("Missing audio stream")?;
+        let mut encoded = Packet::empty();
+        while self.audio.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.audio_index);
+            encoded.rescale_ts(Rational(1, AUDIO_RATE), output_time_base);
+            encoded.write_interleaved(&mut self.octx).map_err(|e| format!("Failed to write audio packet: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.encode_pending_audio(true)?;
+        let _ = self.video.send_eof();
+        self.receive_video_packets()?;
+        let _ = self.audio.send_eof();
+        self.receive_audio_packets()?;
+
+        self.octx.write_trailer().map_err(|e| format!("Failed to write trailer: {}", e))
+    }
+}
+
+
+fn run_reel(job: u64, segments: &[ReelSegment], output_path: &str) -> Result<(), String> {
+    init_ffmpeg();
+    let format = ReelFormat::from_segment(&segments[0])?;
+    update_status(job, |status| {
+        status.width = format.width;
+        status.height = format.height;
+        status.frame_rate = f64::from(format.frame_rate);
+    });
+    println!("Building reel of {} segments at {}x{} {:.2}fps", segments.len(), format.width, format.height, f64::from(format.frame_rate));
+
+    // written next to the destination and renamed at the end so a partial file is never picked up
+    let partial_path = format!("{}.part", output_path);
+    let mut encoder = ReelEncoder::new(&partial_path, format)?;
+
+    let total_duration: f64 = segments.iter().map(ReelSegment::duration).sum::<f64>().max(f64::EPSILON);
+    let mut completed = 0f64;
+
+    for (index, segment) in segments.iter().enumerate() {
+        update_status(job, |status| status.current_segment = index);
+        encoder.encode_segment(segment, |segment_progress| {
+            let progress = (completed + segment_progress * segment.duration()) / total_duration;
+            update_status(job, |status| status.progress = progress.clamp(0f64, 1f64));
+        })?;
+        completed += segment.duration();
+    }
+
+    encoder.finish()?;
+    fs::rename(&partial_path, output_path).map_err(|e| format!("Failed to move {} into place: {}", partial_path, e))?;
+    Ok(())
+}
+
+
+/// Starts concatenating `segments` in order into a single MP4 at `output_path` on a background thread
+pub(crate) fn start_reel(segments: Vec<ReelSegment>, output_path: &str) -> Result<u64, String> {
+    if segments.is_empty() {
+        return Err(String::from("A reel needs at least one segment"));
+    }
+
+    if let Some(segment) = segments.iter().find(|s| !Path::new(&s.file_path).is_file() || !(s.start >= 0f64 && s.end > s.start)) {
+        return Err(format!("Invalid segment {} {}..{}", segment.file_path, segment.start, segment.end));
+    }
+
+    if let Some(parent) = Path::new(output_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create reel directory: {}", e))?;
+    }
+
+    let job = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+    REELS.lock().unwrap().insert(job, ReelStatus {
+        job,
+        output_path: output_path.to_string(),
+        segment_count: segments.len(),
+        current_segment: 0,
+        width: 0,
+        height: 0,
+        frame_rate: 0f64,
+        state: JobState::Running,
+        progress: 0f64,
+        error: None,
+    });
+
+    let output_path = output_path.to_string();
+    std::thread::spawn(move || {
+        let result = run_reel(job, &segments, &output_path);
+        if result.is_err() {
+            let _ = fs::remove_file(format!("{}.part", output_path));
+        }
+
+        update_status(job, |status| match result {
+            Ok(_) => {
+                status.state = JobState::Finished;
+                status.progress = 1f64;
+            }
+            Err(e) => {
+                println!("Reel {} failed: {}", output_path, e);
+                status.state = JobState::Failed;
+                status.error = Some(e);
+            }
+        });
+    });
+
+    Ok(job)
+}
+
+pub(crate) fn reel_status(job: u64) -> Result<ReelStatus, String> {
+    REELS.lock().unwrap()
+        .get(&job)
+        .cloned()
+        .ok_or(format!("Reel job {} does not exist", job))
+}
+
+pub(crate) fn close_reel(job: u64) -> bool {
+    REELS.lock().unwrap().remove(&job).is_some()
+}
@@ -0,0 +1,375 @@
+use crate::clip::{seconds_to_timestamp, stream_start};
+use crate::import::{can_copy_video, copy_stream, AudioTranscoder, StreamPlan, VideoTranscoder};
+use crate::{init_ffmpeg, input_duration};
+use crate::keyframes::{scan_keyframes, Keyframe};
+use ffmpeg_next::format::context::{Input, Output};
+use ffmpeg_next::media::Type as MediaType;
+use ffmpeg_next::{codec, rescale, Dictionary, Packet, Rational, Rescale};
+use rehash_codec_ffi::codec::{ImportMode, MediaSegment, SegmentedStream};
+use rehash_codec_ffi::error::CodecError;
+use rehash_codec_ffi::progress::CodecProgress;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+
+static SEGMENTED: LazyLock<Mutex<HashMap<u64, SegmentSource>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+static NEXT_SCRATCH: AtomicU64 = AtomicU64::new(1);
+
+/// Every keyframe starts a fragment, timestamps are kept as written instead of restarting each track at zero
+const FRAGMENT_FLAGS: &str = "frag_keyframe+empty_moov+default_base_moof+frag_discont";
+/// Used when the avcC can't be read, MSE only checks the profile against what it can decode
+const FALLBACK_AVC_CODEC: &str = "avc1.640028";
+const AAC_CODEC: &str = "mp4a.40.2";
+
+
+/// Where a segment is cut from the source, in video stream timestamps
+#[derive(Debug, Clone, Copy)]
+struct SegmentBounds {
+    start: i64,
+    end: i64,
+    /// Keyframe decoding starts from, at or before `start`
+    keyframe: i64,
+    /// Subtracted from every timestamp so the segment's own timestamps start at zero
+    base: i64,
+}
+
+
+struct SegmentSource {
+    path: String,
+    copy_video: bool,
+    init: Vec<u8>,
+    bounds: Vec<SegmentBounds>,
+}
+
+
+/// Copied video is cut on its keyframes, encoded video on a fixed grid decoded from the keyframe before each cut
+fn layout_segments(keyframes: &[Keyframe], end: i64, length: i64, copy_video: bool) -> Vec<SegmentBounds> {
+    let Some(first) = keyframes.first() else {
+        return Vec::new();
+    };
+
+    let mut bounds: Vec<SegmentBounds> = Vec::new();
+    if copy_video {
+        for keyframe in keyframes.iter().filter(|keyframe| keyframe.pts < end) {
+            if bounds.last().is_some_and(|last| keyframe.pts < last.start + length) {
+                continue;
+            }
+            if let Some(last) = bounds.last_mut() {
+                last.end = keyframe.pts;
+            }
+            bounds.push(SegmentBounds { start: keyframe.pts, end, keyframe: keyframe.pts, base: keyframe.dts });
+        }
+    } else {
+        let mut start = first.pts;
+        while start < end {
+            let before = keyframes.partition_point(|keyframe| keyframe.pts <= start).saturating_sub(1);
+            bounds.push(SegmentBounds { start, end: (start + length).min(end), keyframe: keyframes[before].pts, base: start });
+            start += length;
+        }
+    }
+    bounds
+}
+
+/// Copies H.264 and AAC, anything else is encoded to them. Encoded video only keeps the frames within `bounds`
+fn plan_streams(input: &Input, octx: &mut Output, copy_video: bool, bounds: Option<&SegmentBounds>) -> Result<HashMap<usize, StreamPlan>, String> {
+    let mut plans = HashMap::new();
+
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let video_plan = if copy_video {
+        StreamPlan::Copy(copy_stream(&video, octx)?)
+    } else {
+        let transcoder = VideoTranscoder::segment(&video, octx)?;
+        StreamPlan::Video(match bounds {
+            Some(bounds) => transcoder.with_window(bounds.start..bounds.end, bounds.base),
+            None => transcoder,
+        })
+    };
+    plans.insert(video.index(), video_plan);
+
+    // MSE support for mp3 in mp4 is patchy, so unlike imports only AAC is copied
+    if let Some(audio) = input.streams().best(MediaType::Audio) {
+        let audio_plan = if audio.parameters().id() == codec::Id::AAC {
+            StreamPlan::Copy(copy_stream(&audio, octx)?)
+        } else {
+            StreamPlan::Audio(AudioTranscoder::new(&audio, octx)?)
+        };
+        plans.insert(audio.index(), audio_plan);
+    }
+
+    Ok(plans)
+}
+
+fn write_header(octx: &mut Output) -> Result<(), String> {
+    let mut options = Dictionary::new();
+    options.set("movflags", FRAGMENT_FLAGS);
+    octx.write_header_with(options).map_err(|e| format!("Failed to write header: {}", e))?;
+    Ok(())
+}
+
+// RFC 6381 codecs parameter from the profile, constraints and level bytes of the avcC
+fn avc_codec(octx: &Output, index: usize) -> String {
+    let Some(stream) = octx.stream(index) else {
+        return FALLBACK_AVC_CODEC.to_string();
+    };
+    let parameters = stream.parameters();
+    let extradata = unsafe {
+        let raw = parameters.as_ptr();
+        if (*raw).extradata.is_null() || (*raw).extradata_size < 4 {
+            return FALLBACK_AVC_CODEC.to_string();
+        }
+        std::slice::from_raw_parts((*raw).extradata, (*raw).extradata_size as usize)
+    };
+
+    if extradata[0] == 1 {
+        format!("avc1.{:02X}{:02X}{:02X}", extradata[1], extradata[2], extradata[3])
+    } else {
+        FALLBACK_AVC_CODEC.to_string()
+    }
+}
+
+/// Writes a header with no samples, returning the `SourceBuffer` type of what it describes
+fn mux_init(path: &str, copy_video: bool, output_path: &str) -> Result<String, String> {
+    init_ffmpeg();
+    let input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let mut octx = ffmpeg_next::format::output_as(&output_path, "mp4")
+        .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+
+    let plans = plan_streams(&input, &mut octx, copy_video, None)?;
+    write_header(&mut octx)?;
+
+    let video_index = input.streams().best(MediaType::Video).map(|video| video.index()).ok_or("File has no video stream")?;
+    let mut codecs = vec![avc_codec(&octx, plans[&video_index].output_index())];
+    if plans.len() > 1 {
+        codecs.push(AAC_CODEC.to_string());
+    }
+
+    octx.write_trailer().map_err(|e| format!("Failed to write trailer: {}", e))?;
+    Ok(format!("video/mp4; codecs=\"{}\"", codecs.join(", ")))
+}
+
+fn write_shifted(packet: &mut Packet, shift: i64, time_base: Rational, output_index: usize, octx: &mut Output) -> Result<(), String> {
+    let output_time_base = octx.stream(output_index).map(|s| s.time_base()).ok_or("Missing output stream")?;
+    packet.set_pts(packet.pts().map(|pts| pts - shift));
+    packet.set_dts(packet.dts().map(|dts| dts - shift));
+    packet.rescale_ts(time_base, output_time_base);
+    packet.set_position(-1);
+    packet.set_stream(output_index);
+    packet.write_interleaved(octx).map_err(|e| format!("Failed to write packet: {}", e))
+}
+
+fn mux_segment(path: &str, copy_video: bool, bounds: &SegmentBounds, output_path: &str) -> Result<(), String> {
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let mut octx = ffmpeg_next::format::output_as(&output_path, "mp4")
+        .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let (video_index, video_time_base) = (video.index(), video.time_base());
+    let video_start = stream_start(video.start_time());
+    let seconds = |timestamp: i64| (timestamp - video_start) as f64 * f64::from(video_time_base);
+
+    // audio is cut on the same presentation times and shifted by the same amount as the video
+    let audio = input.streams().best(MediaType::Audio).map(|audio| {
+        let (audio_start, audio_time_base) = (stream_start(audio.start_time()), audio.time_base());
+        let window = seconds_to_timestamp(seconds(bounds.start), audio_start, audio_time_base)
+            ..seconds_to_timestamp(seconds(bounds.end), audio_start, audio_time_base);
+        (audio.index(), audio_time_base, window, seconds_to_timestamp(seconds(bounds.base), audio_start, audio_time_base))
+    });
+
+    let mut plans = plan_streams(&input, &mut octx, copy_video, Some(bounds))?;
+    write_header(&mut octx)?;
+
+    let position = bounds.keyframe.rescale(video_time_base, rescale::TIME_BASE);
+    input.seek(position, ..position).map_err(|e| format!("Failed to seek: {}", e))?;
+
+    let mut started = false;
+    let mut video_done = false;
+    let mut audio_done = audio.is_none();
+    for (stream, mut packet) in input.packets() {
+        let Some(plan) = plans.get_mut(&stream.index()) else {
+            continue;
+        };
+
+        if stream.index() == video_index && !video_done {
+            let Some(pts) = packet.pts().or(packet.dts()) else {
+                continue;
+            };
+
+            match plan {
+                StreamPlan::Copy(output_index) => {
+                    if packet.is_key() && pts >= bounds.end {
+                        video_done = true;
+                        continue;
+                    }
+                    // leading frames of an open GOP are dropped, they reference the segment before
+                    started |= packet.is_key() && pts >= bounds.start;
+                    if started && pts >= bounds.start {
+                        write_shifted(&mut packet, bounds.base, video_time_base, *output_index, &mut octx)?;
+                    }
+                }
+                StreamPlan::Video(transcoder) => {
+                    transcoder.send_packet(&packet, &mut octx)?;
+                    video_done = transcoder.is_past_window();
+                }
+                StreamPlan::Audio(_) => {}
+            }
+        } else if let Some((_, audio_time_base, window, shift)) = audio.as_ref().filter(|(index, ..)| *index == stream.index()) {
+            let Some(pts) = packet.pts() else {
+                continue;
+            };
+            if audio_done || pts < window.start {
+                continue;
+            }
+            if pts >= window.end {
+                audio_done = true;
+                continue;
+            }
+
+            match plan {
+                StreamPlan::Copy(output_index) => write_shifted(&mut packet, *shift, *audio_time_base, *output_index, &mut octx)?,
+                StreamPlan::Audio(transcoder) => {
+                    packet.set_pts(Some(pts - shift));
+                    packet.set_dts(packet.dts().map(|dts| dts - shift));
+                    transcoder.send_packet(&packet, &mut octx)?;
+                }
+                StreamPlan::Video(_) => {}
+            }
+        }
+
+        if video_done && audio_done {
+            break;
+        }
+    }
+
+    let mut plans: Vec<StreamPlan> = plans.into_values().collect();
+    plans.sort_by_key(StreamPlan::output_index);
+    for plan in plans.iter_mut() {
+        match plan {
+            StreamPlan::Copy(_) => {}
+            StreamPlan::Video(transcoder) => transcoder.finish(&mut octx)?,
+            StreamPlan::Audio(transcoder) => transcoder.finish(&mut octx)?,
+        }
+    }
+
+    octx.write_trailer().map_err(|e| format!("Failed to write trailer: {}", e))
+}
+
+/// Runs `mux` against a scratch file and returns what it wrote, the file is always removed
+fn mux_in_scratch<T>(mux: impl FnOnce(&str) -> Result<T, String>) -> Result<(T, Vec<u8>), String> {
+    let scratch = env::temp_dir().join(format!("rehash-segment-{}-{}.mp4", std::process::id(), NEXT_SCRATCH.fetch_add(1, Ordering::Relaxed)));
+    let scratch = scratch.to_string_lossy().to_string();
+
+    let result = mux(&scratch).and_then(|value| {
+        let bytes = fs::read(&scratch).map_err(|e| format!("Failed to read {}: {}", scratch, e))?;
+        Ok((value, bytes))
+    });
+    let _ = fs::remove_file(&scratch);
+    result
+}
+
+/// Concatenates the top level boxes of an MP4 whose type is one of `kinds`, in file order
+fn select_boxes(bytes: &[u8], kinds: &[&[u8; 4]]) -> Result<Vec<u8>, String> {
+    let mut selected = Vec::new();
+    let mut offset = 0usize;
+
+    while offset + 8 <= bytes.len() {
+        let kind = &bytes[offset + 4..offset + 8];
+        let size = match u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) {
+            0 => (bytes.len() - offset) as u64,
+            1 if offset + 16 <= bytes.len() => u64::from_be_bytes(bytes[offset + 8..offset + 16].try_into().unwrap()),
+            size => u64::from(size),
+        };
+        let end = usize::try_from(size).ok()
+            .filter(|size| *size >= 8)
+            .and_then(|size| offset.checked_add(size))
+            .filter(|end| *end <= bytes.len())
+            .ok_or(format!("Truncated {} box at {}", String::from_utf8_lossy(kind), offset))?;
+
+        if kinds.iter().any(|wanted| wanted.as_slice() == kind) {
+            selected.extend_from_slice(&bytes[offset..end]);
+        }
+        offset = end;
+    }
+
+    Ok(selected)
+}
+
+
+/// Indexes `path` into segments of at least `segment_seconds` and prepares the init segment they all play with
+pub(crate) fn open_segmented_stream(path: &str, segment_seconds: f64, progress: &CodecProgress) -> Result<SegmentedStream, String> {
+    if !(segment_seconds > 0f64) {
+        return Err(format!("Invalid segment length {}", segment_seconds));
+    }
+
+    init_ffmpeg();
+    let mut input = ffmpeg_next::format::input(&path).map_err(|e| format!("Failed to open video file: {}", e))?;
+    let duration = input_duration(&input);
+
+    let video = input.streams().best(MediaType::Video).ok_or("File has no video stream")?;
+    let (video_index, video_time_base) = (video.index(), video.time_base());
+    let video_start = stream_start(video.start_time());
+    let copy_video = can_copy_video(&video);
+    let copy_audio = input.streams().best(MediaType::Audio).is_none_or(|audio| audio.parameters().id() == codec::Id::AAC);
+    let mode = if copy_video && copy_audio { ImportMode::Remux } else { ImportMode::Transcode };
+    let seconds = |timestamp: i64| (timestamp - video_start) as f64 * f64::from(video_time_base);
+
+    let (keyframes, last_pts) = scan_keyframes(&mut input, video_index, progress)?;
+    let length = seconds_to_timestamp(segment_seconds, 0, video_time_base).max(1);
+    let bounds = layout_segments(&keyframes, last_pts.map_or(0, |last| last + 1), length, copy_video);
+    if bounds.is_empty() {
+        return Err(String::from("Video stream has no keyframes"));
+    }
+
+    let (mime_type, init) = mux_in_scratch(|scratch| mux_init(path, copy_video, scratch))?;
+    let init = select_boxes(&init, &[b"ftyp", b"moov"])?;
+
+    let segments: Vec<MediaSegment> = bounds.iter().enumerate().map(|(index, bounds)| MediaSegment {
+        index,
+        start: seconds(bounds.start).max(0f64),
+        end: seconds(bounds.end),
+        timestamp_offset: seconds(bounds.base),
+    }).collect();
+
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let stream = SegmentedStream {
+        handle,
+        file_path: path.to_string(),
+        duration: if duration > 0f64 { duration } else { segments.last().map_or(0f64, |segment| segment.end) },
+        mime_type,
+        mode,
+        segments,
+    };
+
+    SEGMENTED.lock().unwrap().insert(handle, SegmentSource { path: path.to_string(), copy_video, init, bounds });
+    println!("Opened segmented stream {} for {} as {:?} ({} segments, {})", handle, path, mode, stream.segments.len(), stream.mime_type);
+
+    Ok(stream)
+}
+
+pub(crate) fn read_init_segment(handle: u64) -> Result<Vec<u8>, CodecError> {
+    let sources = SEGMENTED.lock().unwrap();
+    let source = sources.get(&handle).ok_or_else(|| CodecError::not_found(format!("Unknown segmented stream {}", handle)))?;
+    Ok(source.init.clone())
+}
+
+/// Muxes segment `index` on the calling thread, the lock is only held to look it up
+pub(crate) fn read_media_segment(handle: u64, index: usize) -> Result<Vec<u8>, CodecError> {
+    let (path, copy_video, bounds) = {
+        let sources = SEGMENTED.lock().unwrap();
+        let source = sources.get(&handle).ok_or_else(|| CodecError::not_found(format!("Unknown segmented stream {}", handle)))?;
+        let bounds = *source.bounds.get(index).ok_or_else(|| CodecError::invalid_argument(format!("Segmented stream {} has no segment {}", handle, index)))?;
+        (source.path.clone(), source.copy_video, bounds)
+    };
+
+    mux_in_scratch(|scratch| mux_segment(&path, copy_video, &bounds, scratch))
+        .and_then(|(_, bytes)| select_boxes(&bytes, &[b"moof", b"mdat"]))
+        .map_err(|e| CodecError::encode(format!("Failed to read segment {} of stream {}: {}", index, handle, e)))
+}
+
+pub(crate) fn close_segmented_stream(handle: u64) -> bool {
+    SEGMENTED.lock().unwrap().remove(&handle).is_some()
+}
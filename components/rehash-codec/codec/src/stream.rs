@@ -0,0 +1,91 @@
+use rehash_codec_ffi::codec::{VideoStreamChunk, VideoStreamMeta};
+use rehash_codec_ffi::error::CodecError;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+
+static STREAMS: LazyLock<Mutex<HashMap<u64, VideoStream>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+
+struct VideoStream {
+    file: File,
+    meta: VideoStreamMeta,
+}
+
+
+impl VideoStream {
+    fn read_chunk(&mut self) -> Result<VideoStreamChunk, CodecError> {
+        let position = self.meta.current_position;
+        let remaining = self.meta.total_size.saturating_sub(position);
+        let to_read = remaining.min(self.meta.chunk_size as u64) as usize;
+
+        let mut bytes = vec![0u8; to_read];
+        self.file.read_exact(&mut bytes).map_err(|e| CodecError::io(format!("Failed to read chunk at {}: {}", position, e)))?;
+
+        self.meta.current_position = position + to_read as u64;
+
+        Ok(VideoStreamChunk {
+            bytes,
+            position,
+            is_final: self.meta.is_finished(),
+        })
+    }
+
+    fn seek(&mut self, position: u64) -> Result<(), CodecError> {
+        let position = position.min(self.meta.total_size);
+        self.file.seek(SeekFrom::Start(position)).map_err(|e| CodecError::io(format!("Failed to seek to {}: {}", position, e)))?;
+        self.meta.current_position = position;
+        Ok(())
+    }
+}
+
+
+pub(crate) fn open_stream(path: &str, chunk_size: usize) -> Result<VideoStreamMeta, CodecError> {
+    if chunk_size == 0 {
+        return Err(CodecError::invalid_argument("Chunk size must be greater than zero"));
+    }
+
+    let file = File::open(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => CodecError::not_found(format!("No video file at {}", path)),
+        _ => CodecError::io(format!("Failed to open video file {}: {}", path, e)),
+    })?;
+    let total_size = file.metadata().map_err(|e| CodecError::io(format!("Failed to read metadata of {}: {}", path, e)))?.len();
+
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let meta = VideoStreamMeta::new(handle, path.to_string(), 0, total_size, chunk_size);
+
+    STREAMS.lock().unwrap().insert(handle, VideoStream { file, meta: meta.clone() });
+
+    Ok(meta)
+}
+
+pub(crate) fn stream_meta(handle: u64) -> Result<VideoStreamMeta, CodecError> {
+    let streams = STREAMS.lock().unwrap();
+    let stream = streams.get(&handle).ok_or_else(|| unknown_stream(handle))?;
+    Ok(stream.meta.clone())
+}
+
+pub(crate) fn read_stream_chunk(handle: u64) -> Result<VideoStreamChunk, CodecError> {
+    let mut streams = STREAMS.lock().unwrap();
+    let stream = streams.get_mut(&handle).ok_or_else(|| unknown_stream(handle))?;
+    stream.read_chunk()
+}
+
+pub(crate) fn seek_stream(handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+    let mut streams = STREAMS.lock().unwrap();
+    let stream = streams.get_mut(&handle).ok_or_else(|| unknown_stream(handle))?;
+    stream.seek(position)?;
+    Ok(stream.meta.clone())
+}
+
+fn unknown_stream(handle: u64) -> CodecError {
+    CodecError::not_found(format!("Unknown video stream {}", handle))
+}
+
+pub(crate) fn close_stream(handle: u64) -> bool {
+    STREAMS.lock().unwrap().remove(&handle).is_some()
+}
@@ -0,0 +1,134 @@
+use crate::frame::{FrameDecoder, FrameTarget};
+use crate::keyframes::list_keyframes;
+use crate::probe::probe_video_file;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling::{self, Flags};
+use ffmpeg_next::{codec, encoder, frame, Packet, Rational};
+use rehash_codec_ffi::codec::{Thumbnail, ThumbnailIndex, VideoFrame};
+use rehash_codec_ffi::progress::CodecProgress;
+use std::fs;
+use std::path::Path;
+
+
+struct ThumbnailEncoder {
+    encoder: encoder::Video,
+    scaler: scaling::Context,
+    source_width: u32,
+    source_height: u32,
+    width: u32,
+    height: u32,
+    next_pts: i64,
+}
+
+
+impl ThumbnailEncoder {
+    fn new(source_width: u32, source_height: u32, width: u32) -> Result<Self, String> {
+        let width = width.min(source_width).max(2) & !1;
+        // jpeg chroma subsampling wants even dimensions
+        let height = ((source_height as f64 * width as f64 / source_width as f64).round() as u32).max(2) & !1;
+
+        let jpeg = encoder::find(codec::Id::MJPEG).ok_or("MJPEG encoder is not available")?;
+        let mut context = codec::context::Context::new_with_codec(jpeg).encoder().video()
+            .map_err(|e| format!("Failed to create thumbnail encoder: {}", e))?;
+        context.set_width(width);
+        context.set_height(height);
+        context.set_format(Pixel::YUVJ420P);
+        context.set_time_base(Rational(1, 1));
+        let encoder = context.open_as(jpeg).map_err(|e| format!("Failed to open thumbnail encoder: {}", e))?;
+
+        let scaler = scaling::Context::get(Pixel::RGBA, source_width, source_height, Pixel::YUVJ420P, width, height, Flags::AREA)
+            .map_err(|e| format!("Failed to create thumbnail scaler: {}", e))?;
+
+        Ok(Self { encoder, scaler, source_width, source_height, width, height, next_pts: 0 })
+    }
+
+    fn encode(&mut self, source: &VideoFrame) -> Result<Vec<u8>, String> {
+        if source.width != self.source_width || source.height != self.source_height {
+            return Err(format!("Frame size changed to {}x{}", source.width, source.height));
+        }
+
+        let mut rgba_frame = frame::Video::new(Pixel::RGBA, source.width, source.height);
+        let row_size = source.width as usize * 4;
+        let stride = rgba_frame.stride(0);
+        let data = rgba_frame.data_mut(0);
+        for (row, pixels) in source.rgba.chunks_exact(row_size).enumerate() {
+            data[row * stride..row * stride + row_size].copy_from_slice(pixels);
+        }
+
+        let mut scaled = frame::Video::empty();
+        self.scaler.run(&rgba_frame, &mut scaled).map_err(|e| format!("Failed to scale thumbnail: {}", e))?;
+        scaled.set_pts(Some(self.next_pts));
+        self.next_pts += 1;
+
+        // mjpeg is intra only, every frame sent produces a packet straight away
+        self.encoder.send_frame(&scaled).map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+        let mut packet = Packet::empty();
+        self.encoder.receive_packet(&mut packet).map_err(|e| format!("Failed to receive thumbnail: {}", e))?;
+
+        packet.data().map(|data| data.to_vec()).ok_or("Thumbnail encoder returned no data".to_string())
+    }
+}
+
+
+/// Writes jpeg thumbnails of `path` into `output_dir` every `interval` seconds, or at every keyframe
+/// when `interval` is not positive, progress counts thumbnails rather than frames
+pub(crate) fn generate_thumbnails(path: &str, output_dir: &str, interval: f64, width: u32, progress: &CodecProgress) -> Result<ThumbnailIndex, String> {
+    if width == 0 {
+        return Err("Thumbnail width must be greater than zero".to_string());
+    }
+
+    let duration = probe_video_file(path)?.duration;
+    let interval = if interval > 0f64 { Some(interval) } else { None };
+
+    let targets: Vec<f64> = match interval {
+        Some(interval) => (0..).map(|i| i as f64 * interval).take_while(|t| *t < duration.max(interval)).collect(),
+        None => list_keyframes(path, progress)?,
+    };
+    let total = targets.len() as u64;
+
+    let output_dir = Path::new(output_dir);
+    fs::create_dir_all(output_dir).map_err(|e| format!("Failed to create thumbnail directory: {}", e))?;
+
+    let mut decoder = FrameDecoder::open(path)?;
+    let mut thumbnail_encoder: Option<ThumbnailEncoder> = None;
+    let mut thumbnails: Vec<Thumbnail> = Vec::with_capacity(targets.len());
+
+    for (i, target) in targets.into_iter().enumerate() {
+        progress.report(i as u64, total)?;
+        let frame = match decoder.decode_at(FrameTarget::Seconds(target)) {
+            Ok(frame) => frame,
+            Err(e) => {
+                println!("Skipping thumbnail at {}: {}", target, e);
+                continue;
+            }
+        };
+
+        // short intervals on low frame rate footage can land on the same frame twice
+        if thumbnails.last().is_some_and(|t| t.timestamp == frame.timestamp) {
+            continue;
+        }
+
+        if thumbnail_encoder.is_none() {
+            thumbnail_encoder = Some(ThumbnailEncoder::new(frame.width, frame.height, width)?);
+        }
+        let jpeg = thumbnail_encoder.as_mut().unwrap().encode(&frame)?;
+
+        let file_name = format!("{:06}.jpg", thumbnails.len());
+        fs::write(output_dir.join(&file_name), jpeg).map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+        thumbnails.push(Thumbnail { timestamp: frame.timestamp, file_name });
+    }
+
+    progress.report(total, total)?;
+
+    let (width, height) = thumbnail_encoder.map(|e| (e.width, e.height)).unwrap_or((0, 0));
+    println!("Generated {} thumbnails for {} in {}", thumbnails.len(), path, output_dir.display());
+
+    Ok(ThumbnailIndex {
+        file_path: path.to_string(),
+        duration,
+        interval,
+        width,
+        height,
+        thumbnails,
+    })
+}
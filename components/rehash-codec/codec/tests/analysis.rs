@@ -0,0 +1,73 @@
+mod common;
+
+use common::{assert_close, assert_status, codec, fixture, H264_MP4, MPEG4_MOV, WHISTLE_MP4};
+use rehash_codec_ffi::codec::{AudioEventKind, PlayBoundary};
+use rehash_codec_ffi::error::CodecStatus;
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+
+#[test]
+fn plays_lie_within_the_video() {
+    let processed = Arc::new(AtomicU64::new(0));
+    let reported = processed.clone();
+    let progress = ProgressReporter::new(move |done, _| reported.store(done, Ordering::Relaxed));
+
+    let plays = codec().detect_plays(&fixture(&H264_MP4), &progress).unwrap();
+    assert_close(plays.duration, H264_MP4.duration(), 0.1);
+    assert!(processed.load(Ordering::Relaxed) > 0);
+
+    let mut previous_end = 0f64;
+    for candidate in &plays.candidates {
+        assert!(candidate.start >= previous_end && candidate.start < candidate.end, "{:?}", plays.candidates);
+        assert!(candidate.end <= plays.duration + 1e-6, "{:?}", plays.candidates);
+        previous_end = candidate.end;
+    }
+    if let Some(first) = plays.candidates.first() {
+        assert_eq!(first.boundary, PlayBoundary::StreamStart);
+    }
+}
+
+#[test]
+fn a_still_picture_has_no_plays() {
+    let plays = codec().detect_plays(&fixture(&WHISTLE_MP4), &ProgressReporter::silent()).unwrap();
+    assert!(plays.candidates.is_empty(), "{:?}", plays.candidates);
+}
+
+#[test]
+fn whistles_and_silence_are_found_where_they_were_rendered() {
+    let analysis = codec().detect_audio_events(&fixture(&WHISTLE_MP4), &ProgressReporter::silent()).unwrap();
+    assert_close(analysis.duration, WHISTLE_MP4.duration(), 0.1);
+
+    let whistles = analysis.events.iter().filter(|event| event.kind == AudioEventKind::Whistle).collect::<Vec<_>>();
+    assert_eq!(whistles.len(), 1, "{:?}", analysis.events);
+    assert_close(whistles[0].start, 2.0, 0.1);
+    assert_close(whistles[0].end, 3.0, 0.1);
+    assert_eq!(analysis.next_whistle(0.0).map(|whistle| whistle.start), Some(whistles[0].start));
+    assert!(analysis.next_whistle(2.5).is_none());
+
+    let silences = analysis.events.iter().filter(|event| event.kind == AudioEventKind::Silence).collect::<Vec<_>>();
+    assert_eq!(silences.len(), 2, "{:?}", analysis.events);
+    assert_close(silences[0].start, 0.0, 0.1);
+    assert_close(silences[0].end, 2.0, 0.3);
+    assert_close(silences[1].start, 3.0, 0.3);
+}
+
+#[test]
+fn a_steady_tone_is_not_silence() {
+    let analysis = codec().detect_audio_events(&fixture(&H264_MP4), &ProgressReporter::silent()).unwrap();
+    assert!(analysis.events.iter().all(|event| event.kind != AudioEventKind::Silence), "{:?}", analysis.events);
+}
+
+#[test]
+fn analysis_errors_carry_their_status() {
+    assert_status(codec().detect_audio_events(&fixture(&MPEG4_MOV), &ProgressReporter::silent()), CodecStatus::Decode);
+    assert_status(codec().detect_plays(&fixture(&H264_MP4).with_extension("missing"), &ProgressReporter::silent()), CodecStatus::Decode);
+
+    let cancelled = ProgressReporter::silent();
+    cancelled.token().cancel();
+    assert_status(codec().detect_plays(&fixture(&H264_MP4), &cancelled), CodecStatus::Cancelled);
+    assert_status(codec().detect_audio_events(&fixture(&H264_MP4), &cancelled), CodecStatus::Cancelled);
+    assert_status(codec().get_keyframe_index(&fixture(&H264_MP4), &cancelled), CodecStatus::Cancelled);
+}
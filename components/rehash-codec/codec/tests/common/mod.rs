@@ -0,0 +1,215 @@
+#![allow(dead_code)]
+
+use rehash_codec_ffi::codec::{ImportStatus, ReelStatus};
+use rehash_codec_ffi::error::{CodecError, CodecStatus};
+use rehash_codec_ffi::RehashCodecLibrary;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+#[cfg(target_os = "linux")]
+const LIBRARY_NAME: &str = "librehashcodec.so";
+
+#[cfg(target_os = "windows")]
+const LIBRARY_NAME: &str = "rehashcodec.dll";
+
+const JOB_TIMEOUT: Duration = Duration::from_secs(120);
+
+
+/// Test clip rendered from ffmpeg's lavfi sources the first time a test asks for it
+pub struct Clip {
+    pub name: &'static str,
+    pub extension: &'static str,
+    /// lavfi video source, its size, rate and duration are filled in from the fields below
+    pub source: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: u32,
+    pub seconds: u32,
+    /// frames between keyframes
+    pub gop: u32,
+    pub codec_name: &'static str,
+    pub video_args: &'static [&'static str],
+    /// lavfi audio source and its encoder arguments
+    pub audio: Option<(&'static str, &'static [&'static str])>,
+}
+
+
+impl Clip {
+    pub fn duration(&self) -> f64 {
+        f64::from(self.seconds)
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        u64::from(self.frame_rate * self.seconds)
+    }
+
+    fn video_source(&self) -> String {
+        let separator = if self.source.contains('=') { ':' } else { '=' };
+        format!("{}{}size={}x{}:rate={}:duration={}", self.source, separator, self.width, self.height, self.frame_rate, self.seconds)
+    }
+}
+
+
+const H264: &[&str] = &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-bf", "0", "-sc_threshold", "0"];
+const AAC: &[&str] = &["-c:a", "aac", "-b:a", "96k"];
+
+pub const H264_MP4: Clip = Clip {
+    name: "h264_aac_30fps",
+    extension: "mp4",
+    source: "testsrc",
+    width: 320,
+    height: 240,
+    frame_rate: 30,
+    seconds: 4,
+    gop: 30,
+    codec_name: "h264",
+    video_args: H264,
+    audio: Some(("sine=frequency=440:sample_rate=48000:duration=4", AAC)),
+};
+
+pub const H264_MKV: Clip = Clip {
+    name: "h264_aac_25fps",
+    extension: "mkv",
+    source: "testsrc",
+    width: 640,
+    height: 360,
+    frame_rate: 25,
+    seconds: 3,
+    gop: 25,
+    codec_name: "h264",
+    video_args: H264,
+    audio: Some(("sine=frequency=880:sample_rate=44100:duration=3", AAC)),
+};
+
+pub const MPEG4_MOV: Clip = Clip {
+    name: "mpeg4_24fps",
+    extension: "mov",
+    source: "testsrc",
+    width: 352,
+    height: 288,
+    frame_rate: 24,
+    seconds: 2,
+    gop: 12,
+    codec_name: "mpeg4",
+    video_args: &["-c:v", "mpeg4", "-q:v", "5"],
+    audio: None,
+};
+
+pub const VP9_WEBM: Clip = Clip {
+    name: "vp9_opus_30fps",
+    extension: "webm",
+    source: "testsrc",
+    width: 256,
+    height: 144,
+    frame_rate: 30,
+    seconds: 2,
+    gop: 30,
+    codec_name: "vp9",
+    video_args: &["-c:v", "libvpx-vp9", "-deadline", "realtime", "-b:v", "200k"],
+    audio: Some(("sine=frequency=440:sample_rate=48000:duration=2", &["-c:a", "libopus"])),
+};
+
+/// A still picture over two seconds of silence, a one second 3kHz tone and two more of silence
+pub const WHISTLE_MP4: Clip = Clip {
+    name: "still_whistle_10fps",
+    extension: "mp4",
+    source: "color=c=darkgreen",
+    width: 160,
+    height: 120,
+    frame_rate: 10,
+    seconds: 5,
+    gop: 10,
+    codec_name: "h264",
+    video_args: H264,
+    audio: Some(("aevalsrc=if(between(t\\,2\\,3)\\,0.5*sin(2*PI*3000*t)\\,0):s=48000:d=5", AAC)),
+};
+
+pub const ALL_CLIPS: [&Clip; 5] = [&H264_MP4, &H264_MKV, &MPEG4_MOV, &VP9_WEBM, &WHISTLE_MP4];
+
+
+/// The codec cargo built for this test run, loaded through the same loader the app uses
+pub fn codec() -> &'static RehashCodecLibrary {
+    static CODEC: OnceLock<RehashCodecLibrary> = OnceLock::new();
+
+    CODEC.get_or_init(|| RehashCodecLibrary::new(&library_path().to_string_lossy()).unwrap_or_else(|e| panic!("{}", e)))
+}
+
+/// The cdylib lands next to the deps directory holding the test binary
+pub fn library_path() -> PathBuf {
+    let exe = std::env::current_exe().expect("Failed to find the test binary");
+    exe.parent().and_then(Path::parent).expect("Test binary is not in a target directory").join(LIBRARY_NAME)
+}
+
+/// Path of `clip`, rendering it with the ffmpeg CLI if an earlier run hasn't already
+pub fn fixture(clip: &Clip) -> PathBuf {
+    static RENDERING: Mutex<()> = Mutex::new(());
+    let _rendering = RENDERING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("codec-fixtures");
+    let path = dir.join(format!("{}.{}", clip.name, clip.extension));
+    if path.is_file() {
+        return path;
+    }
+    fs::create_dir_all(&dir).expect("Failed to create the fixture directory");
+
+    // rendered under another name first so a run killed halfway doesn't leave a truncated clip behind
+    let part = dir.join(format!("{}.part.{}", clip.name, clip.extension));
+    let gop = clip.gop.to_string();
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-loglevel", "error", "-y", "-f", "lavfi", "-i"]).arg(clip.video_source());
+    if let Some((source, _)) = clip.audio {
+        command.args(["-f", "lavfi", "-i", source]);
+    }
+    command.args(clip.video_args).args(["-g", &gop, "-keyint_min", &gop]);
+    if let Some((_, audio_args)) = clip.audio {
+        command.args(audio_args);
+    }
+    command.args(["-t", &clip.seconds.to_string()]).arg(&part);
+
+    let output = command.output().unwrap_or_else(|e| panic!("ffmpeg must be on PATH to render test clips: {}", e));
+    assert!(output.status.success(), "ffmpeg failed to render {}: {}", clip.name, String::from_utf8_lossy(&output.stderr));
+    fs::rename(&part, &path).expect("Failed to move the rendered clip into place");
+    path
+}
+
+/// Empty directory for the files one test writes
+pub fn output_dir(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("codec-outputs").join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Failed to create the output directory");
+    dir
+}
+
+pub fn wait_for_import(job: u64) -> ImportStatus {
+    wait_for(|| codec().get_import_status(job).map(|status| status.is_done().then_some(status)))
+}
+
+pub fn wait_for_reel(job: u64) -> ReelStatus {
+    wait_for(|| codec().get_reel_status(job).map(|status| status.is_done().then_some(status)))
+}
+
+fn wait_for<T>(poll: impl Fn() -> Result<Option<T>, CodecError>) -> T {
+    let started = Instant::now();
+    loop {
+        if let Some(done) = poll().unwrap() {
+            return done;
+        }
+        assert!(started.elapsed() < JOB_TIMEOUT, "Job didn't finish within {:?}", JOB_TIMEOUT);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+pub fn assert_status<T: std::fmt::Debug>(result: Result<T, CodecError>, status: CodecStatus) -> CodecError {
+    let error = result.expect_err("Expected the codec call to fail");
+    assert_eq!(error.status, status, "{}", error);
+    error
+}
+
+pub fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+}
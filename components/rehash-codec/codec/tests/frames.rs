@@ -0,0 +1,151 @@
+mod common;
+
+use common::{assert_close, assert_status, codec, fixture, output_dir, Clip, H264_MKV, H264_MP4, MPEG4_MOV, VP9_WEBM};
+use rehash_codec_ffi::codec::VideoFrame;
+use rehash_codec_ffi::error::CodecStatus;
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+
+fn decode(handle: u64, position: f64, is_frame_index: bool) -> VideoFrame {
+    let bytes = codec().decode_frame_at(handle, position, is_frame_index).unwrap();
+    VideoFrame::from_bytes(&bytes).expect("Frame bytes don't match their header")
+}
+
+fn open(clip: &Clip) -> u64 {
+    codec().open_video_stream(&fixture(clip), 1 << 20).unwrap().handle
+}
+
+#[test]
+fn frames_decode_at_the_requested_index_and_time() {
+    for clip in [&H264_MP4, &H264_MKV, &MPEG4_MOV, &VP9_WEBM] {
+        let handle = open(clip);
+        let frame_rate = f64::from(clip.frame_rate);
+
+        for index in [0, 1, u64::from(clip.gop) + 3, clip.frame_count() - 1, 5] {
+            let frame = decode(handle, index as f64, true);
+            assert_eq!((frame.width, frame.height), (clip.width, clip.height), "{}", clip.name);
+            assert_eq!(frame.frame_index, index, "{}", clip.name);
+            assert_close(frame.timestamp, index as f64 / frame_rate, 1e-3);
+        }
+
+        let frame = decode(handle, 1.0, false);
+        assert_eq!(frame.frame_index, u64::from(clip.frame_rate), "{}", clip.name);
+        assert_close(frame.timestamp, 1.0, 1e-3);
+
+        codec().close_video_stream(handle).unwrap();
+    }
+}
+
+#[test]
+fn decoding_the_same_frame_again_is_byte_exact() {
+    let handle = open(&H264_MP4);
+
+    let first = codec().decode_frame_at(handle, 40.0, true).unwrap();
+    // moving away and back again goes through a seek rather than the cached frame
+    codec().decode_frame_at(handle, 110.0, true).unwrap();
+    codec().decode_frame_at(handle, 2.0, true).unwrap();
+    assert_eq!(codec().decode_frame_at(handle, 40.0, true).unwrap(), first);
+
+    // testsrc draws a moving pattern, so neighbouring frames differ
+    assert_ne!(codec().decode_frame_at(handle, 41.0, true).unwrap(), first);
+    codec().close_video_stream(handle).unwrap();
+}
+
+#[test]
+fn decoding_needs_an_open_stream() {
+    let handle = open(&H264_MP4);
+    decode(handle, 0.0, true);
+    codec().close_video_stream(handle).unwrap();
+
+    assert_status(codec().decode_frame_at(handle, 0.0, true), CodecStatus::NotFound);
+}
+
+#[test]
+fn thumbnails_are_taken_at_every_interval() {
+    let dir = output_dir("thumbnails_interval");
+    let index = codec().generate_thumbnails(&fixture(&H264_MP4), &dir, 1.0, 160, &ProgressReporter::silent()).unwrap();
+
+    assert_eq!(index.interval, Some(1.0));
+    assert_eq!((index.width, index.height), (160, 120));
+    assert_close(index.duration, H264_MP4.duration(), 0.1);
+
+    // audio priming can make the file a little longer than its video, the last interval then lands on the final frame
+    let timestamps = index.thumbnails.iter().map(|thumbnail| thumbnail.timestamp).collect::<Vec<_>>();
+    assert!((H264_MP4.seconds as usize..=H264_MP4.seconds as usize + 1).contains(&timestamps.len()), "{:?}", timestamps);
+    for (i, timestamp) in timestamps.iter().take(H264_MP4.seconds as usize).enumerate() {
+        assert_close(*timestamp, i as f64, 1e-3);
+    }
+    assert!(timestamps.iter().all(|timestamp| *timestamp < H264_MP4.duration()));
+
+    for thumbnail in &index.thumbnails {
+        let jpeg = fs::read(dir.join(&thumbnail.file_name)).unwrap();
+        assert_eq!(jpeg[..2], [0xff, 0xd8], "{} is not a JPEG", thumbnail.file_name);
+    }
+}
+
+#[test]
+fn thumbnails_without_an_interval_are_taken_at_keyframes() {
+    let dir = output_dir("thumbnails_keyframes");
+    let processed = Arc::new(AtomicU64::new(0));
+    let reported = processed.clone();
+    let progress = ProgressReporter::new(move |done, _| reported.store(done, Ordering::Relaxed));
+
+    let index = codec().generate_thumbnails(&fixture(&H264_MKV), &dir, 0.0, 320, &progress).unwrap();
+    assert_eq!(index.interval, None);
+    assert_eq!((index.width, index.height), (320, 180));
+
+    let keyframes = codec().get_keyframe_index(&fixture(&H264_MKV), &ProgressReporter::silent()).unwrap().keyframes;
+    let timestamps = index.thumbnails.iter().map(|thumbnail| thumbnail.timestamp).collect::<Vec<_>>();
+    assert_eq!(timestamps.len(), keyframes.len());
+    for (timestamp, keyframe) in timestamps.iter().zip(&keyframes) {
+        assert_close(*timestamp, *keyframe, 1e-3);
+    }
+    assert_eq!(processed.load(Ordering::Relaxed), keyframes.len() as u64);
+}
+
+#[test]
+fn thumbnails_need_a_width() {
+    let error = assert_status(
+        codec().generate_thumbnails(&fixture(&H264_MP4), &output_dir("thumbnails_width"), 1.0, 0, &ProgressReporter::silent()),
+        CodecStatus::Decode,
+    );
+    assert!(error.message.contains("width"), "{}", error);
+}
+
+#[test]
+fn keyframes_follow_the_gop() {
+    for clip in [&H264_MP4, &H264_MKV] {
+        let index = codec().get_keyframe_index(&fixture(clip), &ProgressReporter::silent()).unwrap();
+        assert_close(index.duration, clip.duration(), 0.1);
+
+        // x264 was told not to add keyframes on scene changes, so they are exactly one GOP apart
+        let expected = (0..clip.frame_count()).step_by(clip.gop as usize).map(|frame| frame as f64 / f64::from(clip.frame_rate)).collect::<Vec<_>>();
+        assert_eq!(index.keyframes.len(), expected.len(), "{}: {:?}", clip.name, index.keyframes);
+        for (keyframe, expected) in index.keyframes.iter().zip(expected) {
+            assert_close(*keyframe, expected, 1e-3);
+        }
+        assert_eq!(index.nearest(1.4), Some(index.keyframes[1]));
+    }
+
+    // the mpeg4 encoder may add keyframes of its own, but never leaves one out
+    let index = codec().get_keyframe_index(&fixture(&MPEG4_MOV), &ProgressReporter::silent()).unwrap();
+    assert!(index.keyframes.windows(2).all(|pair| pair[0] < pair[1]));
+    for expected in [0.0, 0.5, 1.0, 1.5] {
+        assert!(index.keyframes.iter().any(|keyframe| (keyframe - expected).abs() < 1e-3), "{} missing from {:?}", expected, index.keyframes);
+    }
+}
+
+#[test]
+fn keyframe_index_reports_progress_and_errors() {
+    let last = Arc::new(AtomicU64::new(0));
+    let reported = last.clone();
+    let progress = ProgressReporter::new(move |done, _| reported.store(done, Ordering::Relaxed));
+    codec().get_keyframe_index(&fixture(&H264_MP4), &progress).unwrap();
+    assert_eq!(last.load(Ordering::Relaxed), H264_MP4.frame_count());
+
+    assert_status(codec().get_keyframe_index(Path::new("/nonexistent/missing.mp4"), &ProgressReporter::silent()), CodecStatus::Decode);
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use common::{assert_close, assert_status, codec, fixture, output_dir, wait_for_import, wait_for_reel, H264_MKV, H264_MP4, MPEG4_MOV, VP9_WEBM};
+use rehash_codec_ffi::codec::{ClipMode, ImportMode, JobState, ReelSegment};
+use rehash_codec_ffi::error::CodecStatus;
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::path::Path;
+
+
+fn frame_at(path: &Path, index: u64) -> Vec<u8> {
+    let handle = codec().open_video_stream(path, 1 << 20).unwrap().handle;
+    let frame = codec().decode_frame_at(handle, index as f64, true).unwrap();
+    codec().close_video_stream(handle).unwrap();
+    frame
+}
+
+#[test]
+fn import_remuxes_playable_streams() {
+    let output = output_dir("import_remux").join("remuxed.mp4");
+    let job = codec().start_import(&fixture(&H264_MKV), &output).unwrap();
+
+    let status = wait_for_import(job);
+    assert_eq!(status.state, JobState::Finished, "{:?}", status.error);
+    assert_eq!(status.mode, ImportMode::Remux);
+    assert_eq!(status.output_path, output.to_string_lossy());
+
+    let probe = codec().probe_video(&output).unwrap();
+    assert!(!probe.needs_import(), "{:?}", probe.unsupported_reason());
+    assert_close(probe.duration, H264_MKV.duration(), 0.1);
+    assert_eq!(probe.audio().unwrap().codec_name, "aac");
+
+    // the video packets are copied, so they decode to the same pixels
+    assert_eq!(frame_at(&output, 30), frame_at(&fixture(&H264_MKV), 30));
+
+    assert!(codec().close_import(job).unwrap());
+    assert!(!codec().close_import(job).unwrap());
+    assert_status(codec().get_import_status(job), CodecStatus::NotFound);
+}
+
+#[test]
+fn import_transcodes_unplayable_streams() {
+    for clip in [&MPEG4_MOV, &VP9_WEBM] {
+        let output = output_dir(&format!("import_{}", clip.name)).join("transcoded.mp4");
+        let job = codec().start_import(&fixture(clip), &output).unwrap();
+
+        let status = wait_for_import(job);
+        assert_eq!(status.state, JobState::Finished, "{}: {:?}", clip.name, status.error);
+        assert_eq!(status.mode, ImportMode::Transcode, "{}", clip.name);
+        assert_eq!(status.progress, 1f64);
+
+        let probe = codec().probe_video(&output).unwrap();
+        let video = probe.video().unwrap();
+        assert_eq!(video.codec_name, "h264");
+        assert_eq!((video.width, video.height), (clip.width, clip.height));
+        assert_eq!(video.frame_count as u64, clip.frame_count(), "{}", clip.name);
+        assert_close(probe.duration, clip.duration(), 0.1);
+        assert!(!probe.needs_import(), "{:?}", probe.unsupported_reason());
+        codec().close_import(job).unwrap();
+    }
+}
+
+#[test]
+fn proxies_are_scaled_to_the_requested_height() {
+    let output = output_dir("proxy").join("proxy.mp4");
+    let job = codec().start_proxy(&fixture(&H264_MKV), &output, 180).unwrap();
+
+    let status = wait_for_import(job);
+    assert_eq!(status.state, JobState::Finished, "{:?}", status.error);
+    assert_eq!(status.mode, ImportMode::Proxy);
+
+    let probe = codec().probe_video(&output).unwrap();
+    let video = probe.video().unwrap();
+    assert_eq!((video.width, video.height), (320, 180));
+    assert_close(probe.duration, H264_MKV.duration(), 0.1);
+
+    // a keyframe every second keeps seeking in the proxy cheap
+    let keyframes = codec().get_keyframe_index(&output, &ProgressReporter::silent()).unwrap().keyframes;
+    assert!(keyframes.windows(2).all(|pair| pair[1] - pair[0] <= 1.0 + 1e-3), "{:?}", keyframes);
+    codec().close_import(job).unwrap();
+}
+
+#[test]
+fn import_errors_carry_their_status() {
+    let output = output_dir("import_errors").join("out.mp4");
+    assert_status(codec().start_import(Path::new("/nonexistent/missing.mkv"), &output), CodecStatus::Failed);
+    assert_status(codec().start_proxy(&fixture(&H264_MKV), &output, 0), CodecStatus::Failed);
+    assert_status(codec().get_import_status(u64::MAX), CodecStatus::NotFound);
+    assert!(!codec().close_import(u64::MAX).unwrap());
+}
+
+#[test]
+fn clips_on_keyframes_are_copied() {
+    let output = output_dir("clip_copy").join("clip.mp4");
+    let source = fixture(&H264_MP4);
+    let clip = codec().export_clip(&source, &output, 1.0, 3.0, &ProgressReporter::silent()).unwrap();
+
+    assert_eq!(clip.mode, ClipMode::Copy);
+    assert_eq!((clip.start, clip.end), (1.0, 3.0));
+    assert_close(codec().probe_video(&output).unwrap().duration, 2.0, 0.1);
+    assert_eq!(frame_at(&output, 0), frame_at(&source, 30));
+}
+
+#[test]
+fn clips_between_keyframes_are_smart_cut() {
+    let output = output_dir("clip_smart_cut").join("clip.mp4");
+    let clip = codec().export_clip(&fixture(&H264_MP4), &output, 1.5, 2.5, &ProgressReporter::silent()).unwrap();
+
+    assert_eq!(clip.mode, ClipMode::SmartCut);
+    let probe = codec().probe_video(&output).unwrap();
+    assert_close(probe.duration, 1.0, 0.1);
+    assert_eq!(probe.video().unwrap().frame_count as u64, u64::from(H264_MP4.frame_rate));
+}
+
+#[test]
+fn clips_of_uncopyable_video_are_reencoded() {
+    let output = output_dir("clip_reencode").join("clip.mp4");
+    let clip = codec().export_clip(&fixture(&MPEG4_MOV), &output, 0.5, 1.5, &ProgressReporter::silent()).unwrap();
+
+    assert_eq!(clip.mode, ClipMode::Reencode);
+    let probe = codec().probe_video(&output).unwrap();
+    assert_eq!(probe.video().unwrap().codec_name, "h264");
+    assert_close(probe.duration, 1.0, 0.1);
+}
+
+#[test]
+fn clip_errors_carry_their_status() {
+    let dir = output_dir("clip_errors");
+    let progress = ProgressReporter::silent();
+
+    let error = assert_status(codec().export_clip(&fixture(&H264_MP4), &dir.join("backwards.mp4"), 2.0, 1.0, &progress), CodecStatus::Encode);
+    assert!(error.message.contains("Invalid clip range"), "{}", error);
+    assert_status(codec().export_clip(Path::new("/nonexistent/missing.mp4"), &dir.join("missing.mp4"), 0.0, 1.0, &progress), CodecStatus::Encode);
+
+    let cancelled = ProgressReporter::silent();
+    cancelled.token().cancel();
+    assert_status(codec().export_clip(&fixture(&H264_MP4), &dir.join("cancelled.mp4"), 1.5, 2.5, &cancelled), CodecStatus::Cancelled);
+    assert!(!dir.join("cancelled.mp4").exists());
+    assert!(!dir.join("cancelled.mp4.part").exists());
+}
+
+#[test]
+fn reels_join_segments_in_the_first_segment_format() {
+    let output = output_dir("reel").join("reel.mp4");
+    let segments = [
+        ReelSegment { file_path: fixture(&H264_MP4).to_string_lossy().to_string(), start: 0.5, end: 1.5 },
+        ReelSegment { file_path: fixture(&H264_MKV).to_string_lossy().to_string(), start: 1.0, end: 2.0 },
+        ReelSegment { file_path: fixture(&MPEG4_MOV).to_string_lossy().to_string(), start: 0.0, end: 0.5 },
+    ];
+    let job = codec().start_reel(&segments, &output).unwrap();
+
+    let status = wait_for_reel(job);
+    assert_eq!(status.state, JobState::Finished, "{:?}", status.error);
+    assert_eq!(status.segment_count, segments.len());
+    assert_eq!((status.width, status.height), (H264_MP4.width, H264_MP4.height));
+    assert_close(status.frame_rate, f64::from(H264_MP4.frame_rate), 1e-6);
+
+    let probe = codec().probe_video(&output).unwrap();
+    let video = probe.video().unwrap();
+    assert_eq!((video.width, video.height), (H264_MP4.width, H264_MP4.height));
+    assert_close(probe.duration, segments.iter().map(ReelSegment::duration).sum(), 0.1);
+    assert!(probe.audio().is_some());
+
+    assert!(codec().close_reel(job).unwrap());
+    assert!(!codec().close_reel(job).unwrap());
+}
+
+#[test]
+fn reel_errors_carry_their_status() {
+    let output = output_dir("reel_errors").join("reel.mp4");
+    let path = fixture(&H264_MP4).to_string_lossy().to_string();
+
+    assert_status(codec().start_reel(&[], &output), CodecStatus::Failed);
+    let backwards = ReelSegment { file_path: path, start: 2.0, end: 1.0 };
+    let error = assert_status(codec().start_reel(&[backwards], &output), CodecStatus::Failed);
+    assert!(error.message.contains("Invalid segment"), "{}", error);
+
+    assert_status(codec().get_reel_status(u64::MAX), CodecStatus::NotFound);
+    assert!(!codec().close_reel(u64::MAX).unwrap());
+}
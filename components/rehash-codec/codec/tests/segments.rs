@@ -0,0 +1,113 @@
+mod common;
+
+use common::{assert_close, assert_status, codec, fixture, Clip, H264_MKV, H264_MP4, MPEG4_MOV, VP9_WEBM};
+use rehash_codec_ffi::codec::{ImportMode, SegmentedStream};
+use rehash_codec_ffi::error::CodecStatus;
+use rehash_codec_ffi::progress::ProgressReporter;
+
+
+fn open(clip: &Clip, segment_seconds: f64) -> SegmentedStream {
+    codec().open_segmented_stream(&fixture(clip), segment_seconds, &ProgressReporter::silent()).unwrap()
+}
+
+// Four character types of the top level boxes, in file order
+fn box_types(bytes: &[u8]) -> Vec<String> {
+    let mut types = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= bytes.len() {
+        let size = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        assert!(size >= 8 && offset + size <= bytes.len(), "Truncated box at {}", offset);
+        types.push(String::from_utf8_lossy(&bytes[offset + 4..offset + 8]).to_string());
+        offset += size;
+    }
+    assert_eq!(offset, bytes.len());
+    types
+}
+
+#[test]
+fn segments_cover_the_whole_video() {
+    for clip in [&H264_MP4, &H264_MKV, &MPEG4_MOV, &VP9_WEBM] {
+        let stream = open(clip, 1.0);
+        assert_close(stream.duration, clip.duration(), 0.1);
+
+        let first = stream.segments.first().unwrap();
+        let last = stream.segments.last().unwrap();
+        assert_close(first.start, 0.0, 1e-3);
+        assert_close(last.end, clip.duration(), 0.1);
+        for (index, pair) in stream.segments.windows(2).enumerate() {
+            assert_eq!(pair[0].index, index);
+            assert_eq!(pair[0].end, pair[1].start, "{}", clip.name);
+        }
+
+        codec().close_segmented_stream(stream.handle).unwrap();
+    }
+}
+
+#[test]
+fn playable_sources_are_remuxed_on_their_keyframes() {
+    let stream = open(&H264_MP4, 1.5);
+    assert_eq!(stream.mode, ImportMode::Remux);
+    assert!(stream.mime_type.starts_with("video/mp4; codecs=\"avc1."), "{}", stream.mime_type);
+    assert!(stream.mime_type.ends_with(", mp4a.40.2\""), "{}", stream.mime_type);
+
+    // a keyframe every second, so segments of at least 1.5s span two GOPs
+    let bounds: Vec<_> = stream.segments.iter().map(|segment| (segment.start, segment.end)).collect();
+    assert_eq!(bounds.len(), 2);
+    assert_close(bounds[0].1, 2.0, 1e-3);
+    assert_close(stream.segment_at(2.5).unwrap().start, 2.0, 1e-3);
+    assert_eq!(stream.segment_at(60.0).unwrap().index, 1);
+
+    codec().close_segmented_stream(stream.handle).unwrap();
+}
+
+#[test]
+fn other_sources_are_transcoded_on_a_fixed_grid() {
+    for clip in [&H264_MKV, &MPEG4_MOV, &VP9_WEBM] {
+        let stream = open(clip, 0.5);
+        assert_eq!(stream.mode, if clip.codec_name == "h264" { ImportMode::Remux } else { ImportMode::Transcode }, "{}", clip.name);
+        assert!(stream.mime_type.contains("avc1."), "{}", stream.mime_type);
+        assert_eq!(stream.mime_type.contains("mp4a.40.2"), clip.audio.is_some(), "{}", clip.name);
+
+        if stream.mode == ImportMode::Transcode {
+            assert_eq!(stream.segments.len(), (clip.duration() / 0.5).round() as usize, "{}", clip.name);
+            for segment in &stream.segments {
+                assert_close(segment.timestamp_offset, segment.start, 1e-3);
+            }
+        }
+
+        codec().close_segmented_stream(stream.handle).unwrap();
+    }
+}
+
+#[test]
+fn init_and_media_segments_are_fragmented_mp4() {
+    for clip in [&H264_MP4, &MPEG4_MOV, &VP9_WEBM] {
+        let stream = open(clip, 1.0);
+
+        let init = codec().read_init_segment(stream.handle).unwrap();
+        assert_eq!(box_types(&init), ["ftyp", "moov"], "{}", clip.name);
+
+        for segment in [0, stream.segments.len() - 1] {
+            let media = codec().read_media_segment(stream.handle, segment).unwrap();
+            let types = box_types(&media);
+            assert!(!types.is_empty() && types.chunks(2).all(|pair| pair == ["moof", "mdat"]), "{}: {:?}", clip.name, types);
+        }
+
+        codec().close_segmented_stream(stream.handle).unwrap();
+    }
+}
+
+#[test]
+fn closed_and_unknown_segments_are_errors() {
+    let stream = open(&H264_MP4, 1.0);
+    assert_status(codec().read_media_segment(stream.handle, stream.segments.len()), CodecStatus::InvalidArgument);
+
+    assert!(codec().close_segmented_stream(stream.handle).unwrap());
+    assert!(!codec().close_segmented_stream(stream.handle).unwrap());
+    assert_status(codec().read_init_segment(stream.handle), CodecStatus::NotFound);
+    assert_status(codec().read_media_segment(stream.handle, 0), CodecStatus::NotFound);
+
+    let path = fixture(&H264_MP4);
+    assert_status(codec().open_segmented_stream(&path, 0.0, &ProgressReporter::silent()), CodecStatus::Decode);
+    assert_status(codec().open_segmented_stream(&path.with_extension("missing"), 1.0, &ProgressReporter::silent()), CodecStatus::Decode);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{assert_close, assert_status, codec, fixture, output_dir, ALL_CLIPS, H264_MKV, H264_MP4, MPEG4_MOV};
+use rehash_codec_ffi::error::CodecStatus;
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+const CHUNK_SIZE: usize = 7919;
+
+
+#[test]
+fn print_codec_version_succeeds() {
+    codec().print_codec_version().unwrap();
+}
+
+#[test]
+fn probe_reports_the_rendered_streams() {
+    for clip in ALL_CLIPS {
+        let path = fixture(clip);
+        let probe = codec().probe_video(&path).unwrap();
+        assert_eq!(probe.file_path, path.to_string_lossy());
+        assert_close(probe.duration, clip.duration(), 0.1);
+
+        let video = probe.video().unwrap_or_else(|| panic!("{} has no video stream", clip.name));
+        assert_eq!(video.codec_name, clip.codec_name);
+        assert_eq!((video.width, video.height), (clip.width, clip.height));
+        assert_close(video.frame_rate, f64::from(clip.frame_rate), 0.01);
+        assert_eq!(probe.audio().is_some(), clip.audio.is_some(), "{}", clip.name);
+    }
+}
+
+#[test]
+fn probe_decides_what_needs_importing() {
+    let probe = |clip| codec().probe_video(&fixture(clip)).unwrap();
+
+    assert!(!probe(&H264_MP4).needs_import());
+    assert!(probe(&H264_MKV).needs_import());
+    assert!(probe(&MPEG4_MOV).needs_import());
+    assert_eq!(probe(&MPEG4_MOV).unsupported_reason().as_deref(), Some("Unsupported video codec: mpeg4"));
+}
+
+#[test]
+fn stream_chunks_add_up_to_the_file() {
+    for clip in ALL_CLIPS {
+        let path = fixture(clip);
+        let file = fs::read(&path).unwrap();
+        let meta = codec().open_video_stream(&path, CHUNK_SIZE).unwrap();
+        assert_eq!((meta.current_position, meta.total_size, meta.chunk_size), (0, file.len() as u64, CHUNK_SIZE));
+
+        let mut streamed = Vec::new();
+        while !codec().get_video_stream_meta(meta.handle).unwrap().is_finished() {
+            let position = codec().get_video_stream_meta(meta.handle).unwrap().current_position as usize;
+            let chunk = codec().read_video_stream_chunk(meta.handle).unwrap();
+
+            assert_eq!(chunk.len(), CHUNK_SIZE.min(file.len() - position));
+            assert_eq!(chunk, file[position..position + chunk.len()]);
+            streamed.extend(chunk);
+        }
+
+        assert_eq!(streamed, file, "{}", clip.name);
+        assert!(codec().read_video_stream_chunk(meta.handle).unwrap().is_empty());
+        assert!(codec().close_video_stream(meta.handle).unwrap());
+    }
+}
+
+#[test]
+fn seeking_moves_where_the_next_chunk_is_read() {
+    let path = fixture(&H264_MP4);
+    let file = fs::read(&path).unwrap();
+    let handle = codec().open_video_stream(&path, CHUNK_SIZE).unwrap().handle;
+
+    let position = file.len() / 3;
+    assert_eq!(codec().seek_video_stream(handle, position as u64).unwrap().current_position, position as u64);
+    assert_eq!(codec().read_video_stream_chunk(handle).unwrap(), file[position..position + CHUNK_SIZE]);
+
+    assert_eq!(codec().seek_video_stream(handle, 0).unwrap().current_position, 0);
+    assert_eq!(codec().read_video_stream_chunk(handle).unwrap(), file[..CHUNK_SIZE]);
+
+    // past the end is clamped to it
+    let end = codec().seek_video_stream(handle, u64::MAX).unwrap();
+    assert_eq!(end.current_position, file.len() as u64);
+    assert!(end.is_finished());
+    assert!(codec().read_video_stream_chunk(handle).unwrap().is_empty());
+
+    codec().close_video_stream(handle).unwrap();
+}
+
+#[test]
+fn streams_reach_past_4_gib() {
+    const TOTAL: u64 = 5 << 30;
+    const MARKER_AT: u64 = (4 << 30) + 12345;
+    let marker = b"past the 32-bit offsets";
+
+    // sparse, so it takes no disk space and reads back as zeroes around the marker
+    let path = output_dir("streams_reach_past_4_gib").join("large.mp4");
+    let mut file = File::create(&path).unwrap();
+    file.set_len(TOTAL).unwrap();
+    file.seek(SeekFrom::Start(MARKER_AT)).unwrap();
+    file.write_all(marker).unwrap();
+    drop(file);
+
+    let meta = codec().open_video_stream(&path, CHUNK_SIZE).unwrap();
+    assert_eq!(meta.total_size, TOTAL);
+
+    assert_eq!(codec().seek_video_stream(meta.handle, MARKER_AT).unwrap().current_position, MARKER_AT);
+    let chunk = codec().read_video_stream_chunk(meta.handle).unwrap();
+    assert_eq!(&chunk[..marker.len()], marker);
+    assert_eq!(codec().get_video_stream_meta(meta.handle).unwrap().current_position, MARKER_AT + CHUNK_SIZE as u64);
+
+    codec().seek_video_stream(meta.handle, TOTAL - 10).unwrap();
+    assert_eq!(codec().read_video_stream_chunk(meta.handle).unwrap().len(), 10);
+    assert!(codec().get_video_stream_meta(meta.handle).unwrap().is_finished());
+
+    codec().close_video_stream(meta.handle).unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn streams_are_independent() {
+    let (mp4, mkv) = (fixture(&H264_MP4), fixture(&H264_MKV));
+    let first = codec().open_video_stream(&mp4, CHUNK_SIZE).unwrap().handle;
+    let second = codec().open_video_stream(&mkv, CHUNK_SIZE).unwrap().handle;
+    assert_ne!(first, second);
+
+    codec().read_video_stream_chunk(first).unwrap();
+    assert_eq!(codec().read_video_stream_chunk(second).unwrap(), fs::read(&mkv).unwrap()[..CHUNK_SIZE]);
+    assert_eq!(codec().get_video_stream_meta(first).unwrap().current_position, CHUNK_SIZE as u64);
+
+    assert!(codec().close_video_stream(first).unwrap());
+    assert!(codec().close_video_stream(second).unwrap());
+}
+
+#[test]
+fn streaming_errors_carry_their_status() {
+    let missing = Path::new("/nonexistent/missing.mp4");
+    assert_status(codec().probe_video(missing), CodecStatus::Decode);
+    assert_status(codec().open_video_stream(missing, CHUNK_SIZE), CodecStatus::NotFound);
+    assert_status(codec().open_video_stream(&fixture(&H264_MP4), 0), CodecStatus::InvalidArgument);
+
+    let handle = codec().open_video_stream(&fixture(&H264_MP4), CHUNK_SIZE).unwrap().handle;
+    assert!(codec().close_video_stream(handle).unwrap());
+    assert!(!codec().close_video_stream(handle).unwrap());
+
+    assert_status(codec().get_video_stream_meta(handle), CodecStatus::NotFound);
+    assert_status(codec().read_video_stream_chunk(handle), CodecStatus::NotFound);
+    assert_status(codec().seek_video_stream(handle, 0), CodecStatus::NotFound);
+}
+
+#[test]
+fn probing_a_file_that_is_not_a_video_fails() {
+    let error = assert_status(codec().probe_video(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))), CodecStatus::Decode);
+    assert!(error.message.contains("Failed to probe"), "{}", error);
+}
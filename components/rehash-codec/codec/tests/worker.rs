@@ -0,0 +1,68 @@
+mod common;
+
+use common::{assert_status, fixture, library_path, H264_MP4};
+use rehash_codec_ffi::error::CodecStatus;
+use rehash_codec_ffi::worker::{self, CodecWorker, WorkerCrash, WORKER_ARG};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+const CHUNK_SIZE: usize = 1 << 16;
+const CRASH_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    // the worker is this same test binary, started again with WORKER_ARG
+    if args.get(1).map(String::as_str) == Some(WORKER_ARG) {
+        if let Err(e) = worker::serve(&args[2..]) {
+            eprintln!("Codec worker failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    handles_of_a_killed_worker_are_stale();
+    println!("test handles_of_a_killed_worker_are_stale ... ok");
+}
+
+fn kill(pid: u32) {
+    let status = if cfg!(windows) {
+        Command::new("taskkill").args(["/F", "/PID", &pid.to_string()]).status()
+    } else {
+        Command::new("kill").args(["-9", &pid.to_string()]).status()
+    };
+    assert!(status.is_ok_and(|status| status.success()), "Failed to kill the codec worker {}", pid);
+}
+
+fn handles_of_a_killed_worker_are_stale() {
+    let crashes = Arc::new(Mutex::new(Vec::<WorkerCrash>::new()));
+    let reported = crashes.clone();
+    let program = std::env::current_exe().unwrap();
+    let worker = CodecWorker::spawn(&program, &library_path(), move |crash| reported.lock().unwrap().push(crash.clone())).unwrap();
+
+    let path = fixture(&H264_MP4);
+    let stale = worker.open_video_stream(&path, CHUNK_SIZE).unwrap();
+    assert_eq!(worker.read_video_stream_chunk(stale.handle).unwrap().len(), CHUNK_SIZE);
+
+    kill(worker.process_id().expect("Codec worker is not running"));
+    let started = Instant::now();
+    while crashes.lock().unwrap().is_empty() {
+        assert!(started.elapsed() < CRASH_TIMEOUT, "Killed codec worker was not reported");
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    // the restarted worker numbers its streams from the start again, the old handle must not reach them
+    let fresh = worker.open_video_stream(&path, CHUNK_SIZE).unwrap();
+    assert_ne!(fresh.handle, stale.handle);
+    assert_status(worker.read_video_stream_chunk(stale.handle), CodecStatus::NotFound);
+    assert_status(worker.decode_frame_at(stale.handle, 0.0, true), CodecStatus::NotFound);
+    assert_status(worker.close_video_stream(stale.handle), CodecStatus::NotFound);
+
+    assert_eq!(worker.get_video_stream_meta(fresh.handle).unwrap().handle, fresh.handle);
+    assert_eq!(worker.read_video_stream_chunk(fresh.handle).unwrap().len(), CHUNK_SIZE);
+    assert!(!worker.decode_frame_at(fresh.handle, 0.0, true).unwrap().is_empty());
+    assert!(worker.close_video_stream(fresh.handle).unwrap());
+}
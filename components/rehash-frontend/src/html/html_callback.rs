@@ -1,8 +1,10 @@
 use crate::callback_event;
 use crate::html::html_events::*;
-use crate::html::html_ui::HtmlVideoUIController;
-use crate::html::html_video::{Event, HtmlVideoPlayerInternal};
+use crate::html::html_ui::{HtmlAudioMarkers, HtmlKeyframeIndex, HtmlPlayMarkers, HtmlReelStatus, HtmlThumbnailPreview, HtmlVideoUIController};
+use crate::html::html_media_source::HtmlMediaSourcePlayerInternal;
+use crate::html::html_video::Event;
 use crate::prelude::*;
+use crate::tauri::tauri_events::VideoSession;
 use crate::video::event::{CallbackController, CallbackEvent};
 use crate::video::video_callback::CallbackClosureWrapper;
 use crate::video::video_callback::SharedVideoPlayer;
@@ -13,9 +15,12 @@ use std::rc::Rc;
 use wasm_bindgen::JsCast;
 use web_sys::{DomRect, Element};
 
+pub(crate) use audio_marker_closure::*;
 pub(crate) use control_closure::*;
 pub(crate) use drag_closure::*;
 pub(crate) use keyboard_closure::*;
+pub(crate) use play_marker_closure::*;
+pub(crate) use thumbnail_closure::*;
 pub(crate) use time_update_closure::*;
 
 type KeyControlType = Rc<str>;
@@ -24,6 +29,10 @@ type KeyControlType = Rc<str>;
 pub(crate) struct HtmlVideoCallbackController {
     video_player: SharedVideoPlayer,
     ui_controller: HtmlVideoUIController,
+    thumbnail_preview: HtmlThumbnailPreview,
+    play_markers: HtmlPlayMarkers,
+    audio_markers: HtmlAudioMarkers,
+    keyframe_index: HtmlKeyframeIndex,
     callback_keyboard_events: HashMap<KeyControlType, Event>,
     callback_control_events: HashMap<KeyControlType, Event>,
     callback_progress_event: Event,
@@ -37,13 +46,25 @@ impl HtmlVideoCallbackController {
     const FULLSCREEN_ID: &'static str = "fullscreen";
     const FAST_FORWARD_ID: &'static str = "fast-forward";
     const REWIND_ID: &'static str = "rewind";
+    const EXPORT_CLIP_ID: &'static str = "export-clip";
+    const CANCEL_OPERATION_ID: &'static str = "cancel-operation";
 
-    pub fn new(video_player: SharedVideoPlayer, ui_controller: HtmlVideoUIController) -> Self {
-        let play_pause_event: Event = callback_event!(PlayPauseEvent<HtmlVideoPlayerInternal>);
+    pub fn new(video_player: SharedVideoPlayer, ui_controller: HtmlVideoUIController, thumbnail_preview: HtmlThumbnailPreview,
+               reel_status: HtmlReelStatus, play_markers: HtmlPlayMarkers, audio_markers: HtmlAudioMarkers,
+               keyframe_index: HtmlKeyframeIndex, session: VideoSession) -> Self {
+        let play_pause_event: Event = callback_event!(PlayPauseEvent<HtmlMediaSourcePlayerInternal>);
         let mute_unmute_event: Event = callback_event!(MuteUnmuteEvent);
-        let progress_event: Event = callback_event!(ProgressBarChangeEvent<HtmlVideoPlayerInternal>);
+        let progress_event: Event = callback_event!(ProgressBarChangeEvent<HtmlMediaSourcePlayerInternal>);
         let settings_event: Event = callback_event!(SettingsEvent);
         let fullscreen_event: Event = callback_event!(FullScreenEvent);
+        let export_clip_event: Event = Rc::new(RefCell::new(ExportClipEvent::new(session.clone())));
+        let cancel_operation_event: Event = callback_event!(CancelOperationEvent);
+        let add_to_reel_event: Event = Rc::new(RefCell::new(AddToReelEvent::new(reel_status.clone(), session.clone())));
+        let export_reel_event: Event = callback_event!(ExportReelEvent);
+        let detect_plays_event: Event = Rc::new(RefCell::new(DetectPlaysEvent::new(play_markers.clone(), session.clone())));
+        let accept_plays_event: Event = Rc::new(RefCell::new(AcceptPlaysEvent::new(play_markers.clone(), reel_status, session.clone())));
+        let detect_audio_event: Event = Rc::new(RefCell::new(DetectAudioEvent::new(audio_markers.clone(), session)));
+        let end_at_whistle_event: Event = Rc::new(RefCell::new(EndAtWhistleEvent::new(audio_markers.clone())));
 
 
         let fast_forward_event: Event = callback_event!(FastForwardEvent);
@@ -52,6 +73,8 @@ impl HtmlVideoCallbackController {
         let playback_increase = callback_event!(PlaybackSpeedEvent<PlaybackIncreaseAction>);
         let playback_decrease = callback_event!(PlaybackSpeedEvent<PlaybackDecreaseAction>);
 
+        let filmstrip_event: Event = Rc::new(RefCell::new(FilmstripToggleEvent::new(thumbnail_preview.clone())));
+
         let keyboard_events: HashMap<KeyControlType, Event> = HashMap::from([
             (Rc::from("Space"), play_pause_event.clone()),
             (Rc::from("m"), mute_unmute_event.clone()),
@@ -59,6 +82,15 @@ impl HtmlVideoCallbackController {
             (Rc::from("z"), rewind_event.clone()),
             (Rc::from("ArrowUp"), playback_increase.clone()),
             (Rc::from("ArrowDown"), playback_decrease.clone()),
+            (Rc::from("f"), filmstrip_event.clone()),
+            (Rc::from("e"), export_clip_event.clone()),
+            (Rc::from("a"), add_to_reel_event.clone()),
+            (Rc::from("r"), export_reel_event.clone()),
+            (Rc::from("p"), detect_plays_event.clone()),
+            (Rc::from("A"), accept_plays_event.clone()),
+            (Rc::from("w"), detect_audio_event.clone()),
+            (Rc::from("n"), end_at_whistle_event.clone()),
+            (Rc::from("Escape"), cancel_operation_event.clone()),
         ]);
 
         let control_events: HashMap<KeyControlType, Event> = HashMap::from([
@@ -68,12 +100,18 @@ impl HtmlVideoCallbackController {
             (Rc::from(Self::FULLSCREEN_ID), fullscreen_event.clone()),
             (Rc::from(Self::FAST_FORWARD_ID), fast_forward_event.clone()),
             (Rc::from(Self::REWIND_ID), rewind_event.clone()),
+            (Rc::from(Self::EXPORT_CLIP_ID), export_clip_event.clone()),
+            (Rc::from(Self::CANCEL_OPERATION_ID), cancel_operation_event.clone()),
         ]);
 
 
         Self {
             video_player,
             ui_controller,
+            thumbnail_preview,
+            play_markers,
+            audio_markers,
+            keyframe_index,
             callback_keyboard_events: keyboard_events,
             callback_control_events: control_events,
             callback_progress_event: progress_event,
@@ -107,7 +145,7 @@ impl CallbackController for HtmlVideoCallbackController {
         let end_dot_id: &str = MoveState::EndClipDot.try_into().unwrap();
 
 
-        let drag_ctx = Rc::new(RefCell::new(DragEventCtx::new(self.video_player.clone())));
+        let drag_ctx = Rc::new(RefCell::new(DragEventCtx::new(self.video_player.clone(), self.keyframe_index.clone())));
 
 
         let doc = self.ui_controller.get_document();
@@ -145,6 +183,23 @@ impl CallbackController for HtmlVideoCallbackController {
         self.ui_controller.register_element_event_listener_specific("mousedown", end_dot_id, mouse_click_end_dot_closure);
 
 
+        let thumbnail_hover_wrapper = Box::new(ThumbnailHoverClosure::new(self.thumbnail_preview.clone()));
+        let thumbnail_hover_closure = CallbackClosureWrapper::create_callback(thumbnail_hover_wrapper);
+        self.ui_controller.register_element_event_listener_specific("mousemove", progress_bar_id, thumbnail_hover_closure);
+
+        let thumbnail_leave_wrapper = Box::new(ThumbnailLeaveClosure::new(self.thumbnail_preview.clone()));
+        let thumbnail_leave_closure = CallbackClosureWrapper::create_callback(thumbnail_leave_wrapper);
+        self.ui_controller.register_element_event_listener_specific("mouseleave", progress_bar_id, thumbnail_leave_closure);
+
+        let play_marker_wrapper = Box::new(PlayMarkerClickClosure::new(self.video_player.clone(), self.play_markers.clone()));
+        let play_marker_closure = CallbackClosureWrapper::create_callback(play_marker_wrapper);
+        self.ui_controller.register_element_event_listener_specific("click", HtmlPlayMarkers::PLAY_MARKERS_ID, play_marker_closure);
+
+        let audio_marker_wrapper = Box::new(AudioMarkerClickClosure::new(self.video_player.clone(), self.audio_markers.clone()));
+        let audio_marker_closure = CallbackClosureWrapper::create_callback(audio_marker_wrapper);
+        self.ui_controller.register_element_event_listener_specific("click", HtmlAudioMarkers::AUDIO_MARKERS_ID, audio_marker_closure);
+
+
         let volume_dom_rec = volume_bar_element.get_bounding_client_rect();
         let progress_dom_rec = progress_bar_element.get_bounding_client_rect();
         let element_dom_recs: Vec<(Element, &DomRect)> = vec![
@@ -179,7 +234,7 @@ mod drag_closure {
         slider_width: f64,
         slider_left: f64,
         slider_type: MoveState,
-        callback: DragClickEvent<HtmlVideoPlayerInternal>,
+        callback: DragClickEvent<HtmlMediaSourcePlayerInternal>,
         ctx: DragEventCtxType,
     }
 
@@ -332,6 +387,123 @@ mod drag_closure {
 
 }
 
+mod thumbnail_closure {
+    use super::*;
+
+    #[derive(Debug)]
+    pub(crate) struct ThumbnailHoverClosure {
+        thumbnail_preview: HtmlThumbnailPreview,
+    }
+
+    impl ThumbnailHoverClosure {
+        pub(crate) fn new(thumbnail_preview: HtmlThumbnailPreview) -> Self {
+            Self { thumbnail_preview }
+        }
+    }
+
+    impl CallbackClosureWrapper<web_sys::MouseEvent> for ThumbnailHoverClosure {
+        fn closure(&mut self, event: web_sys::MouseEvent) {
+            let Some(element) = event.current_target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+                return;
+            };
+
+            // measured per event as the bar moves when the filmstrip is toggled
+            let rec = element.get_bounding_client_rect();
+            if rec.width() <= 0f64 {
+                return;
+            }
+            let percent = ((event.client_x() as f64 - rec.left()) / rec.width()).max(0f64).min(1f64);
+            self.thumbnail_preview.show_preview(percent);
+        }
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct ThumbnailLeaveClosure {
+        thumbnail_preview: HtmlThumbnailPreview,
+    }
+
+    impl ThumbnailLeaveClosure {
+        pub(crate) fn new(thumbnail_preview: HtmlThumbnailPreview) -> Self {
+            Self { thumbnail_preview }
+        }
+    }
+
+    impl CallbackClosureWrapper<web_sys::MouseEvent> for ThumbnailLeaveClosure {
+        fn closure(&mut self, _: web_sys::MouseEvent) {
+            self.thumbnail_preview.hide_preview();
+        }
+    }
+}
+
+mod audio_marker_closure {
+    use super::*;
+
+    /// Seeks to the clicked audio event
+    #[derive(Debug)]
+    pub(crate) struct AudioMarkerClickClosure {
+        video_player: SharedVideoPlayer,
+        audio_markers: HtmlAudioMarkers,
+    }
+
+    impl AudioMarkerClickClosure {
+        pub(crate) fn new(video_player: SharedVideoPlayer, audio_markers: HtmlAudioMarkers) -> Self {
+            Self { video_player, audio_markers }
+        }
+    }
+
+    impl CallbackClosureWrapper<web_sys::MouseEvent> for AudioMarkerClickClosure {
+        fn closure(&mut self, event: web_sys::MouseEvent) {
+            let Some(element) = event.target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+                return;
+            };
+            let Some(audio_event) = element.get_attribute("data-index")
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| self.audio_markers.event(index)) else {
+                return;
+            };
+
+            let video_player = self.video_player.borrow();
+            let length = video_player.get_video_length();
+            if length > 0f64 {
+                video_player.set_video_progress((audio_event.start / length).max(0f64).min(1f64));
+            }
+        }
+    }
+}
+
+mod play_marker_closure {
+    use super::*;
+
+    #[derive(Debug)]
+    pub(crate) struct PlayMarkerClickClosure {
+        video_player: SharedVideoPlayer,
+        play_markers: HtmlPlayMarkers,
+    }
+
+    impl PlayMarkerClickClosure {
+        pub(crate) fn new(video_player: SharedVideoPlayer, play_markers: HtmlPlayMarkers) -> Self {
+            Self { video_player, play_markers }
+        }
+    }
+
+    impl CallbackClosureWrapper<web_sys::MouseEvent> for PlayMarkerClickClosure {
+        fn closure(&mut self, event: web_sys::MouseEvent) {
+            let Some(element) = event.target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+                return;
+            };
+            let Some(candidate) = element.get_attribute("data-index")
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| self.play_markers.candidate(index)) else {
+                return;
+            };
+
+            if let Err(e) = select_play(&self.video_player, &candidate) {
+                debug_console_log!("Failed to select play: {}", e);
+            }
+        }
+    }
+}
+
 mod time_update_closure {
     use super::*;
 
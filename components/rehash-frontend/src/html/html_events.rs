@@ -7,13 +7,19 @@ use std::marker::PhantomData;
 use std::ops::Deref;
 
 pub use crate::prelude::*;
+pub(crate) use audio_events::*;
 pub(crate) use drag_events::*;
+pub(crate) use export_clip_event::*;
 pub(crate) use fast_forward_event::*;
+pub(crate) use filmstrip_event::*;
 pub(crate) use fullscreen_event::*;
 pub(crate) use mute_unmute_event::*;
+pub(crate) use operation_events::*;
 pub(crate) use play_pause_event::*;
 pub(crate) use playback_speed_event::*;
+pub(crate) use plays_events::*;
 pub(crate) use progress_bar_change_event::*;
+pub(crate) use reel_events::*;
 pub(crate) use rewind_event::*;
 pub(crate) use settings_event::*;
 
@@ -217,6 +223,405 @@ pub(crate) mod settings_event {
     }
 }
 
+pub(crate) mod filmstrip_event {
+    use super::*;
+    use crate::html::html_ui::HtmlThumbnailPreview;
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct FilmstripToggleEvent {
+        thumbnail_preview: HtmlThumbnailPreview,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for FilmstripToggleEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            self.thumbnail_preview.toggle_filmstrip();
+            Ok(())
+        }
+
+    }
+
+    impl FilmstripToggleEvent {
+        pub fn new(thumbnail_preview: HtmlThumbnailPreview) -> Self {
+            Self {
+                thumbnail_preview
+            }
+        }
+    }
+}
+
+pub(crate) mod export_clip_event {
+    use super::*;
+    use js_sys::Reflect;
+    use crate::tauri::tauri_events::VideoSession;
+    use rehash_codec_ffi::codec::ClipExport;
+    use rehash_utils::utils::tauri_invoke;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+    /// Times of the start and end dots
+    pub(crate) fn clip_range(ctx: &SharedVideoPlayer) -> RehashResult<(f64, f64)> {
+        let video_player = ctx.borrow();
+        // the end dot reports a negative time until it has been moved
+        let end = match video_player.get_max_progress() {
+            end if end < 0f64 => video_player.get_video_length(),
+            end => end,
+        };
+        let start = video_player.get_min_progress();
+
+        if !(end > start) {
+            return Err(format!("Invalid clip range {}..{}", start, end).into());
+        }
+        Ok((start, end))
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ExportClipEvent {
+        session: VideoSession,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for ExportClipEvent
+    {
+        fn trigger(&mut self, ctx: &mut SharedVideoPlayer) -> RehashResultUnit {
+            let (start, end) = clip_range(ctx)?;
+            let session = self.session.clone();
+
+            spawn_local(async move {
+                match Self::export(&session, start, end).await {
+                    Ok(Some(export)) => console_log!("Exported {:?} clip to {}", export.mode, export.output_path),
+                    Ok(None) => {}
+                    Err(e) => error_log!("Failed to export clip: {}", e.to_string()),
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl ExportClipEvent {
+        pub fn new(session: VideoSession) -> Self {
+            Self {
+                session
+            }
+        }
+
+        async fn export(session: &VideoSession, start: f64, end: f64) -> RehashResult<Option<ClipExport>> {
+            let args = js_sys::Object::new();
+            session.add_to(&args)?;
+            Reflect::set(&args, &"start".into(), &JsValue::from_f64(start))?;
+            Reflect::set(&args, &"end".into(), &JsValue::from_f64(end))?;
+
+            let js_export = JsFuture::from(tauri_invoke("export_clip", args.into())).await?;
+            serde_wasm_bindgen::from_value::<Option<ClipExport>>(js_export).map_err(|e| e.to_string().into())
+        }
+    }
+}
+
+pub(crate) mod operation_events {
+    use super::*;
+    use rehash_utils::utils::tauri_invoke;
+    use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+    /// Cancels every running export and analysis, their final progress events then hide them
+    #[derive(Debug, Clone)]
+    pub(crate) struct CancelOperationEvent {}
+
+    impl CallbackEvent<SharedVideoPlayer> for CancelOperationEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            spawn_local(async move {
+                match JsFuture::from(tauri_invoke("cancel_operation", js_sys::Object::new().into())).await {
+                    Ok(cancelled) => console_log!("Cancelled {} operations", cancelled.as_f64().unwrap_or_default()),
+                    Err(e) => error_log!("Failed to cancel operations: {:?}", e),
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl CancelOperationEvent {
+        pub fn new() -> Self {
+            Self {}
+        }
+    }
+}
+
+pub(crate) mod reel_events {
+    use super::*;
+    use crate::html::html_events::export_clip_event::clip_range;
+    use crate::html::html_ui::HtmlReelStatus;
+    use crate::tauri::tauri_events::VideoSession;
+    use js_sys::Reflect;
+    use rehash_codec_ffi::codec::ReelSegment;
+    use rehash_utils::utils::tauri_invoke;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct AddToReelEvent {
+        reel_status: HtmlReelStatus,
+        session: VideoSession,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for AddToReelEvent
+    {
+        fn trigger(&mut self, ctx: &mut SharedVideoPlayer) -> RehashResultUnit {
+            let (start, end) = clip_range(ctx)?;
+            let (reel_status, session) = (self.reel_status.clone(), self.session.clone());
+
+            spawn_local(async move {
+                match Self::add_segment(&session, start, end).await {
+                    Ok(segments) => reel_status.set_segment_count(segments.len()),
+                    Err(e) => error_log!("Failed to add clip to reel: {}", e.to_string()),
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl AddToReelEvent {
+        pub fn new(reel_status: HtmlReelStatus, session: VideoSession) -> Self {
+            Self {
+                reel_status,
+                session
+            }
+        }
+
+        pub(crate) async fn add_segment(session: &VideoSession, start: f64, end: f64) -> RehashResult<Vec<ReelSegment>> {
+            let args = js_sys::Object::new();
+            session.add_to(&args)?;
+            Reflect::set(&args, &"start".into(), &JsValue::from_f64(start))?;
+            Reflect::set(&args, &"end".into(), &JsValue::from_f64(end))?;
+
+            let js_segments = JsFuture::from(tauri_invoke("add_reel_segment", args.into())).await?;
+            serde_wasm_bindgen::from_value::<Vec<ReelSegment>>(js_segments).map_err(|e| e.to_string().into())
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ExportReelEvent {}
+
+    impl CallbackEvent<SharedVideoPlayer> for ExportReelEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            // progress arrives as reel-progress-event while the reel builds in the background
+            spawn_local(async move {
+                if let Err(e) = JsFuture::from(tauri_invoke("export_reel", JsValue::NULL)).await {
+                    error_log!("Failed to export reel: {:?}", e);
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl ExportReelEvent {
+        pub fn new() -> Self {
+            Self {}
+        }
+    }
+}
+
+pub(crate) mod plays_events {
+    use super::*;
+    use crate::html::html_events::reel_events::AddToReelEvent;
+    use crate::html::html_ui::{HtmlPlayMarkers, HtmlReelStatus};
+    use crate::tauri::tauri_events::VideoSession;
+    use rehash_codec_ffi::codec::{PlayCandidate, PlaySegmentation};
+    use rehash_utils::utils::tauri_invoke;
+    use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct DetectPlaysEvent {
+        play_markers: HtmlPlayMarkers,
+        session: VideoSession,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for DetectPlaysEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            let (play_markers, session) = (self.play_markers.clone(), self.session.clone());
+
+            spawn_local(async move {
+                match Self::detect_plays(&session).await {
+                    Ok(segmentation) => play_markers.show(segmentation),
+                    Err(e) => error_log!("Failed to detect plays: {}", e.to_string()),
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl DetectPlaysEvent {
+        pub fn new(play_markers: HtmlPlayMarkers, session: VideoSession) -> Self {
+            Self {
+                play_markers,
+                session
+            }
+        }
+
+        async fn detect_plays(session: &VideoSession) -> RehashResult<PlaySegmentation> {
+            let args = js_sys::Object::new();
+            session.add_to(&args)?;
+
+            let js_segmentation = JsFuture::from(tauri_invoke("detect_plays", args.into())).await?;
+            serde_wasm_bindgen::from_value::<PlaySegmentation>(js_segmentation).map_err(|e| e.to_string().into())
+        }
+    }
+
+    /// Moves the start and end dots onto the play and seeks to its start
+    pub(crate) fn select_play(ctx: &SharedVideoPlayer, candidate: &PlayCandidate) -> RehashResultUnit {
+        let mut video_player = ctx.borrow_mut();
+        let length = video_player.get_video_length();
+        if !(length > 0f64) {
+            return Err("Video length is unknown".into());
+        }
+
+        let start = (candidate.start / length).max(0f64).min(1f64);
+        let end = (candidate.end / length).max(0f64).min(1f64);
+        // each bound is only accepted when it stays clear of the other, so the end is set on both sides of the start
+        video_player.set_max_progress(end);
+        video_player.set_min_progress(start);
+        video_player.set_max_progress(end);
+        video_player.set_video_progress(start);
+        Ok(())
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct AcceptPlaysEvent {
+        play_markers: HtmlPlayMarkers,
+        reel_status: HtmlReelStatus,
+        session: VideoSession,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for AcceptPlaysEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            let candidates = self.play_markers.candidates();
+            if candidates.is_empty() {
+                return Err("No detected plays to accept".into());
+            }
+
+            let (reel_status, session) = (self.reel_status.clone(), self.session.clone());
+            spawn_local(async move {
+                for candidate in candidates {
+                    match AddToReelEvent::add_segment(&session, candidate.start, candidate.end).await {
+                        Ok(segments) => reel_status.set_segment_count(segments.len()),
+                        Err(e) => {
+                            error_log!("Failed to add play to reel: {}", e.to_string());
+                            break;
+                        }
+                    }
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl AcceptPlaysEvent {
+        pub fn new(play_markers: HtmlPlayMarkers, reel_status: HtmlReelStatus, session: VideoSession) -> Self {
+            Self {
+                play_markers,
+                reel_status,
+                session
+            }
+        }
+    }
+}
+
+pub(crate) mod audio_events {
+    use super::*;
+    use crate::html::html_ui::HtmlAudioMarkers;
+    use crate::tauri::tauri_events::VideoSession;
+    use rehash_codec_ffi::codec::AudioAnalysis;
+    use rehash_utils::utils::tauri_invoke;
+    use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct DetectAudioEvent {
+        audio_markers: HtmlAudioMarkers,
+        session: VideoSession,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for DetectAudioEvent
+    {
+        fn trigger(&mut self, _: &mut SharedVideoPlayer) -> RehashResultUnit {
+            let (audio_markers, session) = (self.audio_markers.clone(), self.session.clone());
+
+            spawn_local(async move {
+                match Self::detect_audio_events(&session).await {
+                    Ok(analysis) => audio_markers.show(analysis),
+                    Err(e) => error_log!("Failed to detect audio events: {}", e.to_string()),
+                }
+            });
+
+            Ok(())
+        }
+
+    }
+
+    impl DetectAudioEvent {
+        pub fn new(audio_markers: HtmlAudioMarkers, session: VideoSession) -> Self {
+            Self {
+                audio_markers,
+                session
+            }
+        }
+
+        async fn detect_audio_events(session: &VideoSession) -> RehashResult<AudioAnalysis> {
+            let args = js_sys::Object::new();
+            session.add_to(&args)?;
+
+            let js_analysis = JsFuture::from(tauri_invoke("detect_audio_events", args.into())).await?;
+            serde_wasm_bindgen::from_value::<AudioAnalysis>(js_analysis).map_err(|e| e.to_string().into())
+        }
+    }
+
+    /// Moves the end dot to the end of the first whistle after the playhead, or after the start dot when that is later
+    #[derive(Debug, Clone)]
+    pub(crate) struct EndAtWhistleEvent {
+        audio_markers: HtmlAudioMarkers,
+    }
+
+    impl CallbackEvent<SharedVideoPlayer> for EndAtWhistleEvent
+    {
+        fn trigger(&mut self, ctx: &mut SharedVideoPlayer) -> RehashResultUnit {
+            if !self.audio_markers.is_analysed() {
+                return Err("Audio has not been analysed".into());
+            }
+
+            let mut video_player = ctx.borrow_mut();
+            let length = video_player.get_video_length();
+            let time = video_player.get_progress().max(video_player.get_min_progress());
+            let whistle = self.audio_markers.next_whistle(time).ok_or("No whistle after the current position")?;
+
+            if length > 0f64 {
+                video_player.set_max_progress((whistle.end / length).min(1f64));
+            }
+            Ok(())
+        }
+
+    }
+
+    impl EndAtWhistleEvent {
+        pub fn new(audio_markers: HtmlAudioMarkers) -> Self {
+            Self {
+                audio_markers
+            }
+        }
+    }
+}
+
 pub(crate) mod fullscreen_event {
     use super::*;
 
@@ -285,6 +690,7 @@ pub(crate) mod fast_forward_event {
 
 pub(crate) mod drag_events {
     use crate::html::html_events::get_video_player_state_return;
+    use crate::html::html_ui::HtmlKeyframeIndex;
     use crate::log_to_tauri;
     use crate::video::event::CallbackEvent;
     use crate::video::video_callback::{SharedVideoPlayer, VideoPlayer, VideoPlayerState};
@@ -350,14 +756,16 @@ pub(crate) mod drag_events {
     pub(crate) struct DragEventCtx {
         currently_moving: MovingCtx,
         video_player: SharedVideoPlayer,
+        keyframe_index: HtmlKeyframeIndex,
         percent: f64,
         clicked: MoveState,
     }
 
     impl DragEventCtx {
-        pub fn new(video_player: SharedVideoPlayer) -> Self {
+        pub fn new(video_player: SharedVideoPlayer, keyframe_index: HtmlKeyframeIndex) -> Self {
             Self {
                 video_player,
+                keyframe_index,
                 currently_moving: Rc::new(Cell::new(MoveState::Nothing)),
                 percent: 0f64,
                 clicked: MoveState::Nothing,
@@ -383,6 +791,15 @@ pub(crate) mod drag_events {
         pub fn get_clicked(&self) -> MoveState {
             self.currently_moving.get()
         }
+
+        // Seeking to a keyframe needs no decoding, which keeps scrubbing smooth on long GOP footage
+        fn keyframe_percent(&self) -> f64 {
+            let length = self.video_player.borrow().get_video_length();
+            if !(length > 0f64) {
+                return self.percent;
+            }
+            self.keyframe_index.snap(self.percent * length) / length
+        }
     }
 
     type Ctx = DragEventCtxType;
@@ -399,7 +816,8 @@ pub(crate) mod drag_events {
                 },
                 MoveState::ProgressBar => {
                     debug_console_log!("Progress bar clicked to drag.");
-                    ctx.video_player.borrow_mut().set_video_progress(ctx.percent);
+                    let percent = ctx.keyframe_percent();
+                    ctx.video_player.borrow_mut().set_video_progress(percent);
                 },
                 MoveState::StartClipDot => {
                     debug_console_log!("Start clip clicked to drag: {}", ctx.percent);
@@ -454,7 +872,10 @@ pub(crate) mod drag_events {
                         let video_uninitialised: VideoPlayer<I, Playing> = get_state_owned(video_player.deref())?;
                         *video_player = get_video_player_state_return(video_uninitialised.pause());
                     }*/
-                    video_player.set_video_progress(ctx.percent);
+                    drop(video_player);
+                    // the exact position is sought once the drag ends
+                    let percent = ctx.keyframe_percent();
+                    ctx.video_player.borrow_mut().set_video_progress(percent);
                 },
                 MoveState::StartClipDot => {
                     debug_console_log!("Start clip clicked, percent: {}", ctx.percent);
@@ -494,6 +915,7 @@ pub(crate) mod drag_events {
             let ctx = ctx.borrow();
             match ctx.currently_moving.get() {
                 MoveState::ProgressBar => {
+                    ctx.video_player.borrow_mut().set_video_progress(ctx.percent);
                     // let mut video_player = ctx.video_player.borrow_mut();
                     // if video_player.get_type_id() == TypeId::of::<Paused>() {
                     //     let video_uninitialised: VideoPlayer<HtmlVideoPlayerInternal, Paused> = get_state_owned(video_player.deref())?;
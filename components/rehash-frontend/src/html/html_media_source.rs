@@ -0,0 +1,453 @@
+use crate::html::html_video::HtmlVideoPlayerInternal;
+use crate::video::video_callback::CallbackClosureWrapper;
+use crate::video::video_internal::{ProgressBound, VideoInternal, VideoPlaybackSpeed, VideoResult, VideoResultUnit};
+use crate::JsResult;
+use js_sys::{Reflect, Uint8Array};
+use rehash_codec_ffi::codec::{MediaSegment, SegmentedStream};
+use rehash_utils::utils::tauri_invoke;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{HtmlVideoElement, MediaSource, MediaSourceReadyState, SourceBuffer, Url};
+
+pub use crate::prelude::*;
+
+/// Seconds of video kept buffered ahead of the playhead
+const BUFFER_AHEAD: f64 = 30.0;
+/// Seconds kept behind the playhead for short rewinds, anything older is evicted
+const BUFFER_BEHIND: f64 = 10.0;
+/// Buffered ranges start and end within a frame or two of their segment's bounds
+const EDGE_TOLERANCE: f64 = 0.1;
+
+type Listener = Box<Closure<dyn FnMut(web_sys::Event)>>;
+
+
+#[derive(Debug, Default)]
+struct MediaSourceState {
+    /// Desktop session the stream was opened in, its segments are only readable through it
+    session: u64,
+    stream: Option<SegmentedStream>,
+    media_source: Option<MediaSource>,
+    source_buffer: Option<SourceBuffer>,
+    url: Option<String>,
+    /// Segments whose media is in the source buffer
+    appended: BTreeSet<usize>,
+    /// Segments the codec failed to produce, skipped rather than asked for again on every timeupdate
+    failed: BTreeSet<usize>,
+    /// A segment is being read, the source buffer only takes one update at a time
+    busy: bool,
+    listeners: Vec<Listener>,
+}
+
+
+/// Plays a segmented stream from the codec through a `MediaSource`. Segments are appended as the playhead
+/// nears them and evicted once it has left them behind, so only a window around it is ever buffered
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlMediaSource {
+    video_element: HtmlVideoElement,
+    state: Rc<RefCell<MediaSourceState>>,
+    generation: Rc<Cell<u32>>,
+}
+
+impl HtmlMediaSource {
+    pub fn new(video_element: HtmlVideoElement) -> Self {
+        let media_source = Self {
+            video_element,
+            state: Rc::new(RefCell::new(MediaSourceState::default())),
+            generation: Rc::new(Cell::new(0)),
+        };
+
+        for event in ["timeupdate", "seeking"] {
+            let closure = CallbackClosureWrapper::create_callback(Box::new(MediaSourcePump { ctx: media_source.clone() }));
+            let _ = media_source.video_element.add_event_listener_with_callback(event, closure.as_ref().as_ref().unchecked_ref());
+            closure.forget();
+        }
+        media_source
+    }
+
+    pub fn is_type_supported(mime_type: &str) -> bool {
+        MediaSource::is_type_supported(mime_type)
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.state.borrow().stream.is_some()
+    }
+
+    pub fn duration(&self) -> Option<f64> {
+        self.state.borrow().stream.as_ref().map(|stream| stream.duration)
+    }
+
+    /// Whether the segment presenting `time` has been appended
+    pub fn is_buffered(&self, time: f64) -> bool {
+        let state = self.state.borrow();
+        state.stream.as_ref()
+            .and_then(|stream| stream.segment_at(time))
+            .is_some_and(|segment| state.appended.contains(&segment.index))
+    }
+
+    /// Points the video at a new `MediaSource` playing `stream` of `session`, closing whatever was attached before
+    pub fn attach(&self, session: u64, stream: SegmentedStream) -> JsResult<()> {
+        self.detach();
+
+        let media_source = MediaSource::new()?;
+        let url = Url::create_object_url_with_source(&media_source)?;
+        let open = CallbackClosureWrapper::create_callback(Box::new(SourceOpenCallback { ctx: self.clone() }));
+        media_source.set_onsourceopen(Some(open.as_ref().as_ref().unchecked_ref()));
+
+        debug_console_log!("Playing {} through a media source as {}", stream.file_path, stream.mime_type);
+        {
+            let mut state = self.state.borrow_mut();
+            state.session = session;
+            state.stream = Some(stream);
+            state.media_source = Some(media_source);
+            state.url = Some(url.clone());
+            state.listeners.push(open);
+        }
+
+        self.video_element.set_src(&url);
+        self.video_element.load();
+        Ok(())
+    }
+
+    /// Forgets the attached stream and closes it in the codec, the video keeps its src until it is given another
+    pub fn detach(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        let state = std::mem::take(&mut *self.state.borrow_mut());
+
+        if let Some(media_source) = &state.media_source {
+            media_source.set_onsourceopen(None);
+        }
+        if let Some(source_buffer) = &state.source_buffer {
+            source_buffer.set_onupdateend(None);
+        }
+        if let Some(url) = &state.url {
+            let _ = Url::revoke_object_url(url);
+        }
+        if let Some(stream) = state.stream {
+            spawn_local(async move {
+                if let Err(e) = close_segmented_stream(state.session, stream.handle).await {
+                    error_log!("Failed to close segmented stream {}: {}", stream.handle, e);
+                }
+            });
+        }
+    }
+
+    fn add_source_buffer(&self) -> JsResult<()> {
+        let mut state = self.state.borrow_mut();
+        // sourceopen fires again when appending after the end of the stream
+        if state.source_buffer.is_some() {
+            return Ok(());
+        }
+        let (Some(stream), Some(media_source)) = (state.stream.as_ref(), state.media_source.as_ref()) else {
+            return Ok(());
+        };
+
+        media_source.set_duration(stream.duration);
+        let source_buffer = media_source.add_source_buffer(&stream.mime_type)?;
+        let (session, handle) = (state.session, stream.handle);
+
+        let updated = CallbackClosureWrapper::create_callback(Box::new(MediaSourcePump { ctx: self.clone() }));
+        source_buffer.set_onupdateend(Some(updated.as_ref().as_ref().unchecked_ref()));
+        state.listeners.push(updated);
+        state.source_buffer = Some(source_buffer.clone());
+        state.busy = true;
+        drop(state);
+
+        let (media_source, generation) = (self.clone(), self.generation.get());
+        spawn_local(async move {
+            let init = read_init_segment(session, handle).await;
+            if media_source.generation.get() != generation {
+                return;
+            }
+
+            media_source.state.borrow_mut().busy = false;
+            let appended = match init {
+                Ok(init) => source_buffer.append_buffer_with_array_buffer_view(&init).map_err(|e| format!("{:?}", e)),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = appended {
+                error_log!("Failed to append the init segment of stream {}: {}", handle, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Evicts what is outside the window around the playhead, otherwise appends the next segment missing from it.
+    /// Runs again on every `updateend` until neither is left to do
+    pub fn pump(&self) {
+        let mut state = self.state.borrow_mut();
+        let Some(source_buffer) = state.source_buffer.clone() else {
+            return;
+        };
+        if state.busy || source_buffer.updating() {
+            return;
+        }
+        let Some(stream) = state.stream.as_ref() else {
+            return;
+        };
+
+        let time = self.video_element.current_time();
+        let (Some(current), Some(last)) = (stream.segment_at(time), stream.segment_at(time + BUFFER_AHEAD)) else {
+            return;
+        };
+        let keep_from = stream.segment_at(time - BUFFER_BEHIND).map_or(current.start, |segment| segment.start);
+        let keep_to = last.end;
+
+        if let Some((start, end)) = eviction(&source_buffer, keep_from, keep_to) {
+            debug_console_log!("Evicting {}..{} at {}", start, end, time);
+            if source_buffer.remove(start, end).is_ok() {
+                let evicted = stream.segments.iter().filter(|segment| segment.start < end && segment.end > start).map(|segment| segment.index);
+                let evicted: Vec<usize> = evicted.collect();
+                state.appended.retain(|index| !evicted.contains(index));
+            }
+            return;
+        }
+
+        let missing = stream.segments[current.index..=last.index].iter()
+            .find(|segment| !state.appended.contains(&segment.index) && !state.failed.contains(&segment.index))
+            .copied();
+        let Some(segment) = missing else {
+            // the video only fires ended once the media source knows nothing more is coming
+            let appended_last = stream.segments.last().is_some_and(|segment| state.appended.contains(&segment.index));
+            if let Some(media_source) = state.media_source.as_ref().filter(|_| appended_last)
+                && media_source.ready_state() == MediaSourceReadyState::Open
+            {
+                let _ = media_source.end_of_stream();
+            }
+            return;
+        };
+
+        let (session, handle) = (state.session, stream.handle);
+        state.busy = true;
+        drop(state);
+
+        let (media_source, generation) = (self.clone(), self.generation.get());
+        spawn_local(async move {
+            let bytes = read_media_segment(session, handle, segment.index).await;
+            if media_source.generation.get() != generation {
+                return;
+            }
+
+            let mut state = media_source.state.borrow_mut();
+            state.busy = false;
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error_log!("Failed to read segment {} of stream {}: {}", segment.index, handle, e);
+                    state.failed.insert(segment.index);
+                    return;
+                }
+            };
+
+            // segments carry their own timestamps from zero, the offset places them on the stream's timeline
+            source_buffer.set_timestamp_offset(segment.timestamp_offset);
+            match source_buffer.append_buffer_with_array_buffer_view(&bytes) {
+                Ok(_) => {
+                    state.appended.insert(segment.index);
+                }
+                Err(e) if is_quota_exceeded(&e) => {
+                    drop(state);
+                    media_source.make_room(&source_buffer, &segment);
+                }
+                Err(e) => {
+                    error_log!("Failed to append segment {} of stream {}: {:?}", segment.index, handle, e);
+                    state.failed.insert(segment.index);
+                }
+            }
+        });
+    }
+
+    // The buffer is full even within the window, so everything but the segment being played goes.
+    // The segment that didn't fit is appended again once the removal ends
+    fn make_room(&self, source_buffer: &SourceBuffer, segment: &MediaSegment) {
+        let time = self.video_element.current_time();
+        let mut state = self.state.borrow_mut();
+        let Some(current) = state.stream.as_ref().and_then(|stream| stream.segment_at(time)).copied() else {
+            return;
+        };
+        debug_console_log!("Buffer is full appending segment {}, keeping only segment {}", segment.index, current.index);
+
+        let Some((start, end)) = eviction(source_buffer, current.start, current.end) else {
+            return;
+        };
+        if source_buffer.remove(start, end).is_ok() {
+            state.appended.retain(|index| *index == current.index);
+        }
+    }
+}
+
+/// First part of the buffered ranges outside `keep_from..keep_to`, the source buffer takes one removal at a time
+fn eviction(source_buffer: &SourceBuffer, keep_from: f64, keep_to: f64) -> Option<(f64, f64)> {
+    let buffered = source_buffer.buffered().ok()?;
+    for range in 0..buffered.length() {
+        let (Ok(start), Ok(end)) = (buffered.start(range), buffered.end(range)) else {
+            continue;
+        };
+        if start < keep_from - EDGE_TOLERANCE {
+            return Some((start, end.min(keep_from)));
+        }
+        if end > keep_to + EDGE_TOLERANCE {
+            return Some((start.max(keep_to), end));
+        }
+    }
+    None
+}
+
+fn is_quota_exceeded(error: &JsValue) -> bool {
+    Reflect::get(error, &"name".into()).ok().and_then(|name| name.as_string()).as_deref() == Some("QuotaExceededError")
+}
+
+
+#[derive(Debug, Clone)]
+struct MediaSourcePump {
+    ctx: HtmlMediaSource,
+}
+
+impl CallbackClosureWrapper<web_sys::Event> for MediaSourcePump {
+    fn closure(&mut self, _: web_sys::Event) {
+        self.ctx.pump();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SourceOpenCallback {
+    ctx: HtmlMediaSource,
+}
+
+impl CallbackClosureWrapper<web_sys::Event> for SourceOpenCallback {
+    fn closure(&mut self, _: web_sys::Event) {
+        if let Err(e) = self.ctx.add_source_buffer() {
+            error_log!("Failed to open the media source: {}", e);
+        }
+    }
+}
+
+
+/// Plays whatever `HtmlMediaSource` has attached, otherwise the video's own src exactly like `HtmlVideoPlayerInternal`
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlMediaSourcePlayerInternal {
+    video: HtmlVideoPlayerInternal,
+    media_source: HtmlMediaSource,
+}
+
+impl HtmlMediaSourcePlayerInternal {
+    pub fn new(video_element: HtmlVideoElement, media_source: HtmlMediaSource) -> Self {
+        Self {
+            video: HtmlVideoPlayerInternal::new(video_element),
+            media_source,
+        }
+    }
+}
+
+impl VideoInternal for HtmlMediaSourcePlayerInternal {
+    fn mute(&self, should_be_muted: bool) -> VideoResultUnit {
+        self.video.mute(should_be_muted)
+    }
+
+    fn fast_forward(&self) -> VideoResultUnit {
+        self.video.fast_forward()
+    }
+
+    fn rewind(&self) -> VideoResultUnit {
+        self.video.rewind()
+    }
+
+    fn pause(&self) -> VideoResultUnit {
+        self.video.pause()
+    }
+
+    fn play(&self) -> VideoResult<::js_sys::Promise> {
+        self.video.play()
+    }
+
+    fn get_volume(&self) {
+        self.video.get_volume()
+    }
+
+    fn get_playback_time(&self) {
+        self.video.get_playback_time()
+    }
+
+    fn get_progress(&self) -> VideoResult<f64> {
+        self.video.get_progress()
+    }
+
+    // known from the segment index before the media source has opened
+    fn get_video_length(&self) -> f64 {
+        self.media_source.duration().unwrap_or_else(|| self.video.get_video_length())
+    }
+
+    fn set_video_progress(&self, time: f64) {
+        self.video.set_video_progress(time);
+        self.media_source.pump();
+    }
+
+    fn ready(&self) -> bool {
+        let progress = self.video.get_progress().unwrap_or_default();
+        self.video.ready() && (!self.media_source.is_attached() || self.media_source.is_buffered(progress))
+    }
+
+    fn set_volume(&self, volume: f64) {
+        self.video.set_volume(volume)
+    }
+
+    fn set_min_progress(&mut self, percent: f64) {
+        self.video.set_min_progress(percent)
+    }
+
+    fn get_min_progress(&self) -> &ProgressBound {
+        self.video.get_min_progress()
+    }
+
+    fn set_max_progress(&mut self, percent: f64) {
+        self.video.set_max_progress(percent)
+    }
+
+    fn get_max_progress(&self) -> &ProgressBound {
+        self.video.get_max_progress()
+    }
+
+    fn set_playback_speed(&self, speed: VideoPlaybackSpeed) {
+        self.video.set_playback_speed(speed)
+    }
+
+    fn increment_video_speed(&mut self) {
+        self.video.increment_video_speed()
+    }
+
+    fn decrement_video_speed(&mut self) {
+        self.video.decrement_video_speed()
+    }
+}
+
+
+async fn read_init_segment(session: u64, handle: u64) -> JsResult<Uint8Array> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+    Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+
+    let js_bytes = JsFuture::from(tauri_invoke("read_init_segment", args.into())).await?;
+    Ok(Uint8Array::new(&js_bytes))
+}
+
+async fn read_media_segment(session: u64, handle: u64, index: usize) -> JsResult<Uint8Array> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+    Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+    Reflect::set(&args, &"index".into(), &(index as f64).into())?;
+
+    let js_bytes = JsFuture::from(tauri_invoke("read_media_segment", args.into())).await?;
+    Ok(Uint8Array::new(&js_bytes))
+}
+
+async fn close_segmented_stream(session: u64, handle: u64) -> JsResult<()> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+    Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+
+    JsFuture::from(tauri_invoke("close_segmented_stream", args.into())).await?;
+    Ok(())
+}
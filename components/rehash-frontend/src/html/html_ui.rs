@@ -1,10 +1,19 @@
-use crate::get_element_as;
-use crate::html::html_video::HtmlVideoPlayerInternal;
+use crate::{get_element_as, JsResult};
+use crate::html::html_media_source::HtmlMediaSourcePlayerInternal;
 use crate::video::video_ui::{VideoUIController, VideoUIRegister};
+use js_sys::Reflect;
+use rehash_codec_ffi::codec::{AudioAnalysis, AudioEvent, AudioEventKind, JobState, KeyframeIndex, OperationProgress, PlayCandidate, PlaySegmentation, ReelStatus, ThumbnailIndex,
+                              VideoFrame, VideoProbe};
+use rehash_utils::utils::tauri_invoke;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::rc::Rc;
 use wasm_bindgen::closure::{Closure, WasmClosure};
-use wasm_bindgen::JsCast;
-use web_sys::{Document, HtmlDivElement, HtmlSpanElement, HtmlVideoElement, SvgElement};
+use wasm_bindgen::{Clamped, JsCast};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Blob, BlobPropertyBag, CanvasRenderingContext2d, Document, HtmlCanvasElement, HtmlDivElement, HtmlImageElement, HtmlSpanElement,
+              HtmlVideoElement, ImageData, SvgElement, Url};
 
 pub use crate::prelude::*;
 
@@ -27,11 +36,11 @@ pub(crate) struct HtmlVideoUIController {
     volume_fill: HtmlDivElement,
     start_dot: HtmlDivElement,
     end_dot: HtmlDivElement,
-
+    frame_canvas: HtmlFrameCanvas,
 }
 
 
-impl VideoUIController<HtmlVideoPlayerInternal> for HtmlVideoUIController {
+impl VideoUIController<HtmlMediaSourcePlayerInternal> for HtmlVideoUIController {
     fn swap_play_button(&self) {
         self.play_icon.style().set_property("display", "none").expect("Failed to set play icon");
         self.pause_icon.style().set_property("display", "block").expect("Failed to set pause icon");
@@ -81,6 +90,14 @@ impl VideoUIController<HtmlVideoPlayerInternal> for HtmlVideoUIController {
         self.end_dot.style().set_property("right", format!("{}%", end_position).as_str())
             .expect("Failed to set end_dot");
     }
+
+    fn show_exact_frame(&self, time: f64) {
+        self.frame_canvas.paint_at(time);
+    }
+
+    fn hide_exact_frame(&self) {
+        self.frame_canvas.hide();
+    }
 }
 
 #[inline]
@@ -155,7 +172,7 @@ impl HtmlVideoUIController {
     const VIDEO_ID: &'static str = "video-player";
 
 
-    pub fn new(document: Document) -> Self {
+    pub fn new(document: Document, frame_canvas: HtmlFrameCanvas) -> Self {
         let play_icon = get_element_as!(&document, Self::PLAY_ICON_ID, SvgElement);
         let pause_icon = get_element_as!(&document, Self::PAUSE_ICON_ID, SvgElement);
 
@@ -200,6 +217,7 @@ impl HtmlVideoUIController {
             volume_fill,
             start_dot,
             end_dot,
+            frame_canvas,
         }
     }
 
@@ -211,6 +229,7 @@ impl HtmlVideoUIController {
 #[derive(Debug, Clone)]
 pub(crate) struct HtmlLoadBar {
     overlay: HtmlDivElement,
+    load_label_text: HtmlSpanElement,
     load_fill: HtmlDivElement,
     load_percentage_text: HtmlSpanElement,
 }
@@ -219,6 +238,7 @@ impl HtmlLoadBar {
     const OVERLAY_ID: &'static str = "load-overlay";
     const LOAD_FILL: &'static str = "load-progress-fill";
     const LOAD_PERCENTAGE_TEXT: &'static str = "load-percentage-text";
+    const LOAD_LABEL_TEXT: &'static str = "load-label-text";
 
 
     pub fn new(document: &Document) -> Self {
@@ -228,9 +248,11 @@ impl HtmlLoadBar {
             .dyn_into::<HtmlDivElement>().expect("Failed to get load fill as div");
         let load_percentage_text = document.get_element_by_id(Self::LOAD_PERCENTAGE_TEXT).unwrap()
             .dyn_into::<HtmlSpanElement>().expect("Failed to get load percentage as div");
+        let load_label_text = get_element_as!(document, Self::LOAD_LABEL_TEXT, HtmlSpanElement);
 
         Self {
             overlay,
+            load_label_text,
             load_fill,
             load_percentage_text,
         }
@@ -245,6 +267,10 @@ impl HtmlLoadBar {
     }
 
 
+    pub fn set_label(&self, label: &str) {
+        self.load_label_text.set_text_content(Some(label));
+    }
+
     pub fn show_loader(&self) {
         let class_list = self.overlay.class_list();
         let _ = class_list.remove_1("load-hidden");
@@ -254,4 +280,609 @@ impl HtmlLoadBar {
         let class_list = self.overlay.class_list();
         let _ = class_list.add_1("load-hidden");
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlReelStatus {
+    text: HtmlSpanElement,
+}
+
+impl HtmlReelStatus {
+    const REEL_STATUS_ID: &'static str = "reel-status";
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            text: get_element_as!(document, Self::REEL_STATUS_ID, HtmlSpanElement),
+        }
+    }
+
+    pub fn set_segment_count(&self, count: usize) {
+        self.text.set_text_content(Some(&format!("Reel: {} clips", count)));
+    }
+
+    pub fn update(&self, status: &ReelStatus) {
+        let text = match status.state {
+            JobState::Running => format!("Reel: clip {}/{} {:.0}%", status.current_segment + 1, status.segment_count, status.progress * 100f64),
+            JobState::Finished => String::from("Reel exported"),
+            JobState::Failed => String::from("Reel failed"),
+        };
+        self.text.set_text_content(Some(&text));
+    }
+}
+
+/// Progress of the codec operations the desktop is running, with a button to cancel them
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlOperationProgress {
+    container: HtmlDivElement,
+    text: HtmlSpanElement,
+    /// operations and their files that haven't sent their final event yet
+    running: Rc<RefCell<HashMap<(String, String), OperationProgress>>>,
+}
+
+impl HtmlOperationProgress {
+    const OPERATION_PROGRESS_ID: &'static str = "operation-progress";
+    const OPERATION_PROGRESS_TEXT_ID: &'static str = "operation-progress-text";
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            container: get_element_as!(document, Self::OPERATION_PROGRESS_ID, HtmlDivElement),
+            text: get_element_as!(document, Self::OPERATION_PROGRESS_TEXT_ID, HtmlSpanElement),
+            running: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    pub fn update(&self, progress: OperationProgress) {
+        {
+            let mut running = self.running.borrow_mut();
+            let key = (progress.operation.clone(), progress.file_path.clone());
+            match progress.state {
+                JobState::Running => running.insert(key, progress),
+                JobState::Finished | JobState::Failed => running.remove(&key),
+            };
+        }
+        self.render();
+    }
+
+    fn render(&self) {
+        let running = self.running.borrow();
+        if running.is_empty() {
+            self.container.style().set_property("display", "none").expect("Failed to hide operation progress");
+            return;
+        }
+
+        let mut texts = running.values()
+            .map(|progress| match progress.total {
+                0 => format!("{}: {} frames", progress.operation, progress.processed),
+                total => format!("{}: {:.0}%", progress.operation, progress.processed as f64 / total as f64 * 100f64),
+            })
+            .collect::<Vec<_>>();
+        texts.sort();
+        self.text.set_text_content(Some(&texts.join(", ")));
+        self.container.style().set_property("display", "flex").expect("Failed to show operation progress");
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlPlayMarkers {
+    document: Document,
+    container: HtmlDivElement,
+    candidates: Rc<RefCell<Vec<PlayCandidate>>>,
+}
+
+impl HtmlPlayMarkers {
+    pub(crate) const PLAY_MARKERS_ID: &'static str = "play-markers";
+
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            document: document.clone(),
+            container: get_element_as!(document, Self::PLAY_MARKERS_ID, HtmlDivElement),
+            candidates: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn show(&self, segmentation: PlaySegmentation) {
+        self.container.set_inner_html("");
+        if segmentation.duration <= 0f64 {
+            self.candidates.borrow_mut().clear();
+            return;
+        }
+
+        for (index, candidate) in segmentation.candidates.iter().enumerate() {
+            let Ok(marker) = self.document.create_element("div").map(|e| e.unchecked_into::<HtmlDivElement>()) else {
+                continue;
+            };
+            marker.set_class_name("play-marker");
+            marker.set_title(&format!("Play {}: {} - {}", index + 1, format_time(candidate.start), format_time(candidate.end)));
+            let _ = marker.set_attribute("data-index", &index.to_string());
+
+            let left = candidate.start / segmentation.duration * 100f64;
+            let width = (candidate.end - candidate.start) / segmentation.duration * 100f64;
+            let _ = marker.style().set_property("left", &format!("{}%", left));
+            let _ = marker.style().set_property("width", &format!("{}%", width));
+            let _ = self.container.append_child(&marker);
+        }
+
+        *self.candidates.borrow_mut() = segmentation.candidates;
+    }
+
+    pub fn clear(&self) {
+        self.container.set_inner_html("");
+        self.candidates.borrow_mut().clear();
+    }
+
+    pub fn candidate(&self, index: usize) -> Option<PlayCandidate> {
+        self.candidates.borrow().get(index).cloned()
+    }
+
+    pub fn candidates(&self) -> Vec<PlayCandidate> {
+        self.candidates.borrow().clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlAudioMarkers {
+    document: Document,
+    container: HtmlDivElement,
+    analysis: Rc<RefCell<Option<AudioAnalysis>>>,
+}
+
+impl HtmlAudioMarkers {
+    pub(crate) const AUDIO_MARKERS_ID: &'static str = "audio-markers";
+
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            document: document.clone(),
+            container: get_element_as!(document, Self::AUDIO_MARKERS_ID, HtmlDivElement),
+            analysis: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn show(&self, analysis: AudioAnalysis) {
+        self.container.set_inner_html("");
+        if analysis.duration <= 0f64 {
+            *self.analysis.borrow_mut() = None;
+            return;
+        }
+
+        for (index, event) in analysis.events.iter().enumerate() {
+            let Ok(marker) = self.document.create_element("div").map(|e| e.unchecked_into::<HtmlDivElement>()) else {
+                continue;
+            };
+            let (class, label) = match event.kind {
+                AudioEventKind::Whistle => ("whistle", "Whistle"),
+                AudioEventKind::LoudnessRise => ("loudness-rise", "Crowd rising"),
+                AudioEventKind::LoudnessDrop => ("loudness-drop", "Crowd dropping"),
+                AudioEventKind::Silence => ("silence", "Silence"),
+            };
+            marker.set_class_name(&format!("audio-marker {}", class));
+            marker.set_title(&format!("{} at {}", label, format_time(event.start)));
+            let _ = marker.set_attribute("data-index", &index.to_string());
+
+            let left = event.start / analysis.duration * 100f64;
+            let _ = marker.style().set_property("left", &format!("{}%", left));
+            // instant events keep the minimum width from the stylesheet
+            if event.end > event.start {
+                let width = (event.end - event.start) / analysis.duration * 100f64;
+                let _ = marker.style().set_property("width", &format!("{}%", width));
+            }
+            let _ = self.container.append_child(&marker);
+        }
+
+        *self.analysis.borrow_mut() = Some(analysis);
+    }
+
+    pub fn clear(&self) {
+        self.container.set_inner_html("");
+        *self.analysis.borrow_mut() = None;
+    }
+
+    pub fn event(&self, index: usize) -> Option<AudioEvent> {
+        self.analysis.borrow().as_ref().and_then(|analysis| analysis.events.get(index).cloned())
+    }
+
+    pub fn next_whistle(&self, time: f64) -> Option<AudioEvent> {
+        self.analysis.borrow().as_ref().and_then(|analysis| analysis.next_whistle(time).cloned())
+    }
+
+    pub fn is_analysed(&self) -> bool {
+        self.analysis.borrow().is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlMetadataPanel {
+    panel: HtmlDivElement,
+    format: HtmlSpanElement,
+    duration: HtmlSpanElement,
+    resolution: HtmlSpanElement,
+    frame_rate: HtmlSpanElement,
+    frame_count: HtmlSpanElement,
+    codecs: HtmlSpanElement,
+    rotation: HtmlSpanElement,
+    creation_time: HtmlSpanElement,
+}
+
+impl HtmlMetadataPanel {
+    const PANEL_ID: &'static str = "metadata-panel";
+    const FORMAT_ID: &'static str = "metadata-format";
+    const DURATION_ID: &'static str = "metadata-duration";
+    const RESOLUTION_ID: &'static str = "metadata-resolution";
+    const FRAME_RATE_ID: &'static str = "metadata-frame-rate";
+    const FRAME_COUNT_ID: &'static str = "metadata-frame-count";
+    const CODECS_ID: &'static str = "metadata-codecs";
+    const ROTATION_ID: &'static str = "metadata-rotation";
+    const CREATION_TIME_ID: &'static str = "metadata-creation-time";
+
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            panel: get_element_as!(document, Self::PANEL_ID, HtmlDivElement),
+            format: get_element_as!(document, Self::FORMAT_ID, HtmlSpanElement),
+            duration: get_element_as!(document, Self::DURATION_ID, HtmlSpanElement),
+            resolution: get_element_as!(document, Self::RESOLUTION_ID, HtmlSpanElement),
+            frame_rate: get_element_as!(document, Self::FRAME_RATE_ID, HtmlSpanElement),
+            frame_count: get_element_as!(document, Self::FRAME_COUNT_ID, HtmlSpanElement),
+            codecs: get_element_as!(document, Self::CODECS_ID, HtmlSpanElement),
+            rotation: get_element_as!(document, Self::ROTATION_ID, HtmlSpanElement),
+            creation_time: get_element_as!(document, Self::CREATION_TIME_ID, HtmlSpanElement),
+        }
+    }
+
+    pub fn update(&self, probe: &VideoProbe) {
+        self.format.set_text_content(Some(probe.format_long_name.as_str()));
+        self.duration.set_text_content(Some(format_time(probe.duration).as_str()));
+
+        match probe.video() {
+            Some(video) => {
+                self.resolution.set_text_content(Some(format!("{}x{}", video.width, video.height).as_str()));
+                self.frame_rate.set_text_content(Some(format!("{:.3}", video.frame_rate).as_str()));
+                self.frame_count.set_text_content(Some(video.frame_count.to_string().as_str()));
+                self.rotation.set_text_content(Some(format!("{}°", video.rotation).as_str()));
+            }
+            None => {
+                self.resolution.set_text_content(Some("-"));
+                self.frame_rate.set_text_content(Some("-"));
+                self.frame_count.set_text_content(Some("-"));
+                self.rotation.set_text_content(Some("-"));
+            }
+        }
+
+        let codecs = probe.streams.iter()
+            .map(|s| s.codec_name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        self.codecs.set_text_content(Some(codecs.as_str()));
+        self.creation_time.set_text_content(Some(probe.creation_time.as_deref().unwrap_or("-")));
+
+        let _ = self.panel.class_list().remove_1("metadata-hidden");
+    }
+
+    pub fn hide(&self) {
+        let _ = self.panel.class_list().add_1("metadata-hidden");
+    }
+}
+
+/// Canvas overlaid on the video that shows the codec's exact frame while paused,
+/// since the webview only seeks to whichever frame it considers closest.
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlFrameCanvas {
+    canvas: HtmlCanvasElement,
+    // session and handle of the stream frames are decoded from
+    stream: Rc<Cell<Option<(u64, u64)>>>,
+    generation: Rc<Cell<u32>>,
+}
+
+impl HtmlFrameCanvas {
+    const CANVAS_ID: &'static str = "frame-canvas";
+
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            canvas: get_element_as!(document, Self::CANVAS_ID, HtmlCanvasElement),
+            stream: Rc::new(Cell::new(None)),
+            generation: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn get_stream(&self) -> Option<(u64, u64)> {
+        self.stream.get()
+    }
+
+    pub fn set_stream(&self, stream: Option<(u64, u64)>) {
+        self.stream.set(stream);
+        self.hide();
+    }
+
+    pub fn paint_at(&self, time: f64) {
+        let Some((session, handle)) = self.stream.get() else {
+            return;
+        };
+
+        // only the latest request gets painted, older frames may still be decoding
+        let generation = self.generation.get().wrapping_add(1);
+        self.generation.set(generation);
+
+        let frame_canvas = self.clone();
+        spawn_local(async move {
+            match decode_frame_at(session, handle, time).await {
+                Ok(frame) if frame_canvas.generation.get() == generation => {
+                    if let Err(e) = frame_canvas.paint(&frame) {
+                        error_log!("Failed to paint frame at {}: {}", frame.timestamp, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error_log!("Failed to decode frame at {}: {}", time, e),
+            }
+        });
+    }
+
+    pub fn hide(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        let _ = self.canvas.class_list().add_1("frame-hidden");
+    }
+
+    fn paint(&self, frame: &VideoFrame) -> JsResult<()> {
+        self.canvas.set_width(frame.width);
+        self.canvas.set_height(frame.height);
+
+        let context = self.canvas.get_context("2d")?
+            .ok_or("Failed to get canvas context")?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| "Failed to cast canvas context")?;
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(frame.rgba.as_slice()), frame.width, frame.height)?;
+        context.put_image_data(&image, 0f64, 0f64)?;
+
+        debug_console_log!("Painted frame {} at {}", frame.frame_index, frame.timestamp);
+        let _ = self.canvas.class_list().remove_1("frame-hidden");
+        Ok(())
+    }
+}
+
+async fn decode_frame_at(session: u64, handle: u64, time: f64) -> JsResult<VideoFrame> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+    Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+    Reflect::set(&args, &"position".into(), &time.into())?;
+    Reflect::set(&args, &"isFrameIndex".into(), &false.into())?;
+
+    let js_frame = JsFuture::from(tauri_invoke("decode_frame_at", args.into())).await?;
+    let bytes = js_sys::Uint8Array::new(&js_frame).to_vec();
+    VideoFrame::from_bytes(&bytes).ok_or("Malformed frame from codec".into())
+}
+
+
+/// Hover preview above the progress bar and the optional filmstrip row under it,
+/// both backed by the thumbnails the desktop app caches for the open video
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlThumbnailPreview {
+    document: Document,
+    progress_container: HtmlDivElement,
+    preview: HtmlDivElement,
+    preview_image: HtmlImageElement,
+    preview_time: HtmlSpanElement,
+    filmstrip: HtmlDivElement,
+    video_path: Rc<RefCell<Option<String>>>,
+    index: Rc<RefCell<Option<ThumbnailIndex>>>,
+    urls: Rc<RefCell<HashMap<usize, String>>>,
+    hover_generation: Rc<Cell<u32>>,
+}
+
+impl HtmlThumbnailPreview {
+    const PROGRESS_CONTAINER_ID: &'static str = "progress-container";
+    const PREVIEW_ID: &'static str = "thumbnail-preview";
+    const PREVIEW_IMAGE_ID: &'static str = "thumbnail-preview-image";
+    const PREVIEW_TIME_ID: &'static str = "thumbnail-preview-time";
+    const FILMSTRIP_ID: &'static str = "filmstrip";
+
+    const THUMBNAIL_INTERVAL: f64 = 2.0;
+    const FILMSTRIP_LENGTH: usize = 12;
+
+
+    pub fn new(document: &Document) -> Self {
+        Self {
+            document: document.clone(),
+            progress_container: get_element_as!(document, Self::PROGRESS_CONTAINER_ID, HtmlDivElement),
+            preview: get_element_as!(document, Self::PREVIEW_ID, HtmlDivElement),
+            preview_image: get_element_as!(document, Self::PREVIEW_IMAGE_ID, HtmlImageElement),
+            preview_time: get_element_as!(document, Self::PREVIEW_TIME_ID, HtmlSpanElement),
+            filmstrip: get_element_as!(document, Self::FILMSTRIP_ID, HtmlDivElement),
+            video_path: Rc::new(RefCell::new(None)),
+            index: Rc::new(RefCell::new(None)),
+            urls: Rc::new(RefCell::new(HashMap::new())),
+            hover_generation: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn load(&self, video_path: String) {
+        self.clear();
+        *self.video_path.borrow_mut() = Some(video_path.clone());
+
+        let thumbnail_preview = self.clone();
+        spawn_local(async move {
+            match get_thumbnails(&video_path, Self::THUMBNAIL_INTERVAL).await {
+                // a different video may have been opened while these were generating
+                Ok(index) if thumbnail_preview.video_path.borrow().as_deref() == Some(video_path.as_str()) => {
+                    debug_console_log!("Loaded {} thumbnails for {}", index.thumbnails.len(), video_path);
+                    *thumbnail_preview.index.borrow_mut() = Some(index);
+                    if thumbnail_preview.is_filmstrip_visible() {
+                        thumbnail_preview.fill_filmstrip();
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error_log!("Failed to load thumbnails for {}: {}", video_path, e),
+            }
+        });
+    }
+
+    pub fn clear(&self) {
+        self.hide_preview();
+        *self.index.borrow_mut() = None;
+        *self.video_path.borrow_mut() = None;
+        for (_, url) in self.urls.borrow_mut().drain() {
+            let _ = Url::revoke_object_url(&url);
+        }
+        self.filmstrip.set_inner_html("");
+    }
+
+    pub fn show_preview(&self, percent: f64) {
+        let (thumbnail, time) = {
+            let index = self.index.borrow();
+            let Some(index) = index.as_ref() else {
+                return;
+            };
+            let time = index.duration * percent;
+            let Some(thumbnail) = index.nearest(time) else {
+                return;
+            };
+            (thumbnail, time)
+        };
+
+        let generation = self.hover_generation.get().wrapping_add(1);
+        self.hover_generation.set(generation);
+
+        self.preview_time.set_text_content(Some(format_time(time).as_str()));
+        let _ = self.preview.style().set_property("left", format!("{}%", percent * 100f64).as_str());
+        let _ = self.preview.class_list().remove_1("thumbnail-hidden");
+
+        let thumbnail_preview = self.clone();
+        spawn_local(async move {
+            match thumbnail_preview.thumbnail_url(thumbnail).await {
+                Ok(url) if thumbnail_preview.hover_generation.get() == generation => thumbnail_preview.preview_image.set_src(&url),
+                Ok(_) => {}
+                Err(e) => {
+                    debug_console_log!("Failed to load thumbnail {}: {}", thumbnail, e);
+                }
+            }
+        });
+    }
+
+    pub fn hide_preview(&self) {
+        self.hover_generation.set(self.hover_generation.get().wrapping_add(1));
+        let _ = self.preview.class_list().add_1("thumbnail-hidden");
+    }
+
+    pub fn is_filmstrip_visible(&self) -> bool {
+        self.progress_container.class_list().contains("filmstrip-visible")
+    }
+
+    pub fn toggle_filmstrip(&self) {
+        let visible = !self.is_filmstrip_visible();
+        let _ = self.progress_container.class_list().toggle_with_force("filmstrip-visible", visible);
+        if visible {
+            self.fill_filmstrip();
+        }
+    }
+
+    fn fill_filmstrip(&self) {
+        self.filmstrip.set_inner_html("");
+
+        let picks: Vec<usize> = match self.index.borrow().as_ref() {
+            Some(index) if !index.thumbnails.is_empty() => {
+                let count = index.thumbnails.len();
+                let length = count.min(Self::FILMSTRIP_LENGTH);
+                (0..length).map(|i| i * count / length).collect()
+            }
+            _ => return,
+        };
+
+        for thumbnail in picks {
+            let Ok(image) = self.document.create_element("img").map(|e| e.unchecked_into::<HtmlImageElement>()) else {
+                continue;
+            };
+            image.set_class_name("filmstrip-frame");
+            let _ = self.filmstrip.append_child(&image);
+
+            let thumbnail_preview = self.clone();
+            spawn_local(async move {
+                if let Ok(url) = thumbnail_preview.thumbnail_url(thumbnail).await {
+                    image.set_src(&url);
+                }
+            });
+        }
+    }
+
+    async fn thumbnail_url(&self, thumbnail: usize) -> JsResult<String> {
+        if let Some(url) = self.urls.borrow().get(&thumbnail) {
+            return Ok(url.clone());
+        }
+
+        let video_path = self.video_path.borrow().clone().ok_or("No video loaded")?;
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"path".into(), &video_path.into())?;
+        Reflect::set(&args, &"index".into(), &(thumbnail as f64).into())?;
+
+        let js_bytes = JsFuture::from(tauri_invoke("get_thumbnail", args.into())).await?;
+        let array = js_sys::Array::new();
+        array.push(&js_sys::Uint8Array::new(&js_bytes));
+
+        let blob_options = BlobPropertyBag::new();
+        blob_options.set_type("image/jpeg");
+        let blob = Blob::new_with_u8_array_sequence_and_options(&array, &blob_options)?;
+        let url = Url::create_object_url_with_blob(&blob)?;
+
+        self.urls.borrow_mut().insert(thumbnail, url.clone());
+        Ok(url)
+    }
+}
+
+/// Keyframes of the open video, seeking to one of these is instant so scrubbing snaps to them
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlKeyframeIndex {
+    video_path: Rc<RefCell<Option<String>>>,
+    index: Rc<RefCell<Option<KeyframeIndex>>>,
+}
+
+impl HtmlKeyframeIndex {
+    pub fn new() -> Self {
+        Self {
+            video_path: Rc::new(RefCell::new(None)),
+            index: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn load(&self, video_path: String) {
+        self.clear();
+        *self.video_path.borrow_mut() = Some(video_path.clone());
+
+        let keyframe_index = self.clone();
+        spawn_local(async move {
+            match get_keyframe_index(&video_path).await {
+                Ok(index) if keyframe_index.video_path.borrow().as_deref() == Some(video_path.as_str()) => {
+                    debug_console_log!("Loaded {} keyframes for {}", index.keyframes.len(), video_path);
+                    *keyframe_index.index.borrow_mut() = Some(index);
+                }
+                Ok(_) => {}
+                Err(e) => error_log!("Failed to load keyframes for {}: {}", video_path, e),
+            }
+        });
+    }
+
+    pub fn clear(&self) {
+        *self.index.borrow_mut() = None;
+        *self.video_path.borrow_mut() = None;
+    }
+
+    /// Nearest keyframe to `time`, or `time` itself until the index has loaded
+    pub fn snap(&self, time: f64) -> f64 {
+        self.index.borrow().as_ref().and_then(|index| index.nearest(time)).unwrap_or(time)
+    }
+}
+
+async fn get_keyframe_index(video_path: &str) -> JsResult<KeyframeIndex> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"path".into(), &video_path.into())?;
+
+    let js_index = JsFuture::from(tauri_invoke("get_keyframe_index", args.into())).await?;
+    serde_wasm_bindgen::from_value(js_index).map_err(|e| e.to_string().into())
+}
+
+async fn get_thumbnails(video_path: &str, interval: f64) -> JsResult<ThumbnailIndex> {
+    let args = js_sys::Object::new();
+    Reflect::set(&args, &"path".into(), &video_path.into())?;
+    Reflect::set(&args, &"interval".into(), &interval.into())?;
+
+    let js_index = JsFuture::from(tauri_invoke("get_thumbnails", args.into())).await?;
+    serde_wasm_bindgen::from_value(js_index).map_err(|e| e.to_string().into())
+}
@@ -1,4 +1,5 @@
 pub(crate) mod html_events;
+pub(crate) mod html_media_source;
 pub(crate) mod html_video;
 pub(crate) mod html_ui;
 pub(crate) mod html_callback;
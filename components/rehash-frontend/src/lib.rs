@@ -5,12 +5,15 @@ mod html;
 mod tauri;
 
 use crate::html::html_callback::HtmlVideoCallbackController;
-use crate::html::html_ui::HtmlLoadBar;
+use crate::html::html_media_source::{HtmlMediaSource, HtmlMediaSourcePlayerInternal};
+use crate::html::html_ui::{HtmlAudioMarkers, HtmlFrameCanvas, HtmlKeyframeIndex, HtmlLoadBar, HtmlMetadataPanel, HtmlOperationProgress, HtmlPlayMarkers, HtmlReelStatus,
+                           HtmlThumbnailPreview};
 use crate::prelude::*;
 use crate::prelude::*;
-use crate::tauri::tauri_callback::FileOpenCallbackController;
+use crate::tauri::tauri_callback::{FileOpenCallbackController, OperationCallbackController, ReelCallbackController};
+use crate::tauri::tauri_events::VideoSession;
 use crate::video::video_player::{SharedVideoPlayer, VideoPlayer, VideoUIController};
-use html::html_video::{HtmlVideoPlayerInternal, HtmlVideoUIController};
+use html::html_video::HtmlVideoUIController;
 use rehash_utils::utils::{set_panic_hook, tauri_invoke};
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -37,12 +40,13 @@ pub fn main() {
 }
 
 #[inline]
-fn create_shared_video_player(html_controller: Rc<dyn VideoUIController<HtmlVideoPlayerInternal>>, html_video_element: HtmlVideoElement) -> SharedVideoPlayer {
+fn create_shared_video_player(html_controller: Rc<dyn VideoUIController<HtmlMediaSourcePlayerInternal>>, html_video_element: HtmlVideoElement,
+                              media_source: HtmlMediaSource) -> SharedVideoPlayer {
     Rc::new(
         RefCell::new(
             Box::new(
                 VideoPlayer::new(
-                    HtmlVideoPlayerInternal::new(html_video_element),
+                    HtmlMediaSourcePlayerInternal::new(html_video_element, media_source),
                     html_controller
                 )
             )
@@ -78,15 +82,34 @@ async fn init() -> RehashResultUnit {
         version_header.set_text_content(Some(&format!("Build Desktop: {}", desktop_version)));
     }
 
-    let html_controller = HtmlVideoUIController::new(document.clone());
-    let video_player = create_shared_video_player(Rc::new(html_controller), video_element.clone());
-
-    let html_controller = HtmlVideoUIController::new(document.clone());
-    let mut callback_controller = HtmlVideoCallbackController::new(video_player.clone(), html_controller);
+    let html_frame_canvas = HtmlFrameCanvas::new(&document);
+    let html_controller = HtmlVideoUIController::new(document.clone(), html_frame_canvas.clone());
+    let html_media_source = HtmlMediaSource::new(video_element.clone());
+    let video_player = create_shared_video_player(Rc::new(html_controller), video_element.clone(), html_media_source.clone());
+
+    let html_thumbnail_preview = HtmlThumbnailPreview::new(&document);
+    let html_reel_status = HtmlReelStatus::new(&document);
+    let html_play_markers = HtmlPlayMarkers::new(&document);
+    let html_audio_markers = HtmlAudioMarkers::new(&document);
+    let html_keyframe_index = HtmlKeyframeIndex::new();
+    let video_session = VideoSession::default();
+    let html_controller = HtmlVideoUIController::new(document.clone(), html_frame_canvas.clone());
+    let mut callback_controller = HtmlVideoCallbackController::new(video_player.clone(), html_controller, html_thumbnail_preview.clone(),
+                                                                   html_reel_status.clone(), html_play_markers.clone(),
+                                                                   html_audio_markers.clone(), html_keyframe_index.clone(), video_session.clone());
     callback_controller.register_events();
 
+    let reel_controller = ReelCallbackController::new(html_reel_status);
+    reel_controller.register_events();
+
+    let operation_controller = OperationCallbackController::new(HtmlOperationProgress::new(&document));
+    operation_controller.register_events();
+
     let html_load_bar = HtmlLoadBar::new(&document);
-    let mut file_open_controller = FileOpenCallbackController::new(video_element, html_load_bar);
+    let html_metadata_panel = HtmlMetadataPanel::new(&document);
+    let mut file_open_controller = FileOpenCallbackController::new(video_element, html_load_bar, html_metadata_panel, html_frame_canvas,
+                                                                  html_thumbnail_preview, html_play_markers, html_audio_markers,
+                                                                  html_keyframe_index, html_media_source, video_session);
     file_open_controller.register_events();
 
     Ok(())
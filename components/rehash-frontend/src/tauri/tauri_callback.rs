@@ -1,8 +1,15 @@
 use crate::callback_event;
-use crate::html::html_ui::HtmlLoadBar;
+use crate::html::html_media_source::HtmlMediaSource;
+use crate::html::html_ui::{HtmlAudioMarkers, HtmlFrameCanvas, HtmlKeyframeIndex, HtmlLoadBar, HtmlMetadataPanel, HtmlOperationProgress, HtmlPlayMarkers, HtmlReelStatus,
+                          HtmlThumbnailPreview};
 use crate::tauri::tauri_callback::file_open_closure::FileOpenClosure;
 use crate::tauri::tauri_events::file_open_event::{FileOpenEvent, FileOpenEventCtx};
+use crate::tauri::tauri_events::import_progress_callback::ImportProgressCallback;
 use crate::tauri::tauri_events::onload_callback::OnLoadCallback;
+use crate::tauri::tauri_events::operation_progress_callback::OperationProgressCallback;
+use crate::tauri::tauri_events::proxy_progress_callback::ProxyProgressCallback;
+use crate::tauri::tauri_events::reel_progress_callback::ReelProgressCallback;
+use crate::tauri::tauri_events::VideoSession;
 use crate::video::event::{CallbackController, CallbackEvent};
 use crate::video::video_callback::CallbackClosureWrapper;
 use js_sys::Reflect;
@@ -23,7 +30,9 @@ pub(crate) struct FileOpenCallbackController {
 
 
 impl FileOpenCallbackController {
-    pub fn new(video_element: HtmlVideoElement, load_bar: HtmlLoadBar) -> Self {
+    pub fn new(video_element: HtmlVideoElement, load_bar: HtmlLoadBar, metadata_panel: HtmlMetadataPanel, frame_canvas: HtmlFrameCanvas,
+               thumbnail_preview: HtmlThumbnailPreview, play_markers: HtmlPlayMarkers,
+               audio_markers: HtmlAudioMarkers, keyframe_index: HtmlKeyframeIndex, media_source: HtmlMediaSource, session: VideoSession) -> Self {
         let open_event = callback_event!(FileOpenEvent);
         let load_callback = Box::new(OnLoadCallback { ctx: load_bar.clone() });
         let closure = CallbackClosureWrapper::create_callback(load_callback);
@@ -32,7 +41,10 @@ impl FileOpenCallbackController {
         closure.forget();
 
         Self {
-            ctx: Arc::new(Mutex::new(FileOpenEventCtx { video_element, video_path: None, load_bar })),
+            ctx: Arc::new(Mutex::new(FileOpenEventCtx {
+                video_element, video_path: None, playable_path: None, load_bar, metadata_panel, frame_canvas, thumbnail_preview, play_markers, audio_markers, keyframe_index,
+                media_source, session,
+            })),
             open_event,
         }
     }
@@ -46,6 +58,67 @@ impl CallbackController for FileOpenCallbackController {
         tauri_listen("select-video-event", file_open_closure.as_ref().as_ref().unchecked_ref());
 
         file_open_closure.forget();
+
+        let load_bar = self.ctx.lock().unwrap().load_bar.clone();
+        let import_progress = Box::new(ImportProgressCallback { ctx: load_bar });
+        let import_progress_closure = CallbackClosureWrapper::create_callback(import_progress);
+
+        tauri_listen("import-progress-event", import_progress_closure.as_ref().as_ref().unchecked_ref());
+
+        import_progress_closure.forget();
+
+        let proxy_progress = Box::new(ProxyProgressCallback { ctx: self.ctx.clone() });
+        let proxy_progress_closure = CallbackClosureWrapper::create_callback(proxy_progress);
+
+        tauri_listen("proxy-progress-event", proxy_progress_closure.as_ref().as_ref().unchecked_ref());
+
+        proxy_progress_closure.forget();
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ReelCallbackController {
+    reel_status: HtmlReelStatus,
+}
+
+
+impl ReelCallbackController {
+    pub fn new(reel_status: HtmlReelStatus) -> Self {
+        Self { reel_status }
+    }
+}
+
+impl CallbackController for ReelCallbackController {
+    fn register_events(&self) {
+        let reel_progress = Box::new(ReelProgressCallback { ctx: self.reel_status.clone() });
+        let reel_progress_closure = CallbackClosureWrapper::create_callback(reel_progress);
+
+        tauri_listen("reel-progress-event", reel_progress_closure.as_ref().as_ref().unchecked_ref());
+
+        reel_progress_closure.forget();
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct OperationCallbackController {
+    operation_progress: HtmlOperationProgress,
+}
+
+
+impl OperationCallbackController {
+    pub fn new(operation_progress: HtmlOperationProgress) -> Self {
+        Self { operation_progress }
+    }
+}
+
+impl CallbackController for OperationCallbackController {
+    fn register_events(&self) {
+        let operation_progress = Box::new(OperationProgressCallback { ctx: self.operation_progress.clone() });
+        let operation_progress_closure = CallbackClosureWrapper::create_callback(operation_progress);
+
+        tauri_listen("codec-progress-event", operation_progress_closure.as_ref().as_ref().unchecked_ref());
+
+        operation_progress_closure.forget();
     }
 }
 
@@ -1,19 +1,49 @@
 use crate::video::event::CallbackEvent;
 use crate::JsResult;
 use js_sys::Reflect;
-use rehash_utils::utils::tauri_invoke;
+use rehash_utils::utils::{tauri_convert_file_src, tauri_invoke};
+use std::cell::Cell;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use wasm_bindgen_futures::{spawn_local, JsFuture};
-use web_sys::{Blob, BlobPropertyBag, HtmlVideoElement, Url};
+use web_sys::HtmlVideoElement;
 
 use crate::prelude::*;
 use crate::tauri::tauri_events::file_open_event::FileOpenEventCtx;
 
 pub(crate) type FileOpenEventCtxType = Arc<Mutex<FileOpenEventCtx>>;
 
+/// Scheme the desktop serves the playing video on
+const VIDEO_PROTOCOL: &str = "rehash";
+
+
+/// Desktop session of this window, opened with the first video. Exports and analyses name it so the desktop
+/// reads the video this window plays rather than another window's
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VideoSession(Rc<Cell<Option<u64>>>);
+
+impl VideoSession {
+    pub fn get(&self) -> Option<u64> {
+        self.0.get()
+    }
+
+    pub fn set(&self, session: u64) {
+        self.0.set(Some(session));
+    }
+
+    /// Sets the session on the `args` of a desktop call
+    pub fn add_to(&self, args: &js_sys::Object) -> JsResult<()> {
+        let session = self.get().ok_or("No video is open")?;
+        Reflect::set(args, &"session".into(), &(session as f64).into())?;
+        Ok(())
+    }
+}
+
 pub(crate) mod file_open_event {
     use super::*;
-    use crate::html::html_ui::HtmlLoadBar;
+    use crate::html::html_media_source::HtmlMediaSource;
+    use crate::html::html_ui::{HtmlAudioMarkers, HtmlFrameCanvas, HtmlKeyframeIndex, HtmlLoadBar, HtmlMetadataPanel, HtmlPlayMarkers, HtmlThumbnailPreview};
+    use rehash_codec_ffi::codec::{SegmentedStream, VideoProbe, VideoStreamMeta};
     use wasm_bindgen::JsValue;
 
     #[derive(Debug, Clone)]
@@ -23,7 +53,19 @@ pub(crate) mod file_open_event {
     pub(crate) struct FileOpenEventCtx {
         pub(crate) video_element: HtmlVideoElement,
         pub(crate) video_path: Option<String>,
+        /// File handed to the player after any import, the proxy of this replaces it once generated
+        pub(crate) playable_path: Option<String>,
         pub(crate) load_bar: HtmlLoadBar,
+        pub(crate) metadata_panel: HtmlMetadataPanel,
+        pub(crate) frame_canvas: HtmlFrameCanvas,
+        pub(crate) thumbnail_preview: HtmlThumbnailPreview,
+        pub(crate) play_markers: HtmlPlayMarkers,
+        pub(crate) audio_markers: HtmlAudioMarkers,
+        pub(crate) keyframe_index: HtmlKeyframeIndex,
+        /// Plays files the webview can't decode itself, instead of importing them first
+        pub(crate) media_source: HtmlMediaSource,
+        /// Desktop session of this window, its streams are only readable through it
+        pub(crate) session: VideoSession,
     }
 
 
@@ -40,9 +82,81 @@ pub(crate) mod file_open_event {
             }
             let arc_ctx = ctx.clone();
             spawn_local(async move {
-                let mutex = arc_ctx.lock().unwrap();
-                if let Some(string) = &mutex.video_path {
-                    load_video_blob(&mutex.video_element, string, &mutex.load_bar).await;
+                let mut mutex = arc_ctx.lock().unwrap();
+                if let Some(string) = mutex.video_path.clone() {
+                    mutex.play_markers.clear();
+                    mutex.audio_markers.clear();
+                    let session = match mutex.session.get() {
+                        Some(session) => session,
+                        None => match open_video_session().await {
+                            Ok(session) => {
+                                mutex.session.set(session);
+                                session
+                            }
+                            Err(e) => {
+                                error_log!("Failed to open a video session: {}", e);
+                                return;
+                            }
+                        },
+                    };
+                    let mut segmented = false;
+                    let playable_path = match probe_video(&string).await {
+                        Ok(probe) => {
+                            mutex.metadata_panel.update(&probe);
+                            if !probe.can_import() {
+                                error_log!("Unable to play {}: {}", string, probe.unsupported_reason().unwrap_or_default());
+                                mutex.thumbnail_preview.clear();
+                                return;
+                            }
+
+                            if probe.needs_import() && play_segmented(&mutex.media_source, &mutex.load_bar, session, &string).await {
+                                segmented = true;
+                                string.clone()
+                            } else if probe.needs_import() {
+                                mutex.load_bar.set_label("Importing...");
+                                mutex.load_bar.update_progress(0f64, 1f64);
+                                mutex.load_bar.show_loader();
+                                match import_video(&string).await {
+                                    Ok(path) => path,
+                                    Err(e) => {
+                                        mutex.load_bar.hide_loader();
+                                        mutex.thumbnail_preview.clear();
+                                        error_log!("Failed to import {}: {}", string, e);
+                                        return;
+                                    }
+                                }
+                            } else {
+                                string.clone()
+                            }
+                        }
+                        Err(e) => {
+                            mutex.metadata_panel.hide();
+                            mutex.thumbnail_preview.clear();
+                            error_log!("Failed to probe {}: {}", string, e);
+                            return;
+                        }
+                    };
+
+                    mutex.playable_path = Some(playable_path.clone());
+                    if !segmented {
+                        mutex.media_source.detach();
+                        mutex.load_bar.set_label("Loading...");
+                        load_video_source(&mutex.video_element, &playable_path, &mutex.load_bar, session).await;
+                    }
+                    mutex.thumbnail_preview.load(playable_path.clone());
+                    mutex.keyframe_index.load(playable_path.clone());
+
+                    // separate stream so frame decoding doesn't disturb the chunked playback read
+                    if let Some((_, previous)) = mutex.frame_canvas.get_stream() {
+                        mutex.frame_canvas.set_stream(None);
+                        if let Err(e) = close_video_stream(session, previous).await {
+                            error_log!("Failed to close frame stream {}: {}", previous, e);
+                        }
+                    }
+                    match open_video_stream(session, &playable_path).await {
+                        Ok(meta) => mutex.frame_canvas.set_stream(Some((session, meta.handle))),
+                        Err(e) => error_log!("Failed to open frame stream for {}: {}", playable_path, e),
+                    }
                 }
             });
 
@@ -57,64 +171,276 @@ pub(crate) mod file_open_event {
         }
     }
 
-    async fn load_video_blob(video_element: &HtmlVideoElement, file_path: &String, load_bar: &HtmlLoadBar) {
+    async fn open_video_session() -> JsResult<u64> {
+        let js_session = JsFuture::from(tauri_invoke("open_video_session", JsValue::NULL)).await?;
+        serde_wasm_bindgen::from_value(js_session).map_err(|e| e.to_string().into())
+    }
+
+    async fn probe_video(file_path: &String) -> JsResult<VideoProbe> {
         let args = js_sys::Object::new();
-        Reflect::set(&args, &"path".into(), &file_path.into()).unwrap();
+        Reflect::set(&args, &"path".into(), &file_path.into())?;
+
+        let js_probe = JsFuture::from(tauri_invoke("probe_video", args.into())).await?;
+        serde_wasm_bindgen::from_value(js_probe).map_err(|e| e.to_string().into())
+    }
+
+    async fn import_video(file_path: &String) -> JsResult<String> {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"path".into(), &file_path.into())?;
 
+        let js_path = JsFuture::from(tauri_invoke("import_video", args.into())).await?;
+        js_path.as_string().ok_or("Expected an imported file path".into())
+    }
+
+    /// Attaches a segmented stream of `file_path` to the media source, false when the codec can't segment it
+    /// or the webview can't play what it would produce, in which case the file is imported as before
+    async fn play_segmented(media_source: &HtmlMediaSource, load_bar: &HtmlLoadBar, session: u64, file_path: &String) -> bool {
+        load_bar.set_label("Preparing...");
+        load_bar.update_progress(0f64, 1f64);
+        load_bar.show_loader();
+
+        let stream = match open_segmented_stream(session, file_path).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug_console_log!("Not segmenting {}: {}", file_path, e);
+                return false;
+            }
+        };
+        if !HtmlMediaSource::is_type_supported(&stream.mime_type) {
+            debug_console_log!("Media source can't play {} as {}", file_path, stream.mime_type);
+            media_source.detach();
+            let _ = close_segmented_stream(session, stream.handle).await;
+            return false;
+        }
+
+        match media_source.attach(session, stream) {
+            Ok(_) => true,
+            Err(e) => {
+                error_log!("Failed to attach {} to the media source: {}", file_path, e);
+                false
+            }
+        }
+    }
+
+    async fn open_segmented_stream(session: u64, file_path: &String) -> JsResult<SegmentedStream> {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+        Reflect::set(&args, &"path".into(), &file_path.into())?;
+
+        let js_stream = JsFuture::from(tauri_invoke("open_segmented_stream", args.into())).await?;
+        serde_wasm_bindgen::from_value(js_stream).map_err(|e| e.to_string().into())
+    }
+
+    async fn close_segmented_stream(session: u64, handle: u64) -> JsResult<()> {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+        Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+
+        JsFuture::from(tauri_invoke("close_segmented_stream", args.into())).await?;
+        Ok(())
+    }
+
+    async fn open_video_stream(session: u64, file_path: &String) -> JsResult<VideoStreamMeta> {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+        Reflect::set(&args, &"path".into(), &file_path.into())?;
+
+        let js_meta = JsFuture::from(tauri_invoke("open_video_stream", args.into())).await?;
+        serde_wasm_bindgen::from_value(js_meta).map_err(|e| e.to_string().into())
+    }
+
+    async fn close_video_stream(session: u64, handle: u64) -> JsResult<()> {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"session".into(), &(session as f64).into())?;
+        Reflect::set(&args, &"handle".into(), &(handle as f64).into())?;
+
+        JsFuture::from(tauri_invoke("close_video_stream", args.into())).await?;
+        Ok(())
+    }
+
+    /// Opens `file_path` for playback and points the video at it, the rehash protocol then serves the ranges the player asks for
+    pub(crate) async fn load_video_source(video_element: &HtmlVideoElement, file_path: &String, load_bar: &HtmlLoadBar, session: u64) {
+        let args = js_sys::Object::new();
+        Reflect::set(&args, &"path".into(), &file_path.into()).unwrap();
+        Reflect::set(&args, &"session".into(), &(session as f64).into()).unwrap();
 
         match JsFuture::from(tauri_invoke("get_video", args.into())).await {
-            Ok(js_length) => {
-                load_bar.show_loader();
-                let const_length = js_length.as_f64().expect("Expected a number") as u32;
-                debug_console_log!("File frontend length: {}", const_length);
-                let mut vec = Vec::with_capacity(const_length as usize);
-                let mut i = 0;
-                let mut length = 0;
-                while length < const_length {
-                    match JsFuture::from(tauri_invoke("get_video_chunk", JsValue::NULL)).await {
-                        Ok(js_chunk) => {
-                            let uint8_array = js_sys::Uint8Array::new(&js_chunk);
-                            let chunk_length = uint8_array.length();
-                            let mut chunk_vec = vec![0u8; chunk_length as usize];
-                            uint8_array.copy_to(&mut chunk_vec);
-                            vec.extend_from_slice(&chunk_vec);
-                            i += 1;
-                            length += chunk_length;
-                            load_bar.update_progress(length as f64, const_length as f64);
-                        },
-                        Err(e) => {
-                            debug_console_log!("Failed to load read chunk: {}", i+1);
-                            debug_console_log!("{:?}", e);
-                            break
-                        }
+            Ok(js_session) => {
+                debug_console_log!("Playing in session {:?}", js_session.as_f64());
+                match tauri_convert_file_src(file_path, Some(VIDEO_PROTOCOL)).as_string() {
+                    Some(url) => {
+                        debug_console_log!("Playing from: {}", url);
+                        video_element.set_src(&url);
+                        video_element.load();
                     }
+                    None => error_log!("Failed to create a video url for {}", file_path),
+                }
+            }
+            Err(e) => {
+                debug_console_log!("Backend error: {:?}", e);
+            }
+        }
+        load_bar.hide_loader();
+    }
+}
+
+pub(crate) mod import_progress_callback {
+    use crate::html::html_ui::HtmlLoadBar;
+    use crate::CallbackClosureWrapper;
+    use js_sys::Reflect;
+    use rehash_codec_ffi::codec::ImportStatus;
+    use crate::error_to_tauri;
+    use rehash_utils::error_log;
+    use wasm_bindgen::JsValue;
+
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ImportProgressCallback {
+        pub(crate) ctx: HtmlLoadBar,
+    }
+
+    impl CallbackClosureWrapper<JsValue> for ImportProgressCallback {
+        fn closure(&mut self, event: JsValue) {
+            let status = Reflect::get(&event, &JsValue::from_str("payload"))
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|payload| serde_wasm_bindgen::from_value::<ImportStatus>(payload).map_err(|e| e.to_string()));
+
+            match status {
+                Ok(status) => self.ctx.update_progress(status.progress, 1f64),
+                Err(e) => error_log!("Malformed import progress event: {}", e),
+            }
+        }
+    }
+}
+
+pub(crate) mod proxy_progress_callback {
+    use super::*;
+    use crate::tauri::tauri_events::file_open_event::load_video_source;
+    use crate::CallbackClosureWrapper;
+    use rehash_codec_ffi::codec::{ImportStatus, JobState};
+    use crate::error_to_tauri;
+    use wasm_bindgen::JsValue;
+
+
+    /// Swaps the playing video for its proxy once that has been generated, keeping the position
+    #[derive(Debug, Clone)]
+    pub(crate) struct ProxyProgressCallback {
+        pub(crate) ctx: FileOpenEventCtxType,
+    }
+
+    impl CallbackClosureWrapper<JsValue> for ProxyProgressCallback {
+        fn closure(&mut self, event: JsValue) {
+            let status = Reflect::get(&event, &JsValue::from_str("payload"))
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|payload| serde_wasm_bindgen::from_value::<ImportStatus>(payload).map_err(|e| e.to_string()));
+
+            let status = match status {
+                Ok(status) => status,
+                Err(e) => {
+                    error_log!("Malformed proxy progress event: {}", e);
+                    return;
                 }
+            };
+            match status.state {
+                JobState::Running => return,
+                JobState::Failed => {
+                    error_log!("Proxy of {} failed: {}", status.file_path, status.error.unwrap_or_default());
+                    return;
+                }
+                JobState::Finished => {}
+            }
 
-                let final_array = js_sys::Uint8Array::new_with_length(vec.len() as u32);
-                final_array.copy_from(&vec);
+            // a file that is still being opened picks the proxy up itself
+            let Ok(ctx) = self.ctx.try_lock() else {
+                return;
+            };
+            // a video playing through the media source has no proxy to switch to
+            if ctx.media_source.is_attached() || ctx.playable_path.as_deref() != Some(status.file_path.as_str()) {
+                return;
+            }
+            let Some(session) = ctx.session.get() else {
+                return;
+            };
+            let (video_element, load_bar) = (ctx.video_element.clone(), ctx.load_bar.clone());
+            drop(ctx);
 
-                let array = js_sys::Array::new();
-                array.push(&final_array);
+            spawn_local(async move {
+                let (time, paused) = (video_element.current_time(), video_element.paused());
+                load_bar.set_label("Switching to proxy...");
+                load_video_source(&video_element, &status.file_path, &load_bar, session).await;
 
-                let blob_options = BlobPropertyBag::new();
-                blob_options.set_type("video/mp4");
+                video_element.set_current_time(time);
+                if !paused {
+                    let _ = video_element.play();
+                }
+            });
+        }
+    }
+}
 
-                match Blob::new_with_u8_array_sequence_and_options(&array, &blob_options) {
-                    Ok(blob) => {
-                        match Url::create_object_url_with_blob(&blob) {
-                            Ok(blob_url) => {
-                                debug_console_log!("Created blob URL: {}", blob_url);
-                                video_element.set_src(&blob_url);
-                                video_element.load();
-                            }
-                            Err(e) => { debug_console_log!("Failed to create blob URL: {:?}", e); }
-                        }
+pub(crate) mod reel_progress_callback {
+    use crate::html::html_ui::HtmlReelStatus;
+    use crate::CallbackClosureWrapper;
+    use js_sys::Reflect;
+    use rehash_codec_ffi::codec::ReelStatus;
+    use crate::error_to_tauri;
+    use rehash_utils::error_log;
+    use wasm_bindgen::JsValue;
+
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ReelProgressCallback {
+        pub(crate) ctx: HtmlReelStatus,
+    }
+
+    impl CallbackClosureWrapper<JsValue> for ReelProgressCallback {
+        fn closure(&mut self, event: JsValue) {
+            let status = Reflect::get(&event, &JsValue::from_str("payload"))
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|payload| serde_wasm_bindgen::from_value::<ReelStatus>(payload).map_err(|e| e.to_string()));
+
+            match status {
+                Ok(status) => {
+                    if let Some(error) = &status.error {
+                        error_log!("Reel {} failed: {}", status.output_path, error);
                     }
-                    Err(e) => { debug_console_log!("Failed to create blob: {:?}", e); }
+                    self.ctx.update(&status);
                 }
+                Err(e) => error_log!("Malformed reel progress event: {}", e),
             }
-            Err(e) => {
-                debug_console_log!("Backend error: {:?}", e);
+        }
+    }
+}
+
+pub(crate) mod operation_progress_callback {
+    use crate::html::html_ui::HtmlOperationProgress;
+    use crate::CallbackClosureWrapper;
+    use js_sys::Reflect;
+    use rehash_codec_ffi::codec::{JobState, OperationProgress};
+    use crate::error_to_tauri;
+    use rehash_utils::error_log;
+    use wasm_bindgen::JsValue;
+
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct OperationProgressCallback {
+        pub(crate) ctx: HtmlOperationProgress,
+    }
+
+    impl CallbackClosureWrapper<JsValue> for OperationProgressCallback {
+        fn closure(&mut self, event: JsValue) {
+            let progress = Reflect::get(&event, &JsValue::from_str("payload"))
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|payload| serde_wasm_bindgen::from_value::<OperationProgress>(payload).map_err(|e| e.to_string()));
+
+            match progress {
+                Ok(progress) => {
+                    if progress.state == JobState::Failed {
+                        error_log!("Operation {} on {} failed or was cancelled", progress.operation, progress.file_path);
+                    }
+                    self.ctx.update(progress);
+                }
+                Err(e) => error_log!("Malformed codec progress event: {}", e),
             }
         }
     }
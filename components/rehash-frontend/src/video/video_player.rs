@@ -89,10 +89,12 @@ where
 
     fn fast_forward(&self) {
         self.internal.fast_forward().expect("Video player failed to fast forward");
+        self.refresh_exact_frame();
     }
 
     fn rewind(&self) {
         self.internal.rewind().expect("Video player failed to rewind");
+        self.refresh_exact_frame();
     }
 
     fn get_progress(&self) -> f64 {
@@ -109,7 +111,8 @@ where
         let duration = self.get_video_length();
         let time = duration * progress;
         self.internal.set_video_progress(time);
-        self.video_controller.update_progress(time, duration)
+        self.video_controller.update_progress(time, duration);
+        self.refresh_exact_frame();
     }
 
     fn set_volume(&self, volume: f64) {
@@ -199,6 +202,13 @@ where
     pub fn get_type(&self) -> TypeId {
         self.type_id
     }
+
+    fn refresh_exact_frame(&self) {
+        if self.type_id == TypeId::of::<Paused>() {
+            let progress = self.internal.get_progress().expect("Video player failed to get progress");
+            self.video_controller.show_exact_frame(progress);
+        }
+    }
 }
 
 
@@ -256,6 +266,7 @@ where
         debug_console_log!("min time: {}", t);
         // self.set_video_progress(t);
         if let Ok(playing) = self.internal.play() {
+            self.video_controller.hide_exact_frame();
             self.video_controller.swap_play_button();
             Ok(self.transition())
         } else {
@@ -272,7 +283,9 @@ where
     pub(crate) fn pause(self) -> VideoPlayerResult<I, Paused> {
         let _ = self.internal.pause().expect("Failed to pause");
         self.video_controller.swap_pause_button();
-        Ok(self.transition())
+        let paused: VideoPlayer<I, Paused> = self.transition();
+        paused.refresh_exact_frame();
+        Ok(paused)
     }
 
     pub(crate) fn set_video_time(self) -> VideoPlayerResult<I, Playing> {
@@ -21,6 +21,10 @@ where
     fn update_start_dot_position(&self, start_position: f64);
 
     fn update_end_dot_position(&self, end_position: f64);
+
+    fn show_exact_frame(&self, time: f64);
+
+    fn hide_exact_frame(&self);
 }
 
 pub(crate) trait VideoUIRegister {
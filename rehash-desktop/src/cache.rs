@@ -0,0 +1,94 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// FNV-1a, unlike `DefaultHasher` its output stays the same between Rust releases so cache keys
+/// written by one build are still found by the next
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3));
+    }
+}
+
+
+fn modified_secs(modified: std::io::Result<SystemTime>) -> u64 {
+    modified
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Key for derived files of `path`, a changed source file gets a new key and the stale entry
+/// is left for `prune_cache_dir` to remove
+pub(crate) fn cache_key(path: &str, settings: impl Hash) -> Result<String, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    let mut hasher = Fnv1a::default();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    modified_secs(metadata.modified()).hash(&mut hasher);
+    settings.hash(&mut hasher);
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Removes the oldest entries of `root` so that at most `max_entries` remain, never touching `keep`
+pub(crate) fn prune_cache_dir(root: &Path, keep: &Path, max_entries: usize) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+
+    let mut cached: Vec<(PathBuf, u64)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path() != *keep)
+        .map(|entry| (entry.path(), modified_secs(entry.metadata().and_then(|m| m.modified()))))
+        .collect();
+
+    if cached.len() < max_entries {
+        return;
+    }
+
+    cached.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    for (path, _) in cached.into_iter().skip(max_entries.saturating_sub(1)) {
+        println!("Removing cached {}", path.display());
+        let _ = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+    }
+}
+
+/// Reads the `analysis` result cached for `path` under `root`, or runs `analyse` and caches what it returns
+pub(crate) fn cached_json<T: Serialize + DeserializeOwned>(root: &Path, path: &str, analysis: &str, max_entries: usize,
+                                                           analyse: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let cache_path = root.join(format!("{}.json", cache_key(path, analysis)?));
+
+    let cached = fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<T>(&json).ok());
+    if let Some(result) = cached {
+        return Ok(result);
+    }
+
+    let result = analyse()?;
+    fs::create_dir_all(root).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+    let json = serde_json::to_string(&result).map_err(|e| e.to_string())?;
+    fs::write(&cache_path, json).map_err(|e| format!("Failed to write {} analysis: {}", analysis, e))?;
+    prune_cache_dir(root, &cache_path, max_entries);
+
+    Ok(result)
+}
@@ -0,0 +1,59 @@
+use crate::cache::{cache_key, prune_cache_dir};
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::{JobState, ImportStatus};
+use std::path::PathBuf;
+use std::time::Duration;
+
+
+const MAX_CACHED_IMPORTS: usize = 8;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ImportCache {
+    root: PathBuf,
+}
+
+
+impl ImportCache {
+    pub fn new(root: PathBuf) -> ImportCache {
+        Self { root }
+    }
+
+    /// Imports `path` into the cache, reporting each status change through `on_progress`,
+    /// and returns the path of the playable MP4
+    pub fn import(&self, video: &VideoState, path: &str, on_progress: impl Fn(&ImportStatus)) -> Result<String, String> {
+        let output_path = self.root.join(format!("{}.mp4", cache_key(path, ())?));
+        let output = output_path.to_string_lossy().to_string();
+
+        if output_path.is_file() {
+            println!("Using cached import {} for {}", output, path);
+            return Ok(output);
+        }
+
+        let job = video.start_import(path, &output)?;
+        let result = wait(video, job, on_progress).map(|_| output);
+
+        prune_cache_dir(&self.root, &output_path, MAX_CACHED_IMPORTS);
+        result
+    }
+}
+
+/// Polls an import or proxy job until it is done and closes it
+pub(crate) fn wait(video: &VideoState, job: u64, on_progress: impl Fn(&ImportStatus)) -> Result<(), String> {
+    let result = loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let status = match video.import_status(job) {
+            Ok(status) => status,
+            Err(e) => break Err(e),
+        };
+        on_progress(&status);
+
+        match status.state {
+            JobState::Running => continue,
+            JobState::Finished => break Ok(()),
+            JobState::Failed => break Err(status.error.unwrap_or(format!("Failed to import {}", status.file_path))),
+        }
+    };
+
+    video.close_import(job);
+    result
+}
@@ -0,0 +1,24 @@
+use crate::cache::cached_json;
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::KeyframeIndex;
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::path::PathBuf;
+
+
+const MAX_CACHED_INDEXES: usize = 64;
+
+pub struct KeyframeCache {
+    root: PathBuf,
+}
+
+
+impl KeyframeCache {
+    pub fn new(root: PathBuf) -> KeyframeCache {
+        Self { root }
+    }
+
+    /// Indexing reads every packet of the file, so it is only done once per source file
+    pub fn get_or_index(&self, video: &VideoState, path: &str, progress: &ProgressReporter) -> Result<KeyframeIndex, String> {
+        cached_json(&self.root, path, "keyframes", MAX_CACHED_INDEXES, || video.keyframe_index(path, progress))
+    }
+}
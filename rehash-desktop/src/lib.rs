@@ -1,8 +1,29 @@
+mod cache;
+mod import;
+mod keyframes;
+#[cfg(test)]
+mod mock_codec;
+mod operations;
+mod plays;
+mod protocol;
+mod proxy;
+mod reel;
+mod thumbnail;
 mod video;
 
+use crate::import::ImportCache;
+use crate::keyframes::KeyframeCache;
+use crate::operations::OperationState;
+use crate::plays::PlayCache;
+use crate::proxy::ProxyCache;
+use crate::reel::ReelState;
+use crate::thumbnail::ThumbnailCache;
 use crate::video::VideoState;
+use rehash_codec_ffi::codec::{AudioAnalysis, ClipExport, KeyframeIndex, PlaySegmentation, ProxyResolution, ReelSegment, SegmentedStream, ThumbnailIndex, VideoProbe, VideoStreamMeta};
+use rehash_codec_ffi::codec_ffi::CodecBackend;
+use rehash_codec_ffi::mp4::Mp4Backend;
+use rehash_codec_ffi::worker::{self, CodecWorker, WorkerCrash, WORKER_ARG};
 use rehash_codec_ffi::RehashCodecLibrary;
-use std::ffi::CString;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
@@ -10,9 +31,9 @@ use tauri::menu::{
     AboutMetadata, Menu, MenuBuilder, MenuItem, MenuItemBuilder, Submenu, SubmenuBuilder,
 };
 use tauri::path::BaseDirectory;
-use tauri::{Emitter, Manager};
-use tauri::{State, Window};
-use tauri_plugin_dialog::{DialogExt, FileDialogBuilder, FilePath};
+use tauri::{Emitter, Manager, Runtime};
+use tauri::{AppHandle, State, Window, WindowEvent};
+use tauri_plugin_dialog::{DialogExt, FileDialogBuilder, FilePath, MessageDialogKind};
 
 pub const DESKTOP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -22,6 +43,13 @@ const CODEC_NAME: &str = "librehashcodec.so";
 #[cfg(target_os = "windows")]
 const CODEC_NAME: &str = "rehashcodec.dll";
 
+/// Set to run the codec in a worker process, so a file that crashes ffmpeg doesn't take the app down with it
+const CODEC_WORKER_ENV: &str = "REHASH_CODEC_WORKER";
+
+const SEGMENT_SECONDS: f64 = 4.0;
+
+const VIDEO_EXTENSIONS: [&str; 7] = ["mp4", "m4v", "mov", "mts", "m2ts", "mkv", "avi"];
+
 #[tauri::command]
 fn get_desktop_build() -> &'static str {
     DESKTOP_VERSION
@@ -38,30 +66,374 @@ fn wasm_error(message: String) {
 }
 
 #[tauri::command]
-fn get_video(state: State<VideoState>, path: String) -> Result<usize, String> {
-    let codec = state.codec.lock().unwrap();
-    let c_path = CString::new(path).expect("CString::new failed");
-    let mut len: usize = 0;
-    let data_ptr = codec.get_bytes_from_video(c_path.as_ptr(), &mut len as *mut usize);
+fn open_video_session<R: Runtime>(window: Window<R>, state: State<VideoState>) -> u64 {
+    state.open_session(window.label())
+}
+
+#[tauri::command]
+fn close_video_session<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64) -> Result<(), String> {
+    state.check_window(window.label(), session)?;
+    state.close_session(session)
+}
+
+// Plays the proxy of `path` in `session`, or in a new session of the window when there is none, and returns the session.
+// Without a proxy the file itself plays while one is generated in the background
+#[tauri::command]
+fn get_video<R: Runtime>(app: AppHandle<R>, window: Window<R>, state: State<VideoState>, path: String, session: Option<u64>) -> Result<u64, String> {
+    let proxy_path = app.try_state::<ProxyCache>().and_then(|proxies| proxies.cached(&path));
+    let has_proxy = proxy_path.is_some();
+
+    let (session, is_new) = match session {
+        Some(session) => {
+            state.check_window(window.label(), session)?;
+            (session, false)
+        }
+        None => (state.open_session(window.label()), true),
+    };
+    state.open_playback_stream(session, &path, proxy_path.unwrap_or(path.clone())).inspect_err(|_| {
+        if is_new {
+            let _ = state.close_session(session);
+        }
+    })?;
 
-    if data_ptr.is_null() {
-        return Err(String::from("Failed to get video data"));
+    if !has_proxy {
+        generate_proxy(app, path);
     }
+    Ok(session)
+}
+
+// Progress is sent as proxy-progress-event, the frontend switches over once it reports the proxy finished
+fn generate_proxy<R: Runtime>(app: AppHandle<R>, path: String) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (Some(video), Some(proxies)) = (app.try_state::<VideoState>(), app.try_state::<ProxyCache>()) else {
+            return;
+        };
+        if !proxies.needs_proxy(&video, &path) {
+            return;
+        }
+
+        let result = proxies.generate(&video, &path, |status| {
+            let _ = app.emit("proxy-progress-event", status);
+        });
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    });
+}
+
+// Chunks go back as raw bytes, a 5 MB chunk as a JSON number array costs far more to build and parse than to read
+#[tauri::command]
+fn get_video_chunk<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64) -> Result<tauri::ipc::Response, String> {
+    state.check_window(window.label(), session)?;
+    Ok(tauri::ipc::Response::new(state.get_bytes(session)?))
+}
+
+#[tauri::command]
+fn open_video_stream<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64, path: String) -> Result<VideoStreamMeta, String> {
+    state.check_window(window.label(), session)?;
+    state.open_stream(session, path)
+}
 
-    let bytes: Vec<u8> = unsafe {
-        Vec::from_raw_parts(data_ptr, len, len)
+// Sent as VideoStreamChunk::to_bytes, the header followed by the chunk
+#[tauri::command]
+fn read_video_stream_chunk<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64, handle: u64) -> Result<tauri::ipc::Response, String> {
+    state.check_window(window.label(), session)?;
+    let chunk = state.read_chunk(session, handle)?;
+    Ok(tauri::ipc::Response::new(chunk.to_bytes()))
+}
+
+#[tauri::command]
+fn seek_video_stream<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64, handle: u64, position: u64) -> Result<VideoStreamMeta, String> {
+    state.check_window(window.label(), session)?;
+    state.seek(session, handle, position)
+}
+
+#[tauri::command]
+fn close_video_stream(window: Window, state: State<VideoState>, session: u64, handle: u64) -> Result<(), String> {
+    state.check_window(window.label(), session)?;
+    state.close(session, handle)
+}
+
+#[tauri::command]
+fn decode_frame_at(window: Window, state: State<VideoState>, session: u64, handle: u64, position: f64, is_frame_index: bool) -> Result<tauri::ipc::Response, String> {
+    state.check_window(window.label(), session)?;
+    let bytes = state.decode_frame(session, handle, position, is_frame_index)?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+// Imports can take minutes for long transcodes, progress is sent as import-progress-event
+#[tauri::command]
+async fn import_video(app: AppHandle, path: String) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        let cache = app.try_state::<ImportCache>().ok_or("Import cache is not available")?;
+        cache.import(&video, &path, |status| {
+            let _ = app.emit("import-progress-event", status);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Plays a file the webview can't through Media Source Extensions, scanning it for segments is what takes time.
+// The file also becomes what `session` plays, so clips and analyses read it
+#[tauri::command]
+async fn open_segmented_stream(app: AppHandle, window: Window, session: u64, path: String) -> Result<SegmentedStream, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        video.check_window(window.label(), session)?;
+        let stream = app.state::<OperationState>().track(&app, "segments", &path, |progress| video.open_segmented_stream(session, &path, SEGMENT_SECONDS, progress))?;
+
+        video.open_playback_stream(session, &path, path.clone()).inspect_err(|_| {
+            let _ = video.close_segmented_stream(session, stream.handle);
+        })?;
+        Ok(stream)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn read_init_segment(window: Window, state: State<VideoState>, session: u64, handle: u64) -> Result<tauri::ipc::Response, String> {
+    state.check_window(window.label(), session)?;
+    Ok(tauri::ipc::Response::new(state.read_init_segment(session, handle)?))
+}
+
+// Segments of a transcoded stream are encoded when read, so this runs off the main thread
+#[tauri::command]
+async fn read_media_segment(app: AppHandle, window: Window, session: u64, handle: u64, index: usize) -> Result<tauri::ipc::Response, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        video.check_window(window.label(), session)?;
+        Ok(tauri::ipc::Response::new(video.read_media_segment(session, handle, index)?))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn close_segmented_stream(window: Window, state: State<VideoState>, session: u64, handle: u64) -> Result<bool, String> {
+    state.check_window(window.label(), session)?;
+    state.close_segmented_stream(session, handle)
+}
+
+// Exports the video `session` plays between the start and end dots, None when the save dialog is cancelled
+#[tauri::command]
+async fn export_clip(app: AppHandle, window: Window, session: u64, start: f64, end: f64) -> Result<Option<ClipExport>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        video.check_window(window.label(), session)?;
+        let path = video.session_path(session)?;
+        let stem = Path::new(&path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+        let output_path = app.dialog()
+            .file()
+            .add_filter("MP4", &["mp4"])
+            .set_file_name(format!("{}_clip.mp4", stem))
+            .blocking_save_file();
+
+        let Some(output_path) = output_path else {
+            return Ok(None);
+        };
+        let output_path = output_path.into_path().map_err(|e| e.to_string())?;
+
+        let output_path = output_path.to_string_lossy();
+        app.state::<OperationState>().track(&app, "clip", &path, |progress| video.export_clip(&path, &output_path, start, end, progress)).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn add_reel_segment(window: Window, video: State<VideoState>, reel: State<ReelState>, session: u64, start: f64, end: f64) -> Result<Vec<ReelSegment>, String> {
+    video.check_window(window.label(), session)?;
+    let file_path = video.session_path(session)?;
+    Ok(reel.add(ReelSegment { file_path, start, end }))
+}
+
+#[tauri::command]
+fn clear_reel(reel: State<ReelState>) {
+    reel.clear();
+}
+
+// Starts building the queued segments into one MP4 and returns straight away with the job id,
+// progress is sent as reel-progress-event. None when the save dialog is cancelled
+#[tauri::command]
+async fn export_reel(app: AppHandle) -> Result<Option<u64>, String> {
+    let segments = app.state::<ReelState>().segments();
+    if segments.is_empty() {
+        return Err(String::from("No segments have been added to the reel"));
+    }
+
+    // the dialog blocks until it is closed, so it is shown off the async runtime as in export_clip
+    let dialog_app = app.clone();
+    let output_path = tauri::async_runtime::spawn_blocking(move || {
+        dialog_app.dialog()
+            .file()
+            .add_filter("MP4", &["mp4"])
+            .set_file_name("reel.mp4")
+            .blocking_save_file()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let Some(output_path) = output_path else {
+        return Ok(None);
     };
-    state.set_bytes(bytes);
+    let output_path = output_path.into_path().map_err(|e| e.to_string())?;
+
+    let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+    let job = video.start_reel(&segments, &output_path.to_string_lossy())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(video) = app.try_state::<VideoState>() else {
+            return;
+        };
+        match ReelState::wait(&video, job, |status| {
+            let _ = app.emit("reel-progress-event", status);
+        }) {
+            Ok(path) => println!("Reel written to {}", path),
+            Err(e) => eprintln!("{}", e),
+        }
+    });
+
+    Ok(Some(job))
+}
+
+// Candidate plays of the video `session` plays
+#[tauri::command]
+async fn detect_plays(app: AppHandle, window: Window, session: u64) -> Result<PlaySegmentation, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        video.check_window(window.label(), session)?;
+        let cache = app.try_state::<PlayCache>().ok_or("Play cache is not available")?;
+        let path = video.session_path(session)?;
+        app.state::<OperationState>().track(&app, "plays", &path, |progress| cache.get_or_detect(&video, &path, progress))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Whistles and crowd noise of the video `session` plays
+#[tauri::command]
+async fn detect_audio_events(app: AppHandle, window: Window, session: u64) -> Result<AudioAnalysis, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        video.check_window(window.label(), session)?;
+        let cache = app.try_state::<PlayCache>().ok_or("Play cache is not available")?;
+        let path = video.session_path(session)?;
+        app.state::<OperationState>().track(&app, "audio", &path, |progress| cache.get_or_detect_audio(&video, &path, progress))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Generation decodes the whole file, so it runs off the main thread
+#[tauri::command]
+async fn get_thumbnails(app: AppHandle, path: String, interval: Option<f64>) -> Result<ThumbnailIndex, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        let cache = app.try_state::<ThumbnailCache>().ok_or("Thumbnail cache is not available")?;
+        let interval = interval.unwrap_or(0f64);
+        app.state::<OperationState>().track(&app, "thumbnails", &path, |progress| cache.get_or_generate(&video, &path, interval, progress))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn get_keyframe_index(app: AppHandle, path: String) -> Result<KeyframeIndex, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let video = app.try_state::<VideoState>().ok_or("Codec is not loaded")?;
+        let cache = app.try_state::<KeyframeCache>().ok_or("Keyframe cache is not available")?;
+        app.state::<OperationState>().track(&app, "keyframes", &path, |progress| cache.get_or_index(&video, &path, progress))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Stops running exports and analyses of `operation`, or all of them, which then fail as cancelled.
+// Progress of those is sent as codec-progress-event
+#[tauri::command]
+fn cancel_operation(operations: State<OperationState>, operation: Option<String>) -> usize {
+    operations.cancel(operation.as_deref())
+}
 
-    Ok(len)
+#[tauri::command]
+fn get_thumbnail(cache: State<ThumbnailCache>, path: String, index: usize) -> Result<tauri::ipc::Response, String> {
+    let bytes = cache.read_thumbnail(&path, index)?;
+    Ok(tauri::ipc::Response::new(bytes))
 }
 
 #[tauri::command]
-fn get_video_chunk(state: State<VideoState>) -> Result<Vec<u8>, String> {
-    let bytes = state.get_bytes().ok_or("Failed to get video data".into());
-    bytes
+fn probe_video(state: State<VideoState>, path: String) -> Result<VideoProbe, String> {
+    state.probe(&path)
+}
+
+// Sessions go with their window, so a closed window doesn't keep its streams open
+fn close_window_sessions<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    if let WindowEvent::Destroyed = event {
+        if let Some(video) = window.try_state::<VideoState>() {
+            video.close_window(window.label());
+        }
+    }
+}
+
+
+fn load_codec(app: &AppHandle, path: &Path) -> Result<Box<dyn CodecBackend>, String> {
+    if std::env::var_os(CODEC_WORKER_ENV).is_none() {
+        return Ok(Box::new(RehashCodecLibrary::new(&path.to_string_lossy())?));
+    }
+
+    // the worker is this same executable started with WORKER_ARG, see run_codec_worker
+    let program = std::env::current_exe().map_err(|e| format!("Failed to find the app executable: {}", e))?;
+    let app = app.clone();
+    Ok(Box::new(CodecWorker::spawn(&program, path, move |crash| report_codec_crash(&app, crash))?))
 }
 
+/// Built-in MP4 demuxer used when the ffmpeg codec can't be loaded, playback of MP4s keeps working
+fn fallback_codec(app: &AppHandle, error: String) -> Box<dyn CodecBackend> {
+    eprintln!("Codec unavailable, falling back to the built-in MP4 demuxer: {}", error);
+
+    app.dialog()
+        .message(format!("The ffmpeg codec could not be loaded, so only MP4 files can be opened. \
+            Thumbnails, frame previews, import, clip and reel export, and play and audio detection are unavailable.\n\n{}", error))
+        .title("Limited video support")
+        .kind(MessageDialogKind::Warning)
+        .show(|_| {});
+    Box::new(Mp4Backend::new())
+}
+
+fn report_codec_crash(app: &AppHandle, crash: &WorkerCrash) {
+    let _ = app.emit("codec-crash-event", crash);
+
+    let message = if crash.file_paths.is_empty() {
+        format!("The codec crashed and has been restarted.\n\n{}", crash.message)
+    } else {
+        format!("The codec crashed while working on {} and has been restarted, the file may be corrupt.\n\n{}", crash.file_paths.join(", "), crash.message)
+    };
+    app.dialog()
+        .message(message)
+        .title("Codec crashed")
+        .kind(MessageDialogKind::Error)
+        .show(|_| {});
+}
+
+/// Serves codec calls when this process was started as the codec worker, None on a normal start
+pub fn run_codec_worker() -> Option<i32> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) != Some(WORKER_ARG) {
+        return None;
+    }
+
+    match worker::serve(&args[2..]) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("Codec worker failed: {}", e);
+            Some(1)
+        }
+    }
+}
 
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -75,6 +447,7 @@ pub fn run() {
     let app = tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, protocol::handle)
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -91,12 +464,18 @@ pub fn run() {
             let menu = MenuBuilder::new(app).items(&[&app_submenu]).build()?;
             app.set_menu(menu)?;
 
+            // dialog filters are case sensitive on some platforms and cameras tend to write upper case
+            let extensions: Vec<String> = VIDEO_EXTENSIONS.iter()
+                .flat_map(|ext| [ext.to_string(), ext.to_uppercase()])
+                .collect();
+
             app.on_menu_event(move |app, event| {
                 if event.id() == open.id() {
                     let cloned = app.clone();
+                    let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
                     app.dialog()
                         .file()
-                        .add_filter("Video", &["mp4"])
+                        .add_filter("Video", &extensions)
                         .pick_file(move |path_buf| match path_buf {
                             Some(p) => {
                                 cloned
@@ -110,16 +489,273 @@ pub fn run() {
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![wasm_log, get_desktop_build, wasm_error, get_video, get_video_chunk])
+        .on_window_event(close_window_sessions)
+        .invoke_handler(tauri::generate_handler![wasm_log, get_desktop_build, wasm_error, open_video_session, close_video_session, get_video, get_video_chunk, probe_video,
+            open_video_stream, read_video_stream_chunk, seek_video_stream, close_video_stream, decode_frame_at, get_thumbnails, get_thumbnail, import_video, export_clip,
+            add_reel_segment, clear_reel, export_reel, detect_plays, detect_audio_events,
+            get_keyframe_index, cancel_operation, open_segmented_stream, read_init_segment, read_media_segment, close_segmented_stream])
         .build(tauri::generate_context!())
         .expect("error while running tauri application");
 
 
-    if let Ok(path) = app.path().resolve(format!("codec/{}", CODEC_NAME), BaseDirectory::Resource) {
-        let rehash_codec = RehashCodecLibrary::new(&path.to_str().unwrap());
-        rehash_codec.print_codec_version();
-        app.manage(VideoState::new(rehash_codec));
+    let rehash_codec = app.path().resolve(format!("codec/{}", CODEC_NAME), BaseDirectory::Resource)
+        .map_err(|e| format!("Failed to find the codec: {}", e))
+        .and_then(|path| load_codec(app.handle(), &path))
+        .unwrap_or_else(|e| fallback_codec(app.handle(), e));
+    if let Err(e) = rehash_codec.print_codec_version() {
+        eprintln!("{}", e);
+    }
+    app.manage(VideoState::new(rehash_codec));
+
+    app.manage(ReelState::new());
+    app.manage(OperationState::new());
+
+    if let Ok(cache_dir) = app.path().app_cache_dir() {
+        app.manage(ThumbnailCache::new(cache_dir.join("thumbnails")));
+        app.manage(ImportCache::new(cache_dir.join("imports")));
+        app.manage(PlayCache::new(cache_dir.join("plays")));
+        app.manage(KeyframeCache::new(cache_dir.join("keyframes")));
+        app.manage(ProxyCache::new(cache_dir.join("proxies"), ProxyResolution::P720));
     }
 
     app.run(|_app_handle, _event| {});
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{close_video_session, get_video, get_video_chunk, open_video_session, open_video_stream, read_video_stream_chunk, seek_video_stream};
+    use crate::mock_codec::{sparse_file, MockCodec};
+    use crate::video::{VideoState, CHUNK_SIZE};
+    use rehash_codec_ffi::codec::{VideoStreamChunk, VideoStreamMeta};
+    use rehash_codec_ffi::codec_ffi::CodecBackend;
+    use rehash_codec_ffi::mp4::Mp4Backend;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+    use std::fs::File;
+    use std::time::Instant;
+    use tauri::ipc::{CallbackFn, InvokeBody, InvokeResponseBody};
+    use tauri::State;
+    use tauri::test::{get_ipc_response, mock_builder, mock_context, noop_assets, MockRuntime, INVOKE_KEY};
+    use tauri::webview::InvokeRequest;
+    use tauri::{App, Runtime, WebviewWindow, WebviewWindowBuilder, Window};
+
+    const PATH: &str = "/videos/game.mp4";
+
+    /// How get_video_chunk answered before it sent raw responses, kept for the encoding benchmark
+    #[tauri::command]
+    fn get_video_chunk_json<R: Runtime>(window: Window<R>, state: State<VideoState>, session: u64) -> Result<Vec<u8>, String> {
+        state.check_window(window.label(), session)?;
+        state.get_bytes(session)
+    }
+
+    fn mock_app(codec: impl CodecBackend + 'static) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+        let app = mock_builder()
+            .manage(VideoState::new(Box::new(codec)))
+            .invoke_handler(tauri::generate_handler![open_video_session, close_video_session, get_video, get_video_chunk, get_video_chunk_json, open_video_stream, read_video_stream_chunk,
+                                                     seek_video_stream])
+            .build(mock_context(noop_assets()))
+            .expect("Failed to build mock app");
+        let webview = WebviewWindowBuilder::new(&app, "main", Default::default()).build().unwrap();
+        (app, webview)
+    }
+
+    fn respond(webview: &WebviewWindow<MockRuntime>, cmd: &str, args: Value) -> Result<InvokeResponseBody, Value> {
+        let request = InvokeRequest {
+            cmd: cmd.to_string(),
+            callback: CallbackFn(0),
+            error: CallbackFn(1),
+            url: if cfg!(windows) { "http://tauri.localhost" } else { "tauri://localhost" }.parse().unwrap(),
+            body: InvokeBody::Json(args),
+            headers: Default::default(),
+            invoke_key: INVOKE_KEY.to_string(),
+        };
+        get_ipc_response(webview, request)
+    }
+
+    fn invoke<T: DeserializeOwned>(webview: &WebviewWindow<MockRuntime>, cmd: &str, args: Value) -> Result<T, Value> {
+        respond(webview, cmd, args).map(|body| body.deserialize().unwrap())
+    }
+
+    /// Bytes of a command that answers with a raw response, failing if they came back as JSON
+    fn invoke_bytes(webview: &WebviewWindow<MockRuntime>, cmd: &str, args: Value) -> Result<Vec<u8>, Value> {
+        respond(webview, cmd, args).map(|body| match body {
+            InvokeResponseBody::Raw(bytes) => bytes,
+            InvokeResponseBody::Json(json) => panic!("{} answered with JSON: {:.100}", cmd, json),
+        })
+    }
+
+    fn error_text(error: Value) -> String {
+        error.as_str().map(str::to_string).unwrap_or_else(|| error.to_string())
+    }
+
+    #[test]
+    fn get_video_chunk_streams_the_opened_video() {
+        let len = CHUNK_SIZE + 1000;
+        let (_app, webview) = mock_app(MockCodec::new().with_file(PATH, MockCodec::pattern(len)));
+
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": PATH })).unwrap();
+
+        let first = invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })).unwrap();
+        let second = invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })).unwrap();
+        assert_eq!((first.len(), second.len()), (CHUNK_SIZE, 1000));
+        assert_eq!([first, second].concat(), MockCodec::pattern(len));
+
+        assert_eq!(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn get_video_switches_to_the_new_video() {
+        let codec = MockCodec::new()
+            .with_file(PATH, MockCodec::pattern(CHUNK_SIZE + 1))
+            .with_file("/videos/short.mp4", MockCodec::pattern(10));
+        let (_app, webview) = mock_app(codec);
+
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": PATH })).unwrap();
+        assert_eq!(invoke::<u64>(&webview, "get_video", json!({ "path": "/videos/short.mp4", "session": session })), Ok(session));
+        assert_eq!(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })), Ok(MockCodec::pattern(10)));
+    }
+
+    #[test]
+    fn get_video_reports_codec_errors() {
+        let (_app, webview) = mock_app(MockCodec::new());
+
+        let error = error_text(invoke::<u64>(&webview, "get_video", json!({ "path": "/videos/missing.mp4" })).unwrap_err());
+        assert!(error.contains("/videos/missing.mp4"), "{}", error);
+        let session = invoke::<u64>(&webview, "open_video_session", json!({})).unwrap();
+        assert_eq!(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })), Err(json!("No video is playing")));
+    }
+
+    #[test]
+    fn get_video_chunk_reports_read_errors() {
+        let (_app, webview) = mock_app(MockCodec::new().with_file(PATH, MockCodec::pattern(100)).failing_read(1));
+
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": PATH })).unwrap();
+        let error = error_text(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })).unwrap_err());
+        assert!(error.contains("Failed to read chunk"), "{}", error);
+
+        // the failed read didn't move the stream on
+        assert_eq!(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })), Ok(MockCodec::pattern(100)));
+    }
+
+    #[test]
+    fn read_video_stream_chunk_sends_its_header_and_bytes() {
+        let len = CHUNK_SIZE + 10;
+        let (_app, webview) = mock_app(MockCodec::new().with_file(PATH, MockCodec::pattern(len)));
+        let session = invoke::<u64>(&webview, "open_video_session", json!({})).unwrap();
+        let meta = invoke::<VideoStreamMeta>(&webview, "open_video_stream", json!({ "session": session, "path": PATH })).unwrap();
+
+        let read = || VideoStreamChunk::from_bytes(&invoke_bytes(&webview, "read_video_stream_chunk", json!({ "session": session, "handle": meta.handle })).unwrap()).unwrap();
+        let (first, second) = (read(), read());
+        assert_eq!((first.position, first.bytes.len(), first.is_final), (0, CHUNK_SIZE, false));
+        assert_eq!((second.position, second.is_final), (CHUNK_SIZE as u64, true));
+        assert_eq!(second.bytes, MockCodec::pattern(len)[CHUNK_SIZE..]);
+
+        let error = error_text(invoke_bytes(&webview, "read_video_stream_chunk", json!({ "session": session, "handle": 42 })).unwrap_err());
+        assert_eq!(error, "Video stream 42 is not open");
+    }
+
+    #[test]
+    fn sessions_stream_their_own_videos() {
+        let angle = "/videos/angle.mp4";
+        let codec = MockCodec::new()
+            .with_file(PATH, MockCodec::pattern(CHUNK_SIZE + 1))
+            .with_file(angle, MockCodec::pattern(CHUNK_SIZE + 2));
+        let (_app, webview) = mock_app(codec);
+
+        let first = invoke::<u64>(&webview, "get_video", json!({ "path": PATH })).unwrap();
+        let second = invoke::<u64>(&webview, "get_video", json!({ "path": angle })).unwrap();
+        assert_ne!(first, second);
+
+        let chunk = |session: u64| invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })).unwrap();
+        assert_eq!((chunk(first).len(), chunk(second).len()), (CHUNK_SIZE, CHUNK_SIZE));
+        assert_eq!((chunk(first).len(), chunk(second).len()), (1, 2));
+
+        // a stream is only read through the session that opened it
+        let meta = invoke::<VideoStreamMeta>(&webview, "open_video_stream", json!({ "session": first, "path": PATH })).unwrap();
+        let error = error_text(invoke_bytes(&webview, "read_video_stream_chunk", json!({ "session": second, "handle": meta.handle })).unwrap_err());
+        assert_eq!(error, format!("Video stream {} is not open", meta.handle));
+
+        invoke::<()>(&webview, "close_video_session", json!({ "session": first })).unwrap();
+        let error = error_text(invoke_bytes(&webview, "get_video_chunk", json!({ "session": first })).unwrap_err());
+        assert_eq!(error, format!("Video session {} is not open", first));
+        assert_eq!(chunk(second), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn windows_only_use_their_own_sessions() {
+        let (app, webview) = mock_app(MockCodec::new().with_file(PATH, MockCodec::pattern(10)));
+        let review = WebviewWindowBuilder::new(&app, "review", Default::default()).build().unwrap();
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": PATH })).unwrap();
+        let meta = invoke::<VideoStreamMeta>(&webview, "open_video_stream", json!({ "session": session, "path": PATH })).unwrap();
+
+        let foreign = json!(format!("Video session {} belongs to another window", session));
+        assert_eq!(invoke_bytes(&review, "get_video_chunk", json!({ "session": session })).unwrap_err(), foreign);
+        assert_eq!(invoke_bytes(&review, "read_video_stream_chunk", json!({ "session": session, "handle": meta.handle })).unwrap_err(), foreign);
+        assert_eq!(invoke::<u64>(&review, "get_video", json!({ "path": PATH, "session": session })).unwrap_err(), foreign);
+        assert_eq!(invoke::<()>(&review, "close_video_session", json!({ "session": session })).unwrap_err(), foreign);
+
+        // none of which touched the session
+        assert_eq!(invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })), Ok(MockCodec::pattern(10)));
+    }
+
+    #[test]
+    fn videos_past_4_gib_keep_their_offsets() {
+        const FILE_SIZE: u64 = 5 << 30;
+        const MARKER_AT: u64 = FILE_SIZE - 100;
+        let path = sparse_file("large-video", FILE_SIZE, MARKER_AT, b"marker");
+        let path_arg = path.to_string_lossy().to_string();
+        let (_app, webview) = mock_app(Mp4Backend::new());
+
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": path_arg })).unwrap();
+        let meta = invoke::<VideoStreamMeta>(&webview, "open_video_stream", json!({ "session": session, "path": path_arg })).unwrap();
+        assert_eq!(meta.total_size, FILE_SIZE);
+
+        let seek = json!({ "session": session, "handle": meta.handle, "position": MARKER_AT });
+        assert_eq!(invoke::<VideoStreamMeta>(&webview, "seek_video_stream", seek).unwrap().current_position, MARKER_AT);
+        let chunk = VideoStreamChunk::from_bytes(&invoke_bytes(&webview, "read_video_stream_chunk", json!({ "session": session, "handle": meta.handle })).unwrap()).unwrap();
+        assert_eq!((chunk.position, chunk.bytes.len(), chunk.is_final), (MARKER_AT, 100, true));
+        assert!(chunk.bytes.starts_with(b"marker"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // Compares what the command side costs to answer with raw bytes against JSON arrays, through the mock runtime.
+    // No webview is involved, so this says nothing about the transfer into one or what the frontend does with it.
+    // cargo test --release -p rehash-desktop -- --ignored --nocapture chunk_response_encoding
+    #[test]
+    #[ignore = "benchmark, reads a multi-GB sparse file"]
+    fn chunk_response_encoding_on_a_multi_gb_file() {
+        const FILE_SIZE: u64 = 3 << 30;
+        const JSON_CHUNKS: usize = 20;
+
+        // sparse, so it takes no disk space and reads back as zeroes
+        let path = std::env::temp_dir().join(format!("rehash-chunk-bench-{}.mp4", std::process::id()));
+        File::create(&path).and_then(|file| file.set_len(FILE_SIZE)).expect("Failed to create the sparse file");
+        let (_app, webview) = mock_app(Mp4Backend::new());
+        let path_arg = path.to_string_lossy().to_string();
+
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": path_arg })).unwrap();
+        let started = Instant::now();
+        let mut read = 0u64;
+        while read < FILE_SIZE {
+            let chunk = invoke_bytes(&webview, "get_video_chunk", json!({ "session": session })).unwrap();
+            assert!(!chunk.is_empty(), "Stream ended at {} of {}", read, FILE_SIZE);
+            read += chunk.len() as u64;
+        }
+        let raw = read as f64 / started.elapsed().as_secs_f64();
+
+        // the same chunks answered as JSON number arrays
+        let session = invoke::<u64>(&webview, "get_video", json!({ "path": path_arg })).unwrap();
+        let started = Instant::now();
+        for _ in 0..JSON_CHUNKS {
+            let chunk = invoke::<Vec<u8>>(&webview, "get_video_chunk_json", json!({ "session": session })).unwrap();
+            assert_eq!(chunk.len(), CHUNK_SIZE);
+        }
+        let json = (JSON_CHUNKS * CHUNK_SIZE) as f64 / started.elapsed().as_secs_f64();
+        let _ = std::fs::remove_file(&path);
+
+        println!("raw responses: {:.0} MB/s over {} GB, JSON arrays: {:.0} MB/s, mock runtime", raw / 1e6, FILE_SIZE >> 30, json / 1e6);
+        assert!(raw > json * 2f64, "Raw chunks ({:.0} MB/s) are not clearly faster than JSON ({:.0} MB/s)", raw / 1e6, json / 1e6);
+    }
+}
@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // the app starts itself again as the codec worker when the codec runs out of process
+    if let Some(code) = rehash_desktop_lib::run_codec_worker() {
+        std::process::exit(code);
+    }
     rehash_desktop_lib::run();
 }
@@ -0,0 +1,123 @@
+use rehash_codec_ffi::codec::VideoStreamMeta;
+use rehash_codec_ffi::codec_ffi::CodecBackend;
+use rehash_codec_ffi::error::CodecError;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+
+/// In-memory stand-in for the codec library, so the desktop can be tested without ffmpeg
+/// Files only exist if added with `with_file`, anything besides streaming fails as unsupported
+pub struct MockCodec {
+    files: HashMap<String, Vec<u8>>,
+    streams: Mutex<HashMap<u64, VideoStreamMeta>>,
+    next_handle: AtomicU64,
+    reads: AtomicUsize,
+    // 1-based read, across every stream, that fails as an io error
+    failing_read: Option<usize>,
+}
+
+
+impl MockCodec {
+    pub fn new() -> MockCodec {
+        Self {
+            files: HashMap::new(),
+            streams: Mutex::new(HashMap::new()),
+            next_handle: AtomicU64::new(1),
+            reads: AtomicUsize::new(0),
+            failing_read: None,
+        }
+    }
+
+    pub fn with_file(mut self, path: &str, bytes: Vec<u8>) -> MockCodec {
+        self.files.insert(path.to_string(), bytes);
+        self
+    }
+
+    pub fn failing_read(mut self, read: usize) -> MockCodec {
+        self.failing_read = Some(read);
+        self
+    }
+
+    /// Bytes whose value follows from their offset, so a chunk shows where in the file it was read from
+    pub fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn path_key(path: &Path) -> String {
+        path.to_string_lossy().to_string()
+    }
+
+    fn with_stream<T>(&self, handle: u64, run: impl FnOnce(&mut VideoStreamMeta) -> Result<T, CodecError>) -> Result<T, CodecError> {
+        let mut streams = self.streams.lock().unwrap();
+        let meta = streams.get_mut(&handle).ok_or_else(|| CodecError::not_found(format!("Unknown video stream {}", handle)))?;
+        run(meta)
+    }
+}
+
+
+impl CodecBackend for MockCodec {
+    fn print_codec_version(&self) -> Result<(), CodecError> {
+        println!("Codec version: mock");
+        Ok(())
+    }
+
+    fn open_video_stream(&self, path: &Path, chunk_size: usize) -> Result<VideoStreamMeta, CodecError> {
+        if chunk_size == 0 {
+            return Err(CodecError::invalid_argument("Chunk size must be greater than zero"));
+        }
+
+        let path = Self::path_key(path);
+        let file = self.files.get(&path).ok_or_else(|| CodecError::not_found(format!("No video file at {}", path)))?;
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        let meta = VideoStreamMeta::new(handle, path, 0, file.len() as u64, chunk_size);
+
+        self.streams.lock().unwrap().insert(handle, meta.clone());
+        Ok(meta)
+    }
+
+    fn get_video_stream_meta(&self, handle: u64) -> Result<VideoStreamMeta, CodecError> {
+        self.with_stream(handle, |meta| Ok(meta.clone()))
+    }
+
+    fn read_video_stream_chunk(&self, handle: u64) -> Result<Vec<u8>, CodecError> {
+        let read = self.reads.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.failing_read == Some(read) {
+            return Err(CodecError::io(format!("Failed to read chunk of stream {}", handle)));
+        }
+
+        self.with_stream(handle, |meta| {
+            let file = &self.files[&meta.file_path];
+            let start = meta.current_position as usize;
+            let end = (start + meta.chunk_size).min(file.len());
+
+            meta.current_position = end as u64;
+            Ok(file[start..end].to_vec())
+        })
+    }
+
+    fn seek_video_stream(&self, handle: u64, position: u64) -> Result<VideoStreamMeta, CodecError> {
+        self.with_stream(handle, |meta| {
+            meta.current_position = position.min(meta.total_size);
+            Ok(meta.clone())
+        })
+    }
+
+    fn close_video_stream(&self, handle: u64) -> Result<bool, CodecError> {
+        Ok(self.streams.lock().unwrap().remove(&handle).is_some())
+    }
+}
+
+
+/// Sparse file of `len` bytes in the temp dir, zeroes apart from `marker` at `marker_at`, so even a
+/// multi-GB file takes no disk space. Removing it is up to the test
+pub fn sparse_file(name: &str, len: u64, marker_at: u64, marker: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rehash-{}-{}.mp4", name, std::process::id()));
+    let mut file = File::create(&path).expect("Failed to create the sparse file");
+    file.set_len(len).expect("Failed to size the sparse file");
+    file.seek(SeekFrom::Start(marker_at)).and_then(|_| file.write_all(marker)).expect("Failed to write the marker");
+    path
+}
@@ -0,0 +1,77 @@
+use rehash_codec_ffi::codec::{JobState, OperationProgress};
+use rehash_codec_ffi::progress::{CancelToken, ProgressReporter};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+
+
+/// Frames between events when the codec can't tell how many there are in total
+const UNKNOWN_TOTAL_STEP: u64 = 250;
+
+
+struct RunningOperation {
+    operation: String,
+    token: CancelToken,
+}
+
+
+/// Long-running codec calls in flight, so they can be cancelled from the frontend
+pub struct OperationState {
+    running: Mutex<HashMap<u64, RunningOperation>>,
+    next_id: AtomicU64,
+}
+
+
+impl OperationState {
+    pub fn new() -> OperationState {
+        Self {
+            running: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Runs `run` with a reporter that sends roughly every percent as codec-progress-event, until it returns
+    /// and a last event with its outcome is sent. `cancel` can stop it
+    pub fn track<T>(&self, app: &AppHandle, operation: &str, path: &str, run: impl FnOnce(&ProgressReporter) -> Result<T, String>) -> Result<T, String> {
+        // processed and total of the last event, the final one repeats them
+        let last_sent = Arc::new((AtomicU64::new(0), AtomicU64::new(0)));
+
+        let reporter = {
+            let (app, last_sent) = (app.clone(), last_sent.clone());
+            let (name, file_path) = (operation.to_string(), path.to_string());
+
+            ProgressReporter::new(move |processed, total| {
+                let step = if total > 0 { (total / 100).max(1) } else { UNKNOWN_TOTAL_STEP };
+                if (total == 0 || processed < total) && processed.saturating_sub(last_sent.0.load(Ordering::Relaxed)) < step {
+                    return;
+                }
+                last_sent.0.store(processed, Ordering::Relaxed);
+                last_sent.1.store(total, Ordering::Relaxed);
+
+                let progress = OperationProgress { operation: name.clone(), file_path: file_path.clone(), processed, total, state: JobState::Running };
+                let _ = app.emit("codec-progress-event", progress);
+            })
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.running.lock().unwrap().insert(id, RunningOperation { operation: operation.to_string(), token: reporter.token() });
+        let result = run(&reporter);
+        self.running.lock().unwrap().remove(&id);
+
+        let state = if result.is_ok() { JobState::Finished } else { JobState::Failed };
+        let (processed, total) = (last_sent.0.load(Ordering::Relaxed), last_sent.1.load(Ordering::Relaxed));
+        let _ = app.emit("codec-progress-event", OperationProgress { operation: operation.to_string(), file_path: path.to_string(), processed, total, state });
+
+        result
+    }
+
+    /// Cancels every running `operation`, or everything when it's None, and returns how many were cancelled
+    pub fn cancel(&self, operation: Option<&str>) -> usize {
+        let running = self.running.lock().unwrap();
+        running.values()
+            .filter(|running| operation.map_or(true, |operation| running.operation == operation))
+            .inspect(|running| running.token.cancel())
+            .count()
+    }
+}
@@ -0,0 +1,28 @@
+use crate::cache::cached_json;
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::{AudioAnalysis, PlaySegmentation};
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::path::PathBuf;
+
+
+const MAX_CACHED_ANALYSES: usize = 64;
+
+pub struct PlayCache {
+    root: PathBuf,
+}
+
+
+impl PlayCache {
+    pub fn new(root: PathBuf) -> PlayCache {
+        Self { root }
+    }
+
+    /// Analysis decodes the whole video, so results are kept per source file
+    pub fn get_or_detect(&self, video: &VideoState, path: &str, progress: &ProgressReporter) -> Result<PlaySegmentation, String> {
+        cached_json(&self.root, path, "plays", MAX_CACHED_ANALYSES, || video.detect_plays(path, progress))
+    }
+
+    pub fn get_or_detect_audio(&self, video: &VideoState, path: &str, progress: &ProgressReporter) -> Result<AudioAnalysis, String> {
+        cached_json(&self.root, path, "audio", MAX_CACHED_ANALYSES, || video.detect_audio_events(path, progress))
+    }
+}
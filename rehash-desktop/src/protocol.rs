@@ -0,0 +1,302 @@
+use crate::video::{VideoState, CHUNK_SIZE};
+use percent_encoding::percent_decode_str;
+use std::path::Path;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{Manager, Runtime, UriSchemeContext, UriSchemeResponder};
+
+
+pub const SCHEME: &str = "rehash";
+
+/// Most bytes served for one range, the player asks for the rest with further requests
+const MAX_RANGE_LEN: u64 = CHUNK_SIZE as u64;
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Whole,
+    /// Inclusive of both ends, the end already clamped to the file
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+
+/// Serves the playing video as `rehash://localhost/<encoded path>` so the `<video>` element can stream and seek it
+/// with range requests. Only paths a session is playing are served, from their proxy if that is what plays
+pub fn handle<R: Runtime>(ctx: UriSchemeContext<'_, R>, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    let app = ctx.app_handle().clone();
+    // reads go through the codec, which may be a worker process, so they stay off the webview thread
+    tauri::async_runtime::spawn_blocking(move || {
+        let response = match app.try_state::<VideoState>() {
+            Some(video) => serve(&video, &request),
+            None => text_response(StatusCode::SERVICE_UNAVAILABLE, "Codec is not loaded"),
+        };
+        responder.respond(response);
+    });
+}
+
+fn serve(video: &VideoState, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let encoded = request.uri().path().strip_prefix('/').unwrap_or_default();
+    let path = percent_decode_str(encoded).decode_utf8_lossy();
+    let Some(meta) = video.playback_stream(&path) else {
+        return text_response(StatusCode::NOT_FOUND, &format!("{} is not playing", path));
+    };
+
+    let total = meta.total_size;
+    let range = request.headers().get(header::RANGE).and_then(|value| value.to_str().ok());
+    let (status, start, len) = match parse_range(range, total) {
+        // media elements always ask for a range, this is only for anything else fetching the url
+        ByteRange::Whole if total <= MAX_RANGE_LEN => (StatusCode::OK, 0, total),
+        // never read a whole game into memory, the start is sent as if it was asked for
+        ByteRange::Whole => (StatusCode::PARTIAL_CONTENT, 0, MAX_RANGE_LEN),
+        ByteRange::Part(start, end) => (StatusCode::PARTIAL_CONTENT, start, (end - start + 1).min(MAX_RANGE_LEN)),
+        ByteRange::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", total))
+                .body(Vec::new())
+                .unwrap();
+        }
+    };
+
+    let bytes = match video.read_range(meta.handle, start, len) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to serve {} from {}: {}", path, start, e);
+            return text_response(StatusCode::INTERNAL_SERVER_ERROR, &e);
+        }
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type(&meta.file_path))
+        .header(header::CONTENT_LENGTH, bytes.len())
+        .header(header::ACCEPT_RANGES, "bytes")
+        // the same url plays the proxy once it has been generated
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    if status == StatusCode::PARTIAL_CONTENT {
+        let end = start + (bytes.len() as u64).max(1) - 1;
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, total));
+    }
+    response.body(bytes).unwrap()
+}
+
+/// First range of a `Range` header, any others are left for the player to ask for again.
+/// A missing or malformed header means the whole file, as HTTP says to ignore a range it can't parse
+fn parse_range(header: Option<&str>, total: u64) -> ByteRange {
+    let Some(ranges) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Whole;
+    };
+    let Some((start, end)) = ranges.split(',').next().and_then(|range| range.split_once('-')) else {
+        return ByteRange::Whole;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let last = total.saturating_sub(1);
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(last)),
+        (Ok(start), Err(_)) if end.is_empty() => (start, last),
+        // the final `suffix` bytes
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => (total.saturating_sub(suffix), last),
+        (Err(_), Ok(_)) if start.is_empty() => return ByteRange::Unsatisfiable,
+        _ => return ByteRange::Whole,
+    };
+
+    if start >= total {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Part(start, end)
+    }
+}
+
+fn content_type(path: &str) -> &'static str {
+    let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        _ => "application/octet-stream",
+    }
+}
+
+fn text_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, serve, ByteRange, MAX_RANGE_LEN};
+    use crate::mock_codec::{sparse_file, MockCodec};
+    use crate::video::{VideoState, CHUNK_SIZE};
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+    use rehash_codec_ffi::mp4::Mp4Backend;
+    use tauri::http::{header, Request, Response, StatusCode};
+
+    const PATH: &str = "/videos/my game.mp4";
+    const URL: &str = "rehash://localhost/%2Fvideos%2Fmy%20game.mp4";
+
+    fn playing(len: usize) -> VideoState {
+        let state = VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(len))));
+        let session = state.open_session("main");
+        state.open_playback_stream(session, PATH, PATH.to_string()).unwrap();
+        state
+    }
+
+    fn get(state: &VideoState, url: &str, range: Option<&str>) -> Response<Vec<u8>> {
+        let mut request = Request::builder().uri(url);
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        serve(state, &request.body(Vec::new()).unwrap())
+    }
+
+    fn header_of(response: &Response<Vec<u8>>, name: header::HeaderName) -> &str {
+        response.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or_default()
+    }
+
+    #[test]
+    fn ranges_are_parsed_and_clamped_to_the_file() {
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), ByteRange::Part(0, 99));
+        assert_eq!(parse_range(Some("bytes=900-"), 1000), ByteRange::Part(900, 999));
+        assert_eq!(parse_range(Some("bytes=900-5000"), 1000), ByteRange::Part(900, 999));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), ByteRange::Part(900, 999));
+        assert_eq!(parse_range(Some("bytes=-5000"), 1000), ByteRange::Part(0, 999));
+        assert_eq!(parse_range(Some("bytes=10-19, 50-59"), 1000), ByteRange::Part(10, 19));
+    }
+
+    #[test]
+    fn ranges_outside_the_file_are_unsatisfiable() {
+        assert_eq!(parse_range(Some("bytes=1000-"), 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=-0"), 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn malformed_ranges_mean_the_whole_file() {
+        for header in [None, Some("bytes=20-10"), Some("bytes=a-b"), Some("items=0-10"), Some("bytes=10")] {
+            assert_eq!(parse_range(header, 1000), ByteRange::Whole, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn a_range_is_served_from_the_playing_stream() {
+        let state = playing(1000);
+
+        let response = get(&state, URL, Some("bytes=100-199"));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body(), &MockCodec::pattern(1000)[100..200]);
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), "bytes 100-199/1000");
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "100");
+        assert_eq!(header_of(&response, header::CONTENT_TYPE), "video/mp4");
+        assert_eq!(header_of(&response, header::ACCEPT_RANGES), "bytes");
+
+        // ranges may come in any order
+        assert_eq!(get(&state, URL, Some("bytes=-10")).body(), &MockCodec::pattern(1000)[990..]);
+        assert_eq!(get(&state, URL, Some("bytes=0-9")).body(), &MockCodec::pattern(1000)[..10]);
+    }
+
+    #[test]
+    fn ranges_leave_the_chunk_reads_where_they_were() {
+        let len = CHUNK_SIZE + 10;
+        let state = VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(len))));
+        let session = state.open_session("main");
+        state.open_playback_stream(session, PATH, PATH.to_string()).unwrap();
+
+        assert_eq!(state.get_bytes(session).unwrap().len(), CHUNK_SIZE);
+        assert_eq!(get(&state, URL, Some("bytes=0-9")).body(), &MockCodec::pattern(len)[..10]);
+        assert_eq!(state.get_bytes(session).unwrap(), MockCodec::pattern(len)[CHUNK_SIZE..]);
+    }
+
+    #[test]
+    fn open_ended_ranges_are_capped() {
+        let len = MAX_RANGE_LEN as usize + 500;
+        let state = playing(len);
+
+        let response = get(&state, URL, Some("bytes=0-"));
+        assert_eq!(response.body().len() as u64, MAX_RANGE_LEN);
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), format!("bytes 0-{}/{}", MAX_RANGE_LEN - 1, len));
+
+        let rest = get(&state, URL, Some(&format!("bytes={}-", MAX_RANGE_LEN)));
+        assert_eq!(rest.body(), &MockCodec::pattern(len)[MAX_RANGE_LEN as usize..]);
+    }
+
+    #[test]
+    fn whole_file_and_unsatisfiable_requests() {
+        let state = playing(1000);
+
+        let whole = get(&state, URL, None);
+        assert_eq!(whole.status(), StatusCode::OK);
+        assert_eq!(whole.body(), &MockCodec::pattern(1000));
+
+        let past_end = get(&state, URL, Some("bytes=1000-"));
+        assert_eq!(past_end.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header_of(&past_end, header::CONTENT_RANGE), "bytes */1000");
+    }
+
+    #[test]
+    fn ranges_past_4_gib_are_served() {
+        const TOTAL: u64 = 5 << 30;
+        const MARKER_AT: u64 = (4 << 30) + 12345;
+        let path = sparse_file("protocol-ranges", TOTAL, MARKER_AT, b"marker");
+        let path = path.to_string_lossy().to_string();
+        let state = VideoState::new(Box::new(Mp4Backend::new()));
+        let session = state.open_session("main");
+        state.open_playback_stream(session, &path, path.clone()).unwrap();
+        let url = format!("rehash://localhost/{}", utf8_percent_encode(&path, NON_ALPHANUMERIC));
+
+        let response = get(&state, &url, Some(&format!("bytes={}-{}", MARKER_AT, MARKER_AT + 99)));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(response.body().starts_with(b"marker"));
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), format!("bytes {}-{}/{}", MARKER_AT, MARKER_AT + 99, TOTAL));
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "100");
+
+        // without a range only the start is read, not the whole file
+        let whole = get(&state, &url, None);
+        assert_eq!(whole.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(whole.body().len() as u64, MAX_RANGE_LEN);
+        assert_eq!(header_of(&whole, header::CONTENT_RANGE), format!("bytes 0-{}/{}", MAX_RANGE_LEN - 1, TOTAL));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn only_the_playing_video_is_served() {
+        let state = VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(10))));
+        assert_eq!(get(&state, URL, Some("bytes=0-")).status(), StatusCode::NOT_FOUND);
+
+        let state = playing(10);
+        assert_eq!(get(&state, "rehash://localhost/%2Fvideos%2Fother.mp4", Some("bytes=0-")).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn the_video_of_every_session_is_served() {
+        let state = playing(10);
+        let angle = "/videos/angle.mp4";
+        let session = state.open_session("review");
+        state.open_playback_stream(session, angle, PATH.to_string()).unwrap();
+
+        assert_eq!(get(&state, URL, Some("bytes=0-")).status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(get(&state, "rehash://localhost/%2Fvideos%2Fangle.mp4", Some("bytes=0-")).body(), &MockCodec::pattern(10));
+
+        state.close_session(session).unwrap();
+        assert_eq!(get(&state, "rehash://localhost/%2Fvideos%2Fangle.mp4", Some("bytes=0-")).status(), StatusCode::NOT_FOUND);
+        assert_eq!(get(&state, URL, Some("bytes=0-")).status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    #[test]
+    fn a_playing_proxy_is_served_for_its_original() {
+        let state = VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(10))));
+        let session = state.open_session("main");
+        state.open_playback_stream(session, "/videos/original.mkv", PATH.to_string()).unwrap();
+
+        let response = get(&state, "rehash://localhost/%2Fvideos%2Foriginal.mkv", Some("bytes=0-"));
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body(), &MockCodec::pattern(10));
+        assert_eq!(header_of(&response, header::CONTENT_TYPE), "video/mp4");
+    }
+}
@@ -0,0 +1,70 @@
+use crate::cache::{cache_key, prune_cache_dir};
+use crate::import;
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::{ImportStatus, ProxyResolution};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+
+const MAX_CACHED_PROXIES: usize = 8;
+/// Sources up to this height play smoothly in the webview as they are
+const MAX_DIRECT_HEIGHT: u32 = 1080;
+
+pub struct ProxyCache {
+    root: PathBuf,
+    resolution: ProxyResolution,
+    generating: Mutex<HashSet<String>>,
+}
+
+
+impl ProxyCache {
+    pub fn new(root: PathBuf, resolution: ProxyResolution) -> ProxyCache {
+        Self {
+            root,
+            resolution,
+            generating: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn proxy_path(&self, path: &str) -> Result<PathBuf, String> {
+        let key = cache_key(path, self.resolution)?;
+        Ok(self.root.join(format!("{}_{}p.mp4", key, self.resolution.height())))
+    }
+
+    /// Path of the finished proxy of `path`, if there is one
+    pub fn cached(&self, path: &str) -> Option<String> {
+        let proxy_path = self.proxy_path(path).ok()?;
+        proxy_path.is_file().then(|| proxy_path.to_string_lossy().to_string())
+    }
+
+    pub fn needs_proxy(&self, video: &VideoState, path: &str) -> bool {
+        video.probe(path)
+            .ok()
+            .and_then(|probe| probe.video().map(|stream| stream.height > MAX_DIRECT_HEIGHT.max(self.resolution.height())))
+            .unwrap_or(false)
+    }
+
+    /// Writes the proxy of `path` into the cache, reporting each status change through `on_progress`,
+    /// and returns its path. Only one proxy of a file is generated at a time
+    pub fn generate(&self, video: &VideoState, path: &str, on_progress: impl Fn(&ImportStatus)) -> Result<String, String> {
+        let proxy_path = self.proxy_path(path)?;
+        let output = proxy_path.to_string_lossy().to_string();
+        if proxy_path.is_file() {
+            return Ok(output);
+        }
+
+        if !self.generating.lock().unwrap().insert(path.to_string()) {
+            return Err(format!("A proxy of {} is already being generated", path));
+        }
+
+        println!("Generating {}p proxy of {}", self.resolution.height(), path);
+        let result = video.start_proxy(path, &output, self.resolution.height())
+            .and_then(|job| import::wait(video, job, on_progress))
+            .map(|_| output);
+
+        self.generating.lock().unwrap().remove(path);
+        prune_cache_dir(&self.root, &proxy_path, MAX_CACHED_PROXIES);
+        result
+    }
+}
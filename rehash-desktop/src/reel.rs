@@ -0,0 +1,56 @@
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::{JobState, ReelSegment, ReelStatus};
+use std::sync::Mutex;
+use std::time::Duration;
+
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Segments queued for the next reel, in playback order
+pub struct ReelState {
+    segments: Mutex<Vec<ReelSegment>>,
+}
+
+
+impl ReelState {
+    pub fn new() -> ReelState {
+        Self {
+            segments: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, segment: ReelSegment) -> Vec<ReelSegment> {
+        let mut segments = self.segments.lock().unwrap();
+        segments.push(segment);
+        segments.clone()
+    }
+
+    pub fn clear(&self) {
+        self.segments.lock().unwrap().clear();
+    }
+
+    pub fn segments(&self) -> Vec<ReelSegment> {
+        self.segments.lock().unwrap().clone()
+    }
+
+    /// Polls `job` until it finishes, reporting each status through `on_progress`
+    pub fn wait(video: &VideoState, job: u64, on_progress: impl Fn(&ReelStatus)) -> Result<String, String> {
+        let result = loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let status = match video.reel_status(job) {
+                Ok(status) => status,
+                Err(e) => break Err(e),
+            };
+            on_progress(&status);
+
+            match status.state {
+                JobState::Running => continue,
+                JobState::Finished => break Ok(status.output_path),
+                JobState::Failed => break Err(status.error.unwrap_or(format!("Failed to build reel {}", status.output_path))),
+            }
+        };
+
+        video.close_reel(job);
+        result
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cache::{cache_key, prune_cache_dir};
+use crate::video::VideoState;
+use rehash_codec_ffi::codec::ThumbnailIndex;
+use rehash_codec_ffi::progress::ProgressReporter;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+
+const THUMBNAIL_WIDTH: u32 = 160;
+const MAX_CACHED_VIDEOS: usize = 32;
+
+pub struct ThumbnailCache {
+    root: PathBuf,
+    indexes: Mutex<HashMap<String, (PathBuf, ThumbnailIndex)>>,
+}
+
+
+impl ThumbnailCache {
+    pub fn new(root: PathBuf) -> ThumbnailCache {
+        Self {
+            root,
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cache_dir(&self, path: &str, interval: f64) -> Result<PathBuf, String> {
+        let key = cache_key(path, (interval.to_bits(), THUMBNAIL_WIDTH))?;
+        Ok(self.root.join(key))
+    }
+
+    pub fn get_or_generate(&self, video: &VideoState, path: &str, interval: f64, progress: &ProgressReporter) -> Result<ThumbnailIndex, String> {
+        let dir = self.cache_dir(path, interval)?;
+        let index_path = dir.join(ThumbnailIndex::INDEX_FILE_NAME);
+
+        let cached = fs::read_to_string(&index_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ThumbnailIndex>(&json).ok());
+
+        let index = match cached {
+            Some(index) => index,
+            None => {
+                // clear out anything left behind by an interrupted generation
+                let _ = fs::remove_dir_all(&dir);
+                let index = video.generate_thumbnails(path, &dir, interval, THUMBNAIL_WIDTH, progress)?;
+                let json = serde_json::to_string(&index).map_err(|e| e.to_string())?;
+                fs::write(&index_path, json).map_err(|e| format!("Failed to write thumbnail index: {}", e))?;
+                prune_cache_dir(&self.root, &dir, MAX_CACHED_VIDEOS);
+                index
+            }
+        };
+
+        self.indexes.lock().unwrap().insert(path.to_string(), (dir, index.clone()));
+        Ok(index)
+    }
+
+    pub fn read_thumbnail(&self, path: &str, index: usize) -> Result<Vec<u8>, String> {
+        let indexes = self.indexes.lock().unwrap();
+        let (dir, thumbnail_index) = indexes.get(path).ok_or(format!("No thumbnails loaded for {}", path))?;
+        let thumbnail = thumbnail_index.thumbnails.get(index).ok_or(format!("Thumbnail {} does not exist", index))?;
+
+        fs::read(dir.join(&thumbnail.file_name)).map_err(|e| format!("Failed to read thumbnail: {}", e))
+    }
+}
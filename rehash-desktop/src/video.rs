@@ -1,43 +1,601 @@
-use rehash_codec_ffi::RehashCodecLibrary;
-use std::sync::Mutex;
+use rehash_codec_ffi::codec::{AudioAnalysis, ClipExport, ImportStatus, KeyframeIndex, PlaySegmentation, ReelSegment, ReelStatus, SegmentedStream, ThumbnailIndex, VideoProbe, VideoStreamChunk, VideoStreamMeta};
+use rehash_codec_ffi::progress::ProgressReporter;
+use rehash_codec_ffi::codec_ffi::CodecBackend;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 
-const CHUNK_SIZE: usize = 5_000_000;
+pub(crate) const CHUNK_SIZE: usize = 5_000_000;
+
+/// Video one window plays and the streams it opened alongside it, all closed with the session or its window
+struct VideoSession {
+    window: String,
+    playback: Option<u64>,
+    // every stream of the session, playback included
+    streams: HashSet<u64>,
+    segmented: Option<u64>,
+}
+
 
 pub struct VideoState {
-    pub(crate) codec: Mutex<RehashCodecLibrary>,
-    pub(crate) bytes: Mutex<Option<Vec<u8>>>,
-    pos: Mutex<usize>,
+    // not behind a lock, so a long analysis doesn't hold up playback
+    pub(crate) codec: Box<dyn CodecBackend>,
+    // each stream has its own lock, held across its reads, so sessions don't wait on each other
+    streams: Mutex<HashMap<u64, Arc<Mutex<VideoStreamMeta>>>>,
+    // original file of streams that play a proxy
+    sources: Mutex<HashMap<u64, String>>,
+    // stream the video protocol reads each playback stream's file through, so its ranges don't move the playback reads
+    served: Mutex<HashMap<u64, u64>>,
+    // locked before streams when both are needed
+    sessions: Mutex<HashMap<u64, VideoSession>>,
+    next_session: AtomicU64,
+    // session that started playing last, its video is served first when several play the same file
+    active_session: Mutex<Option<u64>>,
 }
 
 
 impl VideoState {
-    pub fn new(codec: RehashCodecLibrary) -> VideoState {
+    pub fn new(codec: Box<dyn CodecBackend>) -> VideoState {
         Self {
-            codec: Mutex::new(codec),
-            bytes: Mutex::new(None),
-            pos: Mutex::new(0),
+            codec,
+            streams: Mutex::new(HashMap::new()),
+            sources: Mutex::new(HashMap::new()),
+            served: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            next_session: AtomicU64::new(1),
+            active_session: Mutex::new(None),
         }
     }
 
-    pub fn set_bytes(&self, bytes: Vec<u8>) {
-        self.bytes.lock().unwrap().replace(bytes);
-        *self.pos.lock().unwrap() = 0;
+    fn with_session<T>(&self, session: u64, run: impl FnOnce(&mut VideoSession) -> T) -> Result<T, String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let owner = sessions.get_mut(&session).ok_or_else(|| format!("Video session {} is not open", session))?;
+        Ok(run(owner))
+    }
+
+    fn check_stream(&self, session: u64, handle: u64) -> Result<(), String> {
+        if self.with_session(session, |owner| owner.streams.contains(&handle))? {
+            Ok(())
+        } else {
+            Err(format!("Video stream {} is not open", handle))
+        }
+    }
+
+    fn stream(&self, handle: u64) -> Result<Arc<Mutex<VideoStreamMeta>>, String> {
+        self.streams.lock().unwrap().get(&handle).cloned().ok_or_else(|| format!("Video stream {} is not open", handle))
+    }
+
+    fn check_segmented(&self, session: u64, handle: u64) -> Result<(), String> {
+        if self.with_session(session, |owner| owner.segmented == Some(handle))? {
+            Ok(())
+        } else {
+            Err(format!("Segmented stream {} is not open", handle))
+        }
+    }
+
+    pub fn open_session(&self, window: &str) -> u64 {
+        let session = self.next_session.fetch_add(1, Ordering::Relaxed);
+        let owner = VideoSession { window: window.to_string(), playback: None, streams: HashSet::new(), segmented: None };
+        self.sessions.lock().unwrap().insert(session, owner);
+        session
+    }
+
+    /// Closes the session along with every stream it opened
+    pub fn close_session(&self, session: u64) -> Result<(), String> {
+        let owner = self.sessions.lock().unwrap().remove(&session).ok_or_else(|| format!("Video session {} is not open", session))?;
+        for handle in owner.streams {
+            if let Err(e) = self.close_stream(handle) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some(segmented) = owner.segmented {
+            let _ = self.codec.close_segmented_stream(segmented);
+        }
+
+        let mut active = self.active_session.lock().unwrap();
+        if *active == Some(session) {
+            // another window's video if one is still playing
+            *active = self.sessions.lock().unwrap().iter().find(|(_, owner)| owner.playback.is_some()).map(|(id, _)| *id);
+        }
+        Ok(())
     }
 
-    pub fn get_bytes(&self) -> Option<Vec<u8>> {
-        let bytes = self.bytes.lock().unwrap();
-        let mut p = self.pos.lock().unwrap();
-        let bytes = bytes.as_ref()?;
-        let upper = if *p + CHUNK_SIZE >= bytes.len() {
-            bytes.len()
+    /// Whether `window` opened `session`, a window can only use the sessions it opened
+    pub fn check_window(&self, window: &str, session: u64) -> Result<(), String> {
+        if self.with_session(session, |owner| owner.window == window)? {
+            Ok(())
         } else {
-            *p + CHUNK_SIZE
+            Err(format!("Video session {} belongs to another window", session))
+        }
+    }
+
+    pub fn close_window(&self, window: &str) {
+        let sessions: Vec<u64> = self.sessions.lock().unwrap().iter().filter(|(_, owner)| owner.window == window).map(|(id, _)| *id).collect();
+        for session in sessions {
+            let _ = self.close_session(session);
+        }
+    }
+
+    pub fn open_stream(&self, session: u64, path: String) -> Result<VideoStreamMeta, String> {
+        self.with_session(session, |_| ())?;
+        let meta = self.codec.open_video_stream(Path::new(&path), CHUNK_SIZE)?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(owner) = sessions.get_mut(&session) else {
+            // closed while the file was opened
+            drop(sessions);
+            let _ = self.codec.close_video_stream(meta.handle);
+            return Err(format!("Video session {} is not open", session));
+        };
+        owner.streams.insert(meta.handle);
+        self.streams.lock().unwrap().insert(meta.handle, Arc::new(Mutex::new(meta.clone())));
+        Ok(meta)
+    }
+
+    /// Plays `playback_path` in `session` for `path`, which is still what exports and analyses read.
+    /// Whatever the session played before is closed
+    pub fn open_playback_stream(&self, session: u64, path: &str, playback_path: String) -> Result<VideoStreamMeta, String> {
+        let is_proxy = playback_path != path;
+        let meta = self.open_stream(session, playback_path)?;
+        if is_proxy {
+            self.sources.lock().unwrap().insert(meta.handle, path.to_string());
+        }
+
+        let previous = self.with_session(session, |owner| owner.playback.replace(meta.handle))?;
+        if let Some(previous) = previous.filter(|p| *p != meta.handle) {
+            let _ = self.close(session, previous);
+        }
+        *self.active_session.lock().unwrap() = Some(session);
+        Ok(meta)
+    }
+
+    pub fn read_chunk(&self, session: u64, handle: u64) -> Result<VideoStreamChunk, String> {
+        self.check_stream(session, handle)?;
+        let stream = self.stream(handle)?;
+        // held throughout so a seek can't land between the read and the meta it is measured against
+        let mut meta = stream.lock().unwrap();
+
+        let bytes = self.codec.read_video_stream_chunk(handle)?;
+        *meta = self.codec.get_video_stream_meta(handle)?;
+
+        let position = meta.current_position - bytes.len() as u64;
+        Ok(VideoStreamChunk { bytes, position, is_final: meta.is_finished() })
+    }
+
+    pub fn seek(&self, session: u64, handle: u64, position: u64) -> Result<VideoStreamMeta, String> {
+        self.check_stream(session, handle)?;
+        let stream = self.stream(handle)?;
+        let mut meta = stream.lock().unwrap();
+
+        *meta = self.codec.seek_video_stream(handle, position)?;
+        Ok(meta.clone())
+    }
+
+    pub fn close(&self, session: u64, handle: u64) -> Result<(), String> {
+        self.check_stream(session, handle)?;
+        self.with_session(session, |owner| {
+            owner.streams.remove(&handle);
+            if owner.playback == Some(handle) {
+                owner.playback = None;
+            }
+        })?;
+        self.close_stream(handle)
+    }
+
+    fn close_stream(&self, handle: u64) -> Result<(), String> {
+        self.streams.lock().unwrap().remove(&handle);
+        self.sources.lock().unwrap().remove(&handle);
+        let served = self.served.lock().unwrap().remove(&handle);
+        if let Some(served) = served {
+            let _ = self.close_stream(served);
+        }
+
+        if self.codec.close_video_stream(handle)? {
+            Ok(())
+        } else {
+            Err(format!("Video stream {} was not open", handle))
+        }
+    }
+
+    pub fn decode_frame(&self, session: u64, handle: u64, position: f64, is_frame_index: bool) -> Result<Vec<u8>, String> {
+        self.check_stream(session, handle)?;
+
+        self.codec.decode_frame_at(handle, position, is_frame_index).map_err(String::from)
+    }
+
+    pub fn probe(&self, path: &str) -> Result<VideoProbe, String> {
+        self.codec.probe_video(Path::new(path)).map_err(String::from)
+    }
+
+    pub fn generate_thumbnails(&self, path: &str, output_dir: &Path, interval: f64, width: u32, progress: &ProgressReporter) -> Result<ThumbnailIndex, String> {
+        self.codec.generate_thumbnails(Path::new(path), output_dir, interval, width, progress).map_err(String::from)
+    }
+
+    pub fn start_import(&self, path: &str, output_path: &str) -> Result<u64, String> {
+        self.codec.start_import(Path::new(path), Path::new(output_path)).map_err(String::from)
+    }
+
+    pub fn start_proxy(&self, path: &str, output_path: &str, height: u32) -> Result<u64, String> {
+        self.codec.start_proxy(Path::new(path), Path::new(output_path), height).map_err(String::from)
+    }
+
+    pub fn import_status(&self, job: u64) -> Result<ImportStatus, String> {
+        self.codec.get_import_status(job).map_err(String::from)
+    }
+
+    pub fn close_import(&self, job: u64) -> bool {
+        self.codec.close_import(job).unwrap_or(false)
+    }
+
+    pub fn export_clip(&self, path: &str, output_path: &str, start: f64, end: f64, progress: &ProgressReporter) -> Result<ClipExport, String> {
+        self.codec.export_clip(Path::new(path), Path::new(output_path), start, end, progress).map_err(String::from)
+    }
+
+    pub fn detect_plays(&self, path: &str, progress: &ProgressReporter) -> Result<PlaySegmentation, String> {
+        self.codec.detect_plays(Path::new(path), progress).map_err(String::from)
+    }
+
+    pub fn keyframe_index(&self, path: &str, progress: &ProgressReporter) -> Result<KeyframeIndex, String> {
+        self.codec.get_keyframe_index(Path::new(path), progress).map_err(String::from)
+    }
+
+    pub fn detect_audio_events(&self, path: &str, progress: &ProgressReporter) -> Result<AudioAnalysis, String> {
+        self.codec.detect_audio_events(Path::new(path), progress).map_err(String::from)
+    }
+
+    pub fn start_reel(&self, segments: &[ReelSegment], output_path: &str) -> Result<u64, String> {
+        self.codec.start_reel(segments, Path::new(output_path)).map_err(String::from)
+    }
+
+    pub fn reel_status(&self, job: u64) -> Result<ReelStatus, String> {
+        self.codec.get_reel_status(job).map_err(String::from)
+    }
+
+    pub fn close_reel(&self, job: u64) -> bool {
+        self.codec.close_reel(job).unwrap_or(false)
+    }
+
+    /// Segments of `path` for `session`, replacing the ones it played before
+    pub fn open_segmented_stream(&self, session: u64, path: &str, segment_seconds: f64, progress: &ProgressReporter) -> Result<SegmentedStream, String> {
+        self.with_session(session, |_| ())?;
+        let stream = self.codec.open_segmented_stream(Path::new(path), segment_seconds, progress)?;
+
+        let previous = self.with_session(session, |owner| owner.segmented.replace(stream.handle));
+        match previous {
+            Ok(previous) => {
+                if let Some(previous) = previous {
+                    let _ = self.codec.close_segmented_stream(previous);
+                }
+                Ok(stream)
+            }
+            // closed while the file was scanned
+            Err(e) => {
+                let _ = self.codec.close_segmented_stream(stream.handle);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn read_init_segment(&self, session: u64, handle: u64) -> Result<Vec<u8>, String> {
+        self.check_segmented(session, handle)?;
+        self.codec.read_init_segment(handle).map_err(String::from)
+    }
+
+    pub fn read_media_segment(&self, session: u64, handle: u64, index: usize) -> Result<Vec<u8>, String> {
+        self.check_segmented(session, handle)?;
+        self.codec.read_media_segment(handle, index).map_err(String::from)
+    }
+
+    pub fn close_segmented_stream(&self, session: u64, handle: u64) -> Result<bool, String> {
+        self.check_segmented(session, handle)?;
+        self.with_session(session, |owner| owner.segmented = None)?;
+        Ok(self.codec.close_segmented_stream(handle).unwrap_or(false))
+    }
+
+    fn original_path(&self, handle: u64) -> Option<String> {
+        if let Some(source) = self.sources.lock().unwrap().get(&handle) {
+            return Some(source.clone());
+        }
+        let stream = self.stream(handle).ok()?;
+        let file_path = stream.lock().unwrap().file_path.clone();
+        Some(file_path)
+    }
+
+    /// Original file of the video `session` plays, never its proxy
+    pub fn session_path(&self, session: u64) -> Result<String, String> {
+        let handle = self.with_session(session, |owner| owner.playback)?.ok_or("No video is open")?;
+        self.original_path(handle).ok_or_else(|| String::from("No video is open"))
+    }
+
+    pub fn get_bytes(&self, session: u64) -> Result<Vec<u8>, String> {
+        let handle = self.with_session(session, |owner| owner.playback)?.ok_or("No video is playing")?;
+        self.read_chunk(session, handle).map(|chunk| chunk.bytes)
+    }
+
+    /// Stream of a session playing `path`, which is matched against the original file so a playing proxy is found too.
+    /// The active session comes first when several windows play the same file
+    pub fn playback_stream(&self, path: &str) -> Option<VideoStreamMeta> {
+        let active = *self.active_session.lock().unwrap();
+        let mut playing: Vec<(u64, u64)> = self.sessions.lock().unwrap().iter()
+            .filter_map(|(id, owner)| owner.playback.map(|handle| (*id, handle)))
+            .collect();
+        playing.sort_by_key(|(id, _)| Some(*id) != active);
+
+        let handle = playing.into_iter().map(|(_, handle)| handle).find(|handle| self.original_path(*handle).as_deref() == Some(path))?;
+        let stream = self.stream(handle).ok()?;
+        let meta = stream.lock().unwrap().clone();
+        Some(meta)
+    }
+
+    // Opened with the first range asked of `playback` and closed along with it
+    fn served_stream(&self, playback: u64) -> Result<u64, String> {
+        if let Some(served) = self.served.lock().unwrap().get(&playback) {
+            return Ok(*served);
+        }
+        let path = self.stream(playback)?.lock().unwrap().file_path.clone();
+        let meta = self.codec.open_video_stream(Path::new(&path), CHUNK_SIZE)?;
+
+        let mut served = self.served.lock().unwrap();
+        // another range may have opened one meanwhile, or the playback closed
+        let kept = match served.get(&playback) {
+            Some(other) => Some(*other),
+            None if self.stream(playback).is_err() => None,
+            None => {
+                served.insert(playback, meta.handle);
+                self.streams.lock().unwrap().insert(meta.handle, Arc::new(Mutex::new(meta.clone())));
+                return Ok(meta.handle);
+            }
         };
-        println!("{}..{}", p, bytes.len());
+        drop(served);
+
+        let _ = self.codec.close_video_stream(meta.handle);
+        kept.ok_or_else(|| format!("Video stream {} is not open", playback))
+    }
 
-        let slice = bytes[*p..upper].to_vec();
-        *p = upper;
-        Some(slice)
+    /// Up to `len` bytes from `position` of the file `playback` plays, read in as many chunks as that takes.
+    /// They are read through a stream of their own, which leaves where the playback reads are at
+    pub fn read_range(&self, playback: u64, position: u64, len: u64) -> Result<Vec<u8>, String> {
+        let handle = self.served_stream(playback)?;
+        let stream = self.stream(handle)?;
+        // held throughout so another read can't move the stream between the seek and the reads
+        let mut meta = stream.lock().unwrap();
+
+        *meta = self.codec.seek_video_stream(handle, position)?;
+        let mut bytes = Vec::new();
+        while (bytes.len() as u64) < len && !meta.is_finished() {
+            let chunk = self.codec.read_video_stream_chunk(handle)?;
+            *meta = self.codec.get_video_stream_meta(handle)?;
+            if chunk.is_empty() {
+                break;
+            }
+            bytes.extend(chunk);
+        }
+        bytes.truncate(len as usize);
+
+        Ok(bytes)
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{VideoState, CHUNK_SIZE};
+    use crate::mock_codec::MockCodec;
+    use rehash_codec_ffi::progress::ProgressReporter;
+
+    const PATH: &str = "/videos/game.mp4";
+
+    const WINDOW: &str = "main";
+
+    fn state_with(len: usize) -> VideoState {
+        VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(len))))
+    }
+
+    /// State with a session open, which is what any stream needs
+    fn session_with(len: usize) -> (VideoState, u64) {
+        let state = state_with(len);
+        let session = state.open_session(WINDOW);
+        (state, session)
+    }
+
+    #[test]
+    fn reads_file_in_chunk_size_pieces() {
+        let len = 2 * CHUNK_SIZE + 17;
+        let (state, session) = session_with(len);
+        let meta = state.open_stream(session, PATH.to_string()).unwrap();
+        assert_eq!(meta.total_size, len as u64);
+
+        let chunks = (0..3).map(|_| state.read_chunk(session, meta.handle).unwrap()).collect::<Vec<_>>();
+        let positions = chunks.iter().map(|chunk| chunk.position).collect::<Vec<_>>();
+        let sizes = chunks.iter().map(|chunk| chunk.bytes.len()).collect::<Vec<_>>();
+        let finals = chunks.iter().map(|chunk| chunk.is_final).collect::<Vec<_>>();
+
+        assert_eq!(positions, [0, CHUNK_SIZE as u64, 2 * CHUNK_SIZE as u64]);
+        assert_eq!(sizes, [CHUNK_SIZE, CHUNK_SIZE, 17]);
+        assert_eq!(finals, [false, false, true]);
+        assert_eq!(chunks.into_iter().flat_map(|chunk| chunk.bytes).collect::<Vec<_>>(), MockCodec::pattern(len));
+    }
+
+    #[test]
+    fn file_of_exactly_one_chunk_is_final_after_one_read() {
+        let (state, session) = session_with(CHUNK_SIZE);
+        let handle = state.open_stream(session, PATH.to_string()).unwrap().handle;
+
+        let chunk = state.read_chunk(session, handle).unwrap();
+        assert_eq!((chunk.position, chunk.bytes.len(), chunk.is_final), (0, CHUNK_SIZE, true));
+
+        let past_end = state.read_chunk(session, handle).unwrap();
+        assert_eq!((past_end.position, past_end.bytes.len(), past_end.is_final), (CHUNK_SIZE as u64, 0, true));
+    }
+
+    #[test]
+    fn empty_file_is_final_straight_away() {
+        let (state, session) = session_with(0);
+        let handle = state.open_stream(session, PATH.to_string()).unwrap().handle;
+
+        let chunk = state.read_chunk(session, handle).unwrap();
+        assert!(chunk.bytes.is_empty());
+        assert!(chunk.is_final);
+    }
+
+    #[test]
+    fn reads_resume_from_a_seek() {
+        let len = CHUNK_SIZE + 100;
+        let (state, session) = session_with(len);
+        let handle = state.open_stream(session, PATH.to_string()).unwrap().handle;
+        state.read_chunk(session, handle).unwrap();
+
+        let position = CHUNK_SIZE as u64 - 10;
+        assert_eq!(state.seek(session, handle, position).unwrap().current_position, position);
+        let chunk = state.read_chunk(session, handle).unwrap();
+        assert_eq!(chunk.position, position);
+        assert_eq!(chunk.bytes, MockCodec::pattern(len)[position as usize..]);
+        assert!(chunk.is_final);
+
+        // seeking past the end clamps to it
+        assert_eq!(state.seek(session, handle, u64::MAX).unwrap().current_position, len as u64);
+        assert!(state.read_chunk(session, handle).unwrap().bytes.is_empty());
+    }
+
+    #[test]
+    fn unknown_streams_are_rejected() {
+        let (state, session) = session_with(10);
+
+        assert_eq!(state.read_chunk(session, 42).unwrap_err(), "Video stream 42 is not open");
+        assert_eq!(state.decode_frame(session, 42, 0f64, false).unwrap_err(), "Video stream 42 is not open");
+        assert!(state.close(session, 42).is_err());
+        assert_eq!(state.read_init_segment(session, 42).unwrap_err(), "Segmented stream 42 is not open");
+        assert!(state.read_media_segment(session, 42, 0).is_err());
+        assert_eq!(state.open_stream(42, PATH.to_string()).unwrap_err(), "Video session 42 is not open");
+    }
+
+    #[test]
+    fn codec_errors_reach_the_caller() {
+        let state = VideoState::new(Box::new(MockCodec::new().with_file(PATH, MockCodec::pattern(2 * CHUNK_SIZE)).failing_read(2)));
+        let session = state.open_session(WINDOW);
+
+        let missing = state.open_stream(session, "/videos/missing.mp4".to_string()).unwrap_err();
+        assert!(missing.contains("/videos/missing.mp4"), "{}", missing);
+
+        let handle = state.open_stream(session, PATH.to_string()).unwrap().handle;
+        state.read_chunk(session, handle).unwrap();
+        let failed = state.read_chunk(session, handle).unwrap_err();
+        assert!(failed.contains("Failed to read chunk"), "{}", failed);
+
+        let unsupported = state.probe(PATH).unwrap_err();
+        assert!(unsupported.contains("Unsupported"), "{}", unsupported);
+
+        let segmented = state.open_segmented_stream(session, PATH, 4f64, &ProgressReporter::silent()).unwrap_err();
+        assert!(segmented.contains("Unsupported"), "{}", segmented);
+        assert!(state.close_segmented_stream(session, 1).is_err());
+    }
+
+    #[test]
+    fn playing_another_video_closes_the_previous_one() {
+        let (state, session) = session_with(CHUNK_SIZE + 1);
+        assert_eq!(state.get_bytes(session).unwrap_err(), "No video is playing");
+
+        let first = state.open_playback_stream(session, PATH, PATH.to_string()).unwrap().handle;
+        assert_eq!(state.session_path(session).unwrap(), PATH);
+
+        let second = state.open_playback_stream(session, "/videos/original.mov", PATH.to_string()).unwrap().handle;
+        assert!(state.read_chunk(session, first).is_err());
+        assert_eq!(state.session_path(session).unwrap(), "/videos/original.mov");
+
+        assert_eq!(state.get_bytes(session).unwrap().len(), CHUNK_SIZE);
+        assert_eq!(state.get_bytes(session).unwrap().len(), 1);
+
+        state.close(session, second).unwrap();
+        assert_eq!(state.session_path(session).unwrap_err(), "No video is open");
+        assert!(state.close(session, second).is_err());
+    }
+
+    #[test]
+    fn sessions_read_their_own_videos() {
+        let state = VideoState::new(Box::new(MockCodec::new()
+            .with_file(PATH, MockCodec::pattern(CHUNK_SIZE + 1))
+            .with_file("/videos/angle.mp4", MockCodec::pattern(CHUNK_SIZE + 2))));
+        let (first, second) = (state.open_session(WINDOW), state.open_session(WINDOW));
+        assert_ne!(first, second);
+
+        state.open_playback_stream(first, PATH, PATH.to_string()).unwrap();
+        let angle = state.open_playback_stream(second, "/videos/angle.mp4", "/videos/angle.mp4".to_string()).unwrap().handle;
+
+        // interleaved reads don't move each other's position
+        assert_eq!(state.get_bytes(first).unwrap().len(), CHUNK_SIZE);
+        assert_eq!(state.get_bytes(second).unwrap().len(), CHUNK_SIZE);
+        assert_eq!(state.get_bytes(first).unwrap().len(), 1);
+        assert_eq!(state.get_bytes(second).unwrap().len(), 2);
+
+        // a session can't touch the streams of another
+        assert_eq!(state.read_chunk(first, angle).unwrap_err(), format!("Video stream {} is not open", angle));
+        assert!(state.close(first, angle).is_err());
+
+        assert_eq!(state.playback_stream(PATH).unwrap().file_path, PATH);
+        assert_eq!(state.playback_stream("/videos/angle.mp4").unwrap().handle, angle);
+        // exports and analyses of each read its own video
+        assert_eq!(state.session_path(first).unwrap(), PATH);
+        assert_eq!(state.session_path(second).unwrap(), "/videos/angle.mp4");
+    }
+
+    #[test]
+    fn a_busy_stream_does_not_hold_up_the_others() {
+        let (state, session) = session_with(10);
+        let other = state.open_session("review");
+        let busy = state.open_stream(session, PATH.to_string()).unwrap().handle;
+        let free = state.open_stream(other, PATH.to_string()).unwrap().handle;
+
+        // as if a read of `busy` were waiting on the codec
+        let stream = state.stream(busy).unwrap();
+        let _reading = stream.lock().unwrap();
+        assert_eq!(state.read_chunk(other, free).unwrap().bytes, MockCodec::pattern(10));
+        assert_eq!(state.seek(other, free, 4).unwrap().current_position, 4);
+        assert!(state.open_stream(other, PATH.to_string()).is_ok());
+    }
+
+    #[test]
+    fn closing_a_session_closes_its_streams() {
+        let (state, session) = session_with(10);
+        let other = state.open_session("review");
+        let playback = state.open_playback_stream(session, PATH, PATH.to_string()).unwrap().handle;
+        let frames = state.open_stream(session, PATH.to_string()).unwrap().handle;
+        let served = state.served_stream(playback).unwrap();
+        let other_playback = state.open_playback_stream(other, PATH, PATH.to_string()).unwrap().handle;
+
+        state.close_session(session).unwrap();
+        assert_eq!(state.get_bytes(session).unwrap_err(), format!("Video session {} is not open", session));
+        assert!(state.close_session(session).is_err());
+        for handle in [playback, frames, served] {
+            assert!(state.codec.get_video_stream_meta(handle).is_err(), "Stream {} is still open", handle);
+        }
+
+        // the video of the other window is still playing, and now the active one
+        assert_eq!(state.get_bytes(other).unwrap(), MockCodec::pattern(10));
+        assert_eq!(state.session_path(other).unwrap(), PATH);
+        assert_eq!(state.playback_stream(PATH).unwrap().handle, other_playback);
+    }
+
+    #[test]
+    fn closing_a_window_closes_only_its_sessions() {
+        let (state, session) = session_with(10);
+        let review = state.open_session("review");
+        state.open_playback_stream(session, PATH, PATH.to_string()).unwrap();
+        state.open_playback_stream(review, PATH, PATH.to_string()).unwrap();
+
+        // a window can't close the session of another
+        assert_eq!(state.check_window(WINDOW, review).unwrap_err(), format!("Video session {} belongs to another window", review));
+        state.check_window("review", review).unwrap();
+        assert_eq!(state.get_bytes(review).unwrap(), MockCodec::pattern(10));
+
+        state.close_window("review");
+        assert!(state.get_bytes(review).is_err());
+        assert_eq!(state.get_bytes(session).unwrap(), MockCodec::pattern(10));
+
+        state.close_window(WINDOW);
+        assert!(state.get_bytes(session).is_err());
+        assert!(state.session_path(session).is_err());
+        assert!(state.playback_stream(PATH).is_none());
+    }
+}